@@ -0,0 +1,205 @@
+//! Counts for dashboards
+//!
+//! `GET /aggregate?by=` counts the current chunks of the index grouped by
+//! `domain` (the page's host), `language` (the primary subtag the page
+//! declares) or `crawl_date`, so a dashboard needs no export of the corpus.
+//! Domains and languages come from Qdrant's facet counts, largest first.
+//! Crawl dates are the last `buckets` days, weeks (from Monday) or months in
+//! UTC, oldest first and including the current one, each counted on its own.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use qdrant_client::qdrant::{FacetHit, facet_value::Variant};
+use serde::{Deserialize, Serialize};
+use shared_crawler_api::problem::Problem;
+
+pub const DEFAULT_BUCKETS: usize = 10;
+/// Most groups of a domain or language aggregation
+pub const MAX_GROUPS: usize = 100;
+/// Most crawl date buckets, a year of days
+pub const MAX_DATE_BUCKETS: usize = 366;
+/// Crawl date buckets counted at once
+pub const MAX_CONCURRENT_COUNTS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregateBy {
+    Domain,
+    Language,
+    CrawlDate,
+}
+
+impl AggregateBy {
+    /// Payload field counted by facet
+    pub fn facet_key(self) -> Option<&'static str> {
+        match self {
+            Self::Domain => Some("host"),
+            Self::Language => Some("language"),
+            Self::CrawlDate => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateInterval {
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AggregateQuery {
+    pub by: AggregateBy,
+    /// Bucket size of `crawl_date`
+    #[serde(default)]
+    pub interval: DateInterval,
+    /// Groups to return, or date buckets to count back from now
+    #[serde(default)]
+    pub buckets: Option<usize>,
+    /// Only pages of crawl jobs with this `collection`
+    #[serde(default)]
+    pub collection: Option<String>,
+}
+
+impl AggregateQuery {
+    /// The number of buckets asked for, within its limit
+    pub fn bucket_count(&self) -> Result<usize, Problem> {
+        let max = match self.by {
+            AggregateBy::CrawlDate => MAX_DATE_BUCKETS,
+            AggregateBy::Domain | AggregateBy::Language => MAX_GROUPS,
+        };
+        match self.buckets.unwrap_or(DEFAULT_BUCKETS) {
+            buckets @ 1.. if buckets <= max => Ok(buckets),
+            _ => Err(Problem::bad_request(
+                "buckets_out_of_range",
+                format!("buckets must be between 1 and {max}"),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Bucket {
+    /// Host, language, or the first day of a crawl date bucket
+    pub key: String,
+    /// Start of a crawl date bucket as Unix seconds; it ends where the next
+    /// one starts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<i64>,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Aggregation {
+    pub by: AggregateBy,
+    pub buckets: Vec<Bucket>,
+}
+
+pub fn facet_buckets(hits: Vec<FacetHit>) -> Vec<Bucket> {
+    hits.into_iter()
+        .filter_map(|hit| {
+            let key = match hit.value?.variant? {
+                Variant::StringValue(value) => value,
+                Variant::IntegerValue(value) => value.to_string(),
+                Variant::BoolValue(value) => value.to_string(),
+            };
+            Some(Bucket {
+                key,
+                start: None,
+                count: hit.count,
+            })
+        })
+        .collect()
+}
+
+/// The last `count` buckets up to `now`, oldest first, as keys with their
+/// start and end in Unix seconds
+pub fn date_buckets(now: i64, interval: DateInterval, count: usize) -> Vec<(String, i64, i64)> {
+    let today = DateTime::<Utc>::from_timestamp(now, 0)
+        .unwrap_or_default()
+        .date_naive();
+    let current = match interval {
+        DateInterval::Day => today,
+        DateInterval::Week => {
+            today - Duration::days(i64::from(today.weekday().num_days_from_monday()))
+        }
+        DateInterval::Month => today.with_day(1).unwrap_or(today),
+    };
+    let step = |start: NaiveDate, back: bool| match (interval, back) {
+        (DateInterval::Day, false) => start + Duration::days(1),
+        (DateInterval::Day, true) => start - Duration::days(1),
+        (DateInterval::Week, false) => start + Duration::days(7),
+        (DateInterval::Week, true) => start - Duration::days(7),
+        (DateInterval::Month, false) => start + chrono::Months::new(1),
+        (DateInterval::Month, true) => start - chrono::Months::new(1),
+    };
+    let mut starts = vec![current];
+    while starts.len() < count {
+        starts.push(step(*starts.last().unwrap(), true));
+    }
+    starts.reverse();
+    let unix = |date: NaiveDate| date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+    starts
+        .into_iter()
+        .map(|start| {
+            let key = match interval {
+                DateInterval::Month => start.format("%Y-%m").to_string(),
+                DateInterval::Day | DateInterval::Week => start.format("%Y-%m-%d").to_string(),
+            };
+            (key, unix(start), unix(step(start, false)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_crawl_dates_and_bounds_counts() {
+        let now = 1_792_152_000; // 2026-10-16 12:00, a Friday
+        let keys = |interval, count| {
+            date_buckets(now, interval, count)
+                .into_iter()
+                .map(|(key, _, _)| key)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(DateInterval::Day, 2), ["2026-10-15", "2026-10-16"]);
+        assert_eq!(keys(DateInterval::Week, 2), ["2026-10-05", "2026-10-12"]);
+        assert_eq!(
+            keys(DateInterval::Month, 3),
+            ["2026-08", "2026-09", "2026-10"]
+        );
+        let days = date_buckets(now, DateInterval::Day, 2);
+        assert_eq!(days[0].2, days[1].1);
+        assert_eq!(days[1].2 - days[1].1, 86_400);
+        assert!((days[1].1..days[1].2).contains(&now));
+
+        let query = |by, buckets| AggregateQuery {
+            by,
+            interval: DateInterval::Day,
+            buckets,
+            collection: None,
+        };
+        assert_eq!(
+            query(AggregateBy::Domain, None).bucket_count().unwrap(),
+            DEFAULT_BUCKETS
+        );
+        assert_eq!(
+            query(AggregateBy::CrawlDate, Some(MAX_DATE_BUCKETS))
+                .bucket_count()
+                .unwrap(),
+            MAX_DATE_BUCKETS
+        );
+        for buckets in [0, MAX_GROUPS + 1] {
+            assert_eq!(
+                query(AggregateBy::Language, Some(buckets))
+                    .bucket_count()
+                    .unwrap_err()
+                    .code,
+                "buckets_out_of_range"
+            );
+        }
+    }
+}
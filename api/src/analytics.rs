@@ -0,0 +1,411 @@
+//! Search analytics
+//!
+//! Every `/search` is logged to SQLite at `SEARCH_ANALYTICS_PATH` (default
+//! `search-analytics.sqlite3` in the working directory) with its latency and
+//! result count. Responses carry the log id in `X-Query-Id`; clients report
+//! opened results with `POST /analytics/clicks`. `GET /analytics/queries`
+//! summarizes the most frequent queries and those that found nothing, so
+//! gaps in the index show up. Each search is kept with the collection it
+//! searched, so a tenant's summary and clicks only cover its own searches.
+//! Queries are stored as a salted SHA-256 of the
+//! normalized query unless `SEARCH_ANALYTICS=plain`, since their text can be
+//! personal; `off` disables logging. The salt is `SEARCH_ANALYTICS_SALT` or
+//! one generated into the database, so hashes stay comparable across
+//! restarts but cannot be looked up in a table of common queries. Searches
+//! and clicks are deleted after a year.
+
+use anyhow::Result;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use shared_crawler_api::QDRANT_COLLECTION_NAME;
+use std::{
+    env,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const DEFAULT_PATH: &str = "search-analytics.sqlite3";
+/// How long searches and clicks are kept
+const RETENTION_SECS: i64 = 365 * 24 * 60 * 60;
+
+const SCHEMA: &str = "
+    PRAGMA journal_mode = WAL;
+    PRAGMA synchronous = NORMAL;
+    CREATE TABLE IF NOT EXISTS queries (
+        id INTEGER PRIMARY KEY,
+        query TEXT NOT NULL,
+        logged_at INTEGER NOT NULL,
+        latency_ms INTEGER NOT NULL,
+        results INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS queries_logged_at ON queries (logged_at);
+    CREATE TABLE IF NOT EXISTS clicks (
+        query_id INTEGER NOT NULL,
+        url TEXT NOT NULL,
+        position INTEGER,
+        clicked_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS clicks_query_id ON clicks (query_id);
+    CREATE INDEX IF NOT EXISTS clicks_clicked_at ON clicks (clicked_at);
+    CREATE TABLE IF NOT EXISTS settings (
+        name TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+";
+
+#[derive(Debug, Clone, PartialEq)]
+enum QueryText {
+    Plain,
+    /// Hashed with a salt from `SEARCH_ANALYTICS_SALT`, or generated when `None`
+    Hashed(Option<String>),
+}
+
+pub struct QueryLog {
+    conn: Mutex<Connection>,
+    text: QueryText,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueryStats {
+    pub query: String,
+    pub count: u64,
+    pub avg_latency_ms: f64,
+    pub avg_results: f64,
+    pub clicks: u64,
+    pub last_seen: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QuerySummary {
+    pub since: i64,
+    pub total_queries: u64,
+    pub zero_result_queries: u64,
+    pub top_queries: Vec<QueryStats>,
+    pub zero_result: Vec<QueryStats>,
+}
+
+impl QueryLog {
+    /// `None` when `SEARCH_ANALYTICS=off`
+    pub fn from_env() -> Result<Option<Self>> {
+        let text = match env::var("SEARCH_ANALYTICS").as_deref().map(str::trim) {
+            Ok("off" | "false" | "0") => return Ok(None),
+            Ok("plain") => QueryText::Plain,
+            _ => QueryText::Hashed(
+                env::var("SEARCH_ANALYTICS_SALT")
+                    .ok()
+                    .filter(|salt| !salt.trim().is_empty()),
+            ),
+        };
+        let path = env::var("SEARCH_ANALYTICS_PATH")
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_PATH.to_string());
+        Self::open(path, text).map(Some)
+    }
+
+    fn open(path: impl AsRef<Path>, text: QueryText) -> Result<Self> {
+        Self::init(Connection::open(path)?, text)
+    }
+
+    #[cfg(test)]
+    fn in_memory(text: QueryText) -> Result<Self> {
+        Self::init(Connection::open_in_memory()?, text)
+    }
+
+    fn init(conn: Connection, text: QueryText) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        // Logs from before tenants were told apart only held shared searches
+        let has_collection = conn
+            .prepare("SELECT 1 FROM pragma_table_info('queries') WHERE name = 'collection'")?
+            .exists([])?;
+        if !has_collection {
+            conn.execute(
+                &format!(
+                    "ALTER TABLE queries ADD COLUMN collection TEXT NOT NULL
+                     DEFAULT '{QDRANT_COLLECTION_NAME}'"
+                ),
+                [],
+            )?;
+        }
+        conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS queries_collection ON queries (collection, logged_at)",
+        )?;
+        let text = match text {
+            QueryText::Hashed(None) => {
+                conn.execute(
+                    "INSERT OR IGNORE INTO settings (name, value)
+                     VALUES ('salt', lower(hex(randomblob(32))))",
+                    [],
+                )?;
+                QueryText::Hashed(Some(conn.query_row(
+                    "SELECT value FROM settings WHERE name = 'salt'",
+                    [],
+                    |row| row.get(0),
+                )?))
+            }
+            text => text,
+        };
+        Ok(Self {
+            conn: Mutex::new(conn),
+            text,
+        })
+    }
+
+    /// Record a search of `collection`, dropping those older than the
+    /// retention period; returns the id clients report clicks against
+    pub fn log_query(
+        &self,
+        collection: &str,
+        query: &str,
+        latency: Duration,
+        results: usize,
+    ) -> Result<i64> {
+        let query = query.to_lowercase();
+        let query = match &self.text {
+            QueryText::Plain => query,
+            QueryText::Hashed(salt) => format!(
+                "{:x}",
+                Sha256::new()
+                    .chain_update(salt.as_deref().unwrap_or_default())
+                    .chain_update(query)
+                    .finalize()
+            ),
+        };
+        let logged_at = now();
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO queries (collection, query, logged_at, latency_ms, results)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                collection,
+                query,
+                logged_at,
+                latency.as_millis() as i64,
+                results as i64
+            ],
+        )?;
+        let id = conn.last_insert_rowid();
+        let expired = logged_at - RETENTION_SECS;
+        conn.execute("DELETE FROM queries WHERE logged_at < ?1", [expired])?;
+        conn.execute("DELETE FROM clicks WHERE clicked_at < ?1", [expired])?;
+        Ok(id)
+    }
+
+    /// `false` if no search of `collection` was logged under `query_id`
+    pub fn log_click(
+        &self,
+        collection: &str,
+        query_id: i64,
+        url: &str,
+        position: Option<usize>,
+    ) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let known = conn
+            .query_row(
+                "SELECT 1 FROM queries WHERE id = ?1 AND collection = ?2",
+                params![query_id, collection],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        if known {
+            conn.execute(
+                "INSERT INTO clicks (query_id, url, position, clicked_at) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    query_id,
+                    url,
+                    position.map(|position| position as i64),
+                    now()
+                ],
+            )?;
+        }
+        Ok(known)
+    }
+
+    /// Searches of `collection` logged at or after `since` (Unix seconds),
+    /// `limit` per list
+    pub fn summary(&self, collection: &str, since: i64, limit: usize) -> Result<QuerySummary> {
+        let conn = self.conn.lock().unwrap();
+        let (total_queries, zero_result_queries) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(results = 0), 0) FROM queries
+             WHERE collection = ?1 AND logged_at >= ?2",
+            params![collection, since],
+            |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)),
+        )?;
+        let stats = |zero_results_only: bool| -> Result<Vec<QueryStats>> {
+            let mut statement = conn.prepare_cached(
+                "SELECT q.query, COUNT(*), AVG(q.latency_ms), AVG(q.results),
+                        COALESCE(SUM(c.clicks), 0), MAX(q.logged_at)
+                 FROM queries q
+                 LEFT JOIN (SELECT query_id, COUNT(*) AS clicks FROM clicks GROUP BY query_id) c
+                     ON c.query_id = q.id
+                 WHERE q.collection = ?1 AND q.logged_at >= ?2 AND (?3 = 0 OR q.results = 0)
+                 GROUP BY q.query
+                 ORDER BY COUNT(*) DESC, MAX(q.logged_at) DESC
+                 LIMIT ?4",
+            )?;
+            let params = params![collection, since, zero_results_only, limit as i64];
+            let rows = statement.query_map(params, |row| {
+                Ok(QueryStats {
+                    query: row.get(0)?,
+                    count: row.get::<_, i64>(1)? as u64,
+                    avg_latency_ms: row.get(2)?,
+                    avg_results: row.get(3)?,
+                    clicks: row.get::<_, i64>(4)? as u64,
+                    last_seen: row.get(5)?,
+                })
+            })?;
+            Ok(rows.collect::<Result<_, _>>()?)
+        };
+        Ok(QuerySummary {
+            since,
+            total_queries,
+            zero_result_queries,
+            top_queries: stats(false)?,
+            zero_result: stats(true)?,
+        })
+    }
+}
+
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHARED: &str = QDRANT_COLLECTION_NAME;
+
+    #[test]
+    fn summarizes_top_and_zero_result_queries() {
+        let log = QueryLog::in_memory(QueryText::Plain).unwrap();
+        let latency = Duration::from_millis(40);
+        let first = log.log_query(SHARED, "Rust", latency, 10).unwrap();
+        log.log_query(SHARED, "rust", latency, 8).unwrap();
+        log.log_query(SHARED, "qdrant filters", latency, 0).unwrap();
+        assert!(
+            log.log_click(SHARED, first, "https://www.rust-lang.org/", Some(0))
+                .unwrap()
+        );
+        assert!(
+            !log.log_click(SHARED, first + 100, "https://example.com/", None)
+                .unwrap()
+        );
+
+        let summary = log.summary(SHARED, 0, 10).unwrap();
+        assert_eq!((summary.total_queries, summary.zero_result_queries), (3, 1));
+        assert_eq!(summary.top_queries[0].query, "rust");
+        assert_eq!(summary.top_queries[0].count, 2);
+        assert_eq!(summary.top_queries[0].avg_results, 9.0);
+        assert_eq!(summary.top_queries[0].clicks, 1);
+        assert_eq!(summary.zero_result.len(), 1);
+        assert_eq!(summary.zero_result[0].query, "qdrant filters");
+        assert_eq!(
+            log.summary(SHARED, now() + 60, 10).unwrap().total_queries,
+            0
+        );
+    }
+
+    #[test]
+    fn hashes_queries_with_a_salt() {
+        let log = QueryLog::in_memory(QueryText::Hashed(None)).unwrap();
+        log.log_query(SHARED, "Secret Query", Duration::ZERO, 1)
+            .unwrap();
+        log.log_query(SHARED, "secret query", Duration::ZERO, 1)
+            .unwrap();
+        let summary = log.summary(SHARED, 0, 10).unwrap();
+        let hash = &summary.top_queries[0].query;
+        assert_eq!((hash.len(), summary.top_queries[0].count), (64, 2));
+        assert_ne!(hash, &format!("{:x}", Sha256::digest("secret query")));
+
+        let salted = QueryLog::in_memory(QueryText::Hashed(Some("pepper".into()))).unwrap();
+        salted
+            .log_query(SHARED, "secret query", Duration::ZERO, 1)
+            .unwrap();
+        let other = &salted.summary(SHARED, 0, 10).unwrap().top_queries[0].query;
+        assert_ne!(other, hash);
+        assert_eq!(
+            other,
+            &format!("{:x}", Sha256::digest("peppersecret query"))
+        );
+    }
+
+    #[test]
+    fn deletes_searches_and_clicks_after_a_year() {
+        let log = QueryLog::in_memory(QueryText::Plain).unwrap();
+        let old = log.log_query(SHARED, "old", Duration::ZERO, 1).unwrap();
+        log.log_click(SHARED, old, "https://example.com/", None)
+            .unwrap();
+        {
+            let conn = log.conn.lock().unwrap();
+            let expired = now() - RETENTION_SECS - 1;
+            conn.execute("UPDATE queries SET logged_at = ?1", [expired])
+                .unwrap();
+            conn.execute("UPDATE clicks SET clicked_at = ?1", [expired])
+                .unwrap();
+        }
+        log.log_query(SHARED, "new", Duration::ZERO, 1).unwrap();
+
+        let summary = log.summary(SHARED, 0, 10).unwrap();
+        assert_eq!(summary.total_queries, 1);
+        assert_eq!(summary.top_queries[0].query, "new");
+        let clicks: i64 = log
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM clicks", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(clicks, 0);
+    }
+
+    #[test]
+    fn keeps_tenants_apart() {
+        let log = QueryLog::in_memory(QueryText::Plain).unwrap();
+        let docs = "web_pages__docs";
+        let shared = log.log_query(SHARED, "rust", Duration::ZERO, 1).unwrap();
+        let tenant = log.log_query(docs, "invoices", Duration::ZERO, 1).unwrap();
+
+        assert!(
+            !log.log_click(docs, shared, "https://example.com/", None)
+                .unwrap()
+        );
+        assert!(
+            log.log_click(docs, tenant, "https://example.com/", None)
+                .unwrap()
+        );
+        let summary = log.summary(docs, 0, 10).unwrap();
+        assert_eq!(summary.total_queries, 1);
+        assert_eq!(summary.top_queries[0].query, "invoices");
+        assert_eq!(summary.top_queries[0].clicks, 1);
+        assert_eq!(log.summary(SHARED, 0, 10).unwrap().top_queries[0].clicks, 0);
+    }
+
+    #[test]
+    fn adds_the_collection_to_older_logs() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE queries (
+                id INTEGER PRIMARY KEY,
+                query TEXT NOT NULL,
+                logged_at INTEGER NOT NULL,
+                latency_ms INTEGER NOT NULL,
+                results INTEGER NOT NULL
+            );",
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO queries (query, logged_at, latency_ms, results) VALUES ('rust', ?1, 1, 1)",
+            [now()],
+        )
+        .unwrap();
+
+        let log = QueryLog::init(conn, QueryText::Plain).unwrap();
+        assert_eq!(log.summary(SHARED, 0, 10).unwrap().total_queries, 1);
+    }
+}
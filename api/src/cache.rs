@@ -0,0 +1,255 @@
+use api::search_core::{PreparedSearch, SearchExplain, SearchQuery};
+use shared_crawler_api::caches::{CacheCounters, CacheStats};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+const DEFAULT_CAPACITY: usize = 1_000;
+const DEFAULT_TTL_SECS: u64 = 60;
+
+struct Entry<V> {
+    value: V,
+    domains: HashSet<String>,
+    inserted: Instant,
+    last_used: u64,
+}
+
+struct Inner<V> {
+    entries: HashMap<String, Entry<V>>,
+    clock: u64,
+}
+
+/// In-process LRU cache with a TTL, invalidated per result domain
+pub struct SearchCache<V> {
+    capacity: usize,
+    ttl: Duration,
+    inner: Mutex<Inner<V>>,
+    lookups: CacheCounters,
+}
+
+impl<V: Clone> SearchCache<V> {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            inner: Mutex::new(Inner {
+                entries: HashMap::new(),
+                clock: 0,
+            }),
+            lookups: CacheCounters::default(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let capacity = std::env::var("SEARCH_CACHE_CAPACITY")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_CAPACITY);
+        let ttl = std::env::var("SEARCH_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_TTL_SECS);
+        Self::new(capacity, Duration::from_secs(ttl))
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let clock = inner.clock;
+        let value = match inner.entries.get_mut(key) {
+            Some(entry) if entry.inserted.elapsed() < self.ttl => {
+                entry.last_used = clock;
+                Some(entry.value.clone())
+            }
+            Some(_) => {
+                inner.entries.remove(key);
+                None
+            }
+            None => None,
+        };
+        self.lookups.record(value.is_some());
+        value
+    }
+
+    pub fn insert<'a>(&self, key: String, value: V, urls: impl IntoIterator<Item = &'a str>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let domains = urls.into_iter().filter_map(domain_of).collect();
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let clock = inner.clock;
+        if !inner.entries.contains_key(&key) && inner.entries.len() >= self.capacity {
+            let ttl = self.ttl;
+            inner
+                .entries
+                .retain(|_, entry| entry.inserted.elapsed() < ttl);
+            if inner.entries.len() >= self.capacity {
+                let oldest = inner
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_used)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    inner.entries.remove(&oldest);
+                }
+            }
+        }
+        inner.entries.insert(
+            key,
+            Entry {
+                value,
+                domains,
+                inserted: Instant::now(),
+                last_used: clock,
+            },
+        );
+    }
+
+    /// Drop every entry whose results include one of `domains`; returns the number removed
+    pub fn invalidate_domains(&self, domains: &[String]) -> usize {
+        let domains = domains
+            .iter()
+            .map(|domain| normalize_domain(domain))
+            .collect::<HashSet<_>>();
+        let mut inner = self.inner.lock().unwrap();
+        let before = inner.entries.len();
+        inner
+            .entries
+            .retain(|_, entry| entry.domains.is_disjoint(&domains));
+        before - inner.entries.len()
+    }
+
+    /// Drop every entry; returns the number removed
+    pub fn clear(&self) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let removed = inner.entries.len();
+        inner.entries.clear();
+        self.lookups.reset();
+        removed
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.lookups.stats(self.inner.lock().unwrap().entries.len())
+    }
+}
+
+/// Key of a search of the Qdrant `collection`: everything in `prepared`,
+/// `query` and `explain` that changes its results
+pub fn search_key(
+    collection: &str,
+    prepared: &PreparedSearch,
+    query: &SearchQuery,
+    explain: &SearchExplain,
+) -> String {
+    format!(
+        "{collection}\0{}\0{}\0{}\0{}\0{:?}\0{}\0{}\0{}\0{:?}\0{:?}\0{}\0{:?}",
+        prepared.query.to_lowercase(),
+        prepared.limit,
+        prepared.offset,
+        query.include_unsafe,
+        query.as_of,
+        query.expand,
+        explain.mode.as_str(),
+        query.sort.as_str(),
+        query.collection,
+        prepared.author,
+        prepared.sites,
+        explain.language,
+    )
+}
+
+fn domain_of(url: &str) -> Option<String> {
+    url::Url::parse(url).ok()?.host_str().map(normalize_domain)
+}
+
+fn normalize_domain(domain: &str) -> String {
+    let domain = domain.trim().trim_end_matches('.').to_ascii_lowercase();
+    domain
+        .strip_prefix("www.")
+        .map(str::to_string)
+        .unwrap_or(domain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use api::search_core::prepare_search_query;
+    use serde_json::json;
+
+    #[test]
+    fn expires_and_evicts_least_recently_used() {
+        let cache = SearchCache::new(2, Duration::from_secs(60));
+        cache.insert("a".into(), 1, []);
+        cache.insert("b".into(), 2, []);
+        assert_eq!(cache.get("a"), Some(1));
+        cache.insert("c".into(), 3, []);
+
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("c"), Some(3));
+
+        let expired = SearchCache::new(2, Duration::ZERO);
+        expired.insert("a".into(), 1, []);
+        assert_eq!(expired.get("a"), None);
+    }
+
+    #[test]
+    fn invalidates_entries_for_matching_domains() {
+        let cache = SearchCache::new(10, Duration::from_secs(60));
+        cache.insert("rust".into(), 1, ["https://www.rust-lang.org/learn"]);
+        cache.insert("docs".into(), 2, ["https://docs.rs/tokio"]);
+
+        assert_eq!(cache.invalidate_domains(&["rust-lang.org".to_string()]), 1);
+        assert_eq!(cache.get("rust"), None);
+        assert_eq!(cache.get("docs"), Some(2));
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 1));
+        assert_eq!(cache.clear(), 1);
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn normalizes_search_keys() {
+        let key = |collection: &str, query: serde_json::Value| {
+            let query: SearchQuery = serde_json::from_value(query).unwrap();
+            let prepared = prepare_search_query(&query).unwrap();
+            let explain = SearchExplain::new(query.mode.unwrap_or_default(), &prepared, None);
+            search_key(collection, &prepared, &query, &explain)
+        };
+        let base = key("web_pages", json!({ "query": "rust crawler" }));
+
+        assert_eq!(key("web_pages", json!({ "query": " Rust  Crawler" })), base);
+        assert_ne!(
+            key("web_pages__docs", json!({ "query": "rust crawler" })),
+            base
+        );
+        for (field, value) in [
+            ("query", json!("rust spider")),
+            ("limit", json!(20)),
+            ("offset", json!(10)),
+            ("include_unsafe", json!(true)),
+            ("as_of", json!(1_700_000_000)),
+            ("expand", json!(false)),
+            ("mode", json!("dense")),
+            ("sort", json!("crawled_at")),
+            ("collection", json!("docs")),
+            ("author", json!("Ada Lovelace")),
+            ("language", json!("de")),
+        ] {
+            let mut query = json!({ "query": "rust crawler" });
+            query[field] = value;
+            assert_ne!(key("web_pages", query), base, "{field}");
+        }
+        assert_ne!(
+            key("web_pages", json!({ "query": "rust crawler site:docs.rs" })),
+            base
+        );
+    }
+}
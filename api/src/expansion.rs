@@ -0,0 +1,206 @@
+//! Query expansion for short queries
+//!
+//! With `QUERY_EXPANSION=corpus`, the words of the top results' titles,
+//! headings and tags are embedded and the ones closest to the query are
+//! appended to it; with `QUERY_EXPANSION=llm`, `QUERY_EXPANSION_URL`
+//! receives `POST {"query"}` and answers `{"paraphrases": [...]}`. Each
+//! expanded query is searched as well and the result lists are merged with
+//! reciprocal rank fusion (see `fusion`). Only queries of at most
+//! `QUERY_EXPANSION_MAX_QUERY_TERMS` terms are expanded, and `expand=false`
+//! turns expansion off for one request.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use shared_crawler_api::WebPageResult;
+use std::{collections::HashSet, env};
+
+use crate::ranking::{STOPWORDS, query_terms};
+
+const DEFAULT_MAX_QUERY_TERMS: usize = 3;
+const DEFAULT_TERMS: usize = 3;
+/// Top results whose words are considered
+const FEEDBACK_RESULTS: usize = 10;
+/// Candidate words embedded per query
+const MAX_CANDIDATES: usize = 24;
+const MAX_PARAPHRASES: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpansionMode {
+    Off,
+    /// Nearest words of the top results
+    Corpus,
+    /// Paraphrases from the hook at this URL
+    Llm(String),
+}
+
+pub struct QueryExpansion {
+    pub mode: ExpansionMode,
+    max_query_terms: usize,
+    pub terms: usize,
+}
+
+impl QueryExpansion {
+    pub fn from_env() -> Self {
+        let read = |key: &str, default: usize| {
+            env::var(key)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        let mode = match env::var("QUERY_EXPANSION").as_deref().map(str::trim) {
+            Ok("corpus") => ExpansionMode::Corpus,
+            Ok("llm") => match env::var("QUERY_EXPANSION_URL") {
+                Ok(url) if !url.trim().is_empty() => ExpansionMode::Llm(url),
+                _ => panic!("QUERY_EXPANSION=llm requires QUERY_EXPANSION_URL"),
+            },
+            _ => ExpansionMode::Off,
+        };
+        Self {
+            mode,
+            max_query_terms: read("QUERY_EXPANSION_MAX_QUERY_TERMS", DEFAULT_MAX_QUERY_TERMS),
+            terms: read("QUERY_EXPANSION_TERMS", DEFAULT_TERMS),
+        }
+    }
+
+    /// No expansion
+    pub fn off() -> Self {
+        Self {
+            mode: ExpansionMode::Off,
+            max_query_terms: DEFAULT_MAX_QUERY_TERMS,
+            terms: DEFAULT_TERMS,
+        }
+    }
+
+    /// Whether `query` is short enough to expand
+    pub fn applies(&self, query: &str) -> bool {
+        self.mode != ExpansionMode::Off && query_terms(query).len() <= self.max_query_terms
+    }
+}
+
+/// Words of the top results worth trying as expansions of `query`
+pub fn candidate_terms(results: &[WebPageResult], query: &str) -> Vec<String> {
+    let query = query_terms(query).into_iter().collect::<HashSet<_>>();
+    let mut seen = HashSet::new();
+    results
+        .iter()
+        .take(FEEDBACK_RESULTS)
+        .flat_map(|result| {
+            let data = &result.data;
+            let mut texts = vec![data.page_title.clone(), data.heading_context()];
+            texts.extend(data.tags.iter().cloned());
+            texts
+        })
+        .flat_map(|text| query_terms(&text))
+        .filter(|term| {
+            term.chars().count() >= 3
+                && term.chars().all(char::is_alphabetic)
+                && !STOPWORDS.contains(&term.as_str())
+                && !query.contains(term)
+        })
+        .filter(|term| seen.insert(term.clone()))
+        .take(MAX_CANDIDATES)
+        .collect()
+}
+
+/// The `count` candidates whose embeddings are closest to the query's
+pub fn nearest_terms(
+    query: &[f32],
+    candidates: Vec<String>,
+    embeddings: &[Vec<f32>],
+    count: usize,
+) -> Vec<String> {
+    let mut scored = candidates
+        .into_iter()
+        .zip(embeddings)
+        .map(|(term, embedding)| (cosine(query, embedding), term))
+        .collect::<Vec<_>>();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored
+        .into_iter()
+        .take(count)
+        .map(|(_, term)| term)
+        .collect()
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot = a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+#[derive(Serialize)]
+struct ParaphraseRequest<'a> {
+    query: &'a str,
+}
+
+#[derive(Deserialize)]
+struct ParaphraseResponse {
+    paraphrases: Vec<String>,
+}
+
+/// Paraphrases of `query` from the LLM hook, without blanks and repeats
+pub async fn paraphrases(http: &reqwest::Client, url: &str, query: &str) -> Result<Vec<String>> {
+    let response = http
+        .post(url)
+        .headers(crate::forwarded_request_id())
+        .json(&ParaphraseRequest { query })
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<ParaphraseResponse>()
+        .await?;
+    let mut seen = HashSet::from([query.to_lowercase()]);
+    Ok(response
+        .paraphrases
+        .into_iter()
+        .map(|paraphrase| paraphrase.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|paraphrase| !paraphrase.is_empty() && seen.insert(paraphrase.to_lowercase()))
+        .take(MAX_PARAPHRASES)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_crawler_api::WebPageChunk;
+
+    fn result(url: &str, title: &str) -> WebPageResult {
+        WebPageResult::new(
+            WebPageChunk::new(
+                format!("content of {url}"),
+                None,
+                url.to_string(),
+                title.to_string(),
+                String::new(),
+                vec!["Tokio".to_string()],
+                vec![],
+                0.0,
+                0.0,
+                0,
+            ),
+            1.0,
+        )
+    }
+
+    #[test]
+    fn picks_unseen_words_nearest_to_the_query() {
+        let results = [
+            result("https://a.example/", "Rust async runtime"),
+            result("https://b.example/", "The async book"),
+        ];
+        let candidates = candidate_terms(&results, "async");
+        assert_eq!(candidates, ["rust", "runtime", "tokio", "book"]);
+
+        let embeddings = [
+            vec![0.0, 1.0],
+            vec![1.0, 0.1],
+            vec![0.9, 0.0],
+            vec![-1.0, 0.0],
+        ];
+        assert_eq!(
+            nearest_terms(&[1.0, 0.0], candidates, &embeddings, 2),
+            ["tokio", "runtime"]
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Search results as exports
+//!
+//! `/search?format=ndjson` answers with one result per line instead of a
+//! JSON document, serialized as the client reads them and sent with chunked
+//! transfer encoding. `format=csv` (or `Accept: text/csv`) answers with one
+//! spreadsheet row per result. Results are ranked together before either is
+//! written, so exports take a larger `limit` than JSON answers, up to
+//! `MAX_EXPORT_RESULTS`, but not an unbounded one.
+
+use actix_web::{HttpResponse, web::Bytes};
+use serde::Deserialize;
+use shared_crawler_api::{
+    WebPageResult,
+    csv::{self, CsvWriter},
+};
+
+/// Largest `limit` of an export
+pub const MAX_EXPORT_RESULTS: usize = 1_000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultFormat {
+    #[default]
+    Json,
+    Ndjson,
+    Csv,
+}
+
+/// `results` as newline-delimited JSON, one line per result
+pub fn ndjson_response(results: Vec<WebPageResult>) -> HttpResponse {
+    let lines = results.into_iter().map(|result| {
+        let mut line = serde_json::to_vec(&result)?;
+        line.push(b'\n');
+        Ok::<_, serde_json::Error>(Bytes::from(line))
+    });
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(futures::stream::iter(lines))
+}
+
+/// `results` as CSV, one row per result in rank order
+pub fn csv_response(results: &[WebPageResult]) -> HttpResponse {
+    let mut csv = CsvWriter::new(&[
+        "rank",
+        "score",
+        "url",
+        "title",
+        "heading",
+        "description",
+        "crawled_at",
+        "published_at",
+        "content",
+    ]);
+    for (rank, result) in results.iter().enumerate() {
+        let chunk = &result.data;
+        csv.row([
+            (rank + 1).to_string(),
+            result.score.to_string(),
+            chunk.source_url.clone(),
+            chunk.page_title.clone(),
+            chunk.chunk_heading.clone().unwrap_or_default(),
+            chunk.description.clone(),
+            chunk.crawled_at.to_string(),
+            chunk
+                .published_at
+                .map(|published_at| published_at.to_string())
+                .unwrap_or_default(),
+            chunk.chunk_content.clone(),
+        ]);
+    }
+    HttpResponse::Ok()
+        .content_type(csv::CONTENT_TYPE)
+        .body(csv.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use shared_crawler_api::WebPageChunk;
+
+    #[actix_web::test]
+    async fn streams_one_result_per_line() {
+        let result = |url: &str| {
+            let chunk = WebPageChunk::new(
+                "content".to_string(),
+                None,
+                url.to_string(),
+                String::new(),
+                String::new(),
+                vec![],
+                vec![],
+                0.0,
+                0.0,
+                0,
+            );
+            WebPageResult::new(chunk, 1.0)
+        };
+        let body = to_bytes(
+            ndjson_response(vec![
+                result("https://a.example/"),
+                result("https://b.example/"),
+            ])
+            .into_body(),
+        )
+        .await
+        .unwrap();
+        let lines = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].contains("https://b.example/"));
+    }
+}
@@ -0,0 +1,40 @@
+//! Relaxed retries for searches that find nothing
+//!
+//! A hybrid search comes back empty when every candidate is filtered out:
+//! nothing was indexed before `as_of`, or the only hits are files. The
+//! search is then retried with stemmed terms for the lexical half and, for
+//! `as_of` searches, as a dense-only search of current versions. Responses
+//! answered this way carry `fallback: true`.
+
+use shared_crawler_api::text::{self, Language};
+
+/// Query with common inflections stripped from its terms, so "crawlers
+/// indexing pages" also finds "crawler index page"
+pub fn stem_terms(query: &str, language: Language) -> String {
+    query
+        .split_whitespace()
+        .map(|term| text::stem(term, language))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_common_inflections() {
+        assert_eq!(
+            stem_terms("Crawlers indexing  boxes libraries", Language::English),
+            "crawler index box library"
+        );
+        assert_eq!(
+            stem_terms("class status rust", Language::English),
+            "class status rust"
+        );
+        assert_eq!(
+            stem_terms("bus http/2 c++", Language::English),
+            "bus http/2 c++"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Retrieval strategies and reciprocal rank fusion
+//!
+//! `/search?mode=` picks how candidates are retrieved: `hybrid` (the
+//! default, Qdrant fuses dense, title and body prefetches), `dense`,
+//! `lexical` (title and body BM25 only) or `fusion`, which runs the other
+//! three in parallel and merges their lists here. A chunk that several
+//! strategies rank well beats one that a single strategy ranks first, which
+//! holds up better across query types than any one fixed weighting.
+//! `SEARCH_MODE` sets the default.
+
+use serde::{Deserialize, Serialize};
+use shared_crawler_api::WebPageResult;
+use std::{collections::HashMap, env};
+
+/// Rank offset of reciprocal rank fusion
+const RRF_K: f32 = 60.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetrievalMode {
+    #[default]
+    Hybrid,
+    Dense,
+    Lexical,
+    Fusion,
+}
+
+impl RetrievalMode {
+    /// `SEARCH_MODE`, `hybrid` when unset or unknown
+    pub fn from_env() -> Self {
+        env::var("SEARCH_MODE")
+            .ok()
+            .and_then(|mode| {
+                serde_json::from_value(serde_json::Value::String(mode.trim().to_string())).ok()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Hybrid => "hybrid",
+            Self::Dense => "dense",
+            Self::Lexical => "lexical",
+            Self::Fusion => "fusion",
+        }
+    }
+}
+
+/// Merge ranked result lists: each chunk scores `1 / (k + rank)` summed over
+/// the lists it appears in
+pub fn fuse(lists: Vec<Vec<WebPageResult>>) -> Vec<WebPageResult> {
+    let mut fused: HashMap<(String, String), (f32, WebPageResult)> = HashMap::new();
+    for list in lists {
+        for (rank, result) in list.into_iter().enumerate() {
+            let score = 1.0 / (RRF_K + rank as f32 + 1.0);
+            let key = (
+                result.data.source_url.clone(),
+                result.data.chunk_content.clone(),
+            );
+            fused.entry(key).or_insert((0.0, result)).0 += score;
+        }
+    }
+    let mut results = fused
+        .into_values()
+        .map(|(score, mut result)| {
+            result.score = score;
+            result
+        })
+        .collect::<Vec<_>>();
+    results.sort_by(|a, b| b.score.total_cmp(&a.score));
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_crawler_api::WebPageChunk;
+
+    fn result(url: &str) -> WebPageResult {
+        WebPageResult::new(
+            WebPageChunk::new(
+                "content".to_string(),
+                None,
+                url.to_string(),
+                String::new(),
+                String::new(),
+                vec![],
+                vec![],
+                0.0,
+                0.0,
+                0,
+            ),
+            1.0,
+        )
+    }
+
+    #[test]
+    fn fuses_lists_by_reciprocal_rank() {
+        let (a, b, c) = (
+            result("https://a.example/"),
+            result("https://b.example/"),
+            result("https://c.example/"),
+        );
+        let fused = fuse(vec![vec![a.clone(), b.clone()], vec![b.clone(), c.clone()]]);
+        let urls = fused
+            .iter()
+            .map(|result| result.data.source_url.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://b.example/",
+                "https://a.example/",
+                "https://c.example/"
+            ]
+        );
+        assert_eq!(
+            serde_json::from_str::<RetrievalMode>("\"fusion\"").unwrap(),
+            RetrievalMode::Fusion
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use actix_web::{
+    HttpRequest, HttpResponse,
+    http::header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use shared_crawler_api::problem::Problem;
+
+pub const SEARCH_MAX_AGE: u64 = 60;
+pub const PAGE_MAX_AGE: u64 = 300;
+
+/// JSON response with a content-hash ETag, answering `304 Not Modified` on a match
+pub fn cached_json<T: Serialize>(req: &HttpRequest, value: &T, max_age: u64) -> HttpResponse {
+    let body = match serde_json::to_vec(value) {
+        Ok(body) => body,
+        Err(error) => {
+            return crate::problem_response(Problem::internal(
+                "serialization_failed",
+                error.to_string(),
+            ));
+        }
+    };
+    cached_body(req, body, "application/json", max_age)
+}
+
+/// Response with a content-hash ETag, answering `304 Not Modified` on a match
+pub fn cached_body(
+    req: &HttpRequest,
+    body: Vec<u8>,
+    content_type: &'static str,
+    max_age: u64,
+) -> HttpResponse {
+    let etag = etag(&body);
+    let cache_control = format!("public, max-age={max_age}");
+    if if_none_match(req, &etag) {
+        return HttpResponse::NotModified()
+            .insert_header((ETAG, etag))
+            .insert_header((CACHE_CONTROL, cache_control))
+            .finish();
+    }
+    HttpResponse::Ok()
+        .insert_header((CONTENT_TYPE, content_type))
+        .insert_header((ETAG, etag))
+        .insert_header((CACHE_CONTROL, cache_control))
+        .body(body)
+}
+
+fn etag(body: &[u8]) -> String {
+    let digest = format!("{:x}", Sha256::digest(body));
+    format!("\"{}\"", &digest[..32])
+}
+
+fn if_none_match(req: &HttpRequest, etag: &str) -> bool {
+    req.headers()
+        .get_all(IF_NONE_MATCH)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|candidate| candidate.trim())
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test::TestRequest};
+
+    #[test]
+    fn etags_are_quoted_and_content_addressed() {
+        let tag = etag(b"{}");
+        assert_eq!(tag.len(), 34);
+        assert!(tag.starts_with('"') && tag.ends_with('"'));
+        assert_eq!(tag, etag(b"{}"));
+        assert_ne!(tag, etag(b"[]"));
+    }
+
+    #[test]
+    fn answers_not_modified_for_matching_etag() {
+        let value = serde_json::json!({"total": 0});
+        let fresh = cached_json(&TestRequest::default().to_http_request(), &value, 60);
+        assert_eq!(fresh.status(), StatusCode::OK);
+        assert_eq!(
+            fresh.headers().get(CACHE_CONTROL).unwrap(),
+            "public, max-age=60"
+        );
+        let tag = fresh.headers().get(ETAG).unwrap().to_str().unwrap();
+
+        let request = TestRequest::default()
+            .insert_header((IF_NONE_MATCH, format!("\"other\", W/{tag}")))
+            .to_http_request();
+        assert_eq!(
+            cached_json(&request, &value, 60).status(),
+            StatusCode::NOT_MODIFIED
+        );
+    }
+}
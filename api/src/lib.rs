@@ -0,0 +1,70 @@
+//! Search over the crawled index
+//!
+//! `search_core::SearchCore` owns the Qdrant client, the HTTP client for TEI
+//! and the search settings, and answers searches and plagiarism checks. The
+//! `api` binary serves it over HTTP; other front ends, such as a CLI, can
+//! build one with `SearchCore::from_env` and call it directly.
+
+use qdrant_client::qdrant::{Condition, Filter};
+use shared_crawler_api::{problem::Problem, request_id};
+use std::collections::HashMap;
+
+pub mod expansion;
+pub mod export;
+pub mod fallback;
+pub mod fusion;
+pub mod operators;
+pub mod plagiat;
+pub mod query_intent;
+pub mod ranking;
+pub mod search_core;
+pub mod versions;
+
+pub fn as_of_out_of_range() -> Problem {
+    Problem::bad_request(
+        "as_of_out_of_range",
+        "as_of must be a Unix timestamp in seconds",
+    )
+}
+
+/// Current chunks, or those current at `as_of`
+pub fn version_filter(as_of: Option<i64>) -> Filter {
+    as_of.map_or_else(|| Filter::must([versions::current()]), versions::as_of)
+}
+
+/// Limit `filter` to pages labelled `collection` by their crawl job
+pub fn add_collection(filter: &mut Filter, collection: Option<&str>) {
+    if let Some(collection) = collection {
+        filter
+            .must
+            .push(Condition::matches("collection", collection.to_string()));
+    }
+}
+
+/// `[<request id>] ` for log lines written while handling a request
+pub fn log_tag() -> String {
+    request_id::current()
+        .map(|id| format!("[{id}] "))
+        .unwrap_or_default()
+}
+
+/// `X-Request-Id` of the request being handled, to forward on calls made
+/// for it
+pub fn forwarded_request_id() -> reqwest::header::HeaderMap {
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(value) =
+        request_id::current().and_then(|id| reqwest::header::HeaderValue::from_str(&id).ok())
+    {
+        headers.insert(request_id::HEADER, value);
+    }
+    headers
+}
+
+pub fn payload_json(payload: HashMap<String, qdrant_client::qdrant::Value>) -> serde_json::Value {
+    serde_json::Value::Object(
+        payload
+            .into_iter()
+            .map(|(key, value)| (key, value.into_json()))
+            .collect(),
+    )
+}
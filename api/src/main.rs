@@ -1,340 +1,643 @@
 use actix_cors::Cors;
-use actix_web::{App, HttpResponse, HttpServer, Responder, web};
-use qdrant_client::{
-    Qdrant,
-    qdrant::{
-        Condition, CountPointsBuilder, DocumentBuilder, Filter, PrefetchQueryBuilder, Query,
-        QueryPointsBuilder, RrfBuilder, ScrollPointsBuilder,
-    },
+use actix_web::{
+    App, HttpRequest, HttpResponse, HttpServer, Responder,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{ACCEPT, HeaderName, HeaderValue},
+    middleware, web,
+};
+use futures::{StreamExt, TryStreamExt};
+use qdrant_client::qdrant::{
+    Condition, CountPointsBuilder, Direction, FacetCountsBuilder, Filter, OrderByBuilder,
+    PayloadIncludeSelector, Query, QueryPointsBuilder, Range, Sample, ScrollPointsBuilder,
 };
 use serde::{Deserialize, Serialize};
-use shared_crawler_api::{QDRANT_COLLECTION_NAME, WebPageChunk, WebPageResult, util_fns::load_env};
+use shared_crawler_api::{
+    WebPageChunk,
+    caches::FlushRequest,
+    csv,
+    health::{ComponentHealth, ReadinessReport},
+    listen::Listen,
+    problem::{PROBLEM_CONTENT_TYPE, Problem},
+    request_id, tenant,
+    util_fns::load_env,
+};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::BTreeMap,
     env,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
-mod ranking;
+mod aggregate;
+mod analytics;
+mod cache;
+mod http_cache;
+mod recent;
+mod sitemap;
 
-#[derive(Debug, Deserialize)]
-struct SearchQuery {
-    query: String,
-    #[serde(default = "default_limit")]
-    limit: usize,
-    #[serde(default)]
-    offset: usize,
-}
+use api::{
+    add_collection, as_of_out_of_range, export,
+    export::ResultFormat,
+    log_tag, payload_json,
+    search_core::{self, PlagiatRequest, SearchCore, SearchQuery, SearchResult},
+    version_filter, versions,
+};
 
-const MAX_SEARCH_LIMIT: usize = 50;
-const MAX_SEARCH_OFFSET: usize = 200;
-const SEARCH_PREFETCH_MULTIPLIER: usize = 4;
+const READINESS_TIMEOUT: Duration = Duration::from_secs(3);
+/// Default of `QUERY_TIMEOUT_MS`
+const DEFAULT_QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+const QUERY_ID_HEADER: &str = "x-query-id";
+const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_ANALYTICS_DAYS: i64 = 365;
+const MAX_ANALYTICS_LIMIT: usize = 100;
+const MAX_SAMPLE_SIZE: usize = 100;
 
-fn default_limit() -> usize {
-    10
+struct AppState {
+    core: SearchCore,
+    /// Longest a request may wait for Qdrant and TEI
+    query_timeout: Duration,
+    search_cache: cache::SearchCache<SearchResult>,
+    /// Shared with the blocking threads its SQLite writes run on
+    analytics: Option<Arc<analytics::QueryLog>>,
 }
 
-#[derive(Debug, Deserialize)]
-struct PlagiatRequest {
-    text: String,
-    #[serde(default = "default_threshold")]
-    threshold: f32,
+async fn search(
+    req: HttpRequest,
+    query: web::Query<SearchQuery>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let started = Instant::now();
+    let mut query = query.into_inner();
+    let accept = req
+        .headers()
+        .get(ACCEPT)
+        .and_then(|value| value.to_str().ok());
+    if query.format.is_none() && csv::accepts(accept) {
+        query.format = Some(ResultFormat::Csv);
+    }
+    let prepared = match search_core::prepare_search_query(&query) {
+        Ok(prepared) => prepared,
+        Err(error) => return problem_response(error),
+    };
+    let collection = match tenant_collection(&req) {
+        Ok(collection) => collection,
+        Err(problem) => return problem_response(problem),
+    };
+    if prepared.candidate_limit == 0 {
+        match query.format() {
+            ResultFormat::Ndjson => return export::ndjson_response(Vec::new()),
+            ResultFormat::Csv => return export::csv_response(&[]),
+            ResultFormat::Json => {}
+        }
+        return HttpResponse::Ok().json(SearchResult {
+            total: 0,
+            results: Vec::new(),
+            knowledge_panel: None,
+            fallback: false,
+            expanded_queries: Vec::new(),
+            explain: None,
+        });
+    }
+
+    let explain = data.core.explain(&query, &prepared);
+    let cache_key = cache::search_key(&collection, &prepared, &query, &explain);
+    if let Some(cached) = data.search_cache.get(&cache_key) {
+        let response = search_response(&req, &cached, &query);
+        let total = if cached.fallback { 0 } else { cached.total };
+        return log_search(
+            response,
+            &data,
+            &collection,
+            &prepared.query,
+            started,
+            total,
+        )
+        .await;
+    }
+
+    match within_deadline(
+        data.query_timeout,
+        "search_failed",
+        data.core.search(&collection, &prepared, &query, explain),
+    )
+    .await
+    {
+        Ok((response, urls)) => {
+            data.search_cache
+                .insert(cache_key, response.clone(), urls.iter().map(String::as_str));
+            // Fallback answers count as zero results, as the index had none
+            log_search(
+                search_response(&req, &response, &query),
+                &data,
+                &collection,
+                &prepared.query,
+                started,
+                if response.fallback { 0 } else { response.total },
+            )
+            .await
+        }
+        Err(problem) => problem_response(problem),
+    }
 }
 
-fn default_threshold() -> f32 {
-    0.6
+/// `result` as sent, with its explanation only when asked for
+fn search_response(req: &HttpRequest, result: &SearchResult, query: &SearchQuery) -> HttpResponse {
+    match query.format() {
+        ResultFormat::Ndjson => return export::ndjson_response(result.results.clone()),
+        ResultFormat::Csv => return export::csv_response(&result.results),
+        ResultFormat::Json => {}
+    }
+    if query.explain {
+        return http_cache::cached_json(req, result, http_cache::SEARCH_MAX_AGE);
+    }
+    let result = SearchResult {
+        explain: None,
+        ..result.clone()
+    };
+    http_cache::cached_json(req, &result, http_cache::SEARCH_MAX_AGE)
 }
 
-#[derive(Debug, Serialize)]
-struct SearchResult {
-    results: Vec<WebPageResult>,
+/// Log a search to the analytics database and tag the response with its
+/// `X-Query-Id`; logging failures only cost the header. The insert runs on
+/// actix's blocking thread pool, so waiting for the database lock never
+/// stalls the worker serving other requests.
+async fn log_search(
+    mut response: HttpResponse,
+    data: &AppState,
+    collection: &str,
+    query: &str,
+    started: Instant,
     total: usize,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    knowledge_panel: Option<KnowledgePanel>,
+) -> HttpResponse {
+    let Some(log) = data.analytics.clone() else {
+        return response;
+    };
+    let latency = started.elapsed();
+    let (collection, query) = (collection.to_string(), query.to_string());
+    match web::block(move || log.log_query(&collection, &query, latency, total)).await {
+        Ok(Ok(id)) => {
+            response.headers_mut().insert(
+                HeaderName::from_static(QUERY_ID_HEADER),
+                HeaderValue::from(id),
+            );
+        }
+        Ok(Err(error)) => eprintln!("{}failed to log search: {error}", log_tag()),
+        Err(error) => eprintln!("{}failed to log search: {error}", log_tag()),
+    }
+    response
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
-struct KnowledgePanel {
-    title: String,
-    description: String,
-    source_url: String,
-    tags: Vec<String>,
-    categories: Vec<String>,
+async fn plagiat(
+    http_req: HttpRequest,
+    req: web::Json<PlagiatRequest>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(problem) = search_core::validate_plagiat_request(&req) {
+        return problem_response(problem);
+    }
+    let collection = match tenant_collection(&http_req) {
+        Ok(collection) => collection,
+        Err(problem) => return problem_response(problem),
+    };
+    match within_deadline(
+        data.query_timeout,
+        "plagiat_failed",
+        data.core.check_plagiat(&collection, &req),
+    )
+    .await
+    {
+        Ok(result) => HttpResponse::Ok().json(result),
+        Err(problem) => problem_response(problem),
+    }
 }
 
-#[derive(Debug, Serialize)]
-struct PlagiatResult {
-    is_plagiat: bool,
-    similarity_score: f32,
-    matched_documents: Vec<WebPageResult>,
+#[derive(Debug, Deserialize)]
+struct InvalidateRequest {
+    domains: Vec<String>,
 }
 
-#[derive(Debug, Serialize)]
-struct ErrorResponse {
-    error: String,
+/// Caches `/admin/caches` reports and flushes
+const CACHES: [&str; 1] = ["search"];
+
+/// Entries and hit rate of the search cache
+async fn cache_stats(data: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "search": data.search_cache.stats() }))
 }
 
-struct AppState {
-    qdrant: Qdrant,
-    http: reqwest::Client,
-    tei_url: String,
-    popularity: ranking::DomainPopularity,
+async fn flush_caches(
+    body: Option<web::Json<FlushRequest>>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let request = body.map(web::Json::into_inner).unwrap_or_default();
+    match request.select(&CACHES) {
+        Ok(caches) => {
+            let flushed = caches
+                .into_iter()
+                .map(|cache| (cache, data.search_cache.clear()))
+                .collect::<BTreeMap<_, _>>();
+            HttpResponse::Ok().json(serde_json::json!({ "flushed": flushed }))
+        }
+        Err(problem) => problem_response(problem),
+    }
 }
 
-struct PreparedSearch {
-    query: String,
-    limit: usize,
-    offset: usize,
-    candidate_limit: usize,
+/// Called by the spider after it indexes pages for `domains`
+async fn invalidate_cache(
+    req: web::Json<InvalidateRequest>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let removed = data.search_cache.invalidate_domains(&req.domains);
+    HttpResponse::Ok().json(serde_json::json!({ "removed": removed }))
 }
 
-async fn search(query: web::Query<SearchQuery>, data: web::Data<AppState>) -> impl Responder {
-    let prepared = match prepare_search_query(&query) {
-        Ok(prepared) => prepared,
-        Err(error) => {
-            return HttpResponse::BadRequest().json(ErrorResponse {
-                error: error.to_string(),
-            });
-        }
+#[derive(Debug, Deserialize)]
+struct ClickRequest {
+    /// `X-Query-Id` of the search the result came from
+    query_id: i64,
+    url: String,
+    /// Zero-based rank of the result in the response
+    #[serde(default)]
+    position: Option<usize>,
+}
+
+async fn log_click(
+    http_req: HttpRequest,
+    req: web::Json<ClickRequest>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let Some(log) = data.analytics.clone() else {
+        return problem_response(analytics_disabled());
     };
-    if prepared.candidate_limit == 0 {
-        return HttpResponse::Ok().json(SearchResult {
-            total: 0,
-            results: Vec::new(),
-            knowledge_panel: None,
-        });
+    let collection = match tenant_collection(&http_req) {
+        Ok(collection) => collection,
+        Err(problem) => return problem_response(problem),
+    };
+    if !is_http_url(&req.url) {
+        return problem_response(Problem::bad_request(
+            "invalid_url",
+            "url must be an absolute HTTP(S) URL",
+        ));
     }
-
-    match hybrid_search(&data, &prepared.query, prepared.candidate_limit).await {
-        Ok(mut results) => {
-            results.retain(|result| ranking::is_searchable_page(&result.data.source_url));
-            ranking::apply_ranking_boosts(&mut results, &prepared.query, &data.popularity);
-            let (final_results, total, knowledge_panel) =
-                search_page(results, prepared.limit, prepared.offset);
-            HttpResponse::Ok().json(SearchResult {
-                total,
-                results: final_results,
-                knowledge_panel,
-            })
+    let ClickRequest {
+        query_id,
+        url,
+        position,
+    } = req.into_inner();
+    match web::block(move || log.log_click(&collection, query_id, &url, position)).await {
+        Ok(Ok(true)) => HttpResponse::NoContent().finish(),
+        Ok(Ok(false)) => problem_response(Problem::not_found(
+            "query_not_found",
+            format!("no search was logged with id {query_id}"),
+        )),
+        Ok(Err(error)) => {
+            problem_response(Problem::internal("click_not_logged", error.to_string()))
         }
-        Err(error) => HttpResponse::InternalServerError().json(ErrorResponse {
-            error: error.to_string(),
-        }),
+        Err(error) => problem_response(Problem::internal("click_not_logged", error.to_string())),
     }
 }
 
-fn prepare_search_query(query: &SearchQuery) -> Result<PreparedSearch, &'static str> {
-    let text = query.query.split_whitespace().collect::<Vec<_>>().join(" ");
-    if text.is_empty() {
-        return Err("query must not be empty");
-    }
-    let limit = query.limit.min(MAX_SEARCH_LIMIT);
-    let offset = query.offset.min(MAX_SEARCH_OFFSET);
-    Ok(PreparedSearch {
-        query: text,
-        limit,
-        offset,
-        candidate_limit: limit
-            .saturating_add(offset)
-            .saturating_mul(SEARCH_PREFETCH_MULTIPLIER),
-    })
+#[derive(Debug, Deserialize)]
+struct AnalyticsQuery {
+    #[serde(default = "default_analytics_days")]
+    days: i64,
+    #[serde(default = "default_analytics_limit")]
+    limit: usize,
 }
 
-fn search_page(
-    results: Vec<WebPageResult>,
-    limit: usize,
-    offset: usize,
-) -> (Vec<WebPageResult>, usize, Option<KnowledgePanel>) {
-    let candidate_count = results.len();
-    let results = unique_pages(results, candidate_count);
-    let total = results.len();
-    let knowledge_panel = (offset == 0)
-        .then(|| results.first())
-        .flatten()
-        .and_then(knowledge_panel);
-    (page(results, limit, offset), total, knowledge_panel)
-}
-
-fn page(results: Vec<WebPageResult>, limit: usize, offset: usize) -> Vec<WebPageResult> {
-    results.into_iter().skip(offset).take(limit).collect()
-}
-
-fn knowledge_panel(result: &WebPageResult) -> Option<KnowledgePanel> {
-    let title = result.data.page_title.trim();
-    let description = result.data.description.trim();
-    if title.is_empty() || title == "No Title" || description.is_empty() {
-        return None;
-    }
-
-    Some(KnowledgePanel {
-        title: title.to_string(),
-        description: description.to_string(),
-        source_url: result.data.source_url.clone(),
-        tags: result.data.tags.clone(),
-        categories: result.data.categories.clone(),
-    })
+fn default_analytics_days() -> i64 {
+    7
 }
 
-fn unique_pages(results: Vec<WebPageResult>, limit: usize) -> Vec<WebPageResult> {
-    let mut seen_urls = HashSet::new();
-    let unique = results
-        .into_iter()
-        .filter(|result| seen_urls.insert(result.data.source_url.clone()))
-        .collect::<Vec<_>>();
-    let mut seen_hosts = HashSet::new();
-    let diverse = unique
-        .iter()
-        .enumerate()
-        .filter(|(_, result)| {
-            let url = &result.data.source_url;
-            let source = url::Url::parse(url)
-                .ok()
-                .and_then(|url| url.host_str().map(str::to_owned))
-                .unwrap_or_else(|| url.clone());
-            seen_hosts.insert(source)
-        })
-        .map(|(index, _)| index)
-        .take(limit.min(5))
-        .collect::<Vec<_>>();
-    let mut remaining = unique.into_iter().map(Some).collect::<Vec<_>>();
-    let mut selected = diverse
-        .into_iter()
-        .map(|index| remaining[index].take().unwrap())
-        .collect::<Vec<_>>();
-    let slots = limit.saturating_sub(selected.len());
-    selected.extend(remaining.into_iter().flatten().take(slots));
-    selected
-}
-
-async fn hybrid_search(
-    data: &AppState,
-    text: &str,
-    limit: usize,
-) -> anyhow::Result<Vec<WebPageResult>> {
-    let dense = embed(data, &format!("query: {text}")).await?;
-    let lexical = bm25_document(text);
-    let result = data
-        .qdrant
-        .query(
-            QueryPointsBuilder::new(QDRANT_COLLECTION_NAME)
-                .add_prefetch(
-                    PrefetchQueryBuilder::default()
-                        .query(dense)
-                        .using("dense")
-                        .limit(limit as u64),
-                )
-                .add_prefetch(
-                    PrefetchQueryBuilder::default()
-                        .query(Query::new_nearest(lexical.clone()))
-                        .using("title_bm25")
-                        .limit(limit as u64),
-                )
-                .add_prefetch(
-                    PrefetchQueryBuilder::default()
-                        .query(Query::new_nearest(lexical))
-                        .using("body_bm25")
-                        .limit(limit as u64),
-                )
-                .query(Query::new_rrf(
-                    RrfBuilder::new().weights(vec![2.0, 2.0, 1.0]),
-                ))
-                .limit(limit as u64)
-                .with_payload(true),
-        )
-        .await?;
-    Ok(result
-        .result
-        .into_iter()
-        .filter_map(|point| {
-            WebPageChunk::from_payload_json(&payload_json(point.payload))
-                .map(|data| WebPageResult::new(data, point.score))
-        })
-        .collect())
-}
-
-async fn plagiat(req: web::Json<PlagiatRequest>, data: web::Data<AppState>) -> impl Responder {
-    let result = async {
-        let dense = embed(&data, &format!("query: {}", req.text)).await?;
-        let response = data
-            .qdrant
-            .query(
-                QueryPointsBuilder::new(QDRANT_COLLECTION_NAME)
-                    .query(dense)
-                    .using("dense")
-                    .limit(5)
-                    .with_payload(true),
-            )
-            .await?;
-        Ok::<_, anyhow::Error>(
-            response
-                .result
-                .into_iter()
-                .filter_map(|point| {
-                    WebPageChunk::from_payload_json(&payload_json(point.payload))
-                        .map(|data| WebPageResult::new(data, point.score))
-                })
-                .collect::<Vec<_>>(),
-        )
-    }
-    .await;
+fn default_analytics_limit() -> usize {
+    20
+}
 
-    match result {
-        Ok(results) => {
-            let highest = results.first().map(|result| result.score).unwrap_or(0.0);
-            let matched_documents = results
-                .into_iter()
-                .filter(|result| result.score >= req.threshold)
-                .collect();
-            HttpResponse::Ok().json(PlagiatResult {
-                is_plagiat: highest >= req.threshold,
-                similarity_score: highest,
-                matched_documents,
-            })
+/// Most frequent and zero-result queries of the last `days` days
+async fn query_analytics(
+    http_req: HttpRequest,
+    query: web::Query<AnalyticsQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let Some(log) = data.analytics.clone() else {
+        return problem_response(analytics_disabled());
+    };
+    let collection = match tenant_collection(&http_req) {
+        Ok(collection) => collection,
+        Err(problem) => return problem_response(problem),
+    };
+    if !(1..=MAX_ANALYTICS_DAYS).contains(&query.days) {
+        return problem_response(Problem::bad_request(
+            "days_out_of_range",
+            format!("days must be between 1 and {MAX_ANALYTICS_DAYS}"),
+        ));
+    }
+    if !(1..=MAX_ANALYTICS_LIMIT).contains(&query.limit) {
+        return problem_response(Problem::bad_request(
+            "limit_out_of_range",
+            format!("limit must be between 1 and {MAX_ANALYTICS_LIMIT}"),
+        ));
+    }
+    let since = analytics::now() - query.days * 24 * 60 * 60;
+    let limit = query.limit;
+    match web::block(move || log.summary(&collection, since, limit)).await {
+        Ok(Ok(summary)) => HttpResponse::Ok().json(summary),
+        Ok(Err(error)) => {
+            problem_response(Problem::internal("analytics_failed", error.to_string()))
         }
-        Err(error) => HttpResponse::InternalServerError().json(ErrorResponse {
-            error: error.to_string(),
-        }),
+        Err(error) => problem_response(Problem::internal("analytics_failed", error.to_string())),
     }
 }
 
+fn analytics_disabled() -> Problem {
+    Problem::not_found("analytics_disabled", "search analytics are turned off")
+}
+
 async fn health_check() -> impl Responder {
     HttpResponse::Ok().json(serde_json::json!({"status": "ok", "message": "API is running"}))
 }
 
-async fn count(data: web::Data<AppState>) -> impl Responder {
-    match data
-        .qdrant
-        .count(CountPointsBuilder::new(QDRANT_COLLECTION_NAME).exact(true))
-        .await
+/// Readiness probe: 503 unless Qdrant and TEI both answer
+async fn readiness(data: web::Data<AppState>) -> impl Responder {
+    let (qdrant, tei) = tokio::join!(
+        probe(async { data.core.qdrant.health_check().await.map(|_| ()) }),
+        probe(async {
+            data.core
+                .http
+                .get(format!(
+                    "{}/health",
+                    data.core.config.tei_url.trim_end_matches('/')
+                ))
+                .send()
+                .await?
+                .error_for_status()
+                .map(|_| ())
+        }),
+    );
+    let report = ReadinessReport::new([("qdrant", qdrant), ("tei", tei)]);
+    if report.is_ready() {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::ServiceUnavailable().json(report)
+    }
+}
+
+async fn probe<E: std::fmt::Display>(
+    check: impl Future<Output = Result<(), E>>,
+) -> ComponentHealth {
+    let started = Instant::now();
+    let result = match tokio::time::timeout(READINESS_TIMEOUT, check).await {
+        Ok(result) => result.map_err(|error| error.to_string()),
+        Err(_) => Err("timed out".to_string()),
+    };
+    ComponentHealth::from_result(result, started.elapsed())
+}
+
+#[derive(Debug, Deserialize)]
+struct CountQuery {
+    #[serde(default)]
+    collection: Option<String>,
+}
+
+async fn count(
+    req: HttpRequest,
+    query: web::Query<CountQuery>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let collection = match tenant_collection(&req) {
+        Ok(collection) => collection,
+        Err(problem) => return problem_response(problem),
+    };
+    if let Some(label) = &query.collection
+        && let Err(problem) = tenant::validate_collection(label)
     {
+        return problem_response(problem);
+    }
+    let mut filter = Filter::must([versions::current()]);
+    add_collection(&mut filter, query.collection.as_deref());
+    let counted = data.core.qdrant.count(
+        CountPointsBuilder::new(collection)
+            .filter(filter)
+            .exact(true),
+    );
+    match within_deadline(data.query_timeout, "count_failed", counted).await {
         Ok(response) => HttpResponse::Ok().json(serde_json::json!({
             "count": response.result.map(|value| value.count).unwrap_or(0)
         })),
-        Err(error) => HttpResponse::InternalServerError().json(ErrorResponse {
-            error: error.to_string(),
+        Err(problem) => problem_response(problem),
+    }
+}
+
+fn default_sample_size() -> usize {
+    10
+}
+
+#[derive(Debug, Deserialize)]
+struct SampleQuery {
+    #[serde(default = "default_sample_size")]
+    n: usize,
+    /// Only chunks of this host
+    #[serde(default)]
+    domain: Option<String>,
+    #[serde(default)]
+    collection: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SampledChunk {
+    /// Position of the chunk in its page
+    chunk_index: Option<i64>,
+    #[serde(flatten)]
+    chunk: WebPageChunk,
+}
+
+/// Random current chunks, for auditing extraction and chunking by hand
+async fn sample(
+    req: HttpRequest,
+    query: web::Query<SampleQuery>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    if !(1..=MAX_SAMPLE_SIZE).contains(&query.n) {
+        return problem_response(Problem::bad_request(
+            "sample_size_out_of_range",
+            format!("n must be between 1 and {MAX_SAMPLE_SIZE}"),
+        ));
+    }
+    let collection = match tenant_collection(&req) {
+        Ok(collection) => collection,
+        Err(problem) => return problem_response(problem),
+    };
+    if let Some(label) = &query.collection
+        && let Err(problem) = tenant::validate_collection(label)
+    {
+        return problem_response(problem);
+    }
+    let mut filter = Filter::must([versions::current()]);
+    if let Some(domain) = &query.domain {
+        match sitemap::parse_domain(domain) {
+            Ok(domain) => filter.must.push(Condition::matches("host", domain)),
+            Err(problem) => return problem_response(problem),
+        }
+    }
+    add_collection(&mut filter, query.collection.as_deref());
+    let sampled = data.core.qdrant.query(
+        QueryPointsBuilder::new(collection)
+            .query(Query::new_sample(Sample::Random))
+            .filter(filter)
+            .limit(query.n as u64)
+            .with_payload(true),
+    );
+    match within_deadline(data.query_timeout, "sample_failed", sampled).await {
+        Ok(response) => {
+            let chunks = response
+                .result
+                .into_iter()
+                .filter_map(|point| {
+                    let payload = payload_json(point.payload);
+                    Some(SampledChunk {
+                        chunk_index: payload.get("chunk_index").and_then(|value| value.as_i64()),
+                        chunk: WebPageChunk::from_payload_json(&payload)?,
+                    })
+                })
+                .collect::<Vec<_>>();
+            // Not cacheable, as every request draws a new sample
+            HttpResponse::Ok().json(serde_json::json!({ "chunks": chunks }))
+        }
+        Err(problem) => problem_response(problem),
+    }
+}
+
+async fn aggregate(
+    req: HttpRequest,
+    query: web::Query<aggregate::AggregateQuery>,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let collection = match tenant_collection(&req) {
+        Ok(collection) => collection,
+        Err(problem) => return problem_response(problem),
+    };
+    if let Some(label) = &query.collection
+        && let Err(problem) = tenant::validate_collection(label)
+    {
+        return problem_response(problem);
+    }
+    let buckets = match query.bucket_count() {
+        Ok(buckets) => buckets,
+        Err(problem) => return problem_response(problem),
+    };
+    let mut filter = Filter::must([versions::current()]);
+    add_collection(&mut filter, query.collection.as_deref());
+    let qdrant = &data.core.qdrant;
+    let counted = async {
+        if let Some(key) = query.by.facet_key() {
+            let response = qdrant
+                .facet(
+                    FacetCountsBuilder::new(&collection, key)
+                        .filter(filter)
+                        .limit(buckets as u64)
+                        .exact(true),
+                )
+                .await?;
+            return Ok::<_, anyhow::Error>(aggregate::facet_buckets(response.hits));
+        }
+        let ranges = aggregate::date_buckets(analytics::now(), query.interval, buckets);
+        futures::stream::iter(ranges)
+            .map(|(key, start, end)| {
+                let mut filter = filter.clone();
+                filter.must.push(Condition::range(
+                    "crawled_at",
+                    Range {
+                        gte: Some(start as f64),
+                        lt: Some(end as f64),
+                        ..Default::default()
+                    },
+                ));
+                let collection = &collection;
+                async move {
+                    let response = qdrant
+                        .count(
+                            CountPointsBuilder::new(collection)
+                                .filter(filter)
+                                .exact(true),
+                        )
+                        .await?;
+                    Ok(aggregate::Bucket {
+                        key,
+                        start: Some(start),
+                        count: response.result.map(|value| value.count).unwrap_or(0),
+                    })
+                }
+            })
+            .buffered(aggregate::MAX_CONCURRENT_COUNTS)
+            .try_collect()
+            .await
+    };
+    match within_deadline(data.query_timeout, "aggregate_failed", counted).await {
+        Ok(buckets) => HttpResponse::Ok().json(aggregate::Aggregation {
+            by: query.by,
+            buckets,
         }),
+        Err(problem) => problem_response(problem),
     }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct GetPageRequest {
     pub url: String,
+    /// An earlier version kept by the spider instead of the current one
+    #[serde(default)]
+    pub version: Option<i64>,
+    /// The version that was current at this Unix time
+    #[serde(default)]
+    pub as_of: Option<i64>,
 }
 
-async fn get_page(query: web::Query<GetPageRequest>, data: web::Data<AppState>) -> HttpResponse {
-    match data
-        .qdrant
-        .scroll(
-            ScrollPointsBuilder::new(QDRANT_COLLECTION_NAME)
-                .filter(Filter::must([Condition::matches(
-                    "source_url",
-                    query.url.clone(),
-                )]))
-                .limit(10_000)
-                .with_payload(true)
-                .with_vectors(false),
-        )
-        .await
-    {
+async fn get_page(
+    req: HttpRequest,
+    query: web::Query<GetPageRequest>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    if !is_http_url(&query.url) {
+        return problem_response(Problem::bad_request(
+            "invalid_url",
+            "url must be an absolute HTTP(S) URL",
+        ));
+    }
+    if query.version.is_some_and(|version| version < 1) {
+        return problem_response(Problem::bad_request(
+            "version_out_of_range",
+            "version must be at least 1",
+        ));
+    }
+    if query.as_of.is_some_and(|as_of| as_of < 0) {
+        return problem_response(as_of_out_of_range());
+    }
+    let mut filter = match (query.version, query.as_of) {
+        (Some(_), Some(_)) => {
+            return problem_response(Problem::bad_request(
+                "conflicting_parameters",
+                "version and as_of cannot be combined",
+            ));
+        }
+        (Some(version), None) => Filter::must([versions::numbered(version)]),
+        (None, as_of) => version_filter(as_of),
+    };
+    filter
+        .must
+        .push(Condition::matches("source_url", query.url.clone()));
+    let collection = match tenant_collection(&req) {
+        Ok(collection) => collection,
+        Err(problem) => return problem_response(problem),
+    };
+    let scrolled = data.core.qdrant.scroll(
+        ScrollPointsBuilder::new(collection)
+            .filter(filter)
+            .limit(10_000)
+            .with_payload(true)
+            .with_vectors(false),
+    );
+    match within_deadline(data.query_timeout, "page_lookup_failed", scrolled).await {
         Ok(response) => {
             let mut chunks = response
                 .result
@@ -349,78 +652,369 @@ async fn get_page(query: web::Query<GetPageRequest>, data: web::Data<AppState>)
                 })
                 .collect::<Vec<_>>();
             chunks.sort_by_key(|(index, _)| *index);
-            HttpResponse::Ok().json(
-                chunks
+            http_cache::cached_json(
+                &req,
+                &chunks
                     .into_iter()
                     .map(|(_, chunk)| chunk)
                     .collect::<Vec<_>>(),
+                http_cache::PAGE_MAX_AGE,
             )
         }
-        Err(error) => HttpResponse::InternalServerError().json(ErrorResponse {
-            error: error.to_string(),
-        }),
+        Err(problem) => problem_response(problem),
     }
 }
 
-async fn embed(data: &AppState, input: &str) -> anyhow::Result<Vec<f32>> {
-    let mut response = data
-        .http
-        .post(format!("{}/embed", data.tei_url.trim_end_matches('/')))
-        .json(&serde_json::json!({"inputs": [input]}))
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<Vec<Vec<f32>>>()
-        .await?;
-    anyhow::ensure!(
-        response.len() == 1 && response[0].len() == 384,
-        "TEI returned invalid embedding dimensions"
+#[derive(Debug, Deserialize)]
+struct PageVersionsRequest {
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct PageVersionsResponse {
+    url: String,
+    versions: Vec<versions::PageVersion>,
+}
+
+/// Versions of a page kept by the spider, newest first
+async fn page_versions(
+    req: HttpRequest,
+    query: web::Query<PageVersionsRequest>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    if !is_http_url(&query.url) {
+        return problem_response(Problem::bad_request(
+            "invalid_url",
+            "url must be an absolute HTTP(S) URL",
+        ));
+    }
+    let collection = match tenant_collection(&req) {
+        Ok(collection) => collection,
+        Err(problem) => return problem_response(problem),
+    };
+    let scrolled = data.core.qdrant.scroll(
+        ScrollPointsBuilder::new(collection)
+            .filter(Filter::must([Condition::matches(
+                "source_url",
+                query.url.clone(),
+            )]))
+            .limit(10_000)
+            .with_payload(PayloadIncludeSelector::new(
+                [
+                    "page_version",
+                    "version_number",
+                    "version_since",
+                    "crawled_at",
+                    "superseded_at",
+                ]
+                .map(String::from)
+                .to_vec(),
+            ))
+            .with_vectors(false),
     );
-    Ok(response.remove(0))
+    match within_deadline(data.query_timeout, "page_lookup_failed", scrolled).await {
+        Ok(response) => http_cache::cached_json(
+            &req,
+            &PageVersionsResponse {
+                url: query.url.clone(),
+                versions: versions::history(response.result.into_iter().filter_map(|point| {
+                    versions::PageVersion::from_payload(&payload_json(point.payload))
+                })),
+            },
+            http_cache::PAGE_MAX_AGE,
+        ),
+        Err(problem) => problem_response(problem),
+    }
 }
 
-fn bm25_document(text: &str) -> qdrant_client::qdrant::Document {
-    DocumentBuilder::new(text, "qdrant/bm25")
-        .options(HashMap::from([("language".to_string(), "none".into())]))
-        .build()
+#[derive(Debug, Deserialize)]
+struct SitemapRequest {
+    domain: String,
+    #[serde(default)]
+    collection: Option<String>,
+}
+
+/// sitemap.xml of the host's currently indexed pages
+async fn export_sitemap(
+    req: HttpRequest,
+    query: web::Query<SitemapRequest>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let domain = match sitemap::parse_domain(&query.domain) {
+        Ok(domain) => domain,
+        Err(problem) => return problem_response(problem),
+    };
+    let collection = match tenant_collection(&req) {
+        Ok(collection) => collection,
+        Err(problem) => return problem_response(problem),
+    };
+    if let Some(label) = &query.collection
+        && let Err(problem) = tenant::validate_collection(label)
+    {
+        return problem_response(problem);
+    }
+    // The first chunk of each page stands for the page
+    let mut filter = Filter::must([
+        Condition::matches("host", domain),
+        Condition::matches("chunk_index", 0i64),
+        versions::current(),
+    ]);
+    add_collection(&mut filter, query.collection.as_deref());
+    let scrolled = within_deadline(data.query_timeout, "export_failed", async {
+        let mut pages = BTreeMap::new();
+        let mut offset = None;
+        loop {
+            let mut scroll = ScrollPointsBuilder::new(&collection)
+                .filter(filter.clone())
+                .limit(1_000)
+                .with_payload(PayloadIncludeSelector::new(vec![
+                    "source_url".to_string(),
+                    "crawled_at".to_string(),
+                ]))
+                .with_vectors(false);
+            if let Some(offset) = offset {
+                scroll = scroll.offset(offset);
+            }
+            let response = data.core.qdrant.scroll(scroll).await?;
+            for point in response.result {
+                let payload = payload_json(point.payload);
+                if let Some(url) = payload.get("source_url").and_then(|value| value.as_str()) {
+                    let crawled_at = payload
+                        .get("crawled_at")
+                        .and_then(|value| value.as_i64())
+                        .unwrap_or(0);
+                    pages.insert(url.to_string(), crawled_at);
+                }
+            }
+            offset = response.next_page_offset;
+            if offset.is_none() {
+                return Ok::<_, qdrant_client::QdrantError>(pages);
+            }
+        }
+    })
+    .await;
+    let pages = match scrolled {
+        Ok(pages) => pages,
+        Err(problem) => return problem_response(problem),
+    };
+    http_cache::cached_body(
+        &req,
+        sitemap::render(&pages).into_bytes(),
+        "application/xml; charset=utf-8",
+        http_cache::PAGE_MAX_AGE,
+    )
 }
 
-fn payload_json(payload: HashMap<String, qdrant_client::qdrant::Value>) -> serde_json::Value {
-    serde_json::Value::Object(
-        payload
+async fn recent_pages(
+    req: HttpRequest,
+    query: web::Query<recent::RecentQuery>,
+    data: web::Data<AppState>,
+) -> HttpResponse {
+    let limit = match query.limit() {
+        Ok(limit) => limit,
+        Err(problem) => return problem_response(problem),
+    };
+    let collection = match tenant_collection(&req) {
+        Ok(collection) => collection,
+        Err(problem) => return problem_response(problem),
+    };
+    if let Some(label) = &query.collection
+        && let Err(problem) = tenant::validate_collection(label)
+    {
+        return problem_response(problem);
+    }
+    // The first chunk of the current version stands for the page, so each
+    // URL is listed once
+    let mut filter = Filter::must([Condition::matches("chunk_index", 0i64), versions::current()]);
+    if let Some(domain) = &query.domain {
+        match sitemap::parse_domain(domain) {
+            Ok(domain) => filter.must.push(Condition::matches("host", domain)),
+            Err(problem) => return problem_response(problem),
+        }
+    }
+    add_collection(&mut filter, query.collection.as_deref());
+    let scrolled = data.core.qdrant.scroll(
+        ScrollPointsBuilder::new(&collection)
+            .filter(filter)
+            .order_by(OrderByBuilder::new("crawled_at").direction(Direction::Desc as i32))
+            .limit(limit as u32)
+            .with_payload(PayloadIncludeSelector::new(
+                [
+                    "source_url",
+                    "page_title",
+                    "description",
+                    "crawled_at",
+                    "published_at",
+                ]
+                .map(str::to_string)
+                .to_vec(),
+            ))
+            .with_vectors(false),
+    );
+    let pages = match within_deadline(data.query_timeout, "recent_failed", scrolled).await {
+        Ok(response) => response
+            .result
             .into_iter()
-            .map(|(key, value)| (key, value.into_json()))
-            .collect(),
+            .filter_map(|point| WebPageChunk::from_payload_json(&payload_json(point.payload)))
+            .map(recent::RecentPage::from)
+            .collect::<Vec<_>>(),
+        Err(problem) => return problem_response(problem),
+    };
+    let feed_url = req.full_url().to_string();
+    let (body, content_type) = match query.format {
+        recent::FeedFormat::Json => {
+            return http_cache::cached_json(
+                &req,
+                &serde_json::json!({ "pages": pages }),
+                http_cache::SEARCH_MAX_AGE,
+            );
+        }
+        recent::FeedFormat::Rss => (
+            recent::render_rss(&pages, &feed_url),
+            "application/rss+xml; charset=utf-8",
+        ),
+        recent::FeedFormat::Atom => (
+            recent::render_atom(&pages, &feed_url),
+            "application/atom+xml; charset=utf-8",
+        ),
+    };
+    http_cache::cached_body(
+        &req,
+        body.into_bytes(),
+        content_type,
+        http_cache::SEARCH_MAX_AGE,
+    )
+}
+
+/// Await a request's Qdrant and TEI work for at most `QUERY_TIMEOUT_MS`.
+/// On timeout the work is dropped, which cancels its pending calls, and the
+/// client gets a 504 instead of waiting on a slow vector database.
+async fn within_deadline<T, E: std::fmt::Display>(
+    timeout: Duration,
+    code: &'static str,
+    work: impl Future<Output = Result<T, E>>,
+) -> Result<T, Problem> {
+    match tokio::time::timeout(timeout, work).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(error)) => Err(Problem::internal(code, error.to_string())),
+        Err(_) => Err(query_timed_out(timeout)),
+    }
+}
+
+fn query_timed_out(timeout: Duration) -> Problem {
+    Problem::new(
+        504,
+        "query_timeout",
+        format!("the index did not answer within {} ms", timeout.as_millis()),
     )
 }
 
+/// Collection of the tenant named by the `X-Tenant` header, or the shared
+/// one without it
+fn tenant_collection(req: &HttpRequest) -> Result<String, Problem> {
+    let tenant = req
+        .headers()
+        .get(tenant::HEADER)
+        .map(|value| value.to_str().unwrap_or_default());
+    tenant::collection_name(tenant)
+}
+
+fn is_http_url(value: &str) -> bool {
+    url::Url::parse(value)
+        .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.has_host())
+}
+
+/// Tag the request, its log lines and the calls it makes with its
+/// `X-Request-Id`, and return the ID in the response
+async fn tag_request(
+    req: ServiceRequest,
+    next: middleware::Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let id = request_id::from_header(
+        req.headers()
+            .get(request_id::HEADER)
+            .and_then(|value| value.to_str().ok()),
+    );
+    let (method, path) = (req.method().clone(), req.path().to_string());
+    let mut response = request_id::scope(id.clone(), next.call(req)).await?;
+    if response.status().is_server_error() {
+        eprintln!("[{id}] {method} {path} failed with {}", response.status());
+    }
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    Ok(response)
+}
+
+fn problem_response(problem: Problem) -> HttpResponse {
+    let status = actix_web::http::StatusCode::from_u16(problem.status)
+        .unwrap_or(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR);
+    HttpResponse::build(status)
+        .content_type(PROBLEM_CONTENT_TYPE)
+        .json(problem)
+}
+
+fn json_error(error: actix_web::error::JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    let response = problem_response(Problem::bad_request("invalid_body", error.to_string()));
+    actix_web::error::InternalError::from_response(error, response).into()
+}
+
+fn query_error(error: actix_web::error::QueryPayloadError, _: &HttpRequest) -> actix_web::Error {
+    let response = problem_response(Problem::bad_request(
+        "invalid_query_parameters",
+        error.to_string(),
+    ));
+    actix_web::error::InternalError::from_response(error, response).into()
+}
+
+/// Shared pooled client for TEI and other outbound HTTP calls
+fn http_client_from_env() -> reqwest::Client {
+    let read = |key: &str, default: u64| {
+        env::var(key)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    };
+    reqwest::Client::builder()
+        .user_agent(concat!("my-crawler-api/", env!("CARGO_PKG_VERSION")))
+        .connect_timeout(Duration::from_secs(read(
+            "API_HTTP_CONNECT_TIMEOUT_SECS",
+            5,
+        )))
+        .timeout(Duration::from_secs(read("API_HTTP_TIMEOUT_SECS", 30)))
+        .pool_max_idle_per_host(read("API_HTTP_POOL_MAX_IDLE", 32) as usize)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .tcp_keepalive(Duration::from_secs(60))
+        .build()
+        .expect("failed to create HTTP client")
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     load_env();
-    let host = env::var("API_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-    let port = env::var("API_PORT").unwrap_or_else(|_| "8000".to_string());
-    let bind_address = format!("{host}:{port}");
+    let listen = Listen::from_env("API", 8000)?;
     let allowed_origins =
         env::var("ALLOWED_ORIGINS").unwrap_or_else(|_| "http://localhost:3000".to_string());
-    let qdrant_url = env::var("QDRANT_URL").unwrap_or_else(|_| "http://localhost:6334".to_string());
-    let http = reqwest::Client::new();
-    let popularity = match ranking::load_domain_popularity(&http).await {
-        Ok(popularity) => popularity,
-        Err(error) => {
-            eprintln!("failed to load Tranco popularity list: {error}");
-            ranking::DomainPopularity::default()
-        }
-    };
+    let core = SearchCore::from_env(http_client_from_env())
+        .await
+        .expect("failed to create Qdrant client");
     let state = web::Data::new(AppState {
-        qdrant: Qdrant::from_url(&qdrant_url)
-            .build()
-            .expect("failed to create Qdrant client"),
-        http,
-        tei_url: env::var("TEI_URL").unwrap_or_else(|_| "http://localhost:8080".to_string()),
-        popularity,
+        core,
+        query_timeout: env::var("QUERY_TIMEOUT_MS")
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .filter(|&millis| millis > 0)
+            .map_or(DEFAULT_QUERY_TIMEOUT, Duration::from_millis),
+        search_cache: cache::SearchCache::from_env(),
+        analytics: analytics::QueryLog::from_env()
+            .expect("failed to open analytics database")
+            .map(Arc::new),
     });
 
-    HttpServer::new(move || {
+    println!("Starting search API on {listen}");
+    let server = HttpServer::new(move || {
         let cors = if allowed_origins.trim() == "*" {
             Cors::default()
                 .allow_any_origin()
@@ -432,17 +1026,40 @@ async fn main() -> std::io::Result<()> {
                 .fold(Cors::default(), |cors, origin| {
                     cors.allowed_origin(origin.trim())
                 })
-        };
+                .allowed_header(tenant::HEADER)
+                .allowed_header(request_id::HEADER)
+        }
+        .expose_headers([QUERY_ID_HEADER, REQUEST_ID_HEADER]);
         App::new()
+            .wrap(middleware::Compress::default())
             .wrap(cors)
+            .wrap(middleware::from_fn(tag_request))
             .app_data(state.clone())
+            .app_data(web::JsonConfig::default().error_handler(json_error))
+            .app_data(web::QueryConfig::default().error_handler(query_error))
             .route("/health", web::get().to(health_check))
+            .route("/health/ready", web::get().to(readiness))
             .route("/search", web::get().to(search))
             .route("/plagiat", web::post().to(plagiat))
             .route("/count", web::get().to(count))
+            .route("/aggregate", web::get().to(aggregate))
+            .route("/recent", web::get().to(recent_pages))
+            .route("/sample", web::get().to(sample))
             .route("/page", web::get().to(get_page))
-    })
-    .bind(bind_address)?
+            .route("/page/versions", web::get().to(page_versions))
+            .route("/export/sitemap", web::get().to(export_sitemap))
+            .route("/cache/invalidate", web::post().to(invalidate_cache))
+            .route("/admin/caches", web::get().to(cache_stats))
+            .route("/admin/caches/flush", web::post().to(flush_caches))
+            .route("/analytics/clicks", web::post().to(log_click))
+            .route("/analytics/queries", web::get().to(query_analytics))
+    });
+    match listen {
+        Listen::Http(address) => server.bind(address)?,
+        Listen::Https(address, tls) => server.bind_rustls_0_23(address, *tls)?,
+        #[cfg(unix)]
+        Listen::Unix(path) => server.bind_uds(path)?,
+    }
     .run()
     .await
 }
@@ -451,176 +1068,22 @@ async fn main() -> std::io::Result<()> {
 mod tests {
     use super::*;
 
-    fn result(url: &str) -> WebPageResult {
-        WebPageResult::new(
-            WebPageChunk {
-                chunk_content: String::new(),
-                chunk_heading: None,
-                source_url: url.to_string(),
-                page_title: "Same title".to_string(),
-                description: String::new(),
-                tags: vec![],
-                categories: vec![],
-                paid: 0.0,
-                score: 0.0,
-                crawled_at: 0,
-            },
-            0.0,
+    #[tokio::test]
+    async fn answers_slow_queries_with_a_timeout_problem() {
+        let slow = within_deadline(
+            Duration::from_millis(10),
+            "search_failed",
+            std::future::pending::<Result<(), String>>(),
         )
-    }
-
-    fn described_result(url: &str) -> WebPageResult {
-        let mut result = result(url);
-        result.data.description = "Short summary".to_string();
-        result.data.tags = vec!["rust".to_string()];
-        result
-    }
-
-    #[test]
-    fn keeps_same_title_pages_and_removes_duplicate_chunks() {
-        let pages = unique_pages(
-            vec![
-                result("https://example.com/"),
-                result("https://example.com/a"),
-                result("https://example.com/a"),
-            ],
-            10,
-        );
-
-        assert_eq!(pages.len(), 2);
-        assert_eq!(pages[1].data.source_url, "https://example.com/a");
-    }
-
-    #[test]
-    fn diversifies_first_five_then_preserves_rank_order() {
-        let urls = [
-            "https://a.example/1",
-            "https://a.example/2",
-            "https://b.example/1",
-            "https://c.example/1",
-            "https://d.example/1",
-            "https://e.example/1",
-            "https://f.example/1",
-        ];
-        let pages = unique_pages(urls.into_iter().map(result).collect(), 7);
-        let actual = pages
-            .iter()
-            .map(|page| page.data.source_url.as_str())
-            .collect::<Vec<_>>();
-
-        assert_eq!(
-            actual,
-            [
-                urls[0], urls[2], urls[3], urls[4], urls[5], urls[1], urls[6]
-            ]
-        );
-    }
-
-    #[test]
-    fn fills_from_repeated_hosts_and_handles_malformed_urls() {
-        let urls = [
-            "not a url",
-            "also not a url",
-            "https://example.com/1",
-            "https://example.com/2",
-        ];
-        let pages = unique_pages(urls.into_iter().map(result).collect(), 4);
-
-        assert_eq!(
-            pages
-                .iter()
-                .map(|page| page.data.source_url.as_str())
-                .collect::<Vec<_>>(),
-            urls
-        );
-    }
-
-    #[test]
-    fn diversifies_limits_below_five() {
-        let pages = unique_pages(
-            vec![
-                result("https://a.example/1"),
-                result("https://a.example/2"),
-                result("https://b.example/1"),
-            ],
-            2,
-        );
-
-        assert_eq!(pages[1].data.source_url, "https://b.example/1");
-    }
-
-    #[test]
-    fn offsets_after_deduping_and_diversifying() {
-        let urls = [
-            "https://a.example/1",
-            "https://a.example/1",
-            "https://b.example/1",
-            "https://a.example/2",
-        ];
-        let (pages, total, knowledge_panel) =
-            search_page(urls.into_iter().map(result).collect(), 2, 1);
-
-        assert_eq!(total, 3);
-        assert_eq!(knowledge_panel, None);
-        assert_eq!(
-            pages
-                .iter()
-                .map(|page| page.data.source_url.as_str())
-                .collect::<Vec<_>>(),
-            ["https://b.example/1", "https://a.example/2"]
-        );
-    }
-
-    #[test]
-    fn adds_knowledge_panel_from_first_page_result() {
-        let (_pages, _total, knowledge_panel) = search_page(
-            vec![
-                described_result("https://example.com/"),
-                described_result("https://example.com/about"),
-            ],
-            10,
-            0,
-        );
-
-        assert_eq!(
-            knowledge_panel,
-            Some(KnowledgePanel {
-                title: "Same title".to_string(),
-                description: "Short summary".to_string(),
-                source_url: "https://example.com/".to_string(),
-                tags: vec!["rust".to_string()],
-                categories: vec![],
-            })
-        );
-    }
+        .await
+        .unwrap_err();
+        assert_eq!((slow.status, slow.code), (504, "query_timeout"));
 
-    #[test]
-    fn prepares_search_query_for_retrieval() {
-        let prepared = prepare_search_query(&SearchQuery {
-            query: "  rust   web\tcrawler  ".to_string(),
-            limit: 500,
-            offset: 500,
+        let failed = within_deadline(Duration::from_secs(1), "search_failed", async {
+            Err::<(), _>("unavailable")
         })
-        .unwrap();
-
-        assert_eq!(prepared.query, "rust web crawler");
-        assert_eq!(prepared.limit, MAX_SEARCH_LIMIT);
-        assert_eq!(prepared.offset, MAX_SEARCH_OFFSET);
-        assert_eq!(
-            prepared.candidate_limit,
-            (MAX_SEARCH_LIMIT + MAX_SEARCH_OFFSET) * SEARCH_PREFETCH_MULTIPLIER
-        );
-    }
-
-    #[test]
-    fn rejects_blank_search_query() {
-        assert!(
-            prepare_search_query(&SearchQuery {
-                query: " \n\t ".to_string(),
-                limit: 10,
-                offset: 0,
-            })
-            .is_err()
-        );
+        .await
+        .unwrap_err();
+        assert_eq!((failed.status, failed.code), (500, "search_failed"));
     }
 }
@@ -0,0 +1,143 @@
+//! Search operators typed into `query`
+//!
+//! `site:docs.rs` keeps a search to the pages of a host and `-site:reddit.com`
+//! leaves a host's pages out, so `rust async site:docs.rs -site:reddit.com`
+//! works without separate parameters. Both may be repeated; several `site:`
+//! operators match pages of any of their hosts. A host also matches with or
+//! without `www.`, as sites are crawled under either, while other subdomains
+//! need their own operator. The operators are removed from the text that is
+//! embedded and searched.
+
+use qdrant_client::qdrant::{Condition, Filter};
+use shared_crawler_api::problem::Problem;
+use std::fmt;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SiteFilter {
+    /// Hosts results must be on, any of them
+    pub include: Vec<String>,
+    /// Hosts results must not be on
+    pub exclude: Vec<String>,
+}
+
+impl SiteFilter {
+    /// Limit `filter` to the included hosts and leave out the excluded ones
+    pub fn apply(&self, filter: &mut Filter) {
+        if !self.include.is_empty() {
+            filter
+                .must
+                .push(Condition::matches("host", with_www_variants(&self.include)));
+        }
+        if !self.exclude.is_empty() {
+            filter
+                .must_not
+                .push(Condition::matches("host", with_www_variants(&self.exclude)));
+        }
+    }
+}
+
+/// The operators as typed, e.g. `site:docs.rs -site:reddit.com`, for cache keys
+impl fmt::Display for SiteFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operators = self
+            .include
+            .iter()
+            .map(|host| format!("site:{host}"))
+            .chain(self.exclude.iter().map(|host| format!("-site:{host}")))
+            .collect::<Vec<_>>();
+        f.write_str(&operators.join(" "))
+    }
+}
+
+/// `query`'s words without its operators, and the filter they ask for
+pub fn parse(query: &str) -> Result<(String, SiteFilter), Problem> {
+    let mut words = Vec::new();
+    let mut sites = SiteFilter::default();
+    for word in query.split_whitespace() {
+        let (excluded, operand) = match word.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, word),
+        };
+        let Some(host) = strip_operator(operand) else {
+            words.push(word);
+            continue;
+        };
+        let host = parse_host(host)?;
+        let hosts = if excluded {
+            &mut sites.exclude
+        } else {
+            &mut sites.include
+        };
+        if !hosts.contains(&host) {
+            hosts.push(host);
+        }
+    }
+    Ok((words.join(" "), sites))
+}
+
+/// What follows `site:`, in any case
+fn strip_operator(word: &str) -> Option<&str> {
+    let prefix = word.get(..5)?;
+    prefix.eq_ignore_ascii_case("site:").then(|| &word[5..])
+}
+
+/// The host as the spider stores it, also accepting a URL
+fn parse_host(value: &str) -> Result<String, Problem> {
+    let value = value
+        .strip_prefix("https://")
+        .or_else(|| value.strip_prefix("http://"))
+        .unwrap_or(value);
+    let host = value.split('/').next().unwrap_or_default();
+    url::Host::parse(&host.to_lowercase())
+        .ok()
+        .filter(|_| !host.is_empty())
+        .map(|host| host.to_string())
+        .ok_or_else(|| {
+            Problem::bad_request(
+                "invalid_site",
+                format!("site: must be followed by a host name, got {value:?}"),
+            )
+        })
+}
+
+fn with_www_variants(hosts: &[String]) -> Vec<String> {
+    hosts
+        .iter()
+        .flat_map(|host| {
+            let other = match host.strip_prefix("www.") {
+                Some(bare) => bare.to_string(),
+                None => format!("www.{host}"),
+            };
+            [host.clone(), other]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_site_operators_out_of_the_query() {
+        let (text, sites) =
+            parse("rust async site:docs.rs -site:reddit.com Site:https://Tokio.rs/docs").unwrap();
+        assert_eq!(text, "rust async");
+        assert_eq!(sites.include, ["docs.rs", "tokio.rs"]);
+        assert_eq!(sites.exclude, ["reddit.com"]);
+        assert_eq!(
+            sites.to_string(),
+            "site:docs.rs site:tokio.rs -site:reddit.com"
+        );
+        assert_eq!(
+            with_www_variants(&sites.exclude),
+            ["reddit.com", "www.reddit.com"]
+        );
+
+        let (text, sites) = parse("pre-site: -rust website:x").unwrap();
+        assert_eq!(text, "pre-site: -rust website:x");
+        assert_eq!(sites, SiteFilter::default());
+
+        assert_eq!(parse("rust site:").unwrap_err().code, "invalid_site");
+        assert_eq!(parse("rust -site:[::1").unwrap_err().code, "invalid_site");
+    }
+}
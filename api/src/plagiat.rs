@@ -0,0 +1,139 @@
+//! Passages of a `/plagiat` text
+//!
+//! A long text is compared passage by passage, so a copied paragraph still
+//! stands out in an otherwise original document. Paragraphs are packed into
+//! passages of up to `PASSAGE_CHARS`, longer ones are cut at whitespace, and
+//! each passage is searched on its own. The matches are merged keeping each
+//! chunk's best score.
+
+use shared_crawler_api::WebPageResult;
+use std::collections::HashMap;
+
+/// About one indexed chunk, so passages and chunks are embedded alike
+pub const PASSAGE_CHARS: usize = 1_000;
+/// Passage searches in flight at once for one request
+pub const MAX_CONCURRENT_QUERIES: usize = 4;
+/// Closest chunks per passage
+pub const MATCHES_PER_PASSAGE: u64 = 5;
+/// Matches in a response across all passages
+pub const MAX_MATCHES: usize = 20;
+
+pub fn passages(text: &str) -> Vec<String> {
+    let mut passages = Vec::new();
+    let mut current = String::new();
+    for paragraph in text.split("\n\n") {
+        let paragraph = paragraph.split_whitespace().collect::<Vec<_>>().join(" ");
+        for piece in split_long(&paragraph) {
+            if !current.is_empty()
+                && current.chars().count() + piece.chars().count() >= PASSAGE_CHARS
+            {
+                passages.push(std::mem::take(&mut current));
+            }
+            if !current.is_empty() {
+                current.push('\n');
+            }
+            current.push_str(piece);
+        }
+    }
+    if !current.is_empty() {
+        passages.push(current);
+    }
+    passages
+}
+
+/// `paragraph` in pieces of at most `PASSAGE_CHARS`, cut at spaces
+fn split_long(paragraph: &str) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = paragraph;
+    while rest.chars().count() > PASSAGE_CHARS {
+        let limit = rest
+            .char_indices()
+            .nth(PASSAGE_CHARS)
+            .map_or(rest.len(), |(index, _)| index);
+        let cut = rest[..limit].rfind(' ').unwrap_or(limit);
+        pieces.push(rest[..cut].trim_end());
+        rest = rest[cut..].trim_start();
+    }
+    if !rest.is_empty() {
+        pieces.push(rest);
+    }
+    pieces
+}
+
+/// Matches of all passages, each chunk once with its best score, best first
+pub fn merge(per_passage: Vec<Vec<WebPageResult>>) -> Vec<WebPageResult> {
+    let mut best: HashMap<(String, String), WebPageResult> = HashMap::new();
+    for result in per_passage.into_iter().flatten() {
+        let key = (
+            result.data.source_url.clone(),
+            result.data.chunk_content.clone(),
+        );
+        match best.get(&key) {
+            Some(kept) if kept.score >= result.score => {}
+            _ => {
+                best.insert(key, result);
+            }
+        }
+    }
+    let mut merged = best.into_values().collect::<Vec<_>>();
+    merged.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.data.source_url.cmp(&b.data.source_url))
+    });
+    merged.truncate(MAX_MATCHES);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_crawler_api::WebPageChunk;
+
+    #[test]
+    fn packs_paragraphs_and_merges_best_scores() {
+        let long = "word ".repeat(450);
+        let text = format!("First  paragraph.\n\nSecond\nparagraph.\n\n{long}");
+        let passages = passages(&text);
+        assert_eq!(passages.len(), 4);
+        assert_eq!(passages[0], "First paragraph.\nSecond paragraph.");
+        assert!(
+            passages
+                .iter()
+                .all(|passage| passage.chars().count() <= PASSAGE_CHARS)
+        );
+        assert!(passages[1].starts_with("word") && passages[1].ends_with("word"));
+
+        let result = |url: &str, content: &str, score| {
+            WebPageResult::new(
+                WebPageChunk::new(
+                    content.to_string(),
+                    None,
+                    url.to_string(),
+                    String::new(),
+                    String::new(),
+                    vec![],
+                    vec![],
+                    0.0,
+                    0.0,
+                    0,
+                ),
+                score,
+            )
+        };
+        let merged = merge(vec![
+            vec![result("https://a.example/", "copied", 0.7)],
+            vec![
+                result("https://a.example/", "copied", 0.9),
+                result("https://b.example/", "other", 0.5),
+            ],
+        ]);
+        assert_eq!(
+            merged
+                .iter()
+                .map(|result| (result.data.source_url.as_str(), result.score))
+                .collect::<Vec<_>>(),
+            [("https://a.example/", 0.9), ("https://b.example/", 0.5)]
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Per-query weighting of dense and lexical retrieval
+//!
+//! `alpha` is the share of the hybrid fusion given to the dense vector, the
+//! rest going to title, headings and body BM25 (4:3:2), as headings are
+//! denser relevance signals than body text. Navigational queries (a site
+//! name, a URL, a quoted phrase) and terse keyword queries lean lexical;
+//! questions and longer natural-language queries lean on the embedding.
+//! `HYBRID_ALPHA` fixes alpha for every query instead.
+
+use serde::Serialize;
+use std::env;
+
+use crate::ranking::{STOPWORDS, query_terms};
+
+/// Gives the dense vector the weight `2` it had before alpha was chosen per
+/// query
+pub const BALANCED_ALPHA: f32 = 0.4;
+const LEXICAL_ALPHA: f32 = 0.25;
+const SEMANTIC_ALPHA: f32 = 0.65;
+/// Sum of the fusion weights
+const TOTAL_WEIGHT: f32 = 5.0;
+
+const QUESTION_WORDS: &[&str] = &[
+    "how", "what", "why", "when", "where", "who", "which", "can", "does", "is", "wie", "was",
+    "warum", "wann", "wo", "wer",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryClass {
+    Navigational,
+    Keyword,
+    NaturalLanguage,
+}
+
+impl QueryClass {
+    pub fn alpha(self) -> f32 {
+        match self {
+            Self::Navigational => LEXICAL_ALPHA,
+            Self::Keyword => BALANCED_ALPHA,
+            Self::NaturalLanguage => SEMANTIC_ALPHA,
+        }
+    }
+}
+
+pub fn classify(query: &str) -> QueryClass {
+    let terms = query_terms(query);
+    let looks_like_site =
+        query.split_whitespace().count() == 1 && (query.contains('.') || query.contains("://"));
+    if query.contains('"') || looks_like_site {
+        return QueryClass::Navigational;
+    }
+    let stopwords = terms
+        .iter()
+        .filter(|term| STOPWORDS.contains(&term.as_str()))
+        .count();
+    let question = query.trim_end().ends_with('?')
+        || terms
+            .first()
+            .is_some_and(|term| QUESTION_WORDS.contains(&term.as_str()));
+    if question || terms.len() >= 5 || (terms.len() >= 3 && stopwords * 3 >= terms.len()) {
+        QueryClass::NaturalLanguage
+    } else {
+        QueryClass::Keyword
+    }
+}
+
+/// `HYBRID_ALPHA` as a number between 0 and 1, or `None` to choose per query
+pub fn fixed_alpha_from_env() -> Option<f32> {
+    env::var("HYBRID_ALPHA")
+        .ok()
+        .and_then(|value| value.trim().parse::<f32>().ok())
+        .filter(|alpha| (0.0..=1.0).contains(alpha))
+}
+
+/// Fusion weights of the dense, title BM25, headings BM25 and body BM25
+/// prefetches
+pub fn rrf_weights(alpha: f32) -> [f32; 4] {
+    let lexical = (1.0 - alpha) * TOTAL_WEIGHT;
+    [
+        alpha * TOTAL_WEIGHT,
+        lexical * 4.0 / 9.0,
+        lexical * 3.0 / 9.0,
+        lexical * 2.0 / 9.0,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_queries_by_shape() {
+        assert_eq!(classify("github.com"), QueryClass::Navigational);
+        assert_eq!(classify("\"exact phrase\" rust"), QueryClass::Navigational);
+        assert_eq!(classify("rust tokio"), QueryClass::Keyword);
+        assert_eq!(
+            classify("how do I cancel a tokio task"),
+            QueryClass::NaturalLanguage
+        );
+        assert_eq!(classify("best of the web"), QueryClass::NaturalLanguage);
+        assert_eq!(classify("rust async runtime?"), QueryClass::NaturalLanguage);
+    }
+
+    #[test]
+    fn weights_headings_between_title_and_body() {
+        let weights = rrf_weights(BALANCED_ALPHA);
+        for (weight, expected) in weights.into_iter().zip([2.0, 4.0 / 3.0, 1.0, 2.0 / 3.0]) {
+            assert!((weight - expected).abs() < 1e-5);
+        }
+    }
+}
@@ -1,5 +1,12 @@
+use serde::Deserialize;
 use shared_crawler_api::WebPageResult;
-use std::{collections::HashMap, env, fs::File, io::Read, time::Duration};
+use std::{
+    collections::HashMap,
+    env,
+    fs::File,
+    io::Read,
+    time::{Duration, SystemTime},
+};
 use url::Url;
 use zip::ZipArchive;
 
@@ -9,6 +16,12 @@ const PATH_DEPTH_PENALTY: f32 = 0.12;
 const EXACT_MATCH_BOOST: f32 = 3.0;
 const ROOT_HOST_MATCH_BOOST: f32 = 2.0;
 const POPULARITY_BOOST: f32 = 1.0;
+const CONTENT_QUALITY_WEIGHT: f32 = 1.0;
+/// Boost of a page published just now, halving every `FRESHNESS_HALF_LIFE_DAYS`
+const FRESHNESS_BOOST: f32 = 0.5;
+const FRESHNESS_HALF_LIFE_DAYS: f32 = 180.0;
+/// Light, as breadcrumb levels are short and shared by a whole section
+const BREADCRUMB_MATCH_BOOST: f32 = 0.5;
 const TRANCO_MAX_RANK: u32 = 1_000_000;
 const DEFAULT_TRANCO_URL: &str = "https://tranco-list.eu/top-1m.csv.zip";
 
@@ -76,6 +89,29 @@ fn normalize_host(host: &str) -> String {
         .to_ascii_lowercase()
 }
 
+/// Order of `/search` results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchSort {
+    #[default]
+    Relevance,
+    /// Most recently crawled first
+    CrawledAt,
+    /// Newest `published_at` first; pages without one follow by relevance
+    #[serde(alias = "date")]
+    PublishedAt,
+}
+
+impl SearchSort {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Relevance => "relevance",
+            Self::CrawledAt => "crawled_at",
+            Self::PublishedAt => "published_at",
+        }
+    }
+}
+
 fn popularity_boost(rank: u32) -> f32 {
     let rank = rank.clamp(1, TRANCO_MAX_RANK) as f32;
     POPULARITY_BOOST * (1.0 - (rank.ln() / (TRANCO_MAX_RANK as f32).ln()))
@@ -100,7 +136,15 @@ fn query_match_coverage(query: &str, title: &str, url: &str) -> f32 {
     terms.iter().filter(|term| words.contains(term)).count() as f32 / terms.len() as f32
 }
 
-fn query_terms(query: &str) -> Vec<String> {
+/// Common English and German function words
+pub const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "with", "from", "that", "this", "are", "was", "you", "your", "our", "how",
+    "what", "why", "when", "who", "not", "all", "can", "has", "have", "will", "about", "into",
+    "more", "is", "of", "to", "in", "on", "do", "does", "a", "an", "i", "my", "it", "der", "die",
+    "das", "und", "mit", "von", "für", "ist", "ein", "eine",
+];
+
+pub fn query_terms(query: &str) -> Vec<String> {
     query
         .split(|c: char| !c.is_alphanumeric())
         .filter(|term| !term.is_empty())
@@ -181,6 +225,9 @@ fn root_host_query_match(query: &str, title: &str, url: &str) -> bool {
 /// 2. Domain root boost (root pages get bonus)
 /// 3. Path depth penalty (deeper pages get penalized)
 /// 4. Query-term coverage boost (query words found in title/URL)
+/// 5. Content quality score stored by the spider at index time
+/// 6. Freshness boost for pages with a recent publish date
+/// 7. Breadcrumb boost (query words found in the page's breadcrumb)
 pub fn apply_ranking_boost(result: &mut WebPageResult, query: &str, popularity: &DomainPopularity) {
     let url = &result.data.source_url;
     let title = &result.data.page_title;
@@ -209,6 +256,50 @@ pub fn apply_ranking_boost(result: &mut WebPageResult, query: &str, popularity:
     if let Some(rank) = popularity.rank(url) {
         result.score += popularity_boost(rank);
     }
+
+    result.score += CONTENT_QUALITY_WEIGHT * result.data.score as f32;
+
+    if let Some(published_at) = result.data.published_at {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+        result.score += freshness_boost(published_at, now);
+    }
+
+    result.score += BREADCRUMB_MATCH_BOOST * breadcrumb_coverage(query, &result.data.breadcrumb);
+}
+
+/// Share of the query's terms found in `breadcrumb`
+fn breadcrumb_coverage(query: &str, breadcrumb: &[String]) -> f32 {
+    let terms = query_terms(query);
+    if terms.is_empty() || breadcrumb.is_empty() {
+        return 0.0;
+    }
+    let words = breadcrumb
+        .iter()
+        .flat_map(|level| query_terms(level))
+        .collect::<Vec<_>>();
+    terms.iter().filter(|term| words.contains(term)).count() as f32 / terms.len() as f32
+}
+
+fn freshness_boost(published_at: i64, now: i64) -> f32 {
+    let age_days = (now - published_at).max(0) as f32 / 86_400.0;
+    FRESHNESS_BOOST * 0.5_f32.powf(age_days / FRESHNESS_HALF_LIFE_DAYS)
+}
+
+/// Stable sort of ranked results by `sort`, newest first, keeping the ranked
+/// order of equally dated and undated pages
+pub fn sort_results(results: &mut [WebPageResult], sort: SearchSort) {
+    match sort {
+        SearchSort::Relevance => {}
+        SearchSort::CrawledAt => {
+            results.sort_by_key(|result| std::cmp::Reverse(result.data.crawled_at))
+        }
+        SearchSort::PublishedAt => {
+            results.sort_by_key(|result| std::cmp::Reverse(result.data.published_at))
+        }
+    }
 }
 
 /// Apply ranking boosts to all results and re-sort by score descending
@@ -260,6 +351,8 @@ mod tests {
                     source_url: url.to_string(),
                     chunk_content: "".to_string(),
                     chunk_heading: None,
+                    heading_path: vec![],
+                    headings: vec![],
                     page_title: "".to_string(),
                     description: "".to_string(),
                     tags: vec![],
@@ -268,6 +361,14 @@ mod tests {
                     // Note: this score field inside data is separate from the search result score
                     score: 0.0,
                     crawled_at: 0,
+                    published_at: None,
+                    author: None,
+                    favicon_url: None,
+                    image_url: None,
+                    language: None,
+                    breadcrumb: vec![],
+                    unsafe_content: false,
+                    collection: None,
                 },
             }
         };
@@ -295,12 +396,22 @@ mod tests {
                 page_title: title.to_string(),
                 chunk_content: "".to_string(),
                 chunk_heading: None,
+                heading_path: vec![],
+                headings: vec![],
                 description: "".to_string(),
                 tags: vec![],
                 categories: vec![],
                 paid: 0.0,
                 score: 0.0,
                 crawled_at: 0,
+                published_at: None,
+                author: None,
+                favicon_url: None,
+                image_url: None,
+                language: None,
+                breadcrumb: vec![],
+                unsafe_content: false,
+                collection: None,
             },
         };
 
@@ -321,6 +432,32 @@ mod tests {
         assert!(res3.score < res1.score);
     }
 
+    #[test]
+    fn content_quality_score_adds_to_the_ranking() {
+        let result = |quality| {
+            WebPageResult::new(
+                WebPageChunk::new(
+                    String::new(),
+                    None,
+                    "https://example.com".to_string(),
+                    "Hello World".to_string(),
+                    String::new(),
+                    vec![],
+                    vec![],
+                    0.0,
+                    quality,
+                    0,
+                ),
+                0.5,
+            )
+        };
+        let mut plain = result(0.0);
+        let mut better = result(0.5);
+        apply_ranking_boost(&mut plain, "Benjamin", &DomainPopularity::default());
+        apply_ranking_boost(&mut better, "Benjamin", &DomainPopularity::default());
+        assert!((better.score - plain.score - CONTENT_QUALITY_WEIGHT * 0.5).abs() < 1e-6);
+    }
+
     #[test]
     fn query_boost_rewards_term_coverage_without_substring_matches() {
         assert_eq!(
@@ -346,12 +483,22 @@ mod tests {
                 page_title: "Wikipedia".to_string(),
                 chunk_content: String::new(),
                 chunk_heading: None,
+                heading_path: vec![],
+                headings: vec![],
                 description: String::new(),
                 tags: vec![],
                 categories: vec![],
                 paid: 0.0,
                 score: 0.0,
                 crawled_at: 0,
+                published_at: None,
+                author: None,
+                favicon_url: None,
+                image_url: None,
+                language: None,
+                breadcrumb: vec![],
+                unsafe_content: false,
+                collection: None,
             },
         };
         let mut results = [
@@ -373,12 +520,22 @@ mod tests {
                 page_title: String::new(),
                 chunk_content: String::new(),
                 chunk_heading: None,
+                heading_path: vec![],
+                headings: vec![],
                 description: String::new(),
                 tags: vec![],
                 categories: vec![],
                 paid: 0.0,
                 score: 0.0,
                 crawled_at: 0,
+                published_at: None,
+                author: None,
+                favicon_url: None,
+                image_url: None,
+                language: None,
+                breadcrumb: vec![],
+                unsafe_content: false,
+                collection: None,
             },
         };
         let mut results = [
@@ -411,12 +568,22 @@ mod tests {
                 page_title: String::new(),
                 chunk_content: String::new(),
                 chunk_heading: None,
+                heading_path: vec![],
+                headings: vec![],
                 description: String::new(),
                 tags: vec![],
                 categories: vec![],
                 paid: 0.0,
                 score: 0.0,
                 crawled_at: 0,
+                published_at: None,
+                author: None,
+                favicon_url: None,
+                image_url: None,
+                language: None,
+                breadcrumb: vec![],
+                unsafe_content: false,
+                collection: None,
             },
         };
         let popularity = DomainPopularity::from_csv("1,popular.example\n900000,unknown.example\n");
@@ -429,4 +596,101 @@ mod tests {
 
         assert_eq!(results[0].data.source_url, "https://www.popular.example/");
     }
+
+    #[test]
+    fn breadcrumb_matches_boost_lightly() {
+        let breadcrumb = ["Docs".to_string(), "Crawling Guides".to_string()];
+        assert_eq!(breadcrumb_coverage("crawling docs", &breadcrumb), 1.0);
+        assert_eq!(breadcrumb_coverage("crawling api", &breadcrumb), 0.5);
+        assert_eq!(breadcrumb_coverage("crawling", &[]), 0.0);
+
+        let result = || {
+            WebPageResult::new(
+                WebPageChunk::new(
+                    String::new(),
+                    None,
+                    "https://example.com/docs/crawl".to_string(),
+                    String::new(),
+                    String::new(),
+                    vec![],
+                    vec![],
+                    0.0,
+                    0.0,
+                    0,
+                ),
+                0.0,
+            )
+        };
+        let mut plain = result();
+        let mut matching = result();
+        matching.data.breadcrumb = breadcrumb.to_vec();
+        apply_ranking_boost(&mut plain, "crawling", &DomainPopularity::default());
+        apply_ranking_boost(&mut matching, "crawling", &DomainPopularity::default());
+        assert_eq!(matching.score - plain.score, BREADCRUMB_MATCH_BOOST);
+    }
+
+    #[test]
+    fn freshness_decays_and_date_sorts_keep_undated_pages_last() {
+        let now = 1_767_225_600;
+        assert_eq!(freshness_boost(now, now), FRESHNESS_BOOST);
+        assert_eq!(freshness_boost(now + 60, now), FRESHNESS_BOOST);
+        let half_life = (FRESHNESS_HALF_LIFE_DAYS * 86_400.0) as i64;
+        assert!((freshness_boost(now - half_life, now) - FRESHNESS_BOOST / 2.0).abs() < 1e-6);
+
+        let dated = |url: &str, published_at| {
+            let mut result = WebPageResult::new(
+                WebPageChunk::new(
+                    String::new(),
+                    None,
+                    url.to_string(),
+                    String::new(),
+                    String::new(),
+                    vec![],
+                    vec![],
+                    0.0,
+                    0.0,
+                    0,
+                ),
+                0.0,
+            );
+            result.data.published_at = published_at;
+            result
+        };
+        let mut results = [
+            dated("https://a.example/", None),
+            dated("https://b.example/", Some(100)),
+            dated("https://c.example/", None),
+            dated("https://d.example/", Some(200)),
+        ];
+        let urls = |results: &[WebPageResult]| {
+            results
+                .iter()
+                .map(|result| result.data.source_url.clone())
+                .collect::<Vec<_>>()
+        };
+        sort_results(&mut results, SearchSort::PublishedAt);
+        assert_eq!(
+            urls(&results),
+            [
+                "https://d.example/",
+                "https://b.example/",
+                "https://a.example/",
+                "https://c.example/"
+            ]
+        );
+
+        for (result, crawled_at) in results.iter_mut().zip([1, 3, 2, 3]) {
+            result.data.crawled_at = crawled_at;
+        }
+        sort_results(&mut results, SearchSort::CrawledAt);
+        assert_eq!(
+            urls(&results),
+            [
+                "https://b.example/",
+                "https://c.example/",
+                "https://a.example/",
+                "https://d.example/"
+            ]
+        );
+    }
 }
@@ -0,0 +1,193 @@
+//! Feed of recently indexed pages
+//!
+//! `GET /recent` lists the newest pages by `crawled_at`, each URL once with
+//! its current version, optionally of one host. `format=rss` and
+//! `format=atom` render the same list as a feed for monitoring tools, with
+//! the request's own URL as the feed's link and ID.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use shared_crawler_api::{WebPageChunk, problem::Problem};
+
+use crate::sitemap::escape;
+
+pub const DEFAULT_RECENT_LIMIT: usize = 20;
+pub const MAX_RECENT_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedFormat {
+    #[default]
+    Json,
+    Rss,
+    Atom,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecentQuery {
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Only pages of this host
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// Only pages of crawl jobs with this `collection`
+    #[serde(default)]
+    pub collection: Option<String>,
+    #[serde(default)]
+    pub format: FeedFormat,
+}
+
+impl RecentQuery {
+    pub fn limit(&self) -> Result<usize, Problem> {
+        match self.limit.unwrap_or(DEFAULT_RECENT_LIMIT) {
+            limit @ 1.. if limit <= MAX_RECENT_LIMIT => Ok(limit),
+            _ => Err(Problem::bad_request(
+                "limit_out_of_range",
+                format!("limit must be between 1 and {MAX_RECENT_LIMIT}"),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecentPage {
+    pub url: String,
+    pub title: String,
+    pub description: String,
+    pub crawled_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published_at: Option<i64>,
+}
+
+impl From<WebPageChunk> for RecentPage {
+    fn from(chunk: WebPageChunk) -> Self {
+        Self {
+            url: chunk.source_url,
+            title: chunk.page_title,
+            description: chunk.description,
+            crawled_at: chunk.crawled_at,
+            published_at: chunk.published_at,
+        }
+    }
+}
+
+/// Pages as an RSS 2.0 channel
+pub fn render_rss(pages: &[RecentPage], feed_url: &str) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\">\n<channel>\n",
+    );
+    xml.push_str("  <title>Recently indexed pages</title>\n");
+    xml.push_str(&format!("  <link>{}</link>\n", escape(feed_url)));
+    xml.push_str("  <description>Pages newest by crawl time</description>\n");
+    for page in pages {
+        xml.push_str("  <item>\n");
+        xml.push_str(&format!("    <title>{}</title>\n", escape(&page.title)));
+        xml.push_str(&format!("    <link>{}</link>\n", escape(&page.url)));
+        xml.push_str(&format!(
+            "    <guid isPermaLink=\"false\">{}#{}</guid>\n",
+            escape(&page.url),
+            page.crawled_at
+        ));
+        xml.push_str(&format!(
+            "    <description>{}</description>\n",
+            escape(&page.description)
+        ));
+        xml.push_str(&format!(
+            "    <pubDate>{}</pubDate>\n",
+            date(page.crawled_at).to_rfc2822()
+        ));
+        xml.push_str("  </item>\n");
+    }
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+/// Pages as an Atom feed, updated when its newest page was crawled
+pub fn render_atom(pages: &[RecentPage], feed_url: &str) -> String {
+    let updated = pages.first().map_or(0, |page| page.crawled_at);
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n",
+    );
+    xml.push_str("  <title>Recently indexed pages</title>\n");
+    xml.push_str(&format!("  <id>{}</id>\n", escape(feed_url)));
+    xml.push_str(&format!(
+        "  <link rel=\"self\" href=\"{}\"/>\n",
+        escape(feed_url)
+    ));
+    xml.push_str(&format!("  <updated>{}</updated>\n", atom_date(updated)));
+    for page in pages {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <title>{}</title>\n", escape(&page.title)));
+        xml.push_str(&format!("    <link href=\"{}\"/>\n", escape(&page.url)));
+        xml.push_str(&format!(
+            "    <id>{}#{}</id>\n",
+            escape(&page.url),
+            page.crawled_at
+        ));
+        xml.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            atom_date(page.crawled_at)
+        ));
+        if let Some(published_at) = page.published_at {
+            xml.push_str(&format!(
+                "    <published>{}</published>\n",
+                atom_date(published_at)
+            ));
+        }
+        xml.push_str(&format!(
+            "    <summary>{}</summary>\n",
+            escape(&page.description)
+        ));
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn date(unix: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(unix, 0).unwrap_or_default()
+}
+
+fn atom_date(unix: i64) -> String {
+    date(unix).format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_escaped_rss_and_atom_feeds() {
+        let pages = [RecentPage {
+            url: "https://example.com/?a=1&b=2".to_string(),
+            title: "Tips & <tricks>".to_string(),
+            description: "New".to_string(),
+            crawled_at: 1_792_152_000,
+            published_at: Some(1_792_065_600),
+        }];
+        let feed_url = "http://localhost:8000/recent?format=rss&limit=1";
+
+        let rss = render_rss(&pages, feed_url);
+        assert!(rss.contains("<link>http://localhost:8000/recent?format=rss&amp;limit=1</link>"));
+        assert!(rss.contains("<title>Tips &amp; &lt;tricks&gt;</title>"));
+        assert!(rss.contains("<link>https://example.com/?a=1&amp;b=2</link>"));
+        assert!(rss.contains("<pubDate>Fri, 16 Oct 2026 12:00:00 +0000</pubDate>"));
+
+        let atom = render_atom(&pages, feed_url);
+        assert!(atom.contains("<updated>2026-10-16T12:00:00Z</updated>"));
+        assert!(atom.contains("<published>2026-10-15T12:00:00Z</published>"));
+        assert!(atom.contains("<id>https://example.com/?a=1&amp;b=2#1792152000</id>"));
+
+        let query = |limit| RecentQuery {
+            limit,
+            domain: None,
+            collection: None,
+            format: FeedFormat::Json,
+        };
+        assert_eq!(query(None).limit().unwrap(), DEFAULT_RECENT_LIMIT);
+        assert_eq!(
+            query(Some(MAX_RECENT_LIMIT + 1)).limit().unwrap_err().code,
+            "limit_out_of_range"
+        );
+    }
+}
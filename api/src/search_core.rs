@@ -0,0 +1,1324 @@
+//! Search and plagiarism checks, apart from HTTP
+//!
+//! The `/search` and `/plagiat` handlers validate their requests, pick the
+//! tenant's collection and apply deadlines, caching and the response format;
+//! everything between is here. `prepare_search_query` checks a
+//! `SearchQuery` and turns it into a `PreparedSearch`, `SearchCore::search`
+//! builds the Qdrant queries, parses the points and ranks and pages them into
+//! a `SearchResult`, and `SearchCore::check_plagiat` compares a
+//! `PlagiatRequest`'s passages with the index. None of it knows about actix,
+//! so it can be tested directly and called by other front ends.
+
+use futures::{StreamExt, TryStreamExt};
+use qdrant_client::{
+    Qdrant,
+    qdrant::{
+        Condition, DocumentBuilder, Filter, PrefetchQueryBuilder, Query, QueryPointsBuilder,
+        RrfBuilder, ScoredPoint,
+    },
+};
+use serde::{Deserialize, Serialize};
+use shared_crawler_api::{WebPageChunk, WebPageResult, problem::Problem, tenant, text};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    add_collection, as_of_out_of_range, expansion,
+    export::{MAX_EXPORT_RESULTS, ResultFormat},
+    fallback, forwarded_request_id, fusion, log_tag, operators, payload_json, plagiat,
+    query_intent, ranking, version_filter, versions,
+};
+
+pub const MAX_SEARCH_LIMIT: usize = 50;
+pub const MAX_SEARCH_OFFSET: usize = 200;
+pub const MAX_QUERY_CHARS: usize = 512;
+/// Longest author name the spider keeps
+const MAX_AUTHOR_CHARS: usize = 100;
+const MAX_PLAGIAT_CHARS: usize = 20_000;
+const SEARCH_PREFETCH_MULTIPLIER: usize = 4;
+/// TEI's `--max-client-batch-size` in compose.yml
+const EMBED_BATCH_SIZE: usize = 8;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub query: String,
+    /// Up to `MAX_SEARCH_LIMIT`, or `MAX_EXPORT_RESULTS` with `format=ndjson`
+    /// or `csv`
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+    #[serde(default)]
+    pub offset: usize,
+    /// Also return pages the spider flagged as spam or adult content
+    #[serde(default)]
+    pub include_unsafe: bool,
+    /// Search the page versions that were current at this Unix time
+    #[serde(default)]
+    pub as_of: Option<i64>,
+    /// `false` skips query expansion
+    #[serde(default = "default_expand")]
+    pub expand: bool,
+    /// Retrieval strategy instead of `SEARCH_MODE`
+    #[serde(default)]
+    pub mode: Option<fusion::RetrievalMode>,
+    /// Report how the query was classified and weighted
+    #[serde(default)]
+    pub explain: bool,
+    /// `json` (default), `ndjson`, which streams the results alone, or
+    /// `csv`; without it, `Accept: text/csv` picks `csv`
+    #[serde(default)]
+    pub format: Option<ResultFormat>,
+    /// Only pages of crawl jobs with this `collection`
+    #[serde(default)]
+    pub collection: Option<String>,
+    /// `relevance` (default), or `crawled_at` or `published_at` (alias
+    /// `date`), newest first
+    #[serde(default)]
+    pub sort: ranking::SearchSort,
+    /// Only pages whose `author` is exactly this name
+    #[serde(default)]
+    pub author: Option<String>,
+    /// `en` or `de` to prefer pages in that language, or `any`; without it,
+    /// the query's detected language
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+impl SearchQuery {
+    pub fn format(&self) -> ResultFormat {
+        self.format.unwrap_or_default()
+    }
+}
+
+fn default_limit() -> usize {
+    10
+}
+
+fn default_expand() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlagiatRequest {
+    pub text: String,
+    #[serde(default = "default_threshold")]
+    pub threshold: f32,
+    /// Only compare with pages of crawl jobs with this `collection`
+    #[serde(default)]
+    pub collection: Option<String>,
+}
+
+fn default_threshold() -> f32 {
+    0.6
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    pub results: Vec<WebPageResult>,
+    pub total: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub knowledge_panel: Option<KnowledgePanel>,
+    /// The hybrid search found nothing and a relaxed retry answered
+    pub fallback: bool,
+    /// Expansions of a short query whose results were fused in
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub expanded_queries: Vec<String>,
+    /// Only sent with `explain=true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explain: Option<SearchExplain>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchExplain {
+    pub mode: fusion::RetrievalMode,
+    pub query_class: query_intent::QueryClass,
+    /// Share of the hybrid fusion given to the dense vector
+    pub alpha: f32,
+    /// Fusion weights of the dense, title BM25, headings BM25 and body BM25
+    /// retrieval
+    pub weights: [f32; 4],
+    /// Language results were limited to and the query normalized in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<&'static str>,
+}
+
+impl SearchExplain {
+    pub fn new(
+        mode: fusion::RetrievalMode,
+        prepared: &PreparedSearch,
+        fixed_alpha: Option<f32>,
+    ) -> Self {
+        let query_class = query_intent::classify(&prepared.query);
+        let alpha = fixed_alpha.unwrap_or_else(|| query_class.alpha());
+        Self {
+            mode,
+            query_class,
+            alpha,
+            weights: query_intent::rrf_weights(alpha),
+            language: prepared.language.map(text::Language::code),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KnowledgePanel {
+    pub title: String,
+    pub description: String,
+    pub source_url: String,
+    pub tags: Vec<String>,
+    pub categories: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlagiatResult {
+    pub is_plagiat: bool,
+    pub similarity_score: f32,
+    pub matched_documents: Vec<WebPageResult>,
+}
+
+pub struct PreparedSearch {
+    pub query: String,
+    pub limit: usize,
+    pub offset: usize,
+    pub candidate_limit: usize,
+    /// `author` with its whitespace collapsed, as the spider stores it
+    pub author: Option<String>,
+    /// `site:` and `-site:` operators, no longer part of `query`
+    pub sites: operators::SiteFilter,
+    /// Language asked for or detected in `query`, if any
+    pub language: Option<text::Language>,
+}
+
+pub fn prepare_search_query(query: &SearchQuery) -> Result<PreparedSearch, Problem> {
+    let text = query.query.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() {
+        return Err(Problem::bad_request(
+            "empty_query",
+            "query must not be empty",
+        ));
+    }
+    if text.chars().count() > MAX_QUERY_CHARS {
+        return Err(Problem::bad_request(
+            "query_too_long",
+            format!("query must be at most {MAX_QUERY_CHARS} characters"),
+        ));
+    }
+    let (text, sites) = operators::parse(&text)?;
+    if text.is_empty() {
+        return Err(Problem::bad_request(
+            "empty_query",
+            "query must have search terms besides its site: operators",
+        ));
+    }
+    let max_limit = match query.format() {
+        ResultFormat::Json => MAX_SEARCH_LIMIT,
+        ResultFormat::Ndjson | ResultFormat::Csv => MAX_EXPORT_RESULTS,
+    };
+    let limit = query.limit;
+    if limit > max_limit {
+        return Err(Problem::bad_request(
+            "limit_out_of_range",
+            format!("limit must be between 0 and {max_limit}"),
+        ));
+    }
+    if query.offset > MAX_SEARCH_OFFSET {
+        return Err(Problem::bad_request(
+            "offset_out_of_range",
+            format!("offset must be between 0 and {MAX_SEARCH_OFFSET}"),
+        ));
+    }
+    if query.as_of.is_some_and(|as_of| as_of < 0) {
+        return Err(as_of_out_of_range());
+    }
+    if let Some(collection) = &query.collection {
+        tenant::validate_collection(collection)?;
+    }
+    let author = match &query.author {
+        Some(author) => {
+            let author = author.split_whitespace().collect::<Vec<_>>().join(" ");
+            if author.is_empty() || author.chars().count() > MAX_AUTHOR_CHARS {
+                return Err(Problem::bad_request(
+                    "invalid_author",
+                    format!("author must be 1 to {MAX_AUTHOR_CHARS} characters"),
+                ));
+            }
+            Some(author)
+        }
+        None => None,
+    };
+    let language = match query.language.as_deref().map(str::trim) {
+        None | Some("") => text::detect_language(&text),
+        Some(any) if any.eq_ignore_ascii_case("any") => None,
+        Some(code) => Some(text::Language::from_code(code).ok_or_else(|| {
+            Problem::bad_request("invalid_language", "language must be en, de or any")
+        })?),
+    };
+    let offset = query.offset;
+    Ok(PreparedSearch {
+        query: text,
+        limit,
+        offset,
+        candidate_limit: limit
+            .saturating_add(offset)
+            .saturating_mul(SEARCH_PREFETCH_MULTIPLIER),
+        author,
+        sites,
+        language,
+    })
+}
+
+/// Settings of a `SearchCore`
+pub struct SearchConfig {
+    /// Base URL of the TEI embedding service
+    pub tei_url: String,
+    pub embedding_dimensions: usize,
+    pub expansion: expansion::QueryExpansion,
+    /// Retrieval of searches that do not pick a `mode`
+    pub search_mode: fusion::RetrievalMode,
+    /// Fixed share of the dense vector, instead of one per query class
+    pub hybrid_alpha: Option<f32>,
+    pub lexical: text::LexicalOptions,
+}
+
+impl SearchConfig {
+    /// Reads `TEI_URL`, `EMBEDDING_DIMENSIONS`, `SEARCH_MODE`, the
+    /// `QUERY_EXPANSION*` and `HYBRID_ALPHA` settings and the lexical options
+    pub fn from_env() -> Self {
+        Self {
+            tei_url: env::var("TEI_URL").unwrap_or_else(|_| "http://localhost:8080".to_string()),
+            embedding_dimensions: shared_crawler_api::embedding_dimensions(),
+            expansion: expansion::QueryExpansion::from_env(),
+            search_mode: fusion::RetrievalMode::from_env(),
+            hybrid_alpha: query_intent::fixed_alpha_from_env(),
+            lexical: text::LexicalOptions::from_env(),
+        }
+    }
+}
+
+/// Searches and plagiarism checks against one Qdrant and TEI
+pub struct SearchCore {
+    pub qdrant: Qdrant,
+    /// Client for TEI and the query expansion hook
+    pub http: reqwest::Client,
+    pub config: SearchConfig,
+    popularity: ranking::DomainPopularity,
+    /// Whether each collection has the headings vector, and since when known
+    headings_vectors: Mutex<HashMap<String, (bool, Instant)>>,
+}
+
+impl SearchCore {
+    pub fn new(
+        qdrant: Qdrant,
+        http: reqwest::Client,
+        config: SearchConfig,
+        popularity: ranking::DomainPopularity,
+    ) -> Self {
+        Self {
+            qdrant,
+            http,
+            config,
+            popularity,
+            headings_vectors: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Connects to `QDRANT_URL` with the settings of `SearchConfig::from_env`
+    /// and the Tranco popularity list, which is left empty if it cannot be
+    /// loaded
+    pub async fn from_env(http: reqwest::Client) -> anyhow::Result<Self> {
+        let qdrant_url =
+            env::var("QDRANT_URL").unwrap_or_else(|_| "http://localhost:6334".to_string());
+        let qdrant = Qdrant::from_url(&qdrant_url).build()?;
+        let popularity = match ranking::load_domain_popularity(&http).await {
+            Ok(popularity) => popularity,
+            Err(error) => {
+                eprintln!("failed to load Tranco popularity list: {error}");
+                ranking::DomainPopularity::default()
+            }
+        };
+        Ok(Self::new(
+            qdrant,
+            http,
+            SearchConfig::from_env(),
+            popularity,
+        ))
+    }
+
+    /// How `query` will be retrieved: its own `mode`, or the configured one
+    pub fn explain(&self, query: &SearchQuery, prepared: &PreparedSearch) -> SearchExplain {
+        SearchExplain::new(
+            query.mode.unwrap_or(self.config.search_mode),
+            prepared,
+            self.config.hybrid_alpha,
+        )
+    }
+
+    /// Ranked page of results for `prepared`, and the URLs of every candidate it
+    /// was chosen from, which the search cache is invalidated by
+    pub async fn search(
+        &self,
+        collection: &str,
+        prepared: &PreparedSearch,
+        query: &SearchQuery,
+        explain: SearchExplain,
+    ) -> anyhow::Result<(SearchResult, Vec<String>)> {
+        let Retrieved {
+            mut results,
+            fallback,
+            expanded_queries,
+        } = search_with_fallback(self, collection, prepared, query, &explain).await?;
+        ranking::apply_ranking_boosts(&mut results, &prepared.query, &self.popularity);
+        ranking::sort_results(&mut results, query.sort);
+        let urls = results
+            .iter()
+            .map(|result| result.data.source_url.clone())
+            .collect::<Vec<_>>();
+        let (results, total, knowledge_panel) =
+            search_page(results, prepared.limit, prepared.offset);
+        let result = SearchResult {
+            total,
+            results,
+            knowledge_panel,
+            fallback,
+            expanded_queries,
+            explain: Some(explain),
+        };
+        Ok((result, urls))
+    }
+
+    /// Indexed chunks `req.text` resembles, compared passage by passage
+    pub async fn check_plagiat(
+        &self,
+        collection: &str,
+        req: &PlagiatRequest,
+    ) -> anyhow::Result<PlagiatResult> {
+        let mut filter = Filter::must([versions::current()]);
+        add_collection(&mut filter, req.collection.as_deref());
+        let inputs = plagiat::passages(&req.text)
+            .into_iter()
+            .map(|passage| format!("query: {passage}"))
+            .collect::<Vec<_>>();
+        let embeddings = embed_many(self, &inputs).await?;
+        let per_passage = futures::stream::iter(embeddings)
+            .map(|dense| closest_chunks(self, collection, dense, &filter))
+            .buffer_unordered(plagiat::MAX_CONCURRENT_QUERIES)
+            .try_collect::<Vec<_>>()
+            .await?;
+        let results = plagiat::merge(per_passage);
+        let highest = results.first().map(|result| result.score).unwrap_or(0.0);
+        let matched_documents = results
+            .into_iter()
+            .filter(|result| result.score >= req.threshold)
+            .collect();
+        Ok(PlagiatResult {
+            is_plagiat: highest >= req.threshold,
+            similarity_score: highest,
+            matched_documents,
+        })
+    }
+}
+
+fn search_page(
+    results: Vec<WebPageResult>,
+    limit: usize,
+    offset: usize,
+) -> (Vec<WebPageResult>, usize, Option<KnowledgePanel>) {
+    let candidate_count = results.len();
+    let results = unique_pages(results, candidate_count);
+    let total = results.len();
+    let knowledge_panel = (offset == 0)
+        .then(|| results.first())
+        .flatten()
+        .and_then(knowledge_panel);
+    (page(results, limit, offset), total, knowledge_panel)
+}
+
+fn page(results: Vec<WebPageResult>, limit: usize, offset: usize) -> Vec<WebPageResult> {
+    results.into_iter().skip(offset).take(limit).collect()
+}
+
+fn knowledge_panel(result: &WebPageResult) -> Option<KnowledgePanel> {
+    let title = result.data.page_title.trim();
+    let description = result.data.description.trim();
+    if title.is_empty() || title == "No Title" || description.is_empty() {
+        return None;
+    }
+
+    Some(KnowledgePanel {
+        title: title.to_string(),
+        description: description.to_string(),
+        source_url: result.data.source_url.clone(),
+        tags: result.data.tags.clone(),
+        categories: result.data.categories.clone(),
+    })
+}
+
+fn unique_pages(results: Vec<WebPageResult>, limit: usize) -> Vec<WebPageResult> {
+    let mut seen_urls = HashSet::new();
+    let unique = results
+        .into_iter()
+        .filter(|result| seen_urls.insert(result.data.source_url.clone()))
+        .collect::<Vec<_>>();
+    let mut seen_hosts = HashSet::new();
+    let diverse = unique
+        .iter()
+        .enumerate()
+        .filter(|(_, result)| {
+            let url = &result.data.source_url;
+            let source = url::Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_owned))
+                .unwrap_or_else(|| url.clone());
+            seen_hosts.insert(source)
+        })
+        .map(|(index, _)| index)
+        .take(limit.min(5))
+        .collect::<Vec<_>>();
+    let mut remaining = unique.into_iter().map(Some).collect::<Vec<_>>();
+    let mut selected = diverse
+        .into_iter()
+        .map(|index| remaining[index].take().unwrap())
+        .collect::<Vec<_>>();
+    let slots = limit.saturating_sub(selected.len());
+    selected.extend(remaining.into_iter().flatten().take(slots));
+    selected
+}
+
+/// Results of a search before ranking boosts and paging
+struct Retrieved {
+    results: Vec<WebPageResult>,
+    /// A relaxed retry answered
+    fallback: bool,
+    expanded_queries: Vec<String>,
+}
+
+/// Hybrid search results that are web pages, fused with the results of
+/// expanded queries for short queries and retried with relaxed parameters
+/// when there are none
+async fn search_with_fallback(
+    core: &SearchCore,
+    collection: &str,
+    prepared: &PreparedSearch,
+    query: &SearchQuery,
+    explain: &SearchExplain,
+) -> anyhow::Result<Retrieved> {
+    let limit = prepared.candidate_limit;
+    let (mode, alpha) = (explain.mode, explain.alpha);
+    let lexical = core.config.lexical.for_language(explain.language);
+    let filter = search_filter(query, prepared, query.as_of);
+    let dense = embed(core, &format!("query: {}", prepared.query)).await?;
+    let mut results = retrieve(
+        core,
+        collection,
+        (mode, alpha),
+        dense.clone(),
+        &bm25_document(&lexical, &prepared.query),
+        limit,
+        &filter,
+    )
+    .await?;
+    let expanded_queries = if query.expand && core.config.expansion.applies(&prepared.query) {
+        expand_query(core, &prepared.query, &dense, &results)
+            .await
+            .unwrap_or_else(|error| {
+                eprintln!("{}failed to expand query: {error}", log_tag());
+                Vec::new()
+            })
+    } else {
+        Vec::new()
+    };
+    if !expanded_queries.is_empty() {
+        let mut lists = vec![results];
+        for expanded in &expanded_queries {
+            let dense = embed(core, &format!("query: {expanded}")).await?;
+            lists.push(
+                retrieve(
+                    core,
+                    collection,
+                    (mode, alpha),
+                    dense,
+                    &bm25_document(&lexical, expanded),
+                    limit,
+                    &filter,
+                )
+                .await?,
+            );
+        }
+        results = fusion::fuse(lists);
+    }
+    let retrieved = |results, fallback| Retrieved {
+        results,
+        fallback,
+        expanded_queries: expanded_queries.clone(),
+    };
+    if !results.is_empty() {
+        return Ok(retrieved(results, false));
+    }
+    let stemmed = fallback::stem_terms(&prepared.query, lexical.language);
+    if stemmed != prepared.query.to_lowercase() {
+        let results = retrieve(
+            core,
+            collection,
+            (mode, alpha),
+            dense.clone(),
+            &bm25_document(&lexical, &stemmed),
+            limit,
+            &filter,
+        )
+        .await?;
+        if !results.is_empty() {
+            return Ok(retrieved(results, true));
+        }
+    }
+    if query.as_of.is_none() {
+        return Ok(retrieved(Vec::new(), false));
+    }
+    let relaxed = search_filter(query, prepared, None);
+    let results = dense_search(core, collection, dense, limit, &relaxed).await?;
+    let fallback = !results.is_empty();
+    Ok(retrieved(results, fallback))
+}
+
+/// Expansions of `text` by the configured strategy; `results` are its own
+/// results, the source of corpus terms
+async fn expand_query(
+    core: &SearchCore,
+    text: &str,
+    dense: &[f32],
+    results: &[WebPageResult],
+) -> anyhow::Result<Vec<String>> {
+    match &core.config.expansion.mode {
+        expansion::ExpansionMode::Off => Ok(Vec::new()),
+        expansion::ExpansionMode::Llm(url) => expansion::paraphrases(&core.http, url, text).await,
+        expansion::ExpansionMode::Corpus => {
+            let candidates = expansion::candidate_terms(results, text);
+            if candidates.is_empty() {
+                return Ok(Vec::new());
+            }
+            let inputs = candidates
+                .iter()
+                .map(|term| format!("query: {term}"))
+                .collect::<Vec<_>>();
+            let embeddings = embed_many(core, &inputs).await?;
+            let terms = expansion::nearest_terms(
+                dense,
+                candidates,
+                &embeddings,
+                core.config.expansion.terms,
+            );
+            Ok((!terms.is_empty())
+                .then(|| format!("{text} {}", terms.join(" ")))
+                .into_iter()
+                .collect())
+        }
+    }
+}
+
+/// Filter of a search's parameters and operators, on versions current at
+/// `as_of`
+fn search_filter(query: &SearchQuery, prepared: &PreparedSearch, as_of: Option<i64>) -> Filter {
+    let mut filter = version_filter(as_of);
+    if !query.include_unsafe {
+        filter.must_not.push(Condition::matches("unsafe", true));
+    }
+    add_collection(&mut filter, query.collection.as_deref());
+    if let Some(author) = &prepared.author {
+        filter
+            .must
+            .push(Condition::matches("author", author.clone()));
+    }
+    prepared.sites.apply(&mut filter);
+    // Pages that declare no language are kept, as they may be in any
+    if let Some(language) = prepared.language {
+        filter.must.push(
+            Filter::should([
+                Condition::matches("language", language.code().to_string()),
+                Condition::is_empty("language"),
+            ])
+            .into(),
+        );
+    }
+    filter
+}
+
+/// Candidates for `text` by the chosen strategy and hybrid alpha, without
+/// file URLs
+async fn retrieve(
+    core: &SearchCore,
+    collection: &str,
+    (mode, alpha): (fusion::RetrievalMode, f32),
+    dense: Vec<f32>,
+    lexical: &qdrant_client::qdrant::Document,
+    limit: usize,
+    filter: &Filter,
+) -> anyhow::Result<Vec<WebPageResult>> {
+    let weights = query_intent::rrf_weights(alpha);
+    match mode {
+        fusion::RetrievalMode::Hybrid => {
+            hybrid_search(core, collection, dense, lexical, limit, filter, weights).await
+        }
+        fusion::RetrievalMode::Dense => dense_search(core, collection, dense, limit, filter).await,
+        fusion::RetrievalMode::Lexical => {
+            lexical_search(core, collection, lexical, limit, filter).await
+        }
+        fusion::RetrievalMode::Fusion => {
+            let (hybrid, dense, lexical) = tokio::try_join!(
+                hybrid_search(
+                    core,
+                    collection,
+                    dense.clone(),
+                    lexical,
+                    limit,
+                    filter,
+                    weights
+                ),
+                dense_search(core, collection, dense, limit, filter),
+                lexical_search(core, collection, lexical, limit, filter),
+            )?;
+            Ok(fusion::fuse(vec![hybrid, dense, lexical]))
+        }
+    }
+}
+
+/// Collections created before the spider indexed headings lack this vector
+/// until they are reindexed
+const HEADINGS_VECTOR: &str = "headings_bm25";
+/// BM25 vectors in the order of their `rrf_weights`
+const LEXICAL_VECTORS: [&str; 3] = ["title_bm25", HEADINGS_VECTOR, "body_bm25"];
+/// How long a collection is taken to lack the headings vector before asking
+/// Qdrant again
+const HEADINGS_RECHECK: Duration = Duration::from_secs(60);
+
+/// Whether `collection` has the headings vector, remembered per collection
+async fn has_headings_vector(core: &SearchCore, collection: &str) -> bool {
+    if let Some((present, checked)) = core.headings_vectors.lock().unwrap().get(collection)
+        && (*present || checked.elapsed() < HEADINGS_RECHECK)
+    {
+        return *present;
+    }
+    let Ok(info) = core.qdrant.collection_info(collection).await else {
+        return false;
+    };
+    let present = info
+        .result
+        .and_then(|info| info.config)
+        .and_then(|config| config.params)
+        .and_then(|params| params.sparse_vectors_config)
+        .is_some_and(|sparse| sparse.map.contains_key(HEADINGS_VECTOR));
+    core.headings_vectors
+        .lock()
+        .unwrap()
+        .insert(collection.to_string(), (present, Instant::now()));
+    present
+}
+
+fn bm25_prefetch(
+    lexical: &qdrant_client::qdrant::Document,
+    vector: &str,
+    filter: &Filter,
+    limit: usize,
+) -> PrefetchQueryBuilder {
+    PrefetchQueryBuilder::default()
+        .query(Query::new_nearest(lexical.clone()))
+        .using(vector)
+        .filter(filter.clone())
+        .limit(limit as u64)
+}
+
+/// Fused dense, title, headings and body search, without file URLs
+async fn hybrid_search(
+    core: &SearchCore,
+    collection: &str,
+    dense: Vec<f32>,
+    lexical: &qdrant_client::qdrant::Document,
+    limit: usize,
+    filter: &Filter,
+    weights: [f32; 4],
+) -> anyhow::Result<Vec<WebPageResult>> {
+    let headings = has_headings_vector(core, collection).await;
+    let mut query = QueryPointsBuilder::new(collection).add_prefetch(
+        PrefetchQueryBuilder::default()
+            .query(dense)
+            .using("dense")
+            .filter(filter.clone())
+            .limit(limit as u64),
+    );
+    let mut fusion_weights = vec![weights[0]];
+    for (vector, weight) in LEXICAL_VECTORS.into_iter().zip(&weights[1..]) {
+        if vector == HEADINGS_VECTOR && !headings {
+            continue;
+        }
+        query = query.add_prefetch(bm25_prefetch(lexical, vector, filter, limit));
+        fusion_weights.push(*weight);
+    }
+    let result = core
+        .qdrant
+        .query(
+            query
+                .query(Query::new_rrf(RrfBuilder::new().weights(fusion_weights)))
+                .limit(limit as u64)
+                .with_payload(true),
+        )
+        .await?;
+    Ok(searchable_results(result.result))
+}
+
+/// Title, headings and body BM25 search, without file URLs
+async fn lexical_search(
+    core: &SearchCore,
+    collection: &str,
+    lexical: &qdrant_client::qdrant::Document,
+    limit: usize,
+    filter: &Filter,
+) -> anyhow::Result<Vec<WebPageResult>> {
+    let headings = has_headings_vector(core, collection).await;
+    let mut query = QueryPointsBuilder::new(collection);
+    for vector in LEXICAL_VECTORS {
+        if vector == HEADINGS_VECTOR && !headings {
+            continue;
+        }
+        query = query.add_prefetch(bm25_prefetch(lexical, vector, filter, limit));
+    }
+    let result = core
+        .qdrant
+        .query(
+            query
+                .query(Query::new_rrf(RrfBuilder::new()))
+                .limit(limit as u64)
+                .with_payload(true),
+        )
+        .await?;
+    Ok(searchable_results(result.result))
+}
+
+/// Dense-only search, without file URLs
+async fn dense_search(
+    core: &SearchCore,
+    collection: &str,
+    dense: Vec<f32>,
+    limit: usize,
+    filter: &Filter,
+) -> anyhow::Result<Vec<WebPageResult>> {
+    let result = core
+        .qdrant
+        .query(
+            QueryPointsBuilder::new(collection)
+                .query(dense)
+                .using("dense")
+                .filter(filter.clone())
+                .limit(limit as u64)
+                .with_payload(true),
+        )
+        .await?;
+    Ok(searchable_results(result.result))
+}
+
+fn searchable_results(points: Vec<ScoredPoint>) -> Vec<WebPageResult> {
+    points
+        .into_iter()
+        .filter_map(|point| {
+            WebPageChunk::from_payload_json(&payload_json(point.payload))
+                .map(|data| WebPageResult::new(data, point.score))
+        })
+        .filter(|result| ranking::is_searchable_page(&result.data.source_url))
+        .collect()
+}
+
+/// Chunks closest to one `/plagiat` passage
+async fn closest_chunks(
+    core: &SearchCore,
+    collection: &str,
+    dense: Vec<f32>,
+    filter: &Filter,
+) -> anyhow::Result<Vec<WebPageResult>> {
+    let response = core
+        .qdrant
+        .query(
+            QueryPointsBuilder::new(collection)
+                .query(dense)
+                .using("dense")
+                .filter(filter.clone())
+                .limit(plagiat::MATCHES_PER_PASSAGE)
+                .with_payload(true),
+        )
+        .await?;
+    Ok(response
+        .result
+        .into_iter()
+        .filter_map(|point| {
+            WebPageChunk::from_payload_json(&payload_json(point.payload))
+                .map(|data| WebPageResult::new(data, point.score))
+        })
+        .collect())
+}
+
+pub fn validate_plagiat_request(req: &PlagiatRequest) -> Result<(), Problem> {
+    let chars = req.text.trim().chars().count();
+    if chars == 0 {
+        return Err(Problem::bad_request("empty_text", "text must not be empty"));
+    }
+    if chars > MAX_PLAGIAT_CHARS {
+        return Err(Problem::bad_request(
+            "text_too_long",
+            format!("text must be at most {MAX_PLAGIAT_CHARS} characters"),
+        ));
+    }
+    if !(0.0..=1.0).contains(&req.threshold) {
+        return Err(Problem::bad_request(
+            "threshold_out_of_range",
+            "threshold must be between 0.0 and 1.0",
+        ));
+    }
+    if let Some(collection) = &req.collection {
+        tenant::validate_collection(collection)?;
+    }
+    Ok(())
+}
+
+async fn embed(core: &SearchCore, input: &str) -> anyhow::Result<Vec<f32>> {
+    Ok(embed_many(core, &[input.to_string()]).await?.remove(0))
+}
+
+/// Embeddings of `inputs`, in TEI requests of at most `EMBED_BATCH_SIZE`
+async fn embed_many(core: &SearchCore, inputs: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+    let mut embeddings = Vec::with_capacity(inputs.len());
+    for batch in inputs.chunks(EMBED_BATCH_SIZE) {
+        let mut response = core
+            .http
+            .post(format!(
+                "{}/embed",
+                core.config.tei_url.trim_end_matches('/')
+            ))
+            .headers(forwarded_request_id())
+            .json(&serde_json::json!({ "inputs": batch }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<Vec<f32>>>()
+            .await?;
+        anyhow::ensure!(
+            response.len() == batch.len()
+                && response
+                    .iter()
+                    .all(|vector| vector.len() == core.config.embedding_dimensions),
+            "TEI returned invalid embedding dimensions"
+        );
+        embeddings.append(&mut response);
+    }
+    Ok(embeddings)
+}
+
+/// BM25 query, normalized the way the spider normalizes chunks in the same
+/// language
+fn bm25_document(lexical: &text::LexicalOptions, text: &str) -> qdrant_client::qdrant::Document {
+    DocumentBuilder::new(text::lexical(text, lexical), "qdrant/bm25")
+        .options(HashMap::from([("language".to_string(), "none".into())]))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A core whose Qdrant and TEI do not answer
+    fn offline_core(search_mode: fusion::RetrievalMode, hybrid_alpha: Option<f32>) -> SearchCore {
+        let qdrant = Qdrant::from_url("http://127.0.0.1:1")
+            .skip_compatibility_check()
+            .build()
+            .unwrap();
+        let config = SearchConfig {
+            tei_url: "http://127.0.0.1:1".to_string(),
+            embedding_dimensions: 384,
+            expansion: expansion::QueryExpansion::off(),
+            search_mode,
+            hybrid_alpha,
+            lexical: text::LexicalOptions::default(),
+        };
+        SearchCore::new(
+            qdrant,
+            reqwest::Client::new(),
+            config,
+            ranking::DomainPopularity::default(),
+        )
+    }
+
+    fn search_query(value: serde_json::Value) -> SearchQuery {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn explains_with_the_configured_mode_unless_the_query_picks_one() {
+        let core = offline_core(fusion::RetrievalMode::Dense, Some(0.25));
+        let query = search_query(serde_json::json!({"query": "rust crawler"}));
+        let explain = core.explain(&query, &prepare_search_query(&query).unwrap());
+        assert_eq!(explain.mode, fusion::RetrievalMode::Dense);
+        assert_eq!(explain.alpha, 0.25);
+
+        let query = search_query(serde_json::json!({"query": "rust crawler", "mode": "lexical"}));
+        let explain = core.explain(&query, &prepare_search_query(&query).unwrap());
+        assert_eq!(explain.mode, fusion::RetrievalMode::Lexical);
+    }
+
+    #[tokio::test]
+    async fn fails_searches_while_the_embedding_service_is_down() {
+        let core = offline_core(fusion::RetrievalMode::Hybrid, None);
+        let query = search_query(serde_json::json!({"query": "rust crawler"}));
+        let prepared = prepare_search_query(&query).unwrap();
+        let explain = core.explain(&query, &prepared);
+        assert!(
+            core.search("web_pages", &prepared, &query, explain)
+                .await
+                .is_err()
+        );
+    }
+
+    fn result(url: &str) -> WebPageResult {
+        WebPageResult::new(
+            WebPageChunk {
+                chunk_content: String::new(),
+                chunk_heading: None,
+                heading_path: vec![],
+                headings: vec![],
+                source_url: url.to_string(),
+                page_title: "Same title".to_string(),
+                description: String::new(),
+                tags: vec![],
+                categories: vec![],
+                paid: 0.0,
+                score: 0.0,
+                crawled_at: 0,
+                published_at: None,
+                author: None,
+                favicon_url: None,
+                image_url: None,
+                language: None,
+                breadcrumb: vec![],
+                unsafe_content: false,
+                collection: None,
+            },
+            0.0,
+        )
+    }
+
+    fn described_result(url: &str) -> WebPageResult {
+        let mut result = result(url);
+        result.data.description = "Short summary".to_string();
+        result.data.tags = vec!["rust".to_string()];
+        result
+    }
+
+    #[test]
+    fn keeps_same_title_pages_and_removes_duplicate_chunks() {
+        let pages = unique_pages(
+            vec![
+                result("https://example.com/"),
+                result("https://example.com/a"),
+                result("https://example.com/a"),
+            ],
+            10,
+        );
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[1].data.source_url, "https://example.com/a");
+    }
+
+    #[test]
+    fn diversifies_first_five_then_preserves_rank_order() {
+        let urls = [
+            "https://a.example/1",
+            "https://a.example/2",
+            "https://b.example/1",
+            "https://c.example/1",
+            "https://d.example/1",
+            "https://e.example/1",
+            "https://f.example/1",
+        ];
+        let pages = unique_pages(urls.into_iter().map(result).collect(), 7);
+        let actual = pages
+            .iter()
+            .map(|page| page.data.source_url.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            actual,
+            [
+                urls[0], urls[2], urls[3], urls[4], urls[5], urls[1], urls[6]
+            ]
+        );
+    }
+
+    #[test]
+    fn fills_from_repeated_hosts_and_handles_malformed_urls() {
+        let urls = [
+            "not a url",
+            "also not a url",
+            "https://example.com/1",
+            "https://example.com/2",
+        ];
+        let pages = unique_pages(urls.into_iter().map(result).collect(), 4);
+
+        assert_eq!(
+            pages
+                .iter()
+                .map(|page| page.data.source_url.as_str())
+                .collect::<Vec<_>>(),
+            urls
+        );
+    }
+
+    #[test]
+    fn diversifies_limits_below_five() {
+        let pages = unique_pages(
+            vec![
+                result("https://a.example/1"),
+                result("https://a.example/2"),
+                result("https://b.example/1"),
+            ],
+            2,
+        );
+
+        assert_eq!(pages[1].data.source_url, "https://b.example/1");
+    }
+
+    #[test]
+    fn offsets_after_deduping_and_diversifying() {
+        let urls = [
+            "https://a.example/1",
+            "https://a.example/1",
+            "https://b.example/1",
+            "https://a.example/2",
+        ];
+        let (pages, total, knowledge_panel) =
+            search_page(urls.into_iter().map(result).collect(), 2, 1);
+
+        assert_eq!(total, 3);
+        assert_eq!(knowledge_panel, None);
+        assert_eq!(
+            pages
+                .iter()
+                .map(|page| page.data.source_url.as_str())
+                .collect::<Vec<_>>(),
+            ["https://b.example/1", "https://a.example/2"]
+        );
+    }
+
+    #[test]
+    fn adds_knowledge_panel_from_first_page_result() {
+        let (_pages, _total, knowledge_panel) = search_page(
+            vec![
+                described_result("https://example.com/"),
+                described_result("https://example.com/about"),
+            ],
+            10,
+            0,
+        );
+
+        assert_eq!(
+            knowledge_panel,
+            Some(KnowledgePanel {
+                title: "Same title".to_string(),
+                description: "Short summary".to_string(),
+                source_url: "https://example.com/".to_string(),
+                tags: vec!["rust".to_string()],
+                categories: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn prepares_search_query_for_retrieval() {
+        let prepared = prepare_search_query(&SearchQuery {
+            query: "  rust   web\tcrawler site:docs.rs ".to_string(),
+            limit: MAX_SEARCH_LIMIT,
+            offset: MAX_SEARCH_OFFSET,
+            include_unsafe: false,
+            as_of: None,
+            expand: true,
+            mode: None,
+            explain: false,
+            format: None,
+            collection: None,
+            sort: ranking::SearchSort::Relevance,
+            author: Some(" Jane\t Doe ".to_string()),
+            language: Some("EN".to_string()),
+        })
+        .unwrap();
+
+        assert_eq!(prepared.query, "rust web crawler");
+        assert_eq!(prepared.sites.include, ["docs.rs"]);
+        assert_eq!(prepared.author.as_deref(), Some("Jane Doe"));
+        assert_eq!(prepared.language, Some(text::Language::English));
+        assert_eq!(prepared.limit, MAX_SEARCH_LIMIT);
+        assert_eq!(prepared.offset, MAX_SEARCH_OFFSET);
+        assert_eq!(
+            prepared.candidate_limit,
+            (MAX_SEARCH_LIMIT + MAX_SEARCH_OFFSET) * SEARCH_PREFETCH_MULTIPLIER
+        );
+    }
+
+    #[test]
+    fn rejects_blank_search_query() {
+        assert!(
+            prepare_search_query(&SearchQuery {
+                query: " \n\t ".to_string(),
+                limit: 10,
+                offset: 0,
+                include_unsafe: false,
+                as_of: None,
+                expand: true,
+                mode: None,
+                explain: false,
+                format: None,
+                collection: None,
+                sort: ranking::SearchSort::Relevance,
+                author: None,
+                language: None,
+            })
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_range_search_parameters_with_stable_codes() {
+        let error = |query: &str, limit, offset| {
+            prepare_search_query(&SearchQuery {
+                query: query.to_string(),
+                limit,
+                offset,
+                include_unsafe: false,
+                as_of: None,
+                expand: true,
+                mode: None,
+                explain: false,
+                format: None,
+                collection: None,
+                sort: ranking::SearchSort::Relevance,
+                author: None,
+                language: None,
+            })
+            .err()
+            .map(|problem| (problem.status, problem.code))
+        };
+
+        assert_eq!(error("rust", 51, 0), Some((400, "limit_out_of_range")));
+        assert_eq!(error("rust", 10, 201), Some((400, "offset_out_of_range")));
+        assert_eq!(
+            error(&"a".repeat(MAX_QUERY_CHARS + 1), 10, 0),
+            Some((400, "query_too_long"))
+        );
+        assert_eq!(error("site:docs.rs", 10, 0), Some((400, "empty_query")));
+        assert_eq!(error("rust site:", 10, 0), Some((400, "invalid_site")));
+        let before_epoch = prepare_search_query(&SearchQuery {
+            query: "rust".to_string(),
+            limit: 10,
+            offset: 0,
+            include_unsafe: false,
+            as_of: Some(-1),
+            expand: true,
+            mode: None,
+            explain: false,
+            format: None,
+            collection: None,
+            sort: ranking::SearchSort::Relevance,
+            author: None,
+            language: None,
+        });
+        assert_eq!(before_epoch.err().unwrap().code, "as_of_out_of_range");
+        let blank_author = prepare_search_query(&SearchQuery {
+            query: "rust".to_string(),
+            limit: 10,
+            offset: 0,
+            include_unsafe: false,
+            as_of: None,
+            expand: true,
+            mode: None,
+            explain: false,
+            format: None,
+            collection: None,
+            sort: ranking::SearchSort::Relevance,
+            author: Some("  ".to_string()),
+            language: None,
+        });
+        assert_eq!(blank_author.err().unwrap().code, "invalid_author");
+        let language = |language: &str| {
+            prepare_search_query(&SearchQuery {
+                query: "wie funktioniert der crawler".to_string(),
+                limit: 10,
+                offset: 0,
+                include_unsafe: false,
+                as_of: None,
+                expand: true,
+                mode: None,
+                explain: false,
+                format: None,
+                collection: None,
+                sort: ranking::SearchSort::Relevance,
+                author: None,
+                language: (!language.is_empty()).then(|| language.to_string()),
+            })
+            .map(|prepared| prepared.language)
+            .map_err(|problem| problem.code)
+        };
+        assert_eq!(language(""), Ok(Some(text::Language::German)));
+        assert_eq!(language("any"), Ok(None));
+        assert_eq!(language("fr"), Err("invalid_language"));
+
+        let stream = |limit, format| {
+            prepare_search_query(&SearchQuery {
+                query: "rust".to_string(),
+                limit,
+                offset: 0,
+                include_unsafe: false,
+                as_of: None,
+                expand: true,
+                mode: None,
+                explain: false,
+                format: Some(format),
+                collection: None,
+                sort: ranking::SearchSort::Relevance,
+                author: None,
+                language: None,
+            })
+            .map(|prepared| prepared.limit)
+            .map_err(|problem| problem.code)
+        };
+        assert_eq!(
+            stream(MAX_EXPORT_RESULTS, ResultFormat::Ndjson),
+            Ok(MAX_EXPORT_RESULTS)
+        );
+        assert_eq!(
+            stream(MAX_EXPORT_RESULTS, ResultFormat::Json),
+            Err("limit_out_of_range")
+        );
+        assert_eq!(
+            stream(MAX_EXPORT_RESULTS + 1, ResultFormat::Csv),
+            Err("limit_out_of_range")
+        );
+    }
+
+    #[test]
+    fn validates_plagiat_threshold_and_text() {
+        let request = |text: &str, threshold| PlagiatRequest {
+            text: text.to_string(),
+            threshold,
+            collection: None,
+        };
+
+        assert!(validate_plagiat_request(&request("some text", 0.6)).is_ok());
+        assert_eq!(
+            validate_plagiat_request(&request("some text", 1.5))
+                .unwrap_err()
+                .code,
+            "threshold_out_of_range"
+        );
+        assert_eq!(
+            validate_plagiat_request(&request("  ", 0.6))
+                .unwrap_err()
+                .code,
+            "empty_text"
+        );
+    }
+}
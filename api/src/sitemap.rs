@@ -0,0 +1,89 @@
+//! sitemap.xml of a host's indexed pages
+//!
+//! `GET /export/sitemap?domain=` lists every page of the host whose current
+//! version is indexed, with `lastmod` set to when it was crawled. A sitemap
+//! holds at most 50,000 URLs; larger hosts get their first 50,000 by URL.
+
+use shared_crawler_api::problem::Problem;
+use std::collections::BTreeMap;
+
+/// Limit of the sitemap protocol for one file
+pub const MAX_SITEMAP_URLS: usize = 50_000;
+
+/// The host as the spider stores it on chunks: lowercase, without port
+pub fn parse_domain(domain: &str) -> Result<String, Problem> {
+    url::Host::parse(&domain.trim().to_lowercase())
+        .map(|host| host.to_string())
+        .map_err(|_| Problem::bad_request("invalid_domain", "domain must be a host name"))
+}
+
+/// sitemap.xml for pages keyed by URL with their `crawled_at`
+pub fn render(pages: &BTreeMap<String, i64>) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for (url, crawled_at) in pages.iter().take(MAX_SITEMAP_URLS) {
+        xml.push_str("  <url><loc>");
+        xml.push_str(&escape(url));
+        xml.push_str("</loc>");
+        if *crawled_at > 0 {
+            xml.push_str("<lastmod>");
+            xml.push_str(&w3c_date(*crawled_at));
+            xml.push_str("</lastmod>");
+        }
+        xml.push_str("</url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+/// `text` escaped for XML content and attributes
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// `YYYY-MM-DD` of a Unix time in UTC
+fn w3c_date(unix: i64) -> String {
+    // Days to civil date, after Howard Hinnant's algorithm
+    let days = unix.div_euclid(86_400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_escaped_urls_with_lastmod() {
+        let pages = BTreeMap::from([
+            ("https://example.com/b?x=1&y=2".to_string(), 1_709_251_199),
+            ("https://example.com/a".to_string(), 0),
+        ]);
+        let xml = render(&pages);
+        assert!(xml.contains(
+            "<url><loc>https://example.com/a</loc></url>\n  \
+             <url><loc>https://example.com/b?x=1&amp;y=2</loc><lastmod>2024-02-29</lastmod></url>"
+        ));
+        assert_eq!(w3c_date(0), "1970-01-01");
+        assert_eq!(parse_domain(" Example.COM ").unwrap(), "example.com");
+        assert!(parse_domain("exa mple.com").is_err());
+    }
+}
@@ -0,0 +1,126 @@
+//! Earlier versions of pages
+//!
+//! Re-crawling a changed page replaces its chunks unless the spider runs with
+//! `KEEP_PAGE_VERSIONS`; then the old chunks stay indexed with `superseded_at`
+//! set to the crawl that replaced them. Every chunk carries its page's
+//! `version_number` (from 1) and `version_since`, when that content was first
+//! crawled. Search only sees current chunks, `/page?version=` returns an
+//! earlier version and `/page/versions` lists them. With `as_of=`, `/search`
+//! and `/page` see the versions that were current at that time instead.
+
+use qdrant_client::qdrant::{Condition, Filter, Range};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Chunks of the version of their page that is currently indexed
+pub fn current() -> Condition {
+    Condition::is_empty("superseded_at")
+}
+
+/// Chunks of version `number`; chunks indexed before versions were numbered
+/// are version 1
+pub fn numbered(number: i64) -> Condition {
+    if number == 1 {
+        Filter::should([
+            Condition::matches("version_number", 1i64),
+            Condition::is_empty("version_number"),
+        ])
+        .into()
+    } else {
+        Condition::matches("version_number", number)
+    }
+}
+
+/// Chunks of the version of their page that was current at `timestamp`
+/// (Unix seconds): first crawled by then and not yet superseded. Chunks
+/// indexed before versions were tracked fall back to `crawled_at`.
+pub fn as_of(timestamp: i64) -> Filter {
+    let until = Range {
+        lte: Some(timestamp as f64),
+        ..Default::default()
+    };
+    let after = Range {
+        gt: Some(timestamp as f64),
+        ..Default::default()
+    };
+    Filter::must([
+        Filter::should([
+            Condition::range("version_since", until),
+            Filter::must([
+                Condition::is_empty("version_since"),
+                Condition::range("crawled_at", until),
+            ])
+            .into(),
+        ])
+        .into(),
+        Filter::should([current(), Condition::range("superseded_at", after)]).into(),
+    ])
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PageVersion {
+    pub version: i64,
+    /// Content hash of the version
+    pub page_version: String,
+    /// When the content was first crawled
+    pub since: i64,
+    /// When a re-crawl found newer content; `null` for the current version
+    pub superseded_at: Option<i64>,
+}
+
+impl PageVersion {
+    pub fn from_payload(payload: &serde_json::Value) -> Option<Self> {
+        let integer = |key: &str| payload.get(key).and_then(|value| value.as_i64());
+        Some(Self {
+            version: integer("version_number").unwrap_or(1),
+            page_version: payload.get("page_version")?.as_str()?.to_string(),
+            since: integer("version_since")
+                .or_else(|| integer("crawled_at"))
+                .unwrap_or(0),
+            superseded_at: integer("superseded_at"),
+        })
+    }
+}
+
+/// One entry per version, newest first, from the payloads of a page's chunks
+pub fn history(chunks: impl IntoIterator<Item = PageVersion>) -> Vec<PageVersion> {
+    let versions = chunks
+        .into_iter()
+        .map(|version| (version.version, version))
+        .collect::<BTreeMap<_, _>>();
+    versions.into_values().rev().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_each_version_once_newest_first() {
+        let chunks = [
+            serde_json::json!({ "page_version": "a", "crawled_at": 100, "superseded_at": 200 }),
+            serde_json::json!({ "page_version": "a", "chunk_index": 1, "crawled_at": 100, "superseded_at": 200 }),
+            serde_json::json!({ "page_version": "b", "version_number": 2, "version_since": 200, "crawled_at": 300 }),
+            serde_json::json!({ "version_number": 3 }),
+        ];
+        let versions = history(chunks.iter().filter_map(PageVersion::from_payload));
+
+        assert_eq!(
+            versions,
+            [
+                PageVersion {
+                    version: 2,
+                    page_version: "b".to_string(),
+                    since: 200,
+                    superseded_at: None,
+                },
+                PageVersion {
+                    version: 1,
+                    page_version: "a".to_string(),
+                    since: 100,
+                    superseded_at: Some(200),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,355 @@
+//! Command line client for the spider and search APIs
+
+use anyhow::{Result, bail};
+use clap::{Parser, Subcommand, ValueEnum};
+use reqwest::{Client, RequestBuilder, Response};
+use serde_json::{Value, json};
+use std::{io::Write, time::Duration};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Parser)]
+#[command(
+    name = "crawlctl",
+    version,
+    about = "Drive the crawler and search APIs"
+)]
+struct Cli {
+    /// Base URL of the search API
+    #[arg(
+        long,
+        env = "CRAWLCTL_API_URL",
+        default_value = "http://localhost:8000",
+        global = true
+    )]
+    api_url: String,
+    /// Base URL of the spider API
+    #[arg(
+        long,
+        env = "CRAWLCTL_SPIDER_URL",
+        default_value = "http://localhost:8001",
+        global = true
+    )]
+    spider_url: String,
+    #[arg(long, value_enum, default_value_t = Output::Table, global = true)]
+    output: Output,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Output {
+    Table,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Queue a crawl job
+    Crawl {
+        url: String,
+        #[arg(long, default_value_t = 100)]
+        max_pages: usize,
+        #[arg(long)]
+        max_depth: Option<usize>,
+        /// Follow links to other domains
+        #[arg(long)]
+        all_domains: bool,
+        /// Render every page in the headless browser
+        #[arg(long)]
+        browser: bool,
+        /// Only fetch sitemap URLs modified since they were last indexed
+        #[arg(long)]
+        incremental: bool,
+        /// Block until the job finishes
+        #[arg(long)]
+        wait: bool,
+    },
+    /// Search the index
+    Search {
+        query: String,
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+    },
+    /// Stream the first 1000 results for a query as newline-delimited JSON
+    Export { query: String },
+    /// Show a crawl job, or the crawler totals when no job is given
+    Status { job: Option<String> },
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    if let Err(error) = run(cli).await {
+        eprintln!("error: {error}");
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let http = Client::builder()
+        .user_agent(concat!("crawlctl/", env!("CARGO_PKG_VERSION")))
+        .timeout(Duration::from_secs(30))
+        .build()?;
+    let api_url = cli.api_url.trim_end_matches('/');
+    let spider_url = cli.spider_url.trim_end_matches('/');
+
+    match cli.command {
+        Command::Crawl {
+            url,
+            max_pages,
+            max_depth,
+            all_domains,
+            browser,
+            incremental,
+            wait,
+        } => {
+            let mut body = json!({
+                "url": url,
+                "max_pages": max_pages,
+                "same_domain": !all_domains,
+                "use_browser": browser,
+                "incremental": incremental,
+            });
+            if let Some(max_depth) = max_depth {
+                body["max_depth"] = json!(max_depth);
+            }
+            let queued = send(http.post(format!("{spider_url}/crawl")).json(&body)).await?;
+            if !wait {
+                return print(cli.output, &queued, &["job_id", "message"]);
+            }
+            let job_id = queued["job_id"].as_str().unwrap_or_default();
+            let url = format!("{spider_url}/crawl/{job_id}");
+            loop {
+                let job = send(http.get(&url)).await?;
+                if matches!(
+                    job["status"].as_str(),
+                    Some("completed" | "truncated" | "failed")
+                ) {
+                    return print_job(cli.output, &job);
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+        Command::Search {
+            query,
+            limit,
+            offset,
+        } => {
+            let result = search(&http, api_url, &query, limit, offset).await?;
+            match cli.output {
+                Output::Json => print(cli.output, &result, &[]),
+                Output::Table => {
+                    let rows = result["results"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .map(|hit| {
+                            vec![
+                                format!("{:.3}", hit["score"].as_f64().unwrap_or_default()),
+                                cell(&hit["page_title"]),
+                                cell(&hit["source_url"]),
+                            ]
+                        })
+                        .collect::<Vec<_>>();
+                    println!("{}", table(&["SCORE", "TITLE", "URL"], &rows));
+                    println!("{} of {} results", rows.len(), result["total"]);
+                    Ok(())
+                }
+            }
+        }
+        Command::Export { query } => {
+            let mut response = http
+                .get(format!("{api_url}/search"))
+                .query(&[
+                    ("query", query.as_str()),
+                    ("format", "ndjson"),
+                    ("limit", "1000"),
+                ])
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                return send_failed(response).await;
+            }
+            let mut stdout = std::io::stdout().lock();
+            while let Some(chunk) = response.chunk().await? {
+                stdout.write_all(&chunk)?;
+            }
+            Ok(())
+        }
+        Command::Status { job: Some(job) } => {
+            let job = send(http.get(format!("{spider_url}/crawl/{job}"))).await?;
+            print_job(cli.output, &job)
+        }
+        Command::Status { job: None } => {
+            let status = send(http.get(format!("{spider_url}/status"))).await?;
+            print(
+                cli.output,
+                &status,
+                &[
+                    "queue_size",
+                    "pages_crawled",
+                    "pages_indexed",
+                    "pages_failed",
+                    "pages_skipped_robots",
+                    "pages_skipped_depth",
+                    "pages_skipped_low_quality",
+                    "retries_attempted",
+                ],
+            )
+        }
+    }
+}
+
+async fn search(
+    http: &Client,
+    api_url: &str,
+    query: &str,
+    limit: usize,
+    offset: usize,
+) -> Result<Value> {
+    send(http.get(format!("{api_url}/search")).query(&[
+        ("query", query),
+        ("limit", &limit.to_string()),
+        ("offset", &offset.to_string()),
+    ]))
+    .await
+}
+
+/// Send a request and decode the JSON body, turning problem+json responses into errors
+async fn send(request: RequestBuilder) -> Result<Value> {
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return send_failed(response).await;
+    }
+    Ok(response.json::<Value>().await.unwrap_or(Value::Null))
+}
+
+/// The error of a failed response, with the detail of its problem+json body
+async fn send_failed<T>(response: Response) -> Result<T> {
+    let status = response.status();
+    let body = response.json::<Value>().await.unwrap_or(Value::Null);
+    match (body["code"].as_str(), body["detail"].as_str()) {
+        (Some(code), Some(detail)) => bail!("{status}: {detail} ({code})"),
+        _ => bail!("{status}"),
+    }
+}
+
+fn print_job(output: Output, job: &Value) -> Result<()> {
+    if output == Output::Json {
+        return print(output, job, &[]);
+    }
+    print(output, job, &["id", "url", "status"])?;
+    print(
+        output,
+        &job["progress"],
+        &[
+            "pages_crawled",
+            "pages_indexed",
+            "pages_failed",
+            "pages_skipped_robots",
+            "pages_skipped_depth",
+            "pages_skipped_low_quality",
+            "pages_skipped_off_topic",
+            "pages_skipped_keywords",
+            "pages_skipped_recent",
+            "pages_blocked",
+            "pages_unchanged",
+            "frontier_size",
+        ],
+    )
+}
+
+/// Print `fields` of an object as a two-column table, or the whole value as JSON
+fn print(output: Output, value: &Value, fields: &[&str]) -> Result<()> {
+    match output {
+        Output::Json => println!("{}", serde_json::to_string_pretty(value)?),
+        Output::Table => {
+            let rows = fields
+                .iter()
+                .map(|field| vec![field.to_string(), cell(&value[field])])
+                .collect::<Vec<_>>();
+            println!("{}", table(&[], &rows));
+        }
+    }
+    Ok(())
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        Value::Null => "-".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Left-aligned columns separated by two spaces; the last column is not padded
+fn table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let header = (!headers.is_empty()).then(|| headers.iter().map(|h| h.to_string()).collect());
+    let lines = header.iter().chain(rows).collect::<Vec<&Vec<String>>>();
+    let columns = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+    let widths = (0..columns)
+        .map(|column| {
+            lines
+                .iter()
+                .filter_map(|line| line.get(column))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+
+    lines
+        .iter()
+        .map(|line| {
+            line.iter()
+                .enumerate()
+                .map(|(column, cell)| {
+                    if column + 1 == line.len() {
+                        cell.clone()
+                    } else {
+                        format!("{cell:<width$}", width = widths[column])
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("  ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_table_columns() {
+        let rows = vec![
+            vec![
+                "0.912".to_string(),
+                "Rust".to_string(),
+                "https://rust-lang.org".to_string(),
+            ],
+            vec![
+                "0.5".to_string(),
+                "Tokio docs".to_string(),
+                "https://tokio.rs".to_string(),
+            ],
+        ];
+        assert_eq!(
+            table(&["SCORE", "TITLE", "URL"], &rows),
+            "SCORE  TITLE       URL\n\
+             0.912  Rust        https://rust-lang.org\n\
+             0.5    Tokio docs  https://tokio.rs"
+        );
+    }
+
+    #[test]
+    fn renders_missing_values_as_dash() {
+        assert_eq!(cell(&Value::Null), "-");
+        assert_eq!(cell(&json!("ok")), "ok");
+        assert_eq!(cell(&json!(3)), "3");
+    }
+}
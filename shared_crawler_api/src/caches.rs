@@ -0,0 +1,112 @@
+//! In-process caches for `/admin/caches`
+//!
+//! Both services list their caches with entry counts and hit rates under
+//! `GET /admin/caches`, and `POST /admin/caches/flush` empties them, all or
+//! those named in `caches`, e.g. after a reindex. Hit rates count from the
+//! start of the service or the cache's last flush.
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::problem::Problem;
+
+#[derive(Debug, Default)]
+pub struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CacheCounters {
+    pub fn record(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self, entries: usize) -> CacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        CacheStats {
+            entries,
+            hits,
+            misses,
+            hit_rate: (hits + misses > 0).then(|| hits as f64 / (hits + misses) as f64),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// `null` until the cache was first looked up
+    pub hit_rate: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FlushRequest {
+    /// Caches to flush; all of them when omitted
+    #[serde(default)]
+    pub caches: Option<Vec<String>>,
+}
+
+impl FlushRequest {
+    /// The caches among `known` to flush
+    pub fn select(&self, known: &[&'static str]) -> Result<Vec<&'static str>, Problem> {
+        let Some(caches) = &self.caches else {
+            return Ok(known.to_vec());
+        };
+        caches
+            .iter()
+            .map(|name| {
+                known
+                    .iter()
+                    .find(|known| **known == name.as_str())
+                    .copied()
+                    .ok_or_else(|| {
+                        Problem::bad_request(
+                            "unknown_cache",
+                            format!(
+                                "unknown cache {name:?}; expected one of {}",
+                                known.join(", ")
+                            ),
+                        )
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_hit_rate_and_selects_caches() {
+        let counters = CacheCounters::default();
+        assert_eq!(counters.stats(0).hit_rate, None);
+        counters.record(true);
+        counters.record(true);
+        counters.record(false);
+        counters.record(true);
+        let stats = counters.stats(3);
+        assert_eq!((stats.entries, stats.hits, stats.misses), (3, 3, 1));
+        assert_eq!(stats.hit_rate, Some(0.75));
+
+        let known = ["robots", "fetched_pages"];
+        assert_eq!(FlushRequest::default().select(&known).unwrap(), known);
+        let request = FlushRequest {
+            caches: Some(vec!["robots".to_string()]),
+        };
+        assert_eq!(request.select(&known).unwrap(), ["robots"]);
+        let request = FlushRequest {
+            caches: Some(vec!["dns".to_string()]),
+        };
+        assert_eq!(request.select(&known).unwrap_err().code, "unknown_cache");
+    }
+}
@@ -0,0 +1,79 @@
+//! CSV for spreadsheet exports
+//!
+//! Search results and the spider's reports are available as CSV with
+//! `format=csv` or, when no format is given, `Accept: text/csv`. Rows end in
+//! CRLF and fields are quoted when they contain a comma, quote or line
+//! break. Text starting with `=`, `+`, `-` or `@` gets a leading `'` so
+//! spreadsheets do not evaluate crawled content as a formula.
+
+pub const CONTENT_TYPE: &str = "text/csv; charset=utf-8";
+
+/// Whether an `Accept` header asks for CSV
+pub fn accepts(accept: Option<&str>) -> bool {
+    accept.is_some_and(|accept| {
+        accept
+            .split(',')
+            .any(|range| range.split(';').next().unwrap_or_default().trim() == "text/csv")
+    })
+}
+
+pub struct CsvWriter {
+    out: String,
+}
+
+impl CsvWriter {
+    pub fn new(header: &[&str]) -> Self {
+        let mut writer = Self { out: String::new() };
+        writer.row(header);
+        writer
+    }
+
+    pub fn row<S: AsRef<str>>(&mut self, fields: impl IntoIterator<Item = S>) {
+        for (index, field) in fields.into_iter().enumerate() {
+            if index > 0 {
+                self.out.push(',');
+            }
+            self.field(field.as_ref());
+        }
+        self.out.push_str("\r\n");
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+
+    fn field(&mut self, field: &str) {
+        let formula = field.starts_with(['=', '+', '-', '@']) && field.parse::<f64>().is_err();
+        let quoted = formula || field.contains([',', '"', '\r', '\n']);
+        if quoted {
+            self.out.push('"');
+        }
+        if formula {
+            self.out.push('\'');
+        }
+        self.out.push_str(&field.replace('"', "\"\""));
+        if quoted {
+            self.out.push('"');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_and_defuses_fields() {
+        let mut csv = CsvWriter::new(&["url", "title"]);
+        csv.row(["https://example.com/", "Say \"hi\", then\nleave"]);
+        csv.row(["-1.5", "=HYPERLINK(\"x\")"]);
+        assert_eq!(
+            csv.finish(),
+            "url,title\r\n\
+             https://example.com/,\"Say \"\"hi\"\", then\nleave\"\r\n\
+             -1.5,\"'=HYPERLINK(\"\"x\"\")\"\r\n"
+        );
+        assert!(accepts(Some("text/html, text/csv;q=0.9")));
+        assert!(!accepts(Some("application/json")));
+    }
+}
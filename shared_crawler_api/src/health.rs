@@ -0,0 +1,75 @@
+use serde::Serialize;
+use std::{collections::BTreeMap, time::Duration};
+
+/// Result of probing one backend dependency
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentHealth {
+    pub status: &'static str,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ComponentHealth {
+    pub fn from_result<E: std::fmt::Display>(result: Result<(), E>, latency: Duration) -> Self {
+        let latency_ms = latency.as_millis() as u64;
+        match result {
+            Ok(()) => Self {
+                status: "ok",
+                latency_ms,
+                error: None,
+            },
+            Err(error) => Self {
+                status: "unavailable",
+                latency_ms,
+                error: Some(error.to_string()),
+            },
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Readiness report returned by `/health/ready`
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessReport {
+    pub status: &'static str,
+    pub components: BTreeMap<&'static str, ComponentHealth>,
+}
+
+impl ReadinessReport {
+    pub fn new(components: impl IntoIterator<Item = (&'static str, ComponentHealth)>) -> Self {
+        let components = components.into_iter().collect::<BTreeMap<_, _>>();
+        let status = if components.values().all(ComponentHealth::is_ok) {
+            "ok"
+        } else {
+            "unavailable"
+        };
+        Self { status, components }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.status == "ok"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_is_unavailable_when_any_component_fails() {
+        let ok = ComponentHealth::from_result(Ok::<_, String>(()), Duration::from_millis(3));
+        let down = ComponentHealth::from_result(Err("connection refused"), Duration::ZERO);
+
+        assert!(ReadinessReport::new([("qdrant", ok.clone())]).is_ready());
+        let report = ReadinessReport::new([("qdrant", ok), ("tei", down)]);
+        assert!(!report.is_ready());
+        assert_eq!(
+            report.components["tei"].error.as_deref(),
+            Some("connection refused")
+        );
+    }
+}
@@ -1,9 +1,27 @@
 use serde::{Deserialize, Serialize};
 
+pub mod caches;
+pub mod csv;
+pub mod health;
+pub mod listen;
+pub mod problem;
+pub mod request_id;
+pub mod tenant;
+pub mod text;
 pub mod util_fns;
 
 pub const QDRANT_COLLECTION_NAME: &str = "web_pages";
 
+/// Size of the dense vectors, set with `EMBEDDING_DIMENSIONS` when the model
+/// behind `TEI_URL` is not the default 384-dimensional one
+pub fn embedding_dimensions() -> usize {
+    std::env::var("EMBEDDING_DIMENSIONS")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|&dimensions| dimensions > 0)
+        .unwrap_or(384)
+}
+
 /// Shared data structure for web page data
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebPageChunk {
@@ -11,6 +29,13 @@ pub struct WebPageChunk {
     pub chunk_content: String,
     #[serde(default)]
     pub chunk_heading: Option<String>,
+    /// Enclosing headings from the outermost down, ending with `chunk_heading`
+    #[serde(default)]
+    pub heading_path: Vec<String>,
+    /// Every heading of the page, in document order; indexed as its own
+    /// BM25 field
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headings: Vec<String>,
 
     #[serde(default)]
     pub source_url: String,
@@ -30,6 +55,30 @@ pub struct WebPageChunk {
     pub score: f64,
     #[serde(default)]
     pub crawled_at: i64,
+    /// When the page says it was published, as Unix seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published_at: Option<i64>,
+    /// Who the page says wrote it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// Icon of the page's site, for result cards
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub favicon_url: Option<String>,
+    /// The page's `og:image`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    /// Primary language subtag the page declares, e.g. `en`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Trail from the site's home page down to the page
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub breadcrumb: Vec<String>,
+    /// Flagged as spam or adult content; left out of `/search` by default
+    #[serde(default, rename = "unsafe")]
+    pub unsafe_content: bool,
+    /// Label of the crawl job that indexed the page, e.g. `docs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
 }
 
 fn default_title() -> String {
@@ -58,6 +107,8 @@ impl WebPageChunk {
         Self {
             chunk_content,
             chunk_heading,
+            heading_path: Vec::new(),
+            headings: Vec::new(),
             source_url,
             page_title,
             description,
@@ -66,6 +117,59 @@ impl WebPageChunk {
             paid,
             score,
             crawled_at,
+            published_at: None,
+            author: None,
+            favicon_url: None,
+            image_url: None,
+            language: None,
+            breadcrumb: Vec::new(),
+            unsafe_content: false,
+            collection: None,
+        }
+    }
+
+    pub fn with_heading_path(mut self, heading_path: Vec<String>) -> Self {
+        self.heading_path = heading_path;
+        self
+    }
+
+    pub fn with_headings(mut self, headings: Vec<String>) -> Self {
+        self.headings = headings;
+        self
+    }
+
+    pub fn with_published_at(mut self, published_at: Option<i64>) -> Self {
+        self.published_at = published_at;
+        self
+    }
+
+    pub fn with_author(mut self, author: Option<String>) -> Self {
+        self.author = author;
+        self
+    }
+
+    pub fn with_language(mut self, language: Option<String>) -> Self {
+        self.language = language;
+        self
+    }
+
+    pub fn with_breadcrumb(mut self, breadcrumb: Vec<String>) -> Self {
+        self.breadcrumb = breadcrumb;
+        self
+    }
+
+    pub fn with_images(mut self, favicon_url: Option<String>, image_url: Option<String>) -> Self {
+        self.favicon_url = favicon_url;
+        self.image_url = image_url;
+        self
+    }
+
+    /// Headings joined as `H1 › H2 › H3`, falling back to `chunk_heading`
+    pub fn heading_context(&self) -> String {
+        if self.heading_path.is_empty() {
+            self.chunk_heading.clone().unwrap_or_default()
+        } else {
+            self.heading_path.join(" › ")
         }
     }
 
@@ -0,0 +1,159 @@
+//! Where a service accepts connections
+//!
+//! `<PREFIX>_HOST` and `<PREFIX>_PORT` set the address. With
+//! `<PREFIX>_TLS_CERT` and `<PREFIX>_TLS_KEY` pointing at PEM files, the
+//! certificate chain and its private key, the service terminates TLS itself,
+//! so a small deployment needs no reverse proxy just for HTTPS. Setting only
+//! one of the two is an error rather than a silent fallback to plain HTTP.
+//!
+//! `<PREFIX>_BIND=unix:/run/crawler/api.sock` listens on a Unix domain socket
+//! instead, for a reverse proxy on the same host that should not go through
+//! TCP. A socket file left behind by an earlier run is replaced. TLS is not
+//! offered on a socket; the proxy in front terminates it.
+
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use std::{env, fmt, io, path::PathBuf, sync::Arc};
+
+pub enum Listen {
+    Http(String),
+    Https(String, Box<rustls::ServerConfig>),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl Listen {
+    /// Read `<prefix>_BIND`, or `<prefix>_HOST`, `<prefix>_PORT` and the TLS
+    /// files
+    pub fn from_env(prefix: &str, default_port: u16) -> io::Result<Self> {
+        let read = |name: &str| {
+            env::var(format!("{prefix}_{name}"))
+                .ok()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        if let Some(bind) = read("BIND") {
+            let Some(path) = bind.strip_prefix("unix:").filter(|path| !path.is_empty()) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{prefix}_BIND must be unix:<socket path>"),
+                ));
+            };
+            if read("TLS_CERT").is_some() || read("TLS_KEY").is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{prefix}_BIND cannot be combined with {prefix}_TLS_CERT and {prefix}_TLS_KEY"),
+                ));
+            }
+            return unix_socket(PathBuf::from(path));
+        }
+        let address = format!(
+            "{}:{}",
+            read("HOST").unwrap_or_else(|| "127.0.0.1".to_string()),
+            read("PORT").unwrap_or_else(|| default_port.to_string())
+        );
+        match (read("TLS_CERT"), read("TLS_KEY")) {
+            (None, None) => Ok(Self::Http(address)),
+            (Some(cert), Some(key)) => Ok(Self::Https(address, Box::new(tls_config(&cert, &key)?))),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{prefix}_TLS_CERT and {prefix}_TLS_KEY must be set together"),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Listen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http(address) => write!(f, "http://{address}"),
+            Self::Https(address, _) => write!(f, "https://{address}"),
+            #[cfg(unix)]
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn unix_socket(path: PathBuf) -> io::Result<Listen> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(&path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(&path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ))
+        }
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => return Err(error),
+    }
+    Ok(Listen::Unix(path))
+}
+
+#[cfg(not(unix))]
+fn unix_socket(_: PathBuf) -> io::Result<Listen> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Unix domain sockets are not supported on this platform",
+    ))
+}
+
+fn tls_config(cert: &str, key: &str) -> io::Result<rustls::ServerConfig> {
+    let invalid = |path: &str, error: &dyn fmt::Display| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{path}: {error}"))
+    };
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|error| invalid(cert, &error))?;
+    if chain.is_empty() {
+        return Err(invalid(cert, &"no certificate found"));
+    }
+    let private_key = PrivateKeyDer::from_pem_file(key).map_err(|error| invalid(key, &error))?;
+    rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_no_client_auth()
+        .with_single_cert(chain, private_key)
+        .map_err(|error| invalid(cert, &error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requires_both_tls_files() {
+        env::set_var("LISTEN_TEST_PORT", "9000");
+        let listen = Listen::from_env("LISTEN_TEST", 8000).unwrap();
+        assert_eq!(listen.to_string(), "http://127.0.0.1:9000");
+
+        env::set_var("LISTEN_TEST_TLS_CERT", "/etc/crawler/cert.pem");
+        let error = Listen::from_env("LISTEN_TEST", 8000).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        env::set_var("LISTEN_TEST_TLS_KEY", "/nonexistent/key.pem");
+        let error = Listen::from_env("LISTEN_TEST", 8000).err().unwrap();
+        assert!(
+            error.to_string().contains("/etc/crawler/cert.pem"),
+            "{error}"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn binds_unix_sockets() {
+        let path = env::temp_dir().join(format!("listen-test-{}.sock", std::process::id()));
+        env::set_var("LISTEN_UDS_BIND", format!("unix:{}", path.display()));
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let listen = Listen::from_env("LISTEN_UDS", 8000).unwrap();
+        assert_eq!(listen.to_string(), format!("unix:{}", path.display()));
+        assert!(!path.exists());
+
+        env::set_var("LISTEN_UDS_BIND", "127.0.0.1:8000");
+        assert!(Listen::from_env("LISTEN_UDS", 8000).is_err());
+        env::set_var("LISTEN_UDS_BIND", "unix:/tmp/api.sock");
+        env::set_var("LISTEN_UDS_TLS_CERT", "/etc/crawler/cert.pem");
+        assert!(Listen::from_env("LISTEN_UDS", 8000).is_err());
+    }
+}
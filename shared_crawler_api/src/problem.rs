@@ -0,0 +1,78 @@
+use serde::Serialize;
+
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// RFC 7807 problem details with a stable machine-readable `code`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: &'static str,
+    pub status: u16,
+    pub detail: String,
+    pub code: &'static str,
+    /// `X-Request-Id` of the request that failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl Problem {
+    pub fn new(status: u16, code: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            problem_type: format!("urn:my-crawler:problem:{code}"),
+            title: title(status),
+            status,
+            detail: detail.into(),
+            code,
+            request_id: crate::request_id::current(),
+        }
+    }
+
+    pub fn bad_request(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(400, code, detail)
+    }
+
+    pub fn not_found(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(404, code, detail)
+    }
+
+    pub fn internal(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(500, code, detail)
+    }
+}
+
+fn title(status: u16) -> &'static str {
+    match status {
+        400 => "Bad Request",
+        404 => "Not Found",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ if status >= 500 => "Internal Server Error",
+        _ => "Request Failed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_problem_details() {
+        let problem = Problem::bad_request("invalid_url", "url must be an HTTP(S) URL");
+
+        assert_eq!(
+            serde_json::to_value(problem).unwrap(),
+            serde_json::json!({
+                "type": "urn:my-crawler:problem:invalid_url",
+                "title": "Bad Request",
+                "status": 400,
+                "detail": "url must be an HTTP(S) URL",
+                "code": "invalid_url"
+            })
+        );
+    }
+}
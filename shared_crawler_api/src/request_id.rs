@@ -0,0 +1,79 @@
+//! Request IDs for matching one request's logs across services
+//!
+//! Both services answer every request with an `X-Request-Id` header: the
+//! caller's ID if it sent a usable one, otherwise a new one. Problem
+//! responses carry it as `request_id`, log lines written while handling the
+//! request include it, and calls made on its behalf to TEI, Qdrant's REST
+//! API or the other service forward it. A crawl job keeps the ID of the
+//! request that queued it for its runner's logs and calls.
+
+use std::future::Future;
+
+pub const HEADER: &str = "X-Request-Id";
+
+/// Longest caller-supplied ID that is kept
+const MAX_CHARS: usize = 64;
+
+tokio::task_local! {
+    static CURRENT: String;
+}
+
+/// The caller's `X-Request-Id` if it is 1 to 64 letters, digits, `-`, `_`
+/// or `.`, otherwise a new UUID
+pub fn from_header(value: Option<&str>) -> String {
+    value
+        .map(str::trim)
+        .filter(|value| {
+            !value.is_empty()
+                && value.len() <= MAX_CHARS
+                && value
+                    .bytes()
+                    .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.'))
+        })
+        .map_or_else(|| uuid::Uuid::new_v4().to_string(), str::to_string)
+}
+
+/// Run `future` as part of the request `id`
+pub async fn scope<F: Future>(id: String, future: F) -> F::Output {
+    CURRENT.scope(id, future).await
+}
+
+/// `future` as part of the request being handled now, for work it spawns
+pub fn inherit<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let id = current();
+    async move {
+        match id {
+            Some(id) => scope(id, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// ID of the request being handled, if any
+pub fn current() -> Option<String> {
+    CURRENT.try_with(Clone::clone).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_well_formed_caller_ids() {
+        assert_eq!(from_header(Some(" req-42.a_b ")), "req-42.a_b");
+        for invalid in [None, Some(""), Some("a b"), Some("x\ny")] {
+            assert_eq!(from_header(invalid).len(), 36);
+        }
+        assert_eq!(from_header(Some(&"a".repeat(65))).len(), 36);
+        assert_eq!(current(), None);
+    }
+
+    #[tokio::test]
+    async fn spawned_work_keeps_the_request_id() {
+        let spawned = scope("req-1".to_string(), async {
+            tokio::spawn(inherit(async { current() })).await
+        })
+        .await;
+        assert_eq!(spawned.unwrap().as_deref(), Some("req-1"));
+    }
+}
@@ -0,0 +1,74 @@
+//! Isolated corpora per tenant
+//!
+//! A crawl request's `tenant`, or the API's `X-Tenant` header, selects the
+//! Qdrant collection pages are indexed in and searched: `web_pages` without
+//! a tenant, `web_pages__<tenant>` with one. Tenants never see each other's
+//! pages, versions or duplicate chunks, so one deployment can serve several
+//! teams.
+//!
+//! Within one tenant, a crawl job's `collection` labels its pages, e.g.
+//! `docs` or `competitors`, and searches can be limited to one label. Pages
+//! keep the label of the job that last indexed them.
+
+use crate::{problem::Problem, QDRANT_COLLECTION_NAME};
+
+/// Header the API reads the tenant from
+pub const HEADER: &str = "X-Tenant";
+
+/// Longest tenant or collection name
+const MAX_NAME_CHARS: usize = 48;
+
+/// The collection of `tenant`, or of the default corpus without one
+pub fn collection_name(tenant: Option<&str>) -> Result<String, Problem> {
+    let Some(tenant) = tenant else {
+        return Ok(QDRANT_COLLECTION_NAME.to_string());
+    };
+    check_name("tenant", "invalid_tenant", tenant)?;
+    Ok(format!("{QDRANT_COLLECTION_NAME}__{tenant}"))
+}
+
+/// Check a crawl job's or search's `collection` label
+pub fn validate_collection(collection: &str) -> Result<(), Problem> {
+    check_name("collection", "invalid_collection", collection)
+}
+
+fn check_name(field: &str, code: &'static str, name: &str) -> Result<(), Problem> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_CHARS
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-')
+        && !name.starts_with('-');
+    if valid {
+        Ok(())
+    } else {
+        Err(Problem::bad_request(
+            code,
+            format!(
+                "{field} must be 1 to {MAX_NAME_CHARS} lowercase letters, digits or hyphens, not starting with a hyphen"
+            ),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_tenants_to_their_own_collection() {
+        assert_eq!(collection_name(None).unwrap(), "web_pages");
+        assert_eq!(
+            collection_name(Some("team-42")).unwrap(),
+            "web_pages__team-42"
+        );
+        for tenant in ["", "Team", "a_b", "-a", "../x", &"a".repeat(49)] {
+            assert!(collection_name(Some(tenant)).is_err(), "{tenant}");
+        }
+        assert!(validate_collection("docs").is_ok());
+        assert_eq!(
+            validate_collection("Docs").unwrap_err().code,
+            "invalid_collection"
+        );
+    }
+}
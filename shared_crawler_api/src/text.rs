@@ -0,0 +1,332 @@
+//! Text normalization shared by indexing and search
+//!
+//! The spider runs chunk titles and bodies through `lexical` before building
+//! their BM25 vectors, and the API does the same with queries, so both sides
+//! produce the same terms. Text is always NFC-normalized, HTML entities are
+//! decoded, and it is lowercased with whitespace collapsed. Stopword removal
+//! and stemming are optional (`LEXICAL_STOPWORDS`, `LEXICAL_STEMMING`, for
+//! `LEXICAL_LANGUAGE` `en` or `de`); the spider and the API must agree on
+//! them, and changing them needs a re-crawl. A page that declares `en` or
+//! `de` is normalized in its own language, and so is a query in which
+//! `detect_language` finds one, so their terms still meet.
+
+use icu_normalizer::ComposingNormalizerBorrowed;
+use std::env;
+
+const ENGLISH_STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "has", "have", "in", "is",
+    "it", "its", "of", "on", "or", "that", "the", "this", "to", "was", "were", "will", "with",
+];
+
+const GERMAN_STOPWORDS: &[&str] = &[
+    "der", "die", "das", "den", "dem", "des", "ein", "eine", "einer", "eines", "einem", "einen",
+    "und", "oder", "ist", "sind", "war", "mit", "von", "zu", "zum", "zur", "im", "in", "auf",
+    "für", "an", "am", "bei", "aus", "als", "auch", "es", "sich", "nicht",
+];
+
+/// Words besides the stopwords that give away a query's language
+const ENGLISH_MARKERS: &[&str] = &[
+    "how", "what", "why", "when", "where", "which", "who", "does", "do", "can", "should", "not",
+];
+
+const GERMAN_MARKERS: &[&str] = &[
+    "wie", "warum", "wann", "wo", "welche", "welcher", "wer", "ich", "kann", "ohne", "über", "nach",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    English,
+    German,
+}
+
+impl Language {
+    /// The primary language subtag, as pages declare it
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::English => "en",
+            Self::German => "de",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code.trim().to_ascii_lowercase().as_str() {
+            "en" => Some(Self::English),
+            "de" => Some(Self::German),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LexicalOptions {
+    pub language: Language,
+    pub remove_stopwords: bool,
+    pub stem: bool,
+}
+
+impl LexicalOptions {
+    pub fn from_env() -> Self {
+        let enabled = |key: &str| {
+            env::var(key).is_ok_and(|value| matches!(value.trim(), "1" | "true" | "yes"))
+        };
+        Self {
+            language: env::var("LEXICAL_LANGUAGE")
+                .ok()
+                .and_then(|code| Language::from_code(&code))
+                .unwrap_or_default(),
+            remove_stopwords: enabled("LEXICAL_STOPWORDS"),
+            stem: enabled("LEXICAL_STEMMING"),
+        }
+    }
+
+    /// These options for text declared as `code`, a primary language subtag,
+    /// keeping the configured language for others
+    pub fn for_language(self, code: Option<&str>) -> Self {
+        Self {
+            language: code.and_then(Language::from_code).unwrap_or(self.language),
+            ..self
+        }
+    }
+}
+
+/// The language of a short text such as a query, by its stopwords, question
+/// words and umlauts, or `None` when they do not point one way
+pub fn detect_language(text: &str) -> Option<Language> {
+    let lower = clean(text).to_lowercase();
+    let terms = lower
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .collect::<Vec<_>>();
+    let hits = |lists: [&[&str]; 2]| {
+        terms
+            .iter()
+            .filter(|term| lists.iter().any(|list| list.contains(term)))
+            .count()
+    };
+    let english = hits([ENGLISH_STOPWORDS, ENGLISH_MARKERS]);
+    let german = hits([GERMAN_STOPWORDS, GERMAN_MARKERS])
+        + usize::from(lower.contains(['ä', 'ö', 'ü', 'ß']));
+    match english.cmp(&german) {
+        std::cmp::Ordering::Greater => Some(Language::English),
+        std::cmp::Ordering::Less => Some(Language::German),
+        std::cmp::Ordering::Equal => None,
+    }
+}
+
+/// NFC-normalized `text` with HTML entities decoded and whitespace collapsed
+pub fn clean(text: &str) -> String {
+    let decoded = decode_entities(text);
+    ComposingNormalizerBorrowed::new_nfc()
+        .normalize(&decoded)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `text` as indexed for lexical matching: cleaned, lowercased and, as
+/// configured, without stopwords and stemmed
+pub fn lexical(text: &str, options: &LexicalOptions) -> String {
+    let cleaned = clean(text).to_lowercase();
+    if !options.remove_stopwords && !options.stem {
+        return cleaned;
+    }
+    let stopwords = match options.language {
+        Language::English => ENGLISH_STOPWORDS,
+        Language::German => GERMAN_STOPWORDS,
+    };
+    cleaned
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .filter(|term| !options.remove_stopwords || !stopwords.contains(term))
+        .map(|term| {
+            if options.stem {
+                stem(term, options.language)
+            } else {
+                term.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Light suffix stripping of a lowercase word; words with digits or symbols
+/// are kept as they are
+pub fn stem(term: &str, language: Language) -> String {
+    let lower = term.to_lowercase();
+    if !lower.chars().all(char::is_alphabetic) {
+        return lower;
+    }
+    match language {
+        Language::English => stem_english(lower),
+        Language::German => stem_german(lower),
+    }
+}
+
+fn stem_english(word: String) -> String {
+    let len = word.chars().count();
+    if len > 4 {
+        if let Some(stem) = word.strip_suffix("ies") {
+            return format!("{stem}y");
+        }
+    }
+    if len > 5 {
+        if let Some(stem) = word.strip_suffix("ing") {
+            return stem.to_string();
+        }
+    }
+    if len > 4 {
+        if let Some(stem) = word.strip_suffix("ed") {
+            return stem.to_string();
+        }
+        if let Some(stem) = word.strip_suffix("es") {
+            if ["s", "x", "z", "ch", "sh"]
+                .iter()
+                .any(|suffix| stem.ends_with(suffix))
+            {
+                return stem.to_string();
+            }
+        }
+    }
+    if len > 3 && !word.ends_with("ss") && !word.ends_with("us") {
+        if let Some(stem) = word.strip_suffix('s') {
+            return stem.to_string();
+        }
+    }
+    word
+}
+
+fn stem_german(word: String) -> String {
+    let word = word
+        .replace('ä', "a")
+        .replace('ö', "o")
+        .replace('ü', "u")
+        .replace('ß', "ss");
+    let len = word.chars().count();
+    for suffix in ["ern", "em", "en", "er", "es"] {
+        if len > suffix.len() + 3 {
+            if let Some(stem) = word.strip_suffix(suffix) {
+                return stem.to_string();
+            }
+        }
+    }
+    for suffix in ['e', 's', 'n'] {
+        if len > 4 {
+            if let Some(stem) = word.strip_suffix(suffix) {
+                return stem.to_string();
+            }
+        }
+    }
+    word
+}
+
+/// Decode named entities common in page text and all numeric ones
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| entity_char(&rest[1..=end]).map(|c| (c, end + 2)));
+        match entity {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn entity_char(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "shy" => '\u{ad}',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "auml" => 'ä',
+        "ouml" => 'ö',
+        "uuml" => 'ü',
+        "Auml" => 'Ä',
+        "Ouml" => 'Ö',
+        "Uuml" => 'Ü',
+        "szlig" => 'ß',
+        "eacute" => 'é',
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cleans_entities_unicode_and_whitespace() {
+        assert_eq!(
+            clean("Caf\u{65}\u{301}&nbsp;&amp;  Bar&#8217;s\n&unknown; &#x41;"),
+            "Café & Bar’s &unknown; A"
+        );
+        assert_eq!(clean("AT&T"), "AT&T");
+    }
+
+    #[test]
+    fn applies_optional_stopwords_and_stemming() {
+        let plain = LexicalOptions::default();
+        assert_eq!(lexical("The  Crawlers", &plain), "the crawlers");
+        let english = LexicalOptions {
+            remove_stopwords: true,
+            stem: true,
+            ..plain
+        };
+        assert_eq!(
+            lexical("The crawlers are indexing libraries", &english),
+            "crawler index library"
+        );
+        let german = LexicalOptions {
+            language: Language::German,
+            ..english
+        };
+        assert_eq!(lexical("Die Häuser und Gärten", &german), "haus gart");
+        assert_eq!(english.for_language(Some("DE")), german);
+        assert_eq!(german.for_language(Some("fr")), german);
+    }
+
+    #[test]
+    fn detects_the_language_of_queries() {
+        assert_eq!(
+            detect_language("how to parse the json output"),
+            Some(Language::English)
+        );
+        assert_eq!(
+            detect_language("Wie installiere ich Rust unter Linux"),
+            Some(Language::German)
+        );
+        assert_eq!(detect_language("Größe ändern"), Some(Language::German));
+        assert_eq!(detect_language("tokio"), None);
+        assert_eq!(detect_language("rust in production"), None);
+    }
+}
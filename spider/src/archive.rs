@@ -0,0 +1,131 @@
+//! Raw HTML archive
+//!
+//! With `HTML_ARCHIVE=dir` or `HTML_ARCHIVE=s3`, every fetched page is stored
+//! gzip-compressed in a [`BlobStore`] under a key derived from its content,
+//! and the key is written to the page's chunks in the index (`archive_key`)
+//! so pages can be re-extracted without crawling them again.
+
+use anyhow::Result;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use sha2::{Digest, Sha256};
+use std::{
+    env,
+    io::{Read, Write},
+};
+
+use crate::blob_store::{env_dir, BlobStore};
+
+const DEFAULT_DIR: &str = "html-archive";
+
+pub struct HtmlArchive {
+    store: BlobStore,
+}
+
+impl HtmlArchive {
+    pub fn new(store: BlobStore) -> Self {
+        Self { store }
+    }
+
+    /// `None` unless `HTML_ARCHIVE` is `dir` (under `HTML_ARCHIVE_DIR`) or `s3`
+    pub fn from_env() -> Result<Option<Self>> {
+        let store = BlobStore::configured(
+            &env::var("HTML_ARCHIVE").unwrap_or_default(),
+            env_dir("HTML_ARCHIVE_DIR").unwrap_or_else(|| DEFAULT_DIR.into()),
+        )?;
+        Ok(store.map(Self::new))
+    }
+
+    /// Store a page and return its key; identical pages share one object
+    pub async fn store(&self, html: &str) -> Result<String> {
+        let key = archive_key(html);
+        if self.store.exists(&key).await? {
+            return Ok(key);
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(html.as_bytes())?;
+        self.store
+            .put(&key, encoder.finish()?, "text/html", Some("gzip"))
+            .await?;
+        Ok(key)
+    }
+
+    /// The archived HTML for a key, if present
+    pub async fn load(&self, key: &str) -> Result<Option<String>> {
+        let Some(compressed) = self.store.get(key).await? else {
+            return Ok(None);
+        };
+        let mut html = String::new();
+        GzDecoder::new(compressed.as_slice()).read_to_string(&mut html)?;
+        Ok(Some(html))
+    }
+}
+
+/// `html/<first two hex digits>/<sha256>.html.gz`
+pub fn archive_key(html: &str) -> String {
+    let digest = format!("{:x}", Sha256::digest(html.as_bytes()));
+    format!("html/{}/{digest}.html.gz", &digest[..2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::s3::S3Client;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use url::Url;
+
+    #[tokio::test]
+    async fn stores_pages_by_content() {
+        let root = env::temp_dir().join(format!("html-archive-test-{}", std::process::id()));
+        let archive = HtmlArchive::new(BlobStore::Directory(root.clone()));
+
+        let key = archive.store("<p>hello</p>").await.unwrap();
+        assert_eq!(key, archive_key("<p>hello</p>"));
+        assert!(key.starts_with("html/") && key.ends_with(".html.gz"));
+        assert_eq!(archive.store("<p>hello</p>").await.unwrap(), key);
+        assert_eq!(
+            archive.load(&key).await.unwrap().as_deref(),
+            Some("<p>hello</p>")
+        );
+        assert_eq!(archive.load("html/00/missing.html.gz").await.unwrap(), None);
+
+        tokio::fs::remove_dir_all(root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn loads_gzip_encoded_objects_from_s3() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"<p>archived</p>").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let body = compressed.clone();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request).await;
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(head.as_bytes()).await.unwrap();
+            stream.write_all(&body).await.unwrap();
+        });
+        let archive = HtmlArchive::new(BlobStore::S3(S3Client::new(
+            Url::parse(&format!("http://{address}")).unwrap(),
+            "archive".to_string(),
+            "us-east-1".to_string(),
+            "key".to_string(),
+            "secret".to_string(),
+        )));
+
+        assert_eq!(
+            archive
+                .load("html/ab/page.html.gz")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("<p>archived</p>")
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! Authors of crawled pages
+//!
+//! A page's `author` comes from the first of these that names someone: the
+//! `author` meta tag and its publisher-specific variants, the JSON-LD
+//! `author` of the page's article, an element marked `itemprop="author"`,
+//! then a `rel="author"` link. A page with several authors keeps the first.
+//! Profile URLs, as `article:author` often holds, are not names and are
+//! skipped, as is a leading "By".
+
+use scraper::{ElementRef, Html, Selector};
+use serde_json::Value;
+
+use crate::extractor::json_ld;
+
+/// Meta tags holding the author, by `name` or `property`, compared lowercase
+const AUTHOR_META: &[&str] = &[
+    "author",
+    "article:author",
+    "parsely-author",
+    "sailthru.author",
+    "dc.creator",
+    "dcterms.creator",
+    "byl",
+];
+
+const MAX_AUTHOR_CHARS: usize = 100;
+
+pub fn extract_author(document: &Html) -> Option<String> {
+    let meta = Selector::parse("meta[content]").unwrap();
+    let metas = document
+        .select(&meta)
+        .filter_map(|element| {
+            let value = element.value();
+            let key = value
+                .attr("name")
+                .or_else(|| value.attr("property"))?
+                .to_ascii_lowercase();
+            Some((key, value.attr("content")?))
+        })
+        .collect::<Vec<_>>();
+    AUTHOR_META
+        .iter()
+        .find_map(|wanted| {
+            metas
+                .iter()
+                .filter(|(key, _)| key == wanted)
+                .find_map(|(_, content)| author_name(content))
+        })
+        .or_else(|| json_ld(document).iter().find_map(find_author))
+        .or_else(|| microdata_author(document))
+        .or_else(|| {
+            let link = Selector::parse("a[rel~=author]").unwrap();
+            document
+                .select(&link)
+                .find_map(|element| author_name(&element_text(element)))
+        })
+}
+
+/// The first `author` in a JSON-LD document, searching `@graph` and nested
+/// objects
+fn find_author(value: &Value) -> Option<String> {
+    match value {
+        Value::Array(items) => items.iter().find_map(find_author),
+        Value::Object(object) => object
+            .get("author")
+            .and_then(person_name)
+            .or_else(|| object.values().find_map(find_author)),
+        _ => None,
+    }
+}
+
+/// `author` as a name, a `Person` with a `name`, or a list of either
+fn person_name(value: &Value) -> Option<String> {
+    match value {
+        Value::String(name) => author_name(name),
+        Value::Array(people) => people.iter().find_map(person_name),
+        Value::Object(person) => person.get("name")?.as_str().and_then(author_name),
+        _ => None,
+    }
+}
+
+fn microdata_author(document: &Html) -> Option<String> {
+    let author = Selector::parse("[itemprop~=author]").unwrap();
+    let name = Selector::parse("[itemprop~=name]").unwrap();
+    document.select(&author).find_map(|element| {
+        let text = match element.value().attr("content") {
+            Some(content) => content.to_string(),
+            None => element
+                .select(&name)
+                .next()
+                .map_or_else(|| element_text(element), element_text),
+        };
+        author_name(&text)
+    })
+}
+
+fn element_text(element: ElementRef) -> String {
+    element.text().collect::<Vec<_>>().join(" ")
+}
+
+/// `value` as a display name, unless it is empty, a URL or too long to be one
+fn author_name(value: &str) -> Option<String> {
+    let name = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    let name = match name.get(..3) {
+        Some(by) if by.eq_ignore_ascii_case("by ") => name[3..].to_string(),
+        _ => name,
+    };
+    let is_url = name.starts_with("http://") || name.starts_with("https://");
+    (!name.is_empty() && !is_url && name.chars().count() <= MAX_AUTHOR_CHARS).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_meta_json_ld_and_microdata_authors() {
+        let author = |html: &str| extract_author(&Html::parse_document(html));
+        assert_eq!(
+            author(
+                r#"<meta property="article:author" content="https://example.com/jane">
+                <meta name="author" content=" Jane   Doe ">"#
+            )
+            .as_deref(),
+            Some("Jane Doe")
+        );
+        assert_eq!(
+            author(
+                r#"<script type="application/ld+json">{"@graph": [{"@type": "WebSite"},
+                {"@type": "Article", "author": [{"@type": "Person", "name": "Ada Lovelace"},
+                {"name": "Charles Babbage"}]}]}</script>"#
+            )
+            .as_deref(),
+            Some("Ada Lovelace")
+        );
+        assert_eq!(
+            author(r#"<span itemprop="author"><span itemprop="name">Grace Hopper</span></span>"#)
+                .as_deref(),
+            Some("Grace Hopper")
+        );
+        assert_eq!(
+            author(r#"<a rel="author" href="/about">By Alan Turing</a>"#).as_deref(),
+            Some("Alan Turing")
+        );
+        assert_eq!(author(r#"<meta name="author" content="">"#), None);
+    }
+}
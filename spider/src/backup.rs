@@ -0,0 +1,166 @@
+//! Snapshot backup and restore of the index
+//!
+//! `POST /admin/backup` has Qdrant write a snapshot of the shared or a
+//! tenant's collection, and `POST /admin/restore` recovers the collection
+//! from one of its snapshots, replacing every point in it. Both run in the
+//! background: the response is an operation whose progress
+//! `GET /admin/backups/{id}` reports. One operation runs at a time, and a
+//! restore is refused while crawl jobs are running or queued, as their
+//! writes would be mixed with the restored points.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, sync::Mutex};
+use uuid::Uuid;
+
+/// Finished operations kept for `GET /admin/backups`
+const MAX_OPERATIONS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Snapshot {
+    pub name: String,
+    pub created_at: i64,
+    pub size_bytes: i64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct BackupRequest {
+    /// Back up this tenant's collection instead of the shared one
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestoreRequest {
+    /// Name of a snapshot listed by `GET /admin/backups`
+    pub snapshot: String,
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    Backup,
+    Restore,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Operation {
+    pub id: String,
+    pub kind: OperationKind,
+    pub collection: String,
+    pub status: OperationStatus,
+    /// The snapshot restored from, or the one a backup wrote once it is done
+    pub snapshot: Option<Snapshot>,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// Backup and restore operations, newest first
+#[derive(Default)]
+pub struct Operations {
+    entries: Mutex<VecDeque<Operation>>,
+}
+
+impl Operations {
+    /// Register a running operation, unless another one is still running
+    pub fn start(
+        &self,
+        kind: OperationKind,
+        collection: &str,
+        snapshot: Option<Snapshot>,
+        now: i64,
+    ) -> Option<Operation> {
+        let mut entries = self.entries.lock().unwrap();
+        if entries
+            .iter()
+            .any(|operation| operation.status == OperationStatus::Running)
+        {
+            return None;
+        }
+        let operation = Operation {
+            id: Uuid::new_v4().to_string(),
+            kind,
+            collection: collection.to_string(),
+            status: OperationStatus::Running,
+            snapshot,
+            started_at: now,
+            finished_at: None,
+            error: None,
+        };
+        entries.push_front(operation.clone());
+        entries.truncate(MAX_OPERATIONS);
+        Some(operation)
+    }
+
+    pub fn finish(&self, id: &str, result: Result<Snapshot, String>, now: i64) {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(operation) = entries.iter_mut().find(|operation| operation.id == id) else {
+            return;
+        };
+        operation.finished_at = Some(now);
+        match result {
+            Ok(snapshot) => {
+                operation.status = OperationStatus::Completed;
+                operation.snapshot = Some(snapshot);
+            }
+            Err(error) => {
+                operation.status = OperationStatus::Failed;
+                operation.error = Some(error);
+            }
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Operation> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().find(|operation| operation.id == id).cloned()
+    }
+
+    pub fn recent(&self) -> Vec<Operation> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_one_operation_at_a_time() {
+        let operations = Operations::default();
+        let backup = operations
+            .start(OperationKind::Backup, "web_pages", None, 10)
+            .unwrap();
+        assert!(operations
+            .start(OperationKind::Restore, "web_pages", None, 11)
+            .is_none());
+
+        let snapshot = Snapshot {
+            name: "web_pages-1.snapshot".to_string(),
+            created_at: 12,
+            size_bytes: 1024,
+        };
+        operations.finish(&backup.id, Ok(snapshot.clone()), 12);
+        let done = operations.get(&backup.id).unwrap();
+        assert_eq!(done.status, OperationStatus::Completed);
+        assert_eq!(done.snapshot, Some(snapshot));
+        assert_eq!(done.finished_at, Some(12));
+
+        let restore = operations
+            .start(OperationKind::Restore, "web_pages", None, 13)
+            .unwrap();
+        operations.finish(&restore.id, Err("not found".to_string()), 14);
+        let recent = operations.recent();
+        assert_eq!(recent[0].status, OperationStatus::Failed);
+        assert_eq!(recent[1].id, backup.id);
+    }
+}
@@ -0,0 +1,123 @@
+//! Blob storage for large artifacts
+//!
+//! A store is either a local directory or an S3-compatible bucket (see
+//! [`crate::s3`]). Each feature has its own `off`/`dir`/`s3` switch and key
+//! prefix, e.g. `HTML_ARCHIVE` and `html/` for the raw HTML archive, while
+//! all of them share the bucket configured by the `S3_*` variables.
+
+use anyhow::{bail, Result};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::s3::S3Client;
+
+pub enum BlobStore {
+    Directory(PathBuf),
+    S3(S3Client),
+}
+
+impl BlobStore {
+    /// A store of the given kind: `off` (or empty), `dir` or `s3`
+    pub fn configured(kind: &str, dir: PathBuf) -> Result<Option<Self>> {
+        match kind.trim() {
+            "" | "off" => Ok(None),
+            "dir" => Ok(Some(Self::Directory(dir))),
+            "s3" => Ok(Some(Self::S3(S3Client::from_env()?))),
+            other => bail!("blob store must be off, dir or s3, not {other:?}"),
+        }
+    }
+
+    pub async fn exists(&self, key: &str) -> Result<bool> {
+        match self {
+            Self::Directory(root) => Ok(tokio::fs::try_exists(path(root, key)?).await?),
+            Self::S3(s3) => s3.head_object(key).await,
+        }
+    }
+
+    /// Write an object, replacing any previous one under the same key
+    pub async fn put(
+        &self,
+        key: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        content_encoding: Option<&str>,
+    ) -> Result<()> {
+        match self {
+            Self::Directory(root) => {
+                let path = path(root, key)?;
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                // Write-then-rename so readers never see a partial file
+                let partial = path.with_extension("partial");
+                tokio::fs::write(&partial, body).await?;
+                tokio::fs::rename(&partial, &path).await?;
+                Ok(())
+            }
+            Self::S3(s3) => {
+                s3.put_object(key, body, content_type, content_encoding)
+                    .await
+            }
+        }
+    }
+
+    /// The object's bytes, or `None` if it does not exist
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self {
+            Self::Directory(root) => match tokio::fs::read(path(root, key)?).await {
+                Ok(bytes) => Ok(Some(bytes)),
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(error) => Err(error.into()),
+            },
+            Self::S3(s3) => s3.get_object(key).await,
+        }
+    }
+}
+
+pub fn env_dir(key: &str) -> Option<PathBuf> {
+    env::var(key)
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+        .map(PathBuf::from)
+}
+
+/// Keys are relative `/`-separated paths; anything that could leave the root is refused
+fn path(root: &Path, key: &str) -> Result<PathBuf> {
+    if key.is_empty()
+        || key.starts_with('/')
+        || key
+            .split('/')
+            .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+        bail!("invalid blob key {key:?}");
+    }
+    Ok(root.join(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn round_trips_blobs_in_a_directory() {
+        let root = env::temp_dir().join(format!("blob-store-test-{}", std::process::id()));
+        let store = BlobStore::Directory(root.clone());
+
+        assert!(!store.exists("a/b.txt").await.unwrap());
+        store
+            .put("a/b.txt", b"hello".to_vec(), "text/plain", None)
+            .await
+            .unwrap();
+        assert!(store.exists("a/b.txt").await.unwrap());
+        assert_eq!(
+            store.get("a/b.txt").await.unwrap().as_deref(),
+            Some(&b"hello"[..])
+        );
+        assert_eq!(store.get("a/missing.txt").await.unwrap(), None);
+        assert!(store.get("../escape").await.is_err());
+
+        tokio::fs::remove_dir_all(root).await.unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+//! Breadcrumb trails of crawled pages
+//!
+//! A page's `breadcrumb` is the trail from its site's home page down to it,
+//! e.g. `["Docs", "Guides", "Crawling"]`, which results show as context and
+//! the search API's ranking boosts when it matches the query. It comes from
+//! a JSON-LD `BreadcrumbList`, ordered by `position`, or else from the first
+//! breadcrumb element in the markup: a microdata `BreadcrumbList`, a `<nav>`
+//! labelled as breadcrumbs, or an element with a `breadcrumb(s)` class or ID.
+
+use scraper::{ElementRef, Html, Selector};
+use serde_json::Value;
+
+use crate::extractor::json_ld;
+
+const MAX_LEVELS: usize = 8;
+const MAX_LEVEL_CHARS: usize = 80;
+/// Separators some sites put between levels as text
+const SEPARATORS: &[char] = &['>', '›', '»', '/', '|', '→', '·'];
+
+pub fn extract_breadcrumb(document: &Html) -> Vec<String> {
+    let from_json_ld = json_ld(document)
+        .iter()
+        .find_map(find_list)
+        .map(list_names)
+        .unwrap_or_default();
+    if !from_json_ld.is_empty() {
+        return from_json_ld;
+    }
+    let containers = [
+        r#"[itemtype$="BreadcrumbList"]"#,
+        r#"nav[aria-label*="readcrumb"]"#,
+        ".breadcrumb, .breadcrumbs, #breadcrumb, #breadcrumbs",
+    ];
+    containers
+        .iter()
+        .find_map(|selector| {
+            let selector = Selector::parse(selector).unwrap();
+            document.select(&selector).next().map(markup_levels)
+        })
+        .unwrap_or_default()
+}
+
+/// The first `BreadcrumbList` object, searching `@graph` and nested objects
+fn find_list(value: &Value) -> Option<&Value> {
+    match value {
+        Value::Array(items) => items.iter().find_map(find_list),
+        Value::Object(object) => {
+            let is_list = match object.get("@type") {
+                Some(Value::String(kind)) => kind == "BreadcrumbList",
+                Some(Value::Array(kinds)) => kinds.iter().any(|kind| kind == "BreadcrumbList"),
+                _ => false,
+            };
+            if is_list {
+                Some(value)
+            } else {
+                object.values().find_map(find_list)
+            }
+        }
+        _ => None,
+    }
+}
+
+fn list_names(list: &Value) -> Vec<String> {
+    let Some(Value::Array(items)) = list.get("itemListElement") else {
+        return Vec::new();
+    };
+    let mut levels = items
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| {
+            let position = item
+                .get("position")
+                .and_then(|position| {
+                    position
+                        .as_u64()
+                        .or_else(|| position.as_str()?.trim().parse().ok())
+                })
+                .unwrap_or(index as u64);
+            let name = item
+                .get("name")
+                .or_else(|| item.get("item")?.get("name"))?
+                .as_str()?;
+            Some((position, name))
+        })
+        .collect::<Vec<_>>();
+    levels.sort_by_key(|(position, _)| *position);
+    clean(levels.into_iter().map(|(_, name)| name.to_string()))
+}
+
+fn markup_levels(container: ElementRef) -> Vec<String> {
+    let text = |element: ElementRef| element.text().collect::<Vec<_>>().join(" ");
+    ["[itemprop~=name]", "li", "a"]
+        .iter()
+        .map(|selector| {
+            let selector = Selector::parse(selector).unwrap();
+            clean(container.select(&selector).map(text))
+        })
+        .find(|levels| !levels.is_empty())
+        .unwrap_or_default()
+}
+
+/// Levels with their whitespace collapsed, without separators, blanks or
+/// overlong ones
+fn clean(levels: impl Iterator<Item = String>) -> Vec<String> {
+    levels
+        .map(|level| {
+            let level = level.split_whitespace().collect::<Vec<_>>().join(" ");
+            level
+                .trim_matches(|c: char| c.is_whitespace() || SEPARATORS.contains(&c))
+                .to_string()
+        })
+        .filter(|level| !level.is_empty() && level.chars().count() <= MAX_LEVEL_CHARS)
+        .take(MAX_LEVELS)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_json_ld_lists_and_breadcrumb_markup() {
+        let breadcrumb = |html: &str| extract_breadcrumb(&Html::parse_document(html));
+        assert_eq!(
+            breadcrumb(
+                r#"<script type="application/ld+json">{"@context": "https://schema.org",
+                "@type": "BreadcrumbList", "itemListElement": [
+                {"@type": "ListItem", "position": 2, "name": "Guides"},
+                {"@type": "ListItem", "position": 1, "item": {"@id": "/docs", "name": "Docs"}},
+                {"@type": "ListItem", "position": "3", "name": " Crawling "}]}</script>"#
+            ),
+            ["Docs", "Guides", "Crawling"]
+        );
+        assert_eq!(
+            breadcrumb(
+                r#"<nav aria-label="Breadcrumb"><ol><li><a href="/">Home</a> ›</li>
+                <li><a href="/blog">Blog</a> ›</li><li>Release notes</li></ol></nav>"#
+            ),
+            ["Home", "Blog", "Release notes"]
+        );
+        assert_eq!(
+            breadcrumb(
+                r#"<div class="breadcrumbs"><a href="/">Shop</a> / <a href="/tools">Tools</a></div>"#
+            ),
+            ["Shop", "Tools"]
+        );
+        assert!(breadcrumb("<nav><a href=\"/\">Home</a></nav>").is_empty());
+    }
+}
@@ -0,0 +1,90 @@
+//! Tells the search API which hosts received freshly indexed pages so it can
+//! drop cached search responses containing them.
+
+use reqwest::Client;
+use std::{
+    collections::HashSet,
+    env,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use url::Url;
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct SearchCacheNotifier {
+    http: Client,
+    endpoint: Option<String>,
+    pending: Mutex<HashSet<String>>,
+    last_flush: Mutex<Instant>,
+}
+
+impl SearchCacheNotifier {
+    pub fn new(endpoint: Option<String>) -> Self {
+        Self {
+            http: Client::builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .expect("failed to create HTTP client"),
+            endpoint,
+            pending: Mutex::new(HashSet::new()),
+            last_flush: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Reads `SEARCH_API_URL`; notifications are disabled when it is unset
+    pub fn from_env() -> Self {
+        let endpoint = env::var("SEARCH_API_URL")
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .map(|value| format!("{}/cache/invalidate", value.trim_end_matches('/')));
+        Self::new(endpoint)
+    }
+
+    /// Record an indexed page; batches are sent at most every few seconds
+    pub fn page_indexed(self: &Arc<Self>, url: &Url) {
+        if self.endpoint.is_none() {
+            return;
+        }
+        if let Some(host) = url.host_str() {
+            self.pending.lock().unwrap().insert(host.to_string());
+        }
+        let due = {
+            let mut last_flush = self.last_flush.lock().unwrap();
+            let due = last_flush.elapsed() >= FLUSH_INTERVAL;
+            if due {
+                *last_flush = Instant::now();
+            }
+            due
+        };
+        if due {
+            self.flush();
+        }
+    }
+
+    /// Send all pending hosts in the background
+    pub fn flush(self: &Arc<Self>) {
+        let Some(endpoint) = self.endpoint.clone() else {
+            return;
+        };
+        let domains = std::mem::take(&mut *self.pending.lock().unwrap());
+        if domains.is_empty() {
+            return;
+        }
+        let notifier = self.clone();
+        let request_id = crate::forwarded_request_id();
+        tokio::spawn(async move {
+            let result = notifier
+                .http
+                .post(&endpoint)
+                .headers(request_id)
+                .json(&serde_json::json!({ "domains": domains }))
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(error) = result {
+                tracing::warn!("search cache invalidation failed: {error}");
+            }
+        });
+    }
+}
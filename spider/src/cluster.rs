@@ -0,0 +1,405 @@
+//! Cooperation of several spider instances through Redis
+//!
+//! With `SPIDER_REDIS_URL` set, an instance takes a per-origin lock in Redis
+//! before every request and holds it for the origin's request interval, so
+//! the delay between requests to a site holds across all instances instead
+//! of per instance. Crawl requests with the same `shared_frontier` name, sent
+//! to any of the instances, then work through one frontier and one visited
+//! set kept in Redis: each URL is queued once and fetched by whichever
+//! instance pops it first. An instance finishes its job once it finds the
+//! shared frontier empty, and `max_pages` counts its own pages. The keys of
+//! a shared frontier expire a day after it last queued a URL. When Redis
+//! cannot be reached, origin locks fall back to the instance's own delays
+//! and shared frontiers act as if empty. All runners share one multiplexed
+//! connection, which reconnects on its own after an error.
+
+use async_trait::async_trait;
+use redis::{
+    aio::{ConnectionManager, ConnectionManagerConfig},
+    Client, RedisResult,
+};
+use serde::{Deserialize, Serialize};
+use std::{env, sync::Arc, time::Duration};
+use tokio::sync::OnceCell;
+use url::Url;
+
+use crate::frontier::{Frontier, QueuedUrl};
+
+const KEY_PREFIX: &str = "spider:";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest a runner waits for the answer to a single Redis command
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+/// Lifetime of a shared frontier's keys after its last push
+const SHARED_TTL_SECS: u64 = 24 * 60 * 60;
+/// Shortest wait before asking again for a taken origin lock
+const MIN_LOCK_POLL: Duration = Duration::from_millis(50);
+const MAX_NAME_LEN: usize = 64;
+/// Queue ARGV[2] unless ARGV[1] is in the queued set KEYS[1], as one step so
+/// a failure between the two cannot leave a URL marked queued but missing
+/// from the queue KEYS[2]; refreshes the TTL of both and of KEYS[3]
+const PUSH_SCRIPT: &str = "
+    if redis.call('SADD', KEYS[1], ARGV[1]) == 0 then
+        return 0
+    end
+    redis.call('RPUSH', KEYS[2], ARGV[2])
+    for _, key in ipairs(KEYS) do
+        redis.call('EXPIRE', key, ARGV[3])
+    end
+    return 1
+";
+
+pub struct Cluster {
+    client: Client,
+    /// Opened on first use
+    connection: OnceCell<ConnectionManager>,
+}
+
+impl Cluster {
+    /// The cluster at `SPIDER_REDIS_URL`, if set
+    pub fn from_env() -> anyhow::Result<Option<Arc<Self>>> {
+        let Ok(url) = env::var("SPIDER_REDIS_URL") else {
+            return Ok(None);
+        };
+        if url.trim().is_empty() {
+            return Ok(None);
+        }
+        let client = Client::open(url.trim())?;
+        Ok(Some(Arc::new(Self {
+            client,
+            connection: OnceCell::new(),
+        })))
+    }
+
+    /// A handle to the shared connection, which is cheap to clone
+    async fn connection(&self) -> RedisResult<ConnectionManager> {
+        self.connection
+            .get_or_try_init(|| {
+                let config = ConnectionManagerConfig::new()
+                    .set_connection_timeout(CONNECT_TIMEOUT)
+                    .set_response_timeout(COMMAND_TIMEOUT)
+                    .set_number_of_retries(1);
+                self.client.get_connection_manager_with_config(config)
+            })
+            .await
+            .cloned()
+    }
+
+    pub async fn frontier(&self, name: &str) -> RedisResult<RedisFrontier> {
+        Ok(RedisFrontier {
+            connection: self.connection().await?,
+            keys: SharedKeys::new(name),
+        })
+    }
+
+    pub async fn visited(&self, name: &str) -> RedisResult<SharedVisited> {
+        Ok(SharedVisited {
+            connection: self.connection().await?,
+            keys: SharedKeys::new(name),
+        })
+    }
+
+    /// Wait until no instance has sent a request to `origin` for `interval`,
+    /// then claim the next one
+    pub async fn wait_for_origin(&self, origin: &str, interval: Duration) {
+        let key = format!("{KEY_PREFIX}origin:{origin}");
+        loop {
+            match self.try_lock(&key, interval).await {
+                Ok(None) => return,
+                Ok(Some(wait)) => tokio::time::sleep(wait.clamp(MIN_LOCK_POLL, interval)).await,
+                Err(error) => {
+                    tracing::warn!("origin lock for {origin} failed, not waiting: {error}");
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Take the lock at `key` for `interval`, or how long it is still held
+    async fn try_lock(&self, key: &str, interval: Duration) -> RedisResult<Option<Duration>> {
+        let mut connection = self.connection().await?;
+        let taken: Option<String> = redis::cmd("SET")
+            .arg(key)
+            .arg(1)
+            .arg("NX")
+            .arg("PX")
+            .arg(interval.as_millis().max(1) as u64)
+            .query_async(&mut connection)
+            .await?;
+        if taken.is_some() {
+            return Ok(None);
+        }
+        let remaining: i64 = redis::cmd("PTTL")
+            .arg(key)
+            .query_async(&mut connection)
+            .await?;
+        Ok(Some(Duration::from_millis(remaining.max(0) as u64)))
+    }
+}
+
+/// A valid `shared_frontier` name, which is part of Redis keys
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+struct SharedKeys {
+    /// FIFO list of queued URLs
+    queue: String,
+    /// URLs currently in `queue`
+    queued: String,
+    visited: String,
+}
+
+impl SharedKeys {
+    fn new(name: &str) -> Self {
+        let prefix = format!("{KEY_PREFIX}frontier:{name}:");
+        Self {
+            queue: format!("{prefix}queue"),
+            queued: format!("{prefix}queued"),
+            visited: format!("{prefix}visited"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct QueueEntry {
+    url: String,
+    depth: usize,
+    #[serde(default)]
+    referrer: Option<String>,
+}
+
+impl QueueEntry {
+    fn into_queued(self) -> Option<QueuedUrl> {
+        Some(QueuedUrl {
+            url: Url::parse(&self.url).ok()?,
+            depth: self.depth,
+            referrer: self.referrer,
+        })
+    }
+}
+
+/// A frontier shared by the jobs of one `shared_frontier` name
+pub struct RedisFrontier {
+    connection: ConnectionManager,
+    keys: SharedKeys,
+}
+
+impl RedisFrontier {
+    async fn try_push(&mut self, key: &str, entry: &QueueEntry) -> anyhow::Result<bool> {
+        let added: i64 = redis::cmd("EVAL")
+            .arg(PUSH_SCRIPT)
+            .arg(3)
+            .arg(&self.keys.queued)
+            .arg(&self.keys.queue)
+            .arg(&self.keys.visited)
+            .arg(key)
+            .arg(serde_json::to_string(entry)?)
+            .arg(SHARED_TTL_SECS)
+            .query_async(&mut self.connection)
+            .await?;
+        Ok(added == 1)
+    }
+
+    async fn try_pop(&mut self) -> anyhow::Result<Option<QueuedUrl>> {
+        let connection = &mut self.connection;
+        loop {
+            let entry: Option<String> = redis::cmd("LPOP")
+                .arg(&self.keys.queue)
+                .query_async(connection)
+                .await?;
+            let Some(entry) = entry else {
+                return Ok(None);
+            };
+            let entry = serde_json::from_str::<QueueEntry>(&entry)?;
+            redis::cmd("SREM")
+                .arg(&self.keys.queued)
+                .arg(&entry.url)
+                .query_async::<()>(connection)
+                .await?;
+            if let Some(item) = entry.into_queued() {
+                return Ok(Some(item));
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Frontier for RedisFrontier {
+    async fn push(&mut self, url: Url, depth: usize, referrer: Option<&str>) -> bool {
+        let key = url.to_string();
+        let entry = QueueEntry {
+            url: key.clone(),
+            depth,
+            referrer: referrer.map(str::to_string),
+        };
+        self.try_push(&key, &entry).await.unwrap_or_else(|error| {
+            tracing::warn!("shared frontier push failed, dropping {key}: {error}");
+            false
+        })
+    }
+
+    async fn pop(&mut self) -> Option<QueuedUrl> {
+        self.try_pop().await.unwrap_or_else(|error| {
+            tracing::warn!("shared frontier pop failed: {error}");
+            None
+        })
+    }
+
+    async fn len(&self) -> usize {
+        redis::cmd("LLEN")
+            .arg(&self.keys.queue)
+            .query_async(&mut self.connection.clone())
+            .await
+            .unwrap_or_default()
+    }
+
+    /// The URLs already wait in Redis
+    fn checkpoint(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Leaves the URLs to the other instances' jobs; the keys expire
+    fn clear(&mut self) {}
+
+    async fn next_urls(&self, limit: usize) -> Vec<String> {
+        if limit == 0 {
+            return Vec::new();
+        }
+        redis::cmd("LRANGE")
+            .arg(&self.keys.queue)
+            .arg(0)
+            .arg(limit - 1)
+            .query_async::<Vec<String>>(&mut self.connection.clone())
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| serde_json::from_str::<QueueEntry>(&entry).ok())
+            .map(|entry| entry.url)
+            .collect()
+    }
+}
+
+/// The visited set of a `shared_frontier`
+pub struct SharedVisited {
+    connection: ConnectionManager,
+    keys: SharedKeys,
+}
+
+impl SharedVisited {
+    pub async fn contains(&self, url: &str) -> bool {
+        redis::cmd("SISMEMBER")
+            .arg(&self.keys.visited)
+            .arg(url)
+            .query_async(&mut self.connection.clone())
+            .await
+            .unwrap_or_else(|error| {
+                tracing::warn!("shared visited lookup failed for {url}: {error}");
+                false
+            })
+    }
+
+    pub async fn insert(&mut self, url: &str) {
+        let result = redis::pipe()
+            .cmd("SADD")
+            .arg(&self.keys.visited)
+            .arg(url)
+            .ignore()
+            .cmd("EXPIRE")
+            .arg(&self.keys.visited)
+            .arg(SHARED_TTL_SECS)
+            .ignore()
+            .query_async::<()>(&mut self.connection)
+            .await;
+        if let Err(error) = result {
+            tracing::warn!("failed to record shared visited {url}: {error}");
+        }
+    }
+
+    /// URLs visited by any of the shared jobs
+    pub async fn count(&self) -> usize {
+        redis::cmd("SCARD")
+            .arg(&self.keys.visited)
+            .query_async(&mut self.connection.clone())
+            .await
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_shared_keys_and_round_trips_queue_entries() {
+        assert!(is_valid_name("docs-crawl_2"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("docs:crawl"));
+        assert!(!is_valid_name(&"a".repeat(MAX_NAME_LEN + 1)));
+
+        let keys = SharedKeys::new("docs");
+        assert_eq!(keys.queue, "spider:frontier:docs:queue");
+        assert_eq!(keys.visited, "spider:frontier:docs:visited");
+
+        let entry = serde_json::to_string(&QueueEntry {
+            url: "https://example.com/a".to_string(),
+            depth: 2,
+            referrer: Some("https://example.com/".to_string()),
+        })
+        .unwrap();
+        let item = serde_json::from_str::<QueueEntry>(&entry)
+            .unwrap()
+            .into_queued()
+            .unwrap();
+        assert_eq!(item.url.as_str(), "https://example.com/a");
+        assert_eq!(item.depth, 2);
+        assert_eq!(item.referrer.as_deref(), Some("https://example.com/"));
+    }
+
+    /// Runs against the Redis at `SPIDER_REDIS_URL`, skipped when it is unset
+    #[tokio::test]
+    async fn shares_a_frontier_and_visited_set() {
+        let Some(cluster) = Cluster::from_env().unwrap() else {
+            eprintln!("SPIDER_REDIS_URL is not set, skipping");
+            return;
+        };
+        let name = format!("test-{}", uuid::Uuid::new_v4().simple());
+        let url = |page: usize| Url::parse(&format!("https://example.com/{page}")).unwrap();
+        let mut first = cluster.frontier(&name).await.unwrap();
+        let mut second = cluster.frontier(&name).await.unwrap();
+        for page in 0..3 {
+            assert!(first.push(url(page), 1, None).await);
+        }
+        assert!(!second.push(url(1), 2, None).await);
+        assert_eq!(second.len().await, 3);
+        assert_eq!(
+            second.next_urls(2).await,
+            ["https://example.com/0", "https://example.com/1"]
+        );
+
+        assert_eq!(second.pop().await.unwrap().url, url(0));
+        assert_eq!(first.pop().await.unwrap().url, url(1));
+        // A popped URL may be queued again; the visited set keeps it out
+        assert!(first.push(url(0), 1, None).await);
+        assert_eq!(first.len().await, 2);
+
+        let mut visited = cluster.visited(&name).await.unwrap();
+        let other = cluster.visited(&name).await.unwrap();
+        visited.insert(url(0).as_str()).await;
+        assert!(other.contains(url(0).as_str()).await);
+        assert!(!other.contains(url(2).as_str()).await);
+        assert_eq!(other.count().await, 1);
+
+        let keys = SharedKeys::new(&name);
+        redis::cmd("DEL")
+            .arg(&keys.queue)
+            .arg(&keys.queued)
+            .arg(&keys.visited)
+            .query_async::<()>(&mut cluster.connection().await.unwrap())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+//! Spam and adult-content filter
+//!
+//! With `CONTENT_FILTER=flag` or `CONTENT_FILTER=skip`, each page that passes
+//! the thin-content check is classified by keyword lists and, when
+//! `CONTENT_FILTER_MODEL_URL` is set, by an external model. Flagged pages are
+//! either not indexed (`skip`) or indexed with `unsafe: true`, which `/search`
+//! leaves out unless asked to include it (`flag`).
+//!
+//! The model hook receives `POST {"url", "title", "text"}` and answers
+//! `{"labels": [...]}`, e.g. `["adult"]`; an empty list means safe.
+
+use anyhow::{bail, Result};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use shared_crawler_api::WebPageChunk;
+use std::{env, time::Duration};
+
+const DEFAULT_MIN_HITS: usize = 3;
+/// Text sent to the model hook
+const MAX_MODEL_CHARS: usize = 20_000;
+
+const SPAM_TERMS: &[&str] = &[
+    "buy followers",
+    "casino bonus",
+    "cialis",
+    "free spins",
+    "payday loan",
+    "replica watches",
+    "viagra",
+];
+const ADULT_TERMS: &[&str] = &[
+    "camgirl", "escort", "hentai", "nsfw", "nude", "onlyfans", "porn", "xxx",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterAction {
+    Flag,
+    Skip,
+}
+
+pub struct ContentFilter {
+    pub action: FilterAction,
+    spam_terms: Vec<String>,
+    adult_terms: Vec<String>,
+    min_hits: usize,
+    model: Option<(Client, String)>,
+}
+
+#[derive(Serialize)]
+struct ModelRequest<'a> {
+    url: &'a str,
+    title: &'a str,
+    text: &'a str,
+}
+
+#[derive(Deserialize)]
+struct ModelResponse {
+    #[serde(default)]
+    labels: Vec<String>,
+}
+
+impl ContentFilter {
+    /// `None` unless `CONTENT_FILTER` is `flag` or `skip`; term lists come
+    /// from `CONTENT_FILTER_SPAM_TERMS` / `CONTENT_FILTER_ADULT_TERMS`
+    /// (comma-separated, replacing the built-in lists)
+    pub fn from_env() -> Result<Option<Self>> {
+        let action = match env::var("CONTENT_FILTER").unwrap_or_default().trim() {
+            "" | "off" => return Ok(None),
+            "flag" => FilterAction::Flag,
+            "skip" => FilterAction::Skip,
+            other => bail!("CONTENT_FILTER must be off, flag or skip, not {other:?}"),
+        };
+        let terms = |key: &str, default: &[&str]| match env::var(key) {
+            Ok(value) if !value.trim().is_empty() => value
+                .split(',')
+                .map(|term| term.trim().to_lowercase())
+                .filter(|term| !term.is_empty())
+                .collect(),
+            _ => default.iter().map(|term| term.to_string()).collect(),
+        };
+        let model = env::var("CONTENT_FILTER_MODEL_URL")
+            .ok()
+            .filter(|url| !url.trim().is_empty())
+            .map(|url| {
+                let http = Client::builder()
+                    .timeout(Duration::from_secs(10))
+                    .build()
+                    .unwrap_or_default();
+                (http, url)
+            });
+        Ok(Some(Self {
+            action,
+            spam_terms: terms("CONTENT_FILTER_SPAM_TERMS", SPAM_TERMS),
+            adult_terms: terms("CONTENT_FILTER_ADULT_TERMS", ADULT_TERMS),
+            min_hits: env::var("CONTENT_FILTER_MIN_HITS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_MIN_HITS)
+                .max(1),
+            model,
+        }))
+    }
+
+    /// Labels such as `spam` or `adult`; empty for a safe page
+    pub async fn classify(&self, url: &str, chunks: &[WebPageChunk]) -> Vec<String> {
+        let title = chunks.first().map_or("", |chunk| chunk.page_title.as_str());
+        let text = chunks
+            .iter()
+            .map(|chunk| chunk.chunk_content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut labels = self.keyword_labels(&format!("{title}\n{text}"));
+        if let Some((http, model_url)) = &self.model {
+            let text = text.chars().take(MAX_MODEL_CHARS).collect::<String>();
+            let response = async {
+                http.post(model_url)
+                    .json(&ModelRequest {
+                        url,
+                        title,
+                        text: &text,
+                    })
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<ModelResponse>()
+                    .await
+            };
+            match response.await {
+                Ok(response) => labels.extend(response.labels),
+                Err(error) => tracing::warn!("content filter model failed for {url}: {error}"),
+            }
+        }
+        labels.sort();
+        labels.dedup();
+        labels
+    }
+
+    fn keyword_labels(&self, text: &str) -> Vec<String> {
+        let text = text.to_lowercase();
+        [("spam", &self.spam_terms), ("adult", &self.adult_terms)]
+            .into_iter()
+            .filter(|(_, terms)| {
+                terms
+                    .iter()
+                    .map(|term| count_term(&text, term))
+                    .sum::<usize>()
+                    >= self.min_hits
+            })
+            .map(|(label, _)| label.to_string())
+            .collect()
+    }
+}
+
+/// Occurrences of `term` not inside a longer word
+fn count_term(text: &str, term: &str) -> usize {
+    text.match_indices(term)
+        .filter(|(index, _)| {
+            let before = text[..*index].chars().next_back();
+            let after = text[index + term.len()..].chars().next();
+            !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_pages_by_keyword_hits() {
+        let filter = ContentFilter {
+            action: FilterAction::Flag,
+            spam_terms: vec!["free spins".to_string(), "casino bonus".to_string()],
+            adult_terms: vec!["porn".to_string()],
+            min_hits: 2,
+            model: None,
+        };
+        assert_eq!(
+            filter.keyword_labels("Free spins! Claim your CASINO BONUS today"),
+            ["spam"]
+        );
+        // "porn" inside a longer word does not count
+        assert!(filter
+            .keyword_labels("pornographic history, porn once")
+            .is_empty());
+        assert_eq!(count_term("porn, porn.", "porn"), 2);
+    }
+}
@@ -0,0 +1,130 @@
+//! What changed between two crawls of the same host
+//!
+//! Every job records the `page_version` (content hash) of each page it
+//! fetched. `GET /crawl/{id}/diff` compares a finished job with the previous
+//! finished job whose seed had the same host: pages only in the newer crawl
+//! are new, pages only in the older one were removed (or not reached this
+//! time), and pages in both with different hashes changed. Pages an
+//! incremental crawl skipped as unchanged have no hash and count as unchanged.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::state::StateStore;
+
+/// URLs listed per category; the totals count all of them
+const MAX_DIFF_URLS: usize = 1_000;
+
+/// `page_version` by URL; `None` for a page skipped as unchanged
+pub type PageVersions = HashMap<String, Option<String>>;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CrawlDiff {
+    pub job_id: String,
+    pub host: String,
+    /// `null` for the first crawl of a host; every page is then new
+    pub previous_job_id: Option<String>,
+    pub new: Vec<String>,
+    pub new_total: usize,
+    pub removed: Vec<String>,
+    pub removed_total: usize,
+    pub changed: Vec<String>,
+    pub changed_total: usize,
+    pub unchanged_total: usize,
+}
+
+impl CrawlDiff {
+    /// `None` if the job has not finished or is unknown
+    pub fn load(store: &StateStore, job_id: &str) -> Result<Option<Self>> {
+        let Some((host, finished_at)) = store.crawl_snapshot(job_id)? else {
+            return Ok(None);
+        };
+        let previous_job_id = store.previous_crawl(&host, finished_at, job_id)?;
+        let previous = match &previous_job_id {
+            Some(previous) => store.page_versions(previous)?,
+            None => PageVersions::new(),
+        };
+        let current = store.page_versions(job_id)?;
+        Ok(Some(Self {
+            job_id: job_id.to_string(),
+            host,
+            previous_job_id,
+            ..Self::between(&current, &previous)
+        }))
+    }
+
+    fn between(current: &PageVersions, previous: &PageVersions) -> Self {
+        let mut new = Vec::new();
+        let mut changed = Vec::new();
+        let mut unchanged_total = 0;
+        for (url, version) in current {
+            match (version, previous.get(url)) {
+                (_, None) => new.push(url.clone()),
+                (Some(version), Some(Some(before))) if version != before => {
+                    changed.push(url.clone())
+                }
+                _ => unchanged_total += 1,
+            }
+        }
+        let mut removed = previous
+            .keys()
+            .filter(|url| !current.contains_key(*url))
+            .cloned()
+            .collect::<Vec<_>>();
+        let (new_total, removed_total, changed_total) = (new.len(), removed.len(), changed.len());
+        for urls in [&mut new, &mut removed, &mut changed] {
+            urls.sort();
+            urls.truncate(MAX_DIFF_URLS);
+        }
+        Self {
+            new,
+            new_total,
+            removed,
+            removed_total,
+            changed,
+            changed_total,
+            unchanged_total,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_with_the_previous_crawl_of_the_host() {
+        let store = StateStore::in_memory().unwrap();
+        let pages = [
+            ("old", "https://example.com/", Some("a")),
+            ("old", "https://example.com/gone", Some("b")),
+            ("old", "https://example.com/edited", Some("c")),
+            ("old", "https://example.com/same", Some("d")),
+            ("other", "https://other.example/", Some("x")),
+            ("new", "https://example.com/", Some("a")),
+            ("new", "https://example.com/edited", Some("c2")),
+            ("new", "https://example.com/same", None),
+            ("new", "https://example.com/added", Some("e")),
+        ];
+        for (job_id, url, version) in pages {
+            store.record_page(job_id, url, version).unwrap();
+        }
+        store.record_crawl("old", "example.com", 100).unwrap();
+        store.record_crawl("other", "other.example", 150).unwrap();
+        store.record_crawl("new", "example.com", 200).unwrap();
+
+        let diff = CrawlDiff::load(&store, "new").unwrap().unwrap();
+        assert_eq!(diff.previous_job_id.as_deref(), Some("old"));
+        assert_eq!(diff.new, ["https://example.com/added"]);
+        assert_eq!(diff.removed, ["https://example.com/gone"]);
+        assert_eq!(diff.changed, ["https://example.com/edited"]);
+        assert_eq!(diff.unchanged_total, 2);
+
+        let first = CrawlDiff::load(&store, "old").unwrap().unwrap();
+        assert_eq!(first.previous_job_id, None);
+        assert_eq!(first.new_total, 4);
+        assert!(CrawlDiff::load(&store, "running").unwrap().is_none());
+    }
+}
@@ -1,180 +1,1263 @@
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    StatusCode,
+};
+use serde::Serialize;
+use shared_crawler_api::{caches::CacheStats, problem::Problem, request_id, tenant, WebPageChunk};
 use std::{
-    collections::{HashSet, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
+    env,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicUsize, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::{sync::Mutex, task::JoinHandle};
+use tracing::Instrument;
 use url::Url;
 
 use crate::{
-    index::extract_page,
-    qdrant::PageIndexer,
-    robots::RobotsCache,
+    archive::HtmlArchive,
+    cache_invalidation::SearchCacheNotifier,
+    cluster::{self, Cluster},
+    content_filter::{ContentFilter, FilterAction},
+    domain_profile::{DomainProfiles, RenderStrategy},
+    frontier::{self, Frontier, LocalFrontier, QueuedUrl},
+    index::{extract_page, page_links, ChunkOptions},
+    jobs::{unix_now, CrawlEvent, CrawlJob, CrawlJobs, JobStatus, PageBudget, NEXT_URLS_PREVIEW},
+    keyword_filter::KeywordFilter,
+    page_events::{outcome_name, PageEventPublisher, PageIndexed},
+    pagination, private_network,
+    qdrant::{page_version, PageIndexer},
+    quality::{PageQualityRules, QualityRules},
+    report::FailureKind,
+    robots::{product_token_of, RobotsCache, RobotsTtl},
     sitemap,
+    state::{self, StateStore},
     stats::CrawlStats,
+    visited::VisitedSet,
     web_visitor::{
-        normalize_url, origin, same_origin, FetchError, OriginScheduler, WebVisitorImpl,
+        normalize_url, origin, same_origin, ExtractedLink, FetchError, OriginScheduler,
+        RequestOptions, ThrottledOrigin, WebVisitorImpl,
     },
     web_visitor_browser::BrowserPool,
-    CrawlRequest,
+    webhook::{PageDelivery, PageWebhook, WebhookNotifier, PAGE_QUEUE_CAPACITY},
+    CrawlMode, CrawlRequest,
 };
 
-#[derive(Clone)]
-struct QueuedUrl {
-    url: Url,
-    depth: usize,
+const DEFAULT_MIN_RUNNERS: usize = 1;
+const DEFAULT_MAX_RUNNERS: usize = 4;
+const SCALE_INTERVAL: Duration = Duration::from_secs(1);
+const RUNNER_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_SHUTDOWN_GRACE: Duration = Duration::from_secs(60);
+const MAX_USER_AGENT_LEN: usize = 256;
+const MAX_SCROLL_COUNT: usize = 50;
+const MAX_REQUEST_TIMEOUT_SECS: u64 = 300;
+const MIN_BODY_BYTES: usize = 64 * 1024;
+const MAX_BODY_BYTES: usize = 64 * 1024 * 1024;
+const MAX_EXTRA_HEADERS: usize = 20;
+const MAX_FOCUS_QUERY_CHARS: usize = 1_000;
+/// Set by the crawler itself: the User-Agent through `user_agent`, cookies
+/// through domain profiles
+const RESERVED_HEADERS: &[&str] = &[
+    "host",
+    "user-agent",
+    "cookie",
+    "content-length",
+    "transfer-encoding",
+    "connection",
+];
+
+type RequestQueue = Arc<Mutex<VecDeque<Arc<CrawlJob>>>>;
+
+/// Whether the runners take jobs; `POST /admin/stop` and `/admin/start`
+/// move between the states, and a shutdown stops the loop for good
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Lifecycle {
+    /// Created, runners not spawned yet
+    Starting,
+    /// Runners take queued jobs
+    Running,
+    /// Runners finish the page in flight, checkpoint their job and exit
+    Draining,
+    /// No runners; queued jobs wait for a start
+    Stopped,
 }
 
 pub struct CrawlLoop {
-    requests: Arc<Mutex<VecDeque<CrawlRequest>>>,
-    shutdown: Arc<AtomicBool>,
+    requests: RequestQueue,
+    jobs: Arc<CrawlJobs>,
+    lifecycle: Arc<std::sync::Mutex<Lifecycle>>,
     stats: Arc<CrawlStats>,
     visitor: Arc<WebVisitorImpl>,
     robots: Arc<RobotsCache>,
+    product_token: String,
     indexer: Arc<PageIndexer>,
-    runners: Vec<JoinHandle<()>>,
+    archive: Option<Arc<HtmlArchive>>,
+    webhooks: Arc<WebhookNotifier>,
+    search_cache: Arc<SearchCacheNotifier>,
+    page_events: Arc<PageEventPublisher>,
+    state: Arc<StateStore>,
+    cluster: Option<Arc<Cluster>>,
+    frontier_memory_limit: usize,
+    chunking: ChunkOptions,
+    quality: Arc<QualityRules>,
+    page_quality: PageQualityRules,
+    content_filter: Option<Arc<ContentFilter>>,
+    max_pagination_pages: usize,
+    skip_crawled_within_hours: u64,
+    profiles: Arc<DomainProfiles>,
+    pool: Arc<RunnerPool>,
+    supervisor: Option<JoinHandle<()>>,
+}
+
+/// Runner tasks, scaled between `SPIDER_MIN_RUNNERS` and `SPIDER_MAX_RUNNERS`
+struct RunnerPool {
+    min: usize,
+    max: usize,
+    count: AtomicUsize,
+    busy: AtomicUsize,
+    next_id: AtomicUsize,
+    handles: std::sync::Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl RunnerPool {
+    fn from_env() -> Self {
+        let read = |key: &str, default: usize| {
+            env::var(key)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        let min = read("SPIDER_MIN_RUNNERS", DEFAULT_MIN_RUNNERS).max(1);
+        let max = read("SPIDER_MAX_RUNNERS", DEFAULT_MAX_RUNNERS).max(min);
+        Self {
+            min,
+            max,
+            count: AtomicUsize::new(0),
+            busy: AtomicUsize::new(0),
+            next_id: AtomicUsize::new(0),
+            handles: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Claim permission for an idle runner to exit without dropping below `min`
+    fn try_retire(&self) -> bool {
+        self.count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count > self.min).then(|| count - 1)
+            })
+            .is_ok()
+    }
+}
+
+/// Everything a runner task needs to process jobs
+#[derive(Clone)]
+struct CrawlRunner {
+    id: usize,
+    lifecycle: Arc<std::sync::Mutex<Lifecycle>>,
+    stats: Arc<CrawlStats>,
+    visitor: Arc<WebVisitorImpl>,
+    robots: Arc<RobotsCache>,
+    product_token: String,
+    indexer: Arc<PageIndexer>,
+    archive: Option<Arc<HtmlArchive>>,
+    webhooks: Arc<WebhookNotifier>,
+    search_cache: Arc<SearchCacheNotifier>,
+    page_events: Arc<PageEventPublisher>,
+    state: Arc<StateStore>,
+    cluster: Option<Arc<Cluster>>,
+    frontier_memory_limit: usize,
+    chunking: ChunkOptions,
+    quality: Arc<QualityRules>,
+    page_quality: PageQualityRules,
+    content_filter: Option<Arc<ContentFilter>>,
+    max_pagination_pages: usize,
+    skip_crawled_within_hours: u64,
+    profiles: Arc<DomainProfiles>,
+}
+
+/// What the page stages of one job share
+struct JobContext<'a> {
+    job: &'a CrawlJob,
+    /// With the job's user agent and request options
+    visitor: &'a Arc<WebVisitorImpl>,
+    product_token: &'a str,
+    collection: String,
+    keywords: KeywordFilter,
+    /// Embedding of the job's `focus_query`
+    focus: Option<Vec<f32>>,
+    page_webhook: Option<PageWebhook>,
 }
 
 impl CrawlLoop {
     pub fn new(
         stats: Arc<CrawlStats>,
+        jobs: Arc<CrawlJobs>,
         indexer: Arc<PageIndexer>,
+        archive: Option<Arc<HtmlArchive>>,
+        state: Arc<StateStore>,
         product_token: String,
         user_agent: String,
     ) -> Self {
-        let visitor = Arc::new(WebVisitorImpl::new(&user_agent, OriginScheduler::default()));
-        let robots = Arc::new(RobotsCache::new(visitor.clone(), product_token));
+        let profiles =
+            Arc::new(DomainProfiles::load(state.clone()).expect("failed to load domain profiles"));
+        let cluster = Cluster::from_env().expect("invalid SPIDER_REDIS_URL");
+        let visitor = Arc::new(
+            WebVisitorImpl::new(
+                &user_agent,
+                OriginScheduler::default().with_cluster(cluster.clone()),
+            )
+            .with_domain_profiles(profiles.clone()),
+        );
+        let robots = Arc::new(RobotsCache::new(RobotsTtl::from_env()));
+        let webhooks = Arc::new(WebhookNotifier::from_env(&user_agent));
         Self {
             requests: Arc::new(Mutex::new(VecDeque::new())),
-            shutdown: Arc::new(AtomicBool::new(true)),
+            jobs,
+            lifecycle: Arc::new(std::sync::Mutex::new(Lifecycle::Starting)),
             stats,
             visitor,
             robots,
+            product_token,
             indexer,
-            runners: Vec::new(),
+            archive,
+            webhooks,
+            search_cache: Arc::new(SearchCacheNotifier::from_env()),
+            page_events: Arc::new(PageEventPublisher::from_env()),
+            state,
+            cluster,
+            frontier_memory_limit: frontier::memory_limit_from_env(),
+            chunking: ChunkOptions::from_env(),
+            quality: Arc::new(QualityRules::from_env()),
+            page_quality: PageQualityRules::from_env(),
+            content_filter: ContentFilter::from_env()
+                .expect("invalid content filter configuration")
+                .map(Arc::new),
+            max_pagination_pages: pagination::max_pages_from_env(),
+            skip_crawled_within_hours: state::skip_crawled_within_hours_from_env(),
+            profiles,
+            pool: Arc::new(RunnerPool::from_env()),
+            supervisor: None,
         }
     }
 
-    pub async fn add_crawl_request(&self, mut request: CrawlRequest) -> Result<(), String> {
-        let seed = normalize_url(&request.url).ok_or_else(|| "invalid HTTP(S) URL".to_string())?;
+    pub async fn add_crawl_request(
+        &self,
+        mut request: CrawlRequest,
+    ) -> Result<Arc<CrawlJob>, Problem> {
+        let seed = normalize_url(&request.url)
+            .ok_or_else(|| Problem::bad_request("invalid_url", "invalid HTTP(S) URL"))?;
         if is_crawl_trap(&seed) {
-            return Err("login/signup/search URLs are not crawlable".to_string());
+            return Err(Problem::bad_request(
+                "uncrawlable_url",
+                "login/signup/search URLs are not crawlable",
+            ));
+        }
+        if request.max_pages == 0 {
+            return Err(Problem::bad_request(
+                "max_pages_out_of_range",
+                "max_pages must be at least 1",
+            ));
+        }
+        if request.max_pages_per_host == Some(0) {
+            return Err(Problem::bad_request(
+                "max_pages_per_host_out_of_range",
+                "max_pages_per_host must be at least 1",
+            ));
+        }
+        if request.max_duration_secs == Some(0) {
+            return Err(Problem::bad_request(
+                "max_duration_secs_out_of_range",
+                "max_duration_secs must be at least 1",
+            ));
+        }
+        self.chunking
+            .with_overrides(
+                request.chunk_target_chars,
+                request.chunk_max_chars,
+                request.chunk_overlap_chars,
+            )
+            .map_err(|detail| Problem::bad_request("chunk_size_out_of_range", detail))?;
+        if let Some(name) = &request.shared_frontier {
+            if self.cluster.is_none() {
+                return Err(Problem::bad_request(
+                    "shared_frontier_unavailable",
+                    "shared_frontier needs SPIDER_REDIS_URL",
+                ));
+            }
+            if !cluster::is_valid_name(name) {
+                return Err(Problem::bad_request(
+                    "invalid_shared_frontier",
+                    "shared_frontier must be 1 to 64 letters, digits, '-' or '_'",
+                ));
+            }
+        }
+        if request
+            .bloom_fp_rate
+            .is_some_and(|rate| !(rate > 0.0 && rate < 1.0))
+        {
+            return Err(Problem::bad_request(
+                "bloom_fp_rate_out_of_range",
+                "bloom_fp_rate must be between 0 and 1 (exclusive)",
+            ));
+        }
+        if request
+            .skip_crawled_within_hours
+            .is_some_and(|hours| hours > state::MAX_SKIP_CRAWLED_WITHIN_HOURS)
+        {
+            return Err(Problem::bad_request(
+                "skip_crawled_within_hours_out_of_range",
+                format!(
+                    "skip_crawled_within_hours must be at most {}",
+                    state::MAX_SKIP_CRAWLED_WITHIN_HOURS
+                ),
+            ));
+        }
+        if request.scroll_count > MAX_SCROLL_COUNT {
+            return Err(Problem::bad_request(
+                "scroll_count_out_of_range",
+                format!("scroll_count must be at most {MAX_SCROLL_COUNT}"),
+            ));
+        }
+        if let Some(user_agent) = &request.user_agent {
+            validate_user_agent(user_agent)?;
+        }
+        request_options(&request)?;
+        if let Some(focus_query) = &request.focus_query {
+            if focus_query.trim().is_empty() || focus_query.chars().count() > MAX_FOCUS_QUERY_CHARS
+            {
+                return Err(Problem::bad_request(
+                    "invalid_focus_query",
+                    format!("focus_query must be 1 to {MAX_FOCUS_QUERY_CHARS} characters"),
+                ));
+            }
+        }
+        KeywordFilter::new(&request.must_contain, &request.must_not_contain)?;
+        tenant::collection_name(request.tenant.as_deref())?;
+        if let Some(collection) = &request.collection {
+            tenant::validate_collection(collection)?;
+        }
+        if !(0.0..=1.0).contains(&request.focus_threshold) {
+            return Err(Problem::bad_request(
+                "focus_threshold_out_of_range",
+                "focus_threshold must be between 0 and 1",
+            ));
+        }
+        if let Err(error) = self.visitor.check_address(&seed).await {
+            return Err(Problem::bad_request(
+                "private_address",
+                format!("{error}; set ALLOW_PRIVATE_NETWORKS to crawl it"),
+            ));
         }
         request.url = seed.to_string();
-        self.requests.lock().await.push_back(request);
-        Ok(())
+        if let Some(callback_url) = &request.callback_url {
+            let callback = self
+                .webhook_url(callback_url, "callback_url", "invalid_callback_url")
+                .await?;
+            request.callback_url = Some(callback);
+        }
+        if let Some(page_callback_url) = &request.page_callback_url {
+            let callback = self
+                .webhook_url(
+                    page_callback_url,
+                    "page_callback_url",
+                    "invalid_page_callback_url",
+                )
+                .await?;
+            request.page_callback_url = Some(callback);
+        }
+        let job = self.jobs.create(request).await;
+        self.requests.lock().await.push_back(job.clone());
+        Ok(job)
+    }
+
+    /// `value` as an HTTP(S) webhook URL outside private address space,
+    /// keeping the query string: callback endpoints often carry tokens there
+    async fn webhook_url(
+        &self,
+        value: &str,
+        field: &str,
+        code: &'static str,
+    ) -> Result<String, Problem> {
+        let url = Url::parse(value)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https") && url.has_host())
+            .ok_or_else(|| Problem::bad_request(code, format!("invalid {field}")))?;
+        if let Err(error) = self.visitor.check_address(&url).await {
+            return Err(Problem::bad_request(
+                "private_address",
+                format!("{field}: {error}; set ALLOW_PRIVATE_NETWORKS to allow it"),
+            ));
+        }
+        Ok(url.to_string())
+    }
+
+    pub fn domain_profiles(&self) -> Arc<DomainProfiles> {
+        self.profiles.clone()
     }
 
     pub async fn queue_size(&self) -> usize {
         self.requests.lock().await.len()
     }
 
-    pub fn run(&mut self) {
-        for id in 0..4 {
-            let requests = self.requests.clone();
-            let shutdown = self.shutdown.clone();
-            let stats = self.stats.clone();
-            let visitor = self.visitor.clone();
-            let robots = self.robots.clone();
-            let indexer = self.indexer.clone();
-            self.runners.push(tokio::spawn(async move {
-                while shutdown.load(Ordering::Relaxed) {
-                    let request = requests.lock().await.pop_front();
-                    if let Some(request) = request {
-                        crawl_request(id, request, &visitor, &robots, &indexer, &stats).await;
-                    } else {
-                        tokio::time::sleep(Duration::from_millis(200)).await;
-                    }
+    /// Jobs waiting for a free runner, in the order they will start
+    pub async fn pending(&self) -> Vec<Arc<CrawlJob>> {
+        self.requests.lock().await.iter().cloned().collect()
+    }
+
+    /// Forget cached robots.txt files for `origins`, or all of them
+    pub async fn flush_robots(&self, origins: Option<&[String]>) -> usize {
+        self.robots.flush(origins).await
+    }
+
+    pub async fn robots_stats(&self) -> CacheStats {
+        self.robots.stats().await
+    }
+
+    /// Origins currently slowed down after 429/503 responses
+    pub async fn throttled_origins(&self) -> Vec<ThrottledOrigin> {
+        self.visitor.throttled_origins().await
+    }
+
+    /// Runner tasks alive and runner tasks currently crawling
+    pub fn runner_counts(&self) -> (usize, usize) {
+        (
+            self.pool.count.load(Ordering::Relaxed),
+            self.pool.busy.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Where the loop is in its lifecycle; `Draining` turns into `Stopped`
+    /// once the last runner has exited
+    pub fn lifecycle(&self) -> Lifecycle {
+        let mut lifecycle = self.lifecycle.lock().unwrap();
+        if *lifecycle == Lifecycle::Draining && self.pool.count.load(Ordering::SeqCst) == 0 {
+            *lifecycle = Lifecycle::Stopped;
+        }
+        *lifecycle
+    }
+
+    /// Spawn the runners, first queueing the jobs checkpointed by the last
+    /// shutdown or `stop` ahead of jobs queued since; returns the number of
+    /// resumed jobs. Refused while the loop is still draining.
+    pub async fn start(&mut self) -> Result<usize, Problem> {
+        match self.lifecycle() {
+            Lifecycle::Running => return Ok(0),
+            Lifecycle::Draining => {
+                return Err(Problem::new(
+                    409,
+                    "crawler_draining",
+                    "the crawler is still stopping; start it again once it has stopped",
+                ))
+            }
+            Lifecycle::Starting | Lifecycle::Stopped => {}
+        }
+        let resumed = self.resume_checkpoints().await;
+        *self.lifecycle.lock().unwrap() = Lifecycle::Running;
+        self.spawn_runners();
+        Ok(resumed)
+    }
+
+    /// Stop taking jobs: each runner finishes the page in flight, checkpoints
+    /// its job and exits. Queued jobs, and jobs added meanwhile, wait for
+    /// `start`.
+    pub fn stop(&mut self) -> Lifecycle {
+        {
+            let mut lifecycle = self.lifecycle.lock().unwrap();
+            match *lifecycle {
+                Lifecycle::Running => *lifecycle = Lifecycle::Draining,
+                Lifecycle::Starting => *lifecycle = Lifecycle::Stopped,
+                Lifecycle::Draining | Lifecycle::Stopped => {}
+            }
+        }
+        if let Some(supervisor) = self.supervisor.take() {
+            supervisor.abort();
+        }
+        self.lifecycle()
+    }
+
+    /// `stop`, then wait up to `grace` for the runners and keep queued jobs
+    /// and the counters for the next start. Runners still busy after `grace`
+    /// are aborted and their jobs are not resumed.
+    pub async fn shutdown(&mut self, grace: Duration) {
+        self.stop();
+        let runners = std::mem::take(&mut *self.pool.handles.lock().unwrap());
+        let deadline = tokio::time::Instant::now() + grace;
+        for mut runner in runners {
+            if tokio::time::timeout_at(deadline, &mut runner)
+                .await
+                .is_err()
+            {
+                runner.abort();
+                self.pool.count.fetch_sub(1, Ordering::SeqCst);
+                tracing::warn!("a runner did not finish its page within {grace:?}; aborted it");
+            }
+        }
+        for job in self.requests.lock().await.drain(..) {
+            let record = job.checkpoint_record(job.resumed);
+            if let Err(error) = self.state.checkpoint_job(&job.id, job.created_at, &record) {
+                tracing::warn!("failed to checkpoint queued job {}: {error}", job.id);
+            }
+            job.finish(JobStatus::Interrupted);
+        }
+        let saved = serde_json::to_value(self.stats.snapshot())
+            .map_err(anyhow::Error::from)
+            .and_then(|snapshot| self.state.save_stats(&snapshot));
+        if let Err(error) = saved {
+            tracing::warn!("failed to save crawl stats: {error:#}");
+        }
+    }
+
+    /// A runner sharing this loop's services; `spawn_runner` numbers it
+    fn runner(&self) -> CrawlRunner {
+        CrawlRunner {
+            id: 0,
+            lifecycle: self.lifecycle.clone(),
+            stats: self.stats.clone(),
+            visitor: self.visitor.clone(),
+            robots: self.robots.clone(),
+            product_token: self.product_token.clone(),
+            indexer: self.indexer.clone(),
+            archive: self.archive.clone(),
+            webhooks: self.webhooks.clone(),
+            search_cache: self.search_cache.clone(),
+            page_events: self.page_events.clone(),
+            state: self.state.clone(),
+            cluster: self.cluster.clone(),
+            frontier_memory_limit: self.frontier_memory_limit,
+            chunking: self.chunking,
+            quality: self.quality.clone(),
+            page_quality: self.page_quality,
+            content_filter: self.content_filter.clone(),
+            max_pagination_pages: self.max_pagination_pages,
+            skip_crawled_within_hours: self.skip_crawled_within_hours,
+            profiles: self.profiles.clone(),
+        }
+    }
+
+    fn spawn_runners(&mut self) {
+        let template = self.runner();
+        for _ in 0..self.pool.min {
+            spawn_runner(&self.pool, &self.requests, &template);
+        }
+
+        let pool = self.pool.clone();
+        let requests = self.requests.clone();
+        let jobs = self.jobs.clone();
+        self.supervisor = Some(tokio::spawn(async move {
+            while template.is_running() {
+                tokio::time::sleep(SCALE_INTERVAL).await;
+                unpark_jobs(&jobs, &template.state, &template.profiles, &requests).await;
+                let pending = requests
+                    .lock()
+                    .await
+                    .iter()
+                    .filter_map(|job| job_origin(job))
+                    .collect::<Vec<_>>();
+                let running = jobs.running().await;
+                let busy_origins = running
+                    .iter()
+                    .filter_map(|job| job_origin(job))
+                    .collect::<HashSet<_>>();
+                let desired =
+                    desired_runners(&pending, &busy_origins, running.len(), pool.min, pool.max);
+                while template.is_running() && pool.count.load(Ordering::SeqCst) < desired {
+                    spawn_runner(&pool, &requests, &template);
                 }
-            }));
+            }
+        }));
+    }
+
+    /// Queue again the parked jobs waiting for a host that is no longer
+    /// paused; returns how many. The supervisor also does so every few seconds.
+    pub async fn unpark(&self) -> usize {
+        unpark_jobs(&self.jobs, &self.state, &self.profiles, &self.requests).await
+    }
+
+    /// Queue the jobs checkpointed by the last shutdown or `stop`, ahead of
+    /// those queued since
+    async fn resume_checkpoints(&self) -> usize {
+        let checkpoints = match self.state.checkpoints() {
+            Ok(checkpoints) => checkpoints,
+            Err(error) => {
+                tracing::warn!("failed to read checkpointed jobs: {error}");
+                return 0;
+            }
+        };
+        let mut resumed = Vec::new();
+        for (id, created_at, record) in checkpoints {
+            match self.jobs.resume(&id, created_at, &record).await {
+                Ok(job) => resumed.push(job),
+                Err(error) => tracing::warn!("failed to resume job {id}: {error:#}"),
+            }
+            if let Err(error) = self.state.remove_checkpoint(&id) {
+                tracing::warn!("failed to remove checkpoint of job {id}: {error}");
+            }
+        }
+        let count = resumed.len();
+        let mut requests = self.requests.lock().await;
+        for job in resumed.into_iter().rev() {
+            requests.push_front(job);
         }
+        count
     }
 }
 
+fn spawn_runner(pool: &Arc<RunnerPool>, requests: &RequestQueue, template: &CrawlRunner) {
+    let mut runner = template.clone();
+    runner.id = pool.next_id.fetch_add(1, Ordering::Relaxed);
+    runner.visitor = Arc::new(template.visitor.for_runner());
+    pool.count.fetch_add(1, Ordering::SeqCst);
+    let task_pool = pool.clone();
+    let requests = requests.clone();
+    let handle = tokio::spawn(async move {
+        let pool = task_pool;
+        let mut idle_since = Instant::now();
+        while runner.is_running() {
+            let job = requests.lock().await.pop_front();
+            if let Some(job) = job {
+                pool.busy.fetch_add(1, Ordering::Relaxed);
+                let span =
+                    tracing::info_span!("crawl", job = %job.id, request_id = %job.request_id);
+                request_id::scope(job.request_id.clone(), runner.crawl(&job).instrument(span))
+                    .await;
+                pool.busy.fetch_sub(1, Ordering::Relaxed);
+                runner.search_cache.flush();
+                if job.status().is_finished() {
+                    notify_completion(&runner.webhooks, job);
+                }
+                idle_since = Instant::now();
+            } else if idle_since.elapsed() >= RUNNER_IDLE_TIMEOUT && pool.try_retire() {
+                tracing::debug!("runner[{}] idle, stopping", runner.id);
+                return;
+            } else {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+        }
+        tracing::debug!("runner[{}] stopped", runner.id);
+        pool.count.fetch_sub(1, Ordering::SeqCst);
+    });
+    let mut handles = pool.handles.lock().unwrap();
+    handles.retain(|handle| !handle.is_finished());
+    handles.push(handle);
+}
+
+async fn unpark_jobs(
+    jobs: &CrawlJobs,
+    state: &StateStore,
+    profiles: &DomainProfiles,
+    requests: &RequestQueue,
+) -> usize {
+    let mut unparked = 0;
+    for job in jobs.parked().await {
+        if !job.unpark(|host| profiles.is_host_paused(host)) {
+            continue;
+        }
+        let record = job.checkpoint_record(true);
+        if let Err(error) = state.remove_checkpoint(&job.id) {
+            tracing::warn!("failed to remove checkpoint of job {}: {error}", job.id);
+        }
+        match jobs.resume(&job.id, job.created_at, &record).await {
+            Ok(resumed) => {
+                requests.lock().await.push_back(resumed);
+                unparked += 1;
+            }
+            Err(error) => tracing::warn!("failed to unpark job {}: {error:#}", job.id),
+        }
+    }
+    unparked
+}
+
+fn job_origin(job: &CrawlJob) -> Option<String> {
+    origin(&Url::parse(&job.request.url).ok()?)
+}
+
+/// One runner per running job, plus one for each pending origin nobody is crawling yet;
+/// more runners on an origin that is already busy would only wait on its rate limit
+fn desired_runners(
+    pending: &[String],
+    busy_origins: &HashSet<String>,
+    busy: usize,
+    min: usize,
+    max: usize,
+) -> usize {
+    let new_origins = pending
+        .iter()
+        .filter(|origin| !busy_origins.contains(*origin))
+        .collect::<HashSet<_>>()
+        .len();
+    (busy + new_origins).clamp(min, max)
+}
+
+/// Dropping the loop without `shutdown` abandons its runners mid-page
 impl Drop for CrawlLoop {
     fn drop(&mut self) {
-        self.shutdown.store(false, Ordering::Relaxed);
-        for runner in &self.runners {
+        if let Some(supervisor) = &self.supervisor {
+            supervisor.abort();
+        }
+        for runner in self.pool.handles.lock().unwrap().iter() {
             runner.abort();
         }
     }
 }
 
-async fn crawl_request(
-    id: usize,
-    request: CrawlRequest,
-    visitor: &Arc<WebVisitorImpl>,
-    robots: &Arc<RobotsCache>,
-    indexer: &Arc<PageIndexer>,
-    stats: &Arc<CrawlStats>,
-) {
-    let seed = normalize_url(&request.url).unwrap();
-    let mut frontier = VecDeque::from([QueuedUrl {
-        url: seed.clone(),
-        depth: 0,
-    }]);
-    let mut queued = HashSet::from([seed.to_string()]);
-    let mut visited = HashSet::new();
-    let mut blocked_origins = HashSet::new();
-    let mut pages = 0usize;
-    let mut indexed = 0usize;
-    let mut skipped_depth = 0usize;
-    let mut skipped_robots = 0usize;
-    let mut blocked = 0usize;
-    let mut failed = 0usize;
-
-    let seed_policy = robots.policy(&seed).await;
-    if seed_policy.allowed {
-        for url in sitemap::discover(
-            visitor.clone(),
-            &seed,
-            seed_policy.sitemaps,
-            request.max_pages,
-        )
-        .await
-        {
-            enqueue(&mut frontier, &mut queued, &visited, url, 1);
-        }
+impl CrawlRunner {
+    fn is_running(&self) -> bool {
+        *self.lifecycle.lock().unwrap() == Lifecycle::Running
     }
 
-    while let Some(item) = frontier.pop_front() {
-        queued.remove(item.url.as_str());
-        if pages >= request.max_pages || item.depth > request.max_depth {
-            if item.depth > request.max_depth {
-                skipped_depth += 1;
-                stats.inc_skipped_depth();
+    async fn crawl(&self, job: &CrawlJob) {
+        let Self {
+            id,
+            stats,
+            visitor,
+            robots,
+            product_token,
+            indexer,
+            webhooks,
+            state,
+            cluster,
+            frontier_memory_limit,
+            chunking: server_chunking,
+            max_pagination_pages,
+            skip_crawled_within_hours,
+            profiles,
+            ..
+        } = self;
+        let request = &job.request;
+        let deadline = request
+            .max_duration_secs
+            .map(|secs| Instant::now() + Duration::from_secs(secs));
+        // Dropped when the job ends; pages still queued are delivered after it
+        let page_webhook = request
+            .page_callback_url
+            .as_ref()
+            .map(|url| PageWebhook::start(webhooks.clone(), url.clone(), PAGE_QUEUE_CAPACITY));
+        // One identity for page fetches, robots.txt, sitemaps and the browser;
+        // the options were validated when the job was queued
+        let options = request_options(request).unwrap_or_default();
+        let job_visitor = (request.user_agent.is_some() || options != RequestOptions::default())
+            .then(|| {
+                let visitor = visitor.with_request_options(options);
+                Arc::new(match request.user_agent.as_deref() {
+                    Some(user_agent) => visitor.with_user_agent(user_agent),
+                    None => visitor,
+                })
+            });
+        let visitor = job_visitor.as_ref().unwrap_or(visitor);
+        let product_token = request
+            .user_agent
+            .as_deref()
+            .map_or(product_token.as_str(), product_token_of);
+        // Validated when the job was queued
+        let chunking = server_chunking
+            .with_overrides(
+                request.chunk_target_chars,
+                request.chunk_max_chars,
+                request.chunk_overlap_chars,
+            )
+            .unwrap_or(*server_chunking);
+        let keywords = KeywordFilter::new(&request.must_contain, &request.must_not_contain)
+            .unwrap_or_default();
+        let collection = tenant::collection_name(request.tenant.as_deref()).unwrap_or_default();
+        let seed = normalize_url(&request.url).unwrap();
+        job.start();
+        let focus = match &request.focus_query {
+            Some(query) => match indexer.embed_query(query).await {
+                Ok(vector) => Some(vector),
+                Err(error) => {
+                    tracing::warn!("job {} cannot embed its focus_query: {error}", job.id);
+                    job.emit(CrawlEvent::Error {
+                        url: seed.to_string(),
+                        message: format!("failed to embed focus_query: {error}"),
+                    });
+                    job.finish(JobStatus::Failed);
+                    return;
+                }
+            },
+            None => None,
+        };
+        let context = JobContext {
+            job,
+            visitor,
+            product_token,
+            collection,
+            keywords,
+            focus,
+            page_webhook,
+        };
+        let shared = match (&request.shared_frontier, cluster) {
+            (Some(name), Some(cluster)) => {
+                let shared = match cluster.frontier(name).await {
+                    Ok(frontier) => cluster
+                        .visited(name)
+                        .await
+                        .map(|visited| (frontier, visited)),
+                    Err(error) => Err(error),
+                };
+                match shared {
+                    Ok(shared) => Some(shared),
+                    Err(error) => {
+                        tracing::warn!("job {} cannot reach its shared frontier: {error}", job.id);
+                        job.emit(CrawlEvent::Error {
+                            url: seed.to_string(),
+                            message: format!("failed to reach shared frontier {name}: {error}"),
+                        });
+                        job.finish(JobStatus::Failed);
+                        return;
+                    }
+                }
             }
-            continue;
+            _ => None,
+        };
+        let (mut frontier, mut visited): (Box<dyn Frontier>, _) = match shared {
+            Some((frontier, visited)) => {
+                (Box::new(frontier), VisitedSet::Shared(Box::new(visited)))
+            }
+            None => (
+                Box::new(match job.resumed {
+                    true => LocalFrontier::resume(state.clone(), &job.id, *frontier_memory_limit),
+                    false => LocalFrontier::new(state.clone(), &job.id, *frontier_memory_limit),
+                }),
+                match request.bloom_fp_rate {
+                    Some(rate) => {
+                        VisitedSet::bloom(state.clone(), &job.id, request.max_pages, rate)
+                    }
+                    None => VisitedSet::exact(),
+                },
+            ),
+        };
+        if job.resumed {
+            if let Err(error) = visited.restore(state, &job.id) {
+                tracing::warn!("failed to restore visited URLs of job {}: {error}", job.id);
+            }
+        } else {
+            frontier.push(seed.clone(), 0, None).await;
         }
-        let url_key = item.url.to_string();
-        if visited.contains(&url_key)
-            || origin(&item.url).is_some_and(|value| blocked_origins.contains(&value))
+        let mut blocked_origins = HashSet::new();
+        let mut budget = match job.resumed {
+            true => job.budget(),
+            false => PageBudget::default(),
+        };
+        let max_pagination_pages = request
+            .max_pagination_pages
+            .unwrap_or(*max_pagination_pages);
+        // A link check fetches every page, and its fetches do not count as crawls
+        let linkcheck = request.mode == CrawlMode::Linkcheck;
+        let skip_crawled_within_hours = match linkcheck {
+            true => 0,
+            false => request
+                .skip_crawled_within_hours
+                .unwrap_or(*skip_crawled_within_hours),
+        };
+        if !linkcheck {
+            let collection = &context.collection;
+            if let Err(error) = indexer.ensure_collection(collection).await {
+                tracing::warn!(
+                    "job {} cannot create collection {collection}: {error}",
+                    job.id
+                );
+                job.emit(CrawlEvent::Error {
+                    url: seed.to_string(),
+                    message: format!("failed to create collection {collection}: {error}"),
+                });
+                job.finish(JobStatus::Failed);
+                return;
+            }
+        }
+        // Fetch times count per tenant, as tenants do not share pages
+        let fetch_key = |url: &str| match &request.tenant {
+            Some(tenant) => format!("{tenant} {url}"),
+            None => url.to_string(),
+        };
+        // Queued pagination pages, which bypass `max_pages` and `max_depth`
+        let mut pagination_urls = HashSet::new();
+        let mut pagination_queued = 0usize;
+        // URLs found broken by this job, whose later linking pages are recorded
+        let mut broken = HashMap::new();
+        let mut truncated = false;
+
+        // A paused seed host's robots.txt and sitemaps are not fetched; its
+        // pages wait in the frontier
+        let seed_paused = profiles.is_paused(&seed);
+        if !job.resumed && !seed_paused {
+            let seed_policy = robots.policy(&seed, visitor, product_token).await;
+            if seed_policy.allowed {
+                let pages = sitemap::discover(
+                    visitor.clone(),
+                    &seed,
+                    seed_policy.sitemaps,
+                    request.max_pages,
+                )
+                .await;
+                let unchanged = if request.incremental && !linkcheck {
+                    unchanged_pages(indexer, &context.collection, &seed, &pages).await
+                } else {
+                    HashSet::new()
+                };
+                job.update(|progress| progress.pages_unchanged = unchanged.len());
+                for (url, _) in pages {
+                    if unchanged.contains(url.as_str()) {
+                        // Not fetched, not even when a crawled page links to it
+                        if let Err(error) = state.record_page(&job.id, url.as_str(), None) {
+                            tracing::warn!("failed to record unchanged page {url}: {error}");
+                        }
+                        visited.insert(url.to_string()).await;
+                    } else {
+                        enqueue(frontier.as_mut(), &visited, url, 1, None).await;
+                    }
+                }
+            }
+        }
+        let host_budget_spent = |host_pages: &HashMap<String, usize>, url: &Url| {
+            request.max_pages_per_host.is_some_and(|limit| {
+                host_pages
+                    .get(url.host_str().unwrap_or_default())
+                    .is_some_and(|&count| count >= limit)
+            })
+        };
+
+        // URLs in a row put back for a paused host, and their hosts
+        let mut deferred = 0usize;
+        let mut paused_hosts = HashSet::new();
+        loop {
+            // A stop lets the page in flight finish and keeps the rest
+            if !self.is_running() {
+                self.checkpoint(job, frontier.as_mut(), &visited, &budget)
+                    .await;
+                visitor.clear_cookies();
+                return;
+            }
+            let Some(item) = frontier.pop().await else {
+                break;
+            };
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                tracing::info!(
+                    "runner[{id}] job {} reached max_duration_secs with {} URLs left",
+                    job.id,
+                    frontier.len().await + 1
+                );
+                truncated = true;
+                break;
+            }
+            let paginated = pagination_urls.remove(item.url.as_str());
+            if !paginated && (budget.pages >= request.max_pages || item.depth > request.max_depth) {
+                if item.depth > request.max_depth {
+                    job.update(|progress| progress.pages_skipped_depth += 1);
+                    stats.inc_skipped_depth();
+                }
+                continue;
+            }
+            let url_key = item.url.to_string();
+            let profile = profiles.for_url(&item.url);
+            if visited.contains(&url_key).await
+                || host_budget_spent(&budget.host_pages, &item.url)
+                || origin(&item.url).is_some_and(|value| blocked_origins.contains(&value))
+                || (item.url != seed && profile.as_ref().is_some_and(|p| !p.includes(&item.url)))
+            {
+                continue;
+            }
+            if profiles.is_paused(&item.url) {
+                if paginated {
+                    pagination_urls.insert(url_key);
+                }
+                paused_hosts.insert(item.url.host_str().unwrap_or_default().to_string());
+                frontier
+                    .push(item.url, item.depth, item.referrer.as_deref())
+                    .await;
+                deferred += 1;
+                if deferred > frontier.len().await {
+                    // Every URL left is on a paused host: free the runner
+                    // until one of them is resumed
+                    self.save_checkpoint(job, frontier.as_mut(), &visited, &budget)
+                        .await;
+                    job.park(paused_hosts);
+                    visitor.clear_cookies();
+                    return;
+                }
+                continue;
+            }
+            deferred = 0;
+            paused_hosts.clear();
+            let render = profile.as_ref().map(|p| p.render).unwrap_or_default();
+            let use_browser =
+                !linkcheck && (request.use_browser || render == RenderStrategy::Browser);
+            let chunking = profile.as_ref().map_or(chunking, |p| {
+                p.chunking(
+                    *server_chunking,
+                    request.chunk_target_chars,
+                    request.chunk_max_chars,
+                    request.chunk_overlap_chars,
+                )
+            });
+            visited.insert(url_key.clone()).await;
+
+            // The seed is always fetched so a repeated crawl still finds links
+            let skip_hours = match item.url == seed {
+                true => 0,
+                false => skip_crawled_within_hours,
+            };
+            if !self
+                .admits(&context, &item.url, &fetch_key(&url_key), skip_hours)
+                .await
+            {
+                continue;
+            }
+
+            let Some((final_url, mut html)) = self
+                .fetch(
+                    &context,
+                    &item,
+                    use_browser,
+                    &mut blocked_origins,
+                    &mut broken,
+                )
+                .await
+            else {
+                continue;
+            };
+            visited.insert(final_url.to_string()).await;
+            let fetched_at = unix_now();
+            for url in [url_key.as_str(), final_url.as_str()] {
+                if !linkcheck {
+                    if let Err(error) = state.record_fetch(&fetch_key(url), fetched_at) {
+                        tracing::warn!("failed to record fetch of {url}: {error}");
+                    }
+                }
+                if let Err(error) = state.clear_broken_link(url) {
+                    tracing::warn!("failed to clear broken link {url}: {error}");
+                }
+            }
+            if is_crawl_trap(&final_url) {
+                continue;
+            }
+
+            if !linkcheck && !use_browser && render != RenderStrategy::Http && needs_browser(&html)
+            {
+                if let Ok(browser_html) = BrowserPool::fetch_page_with_options(
+                    final_url.as_str(),
+                    visitor.user_agent(),
+                    request.wait_for_selector.as_deref(),
+                    request.wait_timeout_ms,
+                    request.scroll_count,
+                )
+                .await
+                {
+                    if !browser_html.trim().is_empty() {
+                        html = browser_html;
+                    }
+                }
+            }
+
+            if !paginated {
+                budget.pages += 1;
+            }
+            *budget
+                .host_pages
+                .entry(item.url.host_str().unwrap_or_default().to_string())
+                .or_insert(0) += 1;
+            stats.inc_crawled();
+            job.emit(CrawlEvent::Fetched {
+                url: final_url.to_string(),
+            });
+            job.update(|progress| progress.pages_crawled += 1);
+
+            let (pagination, links) = if linkcheck {
+                // Pages of other sites are only checked, not followed
+                if request.same_domain && !same_origin(&seed, &final_url) {
+                    (Vec::new(), Vec::new())
+                } else {
+                    page_links(&final_url, &html)
+                }
+            } else {
+                self.process_page(&context, &final_url, &html, &chunking, item.depth)
+                    .await
+            };
+            for link in pagination {
+                if pagination_queued >= max_pagination_pages {
+                    break;
+                }
+                if host_budget_spent(&budget.host_pages, &link) {
+                    continue;
+                }
+                let key = link.to_string();
+                if enqueue(
+                    frontier.as_mut(),
+                    &visited,
+                    link,
+                    item.depth,
+                    Some(final_url.as_str()),
+                )
+                .await
+                {
+                    pagination_urls.insert(key);
+                    pagination_queued += 1;
+                }
+            }
+            for ExtractedLink { url: link, .. } in links {
+                if (request.same_domain && !linkcheck && !same_origin(&seed, &link))
+                    || host_budget_spent(&budget.host_pages, &link)
+                {
+                    continue;
+                }
+                if let Some(reason) = broken.get(link.as_str()) {
+                    record_broken_link(state, link.as_str(), final_url.as_str(), reason);
+                    continue;
+                }
+                enqueue(
+                    frontier.as_mut(),
+                    &visited,
+                    link,
+                    item.depth + 1,
+                    Some(final_url.as_str()),
+                )
+                .await;
+            }
+            job.set_next_urls(frontier.next_urls(NEXT_URLS_PREVIEW).await);
+            let (frontier_size, visited_count) = (frontier.len().await, visited.len().await);
+            job.update(|progress| {
+                progress.frontier_size = frontier_size;
+                progress.visited = visited_count;
+            });
+        }
+        let visited_count = visited.len().await;
+        job.update(|progress| {
+            progress.frontier_size = 0;
+            progress.visited = visited_count;
+        });
+        job.finish(if truncated {
+            JobStatus::Truncated
+        } else {
+            JobStatus::Completed
+        });
+        let finished_at = job.summary().finished_at.unwrap_or_default();
+        if let Err(error) = state.record_job(&job.id, finished_at, &job.history_record()) {
+            tracing::warn!("failed to record job {} in the history: {error}", job.id);
+        }
+        // Without page versions a link check has nothing to diff
+        if !linkcheck {
+            if let Err(error) =
+                state.record_crawl(&job.id, seed.host_str().unwrap_or_default(), finished_at)
+            {
+                tracing::warn!("failed to record pages of job {}: {error}", job.id);
+            }
+        }
+        let progress = job.progress();
+        tracing::info!(
+            "runner[{id}] finished crawl job={} seed={} crawled={} indexed={} visited={} skipped_robots={} skipped_depth={} skipped_low_quality={} blocked={} failed={} max_pages={} max_depth={} same_domain={}",
+            job.id,
+            seed,
+            progress.pages_crawled,
+            progress.pages_indexed,
+            progress.visited,
+            progress.pages_skipped_robots,
+            progress.pages_skipped_depth,
+            progress.pages_skipped_low_quality,
+            progress.pages_blocked,
+            progress.pages_failed,
+            request.max_pages,
+            request.max_depth,
+            request.same_domain,
+        );
+        frontier.clear();
+        visited.clear();
+        if job.resumed {
+            // An exact visited set leaves its checkpointed URLs behind
+            if let Err(error) = state.clear_job(&job.id) {
+                tracing::warn!("failed to clear checkpoint of job {}: {error}", job.id);
+            }
+        }
+        visitor.clear_cookies();
+    }
+
+    /// Whether `url` may be fetched: not fetched within
+    /// `skip_crawled_within_hours` under `fetch_key` and allowed by
+    /// robots.txt. Skipped URLs are reported on the job.
+    async fn admits(
+        &self,
+        context: &JobContext<'_>,
+        url: &Url,
+        fetch_key: &str,
+        skip_crawled_within_hours: u64,
+    ) -> bool {
+        let job = context.job;
+        if skip_crawled_within_hours > 0
+            && self
+                .state
+                .fetched_within(fetch_key, skip_crawled_within_hours, unix_now())
+                .unwrap_or_else(|error| {
+                    tracing::warn!("failed to look up last fetch of {url}: {error}");
+                    false
+                })
         {
-            continue;
+            job.emit(CrawlEvent::Skipped {
+                url: url.to_string(),
+                reason: "recently_crawled",
+            });
+            job.update(|progress| progress.pages_skipped_recent += 1);
+            return false;
         }
-        visited.insert(url_key.clone());
 
-        let policy = robots.policy(&item.url).await;
+        let policy = self
+            .robots
+            .policy(url, context.visitor, context.product_token)
+            .await;
         if !policy.allowed {
-            skipped_robots += 1;
-            stats.inc_skipped_robots();
-            continue;
+            job.emit(CrawlEvent::Skipped {
+                url: url.to_string(),
+                reason: "robots",
+            });
+            job.update(|progress| progress.pages_skipped_robots += 1);
+            self.stats.inc_skipped_robots();
+            return false;
         }
+        true
+    }
 
-        let fetched = if request.use_browser {
-            BrowserPool::fetch_page_with_options(
-                item.url.as_str(),
-                request.wait_for_selector.as_deref(),
-                request.wait_timeout_ms,
-            )
-            .await
-            .map(|html| (item.url.clone(), html))
-            .map_err(|error| FetchError::Redirect(error.to_string()))
+    /// `item` as `(final URL, HTML)`, fetched over HTTP or in the browser.
+    /// Failures are reported on the job; blocked origins and broken links are
+    /// remembered for the rest of it.
+    async fn fetch(
+        &self,
+        context: &JobContext<'_>,
+        item: &QueuedUrl,
+        use_browser: bool,
+        blocked_origins: &mut HashSet<String>,
+        broken: &mut HashMap<String, &'static str>,
+    ) -> Option<(Url, String)> {
+        let Self { id, stats, .. } = self;
+        let (job, visitor) = (context.job, context.visitor);
+        let request = &job.request;
+        let url_key = item.url.to_string();
+        let fetched = if use_browser {
+            match visitor.check_address(&item.url).await {
+                Ok(()) => BrowserPool::fetch_page_with_options(
+                    item.url.as_str(),
+                    visitor.user_agent(),
+                    request.wait_for_selector.as_deref(),
+                    request.wait_timeout_ms,
+                    request.scroll_count,
+                )
+                .await
+                .map(|html| {
+                    job.record(|report| report.fetched("browser", html.len()));
+                    (item.url.clone(), html)
+                })
+                .map_err(|error| {
+                    match private_network::blocked_host(error.as_ref()) {
+                        Some(host) => FetchError::PrivateAddress(host),
+                        None => FetchError::Browser(format!("{error:#}")),
+                    }
+                }),
+                Err(error) => Err(error),
+            }
         } else {
             visitor.fetch_html(item.url.as_str()).await.map(|result| {
+                stats.inc_protocol(result.version);
+                job.record(|report| report.fetched(result.status.as_u16(), result.body.len()));
                 (
                     result.final_url,
                     String::from_utf8_lossy(&result.body).into_owned(),
@@ -182,92 +1265,475 @@ async fn crawl_request(
             })
         };
 
-        let (final_url, mut html) = match fetched {
-            Ok(value) => value,
+        match fetched {
+            Ok(value) => Some(value),
             Err(FetchError::Blocked(value)) => {
-                blocked += 1;
+                job.record(|report| report.fetched(StatusCode::FORBIDDEN.as_u16(), 0));
+                job.emit(CrawlEvent::Skipped {
+                    url: url_key,
+                    reason: "blocked",
+                });
+                job.update(|progress| progress.pages_blocked += 1);
                 if let Ok(url) = Url::parse(&value) {
                     if let Some(origin) = origin(&url) {
                         blocked_origins.insert(origin);
                     }
                 }
-                continue;
+                None
+            }
+            Err(FetchError::PrivateAddress(host)) => {
+                tracing::warn!("runner[{id}] refused {}: {host} is private", item.url);
+                job.emit(CrawlEvent::Skipped {
+                    url: url_key,
+                    reason: "private_address",
+                });
+                None
             }
             Err(error) => {
+                if let FetchError::Http(status) = &error {
+                    job.record(|report| report.fetched(status.as_u16(), 0));
+                }
                 tracing::warn!("runner[{id}] failed {}: {error}", item.url);
-                failed += 1;
-                stats.inc_failed();
-                continue;
+                if let (Some(reason), Some(source)) =
+                    (error.broken_reason(), item.referrer.as_deref())
+                {
+                    record_broken_link(&self.state, &url_key, source, reason);
+                    broken.insert(url_key.clone(), reason);
+                }
+                job.emit(CrawlEvent::Error {
+                    url: url_key,
+                    message: error.to_string(),
+                });
+                job.update(|progress| progress.pages_failed += 1);
+                job.record(|report| report.failed(error.kind()));
+                stats.inc_failed(error.kind());
+                None
             }
-        };
-        visited.insert(final_url.to_string());
-        if is_crawl_trap(&final_url) {
-            continue;
         }
+    }
 
-        if !request.use_browser && needs_browser(&html) {
-            if let Ok(browser_html) = BrowserPool::fetch_page_with_options(
-                final_url.as_str(),
-                request.wait_for_selector.as_deref(),
-                request.wait_timeout_ms,
-            )
-            .await
-            {
-                if !browser_html.trim().is_empty() {
-                    html = browser_html;
+    /// Extract a fetched page, record its version and SEO audit and index it
+    /// unless the quality, keyword, focus or content filter turns it down;
+    /// returns the pagination and links to follow
+    async fn process_page(
+        &self,
+        context: &JobContext<'_>,
+        final_url: &Url,
+        html: &str,
+        chunking: &ChunkOptions,
+        depth: usize,
+    ) -> (Vec<Url>, Vec<ExtractedLink>) {
+        let Self {
+            stats,
+            indexer,
+            state,
+            quality,
+            page_quality,
+            content_filter,
+            ..
+        } = self;
+        let job = context.job;
+        let request = &job.request;
+        let extracted = extract_page(final_url, html, chunking);
+        let rel_canonical = extracted
+            .seo
+            .canonical
+            .as_deref()
+            .and_then(|canonical| Url::parse(canonical).ok());
+        let version = page_version(&extracted.chunks);
+        if let Err(error) = state.record_page(&job.id, final_url.as_str(), Some(&version)) {
+            tracing::warn!("failed to record page version of {final_url}: {error}");
+        }
+        if let Err(error) = state.record_seo_audit(
+            final_url.as_str(),
+            final_url.host_str().unwrap_or_default(),
+            &extracted.seo,
+            unix_now(),
+        ) {
+            tracing::warn!("failed to record SEO audit of {final_url}: {error}");
+        }
+        let thin = page_quality.rejects(&extracted.metrics);
+        let unwanted = match &thin {
+            None => context.keywords.rejects(&extracted.chunks),
+            Some(_) => None,
+        };
+        let off_topic = match (&context.focus, &thin) {
+            (Some(focus), None) if unwanted.is_none() => {
+                match indexer.relevance(focus, &extracted.chunks).await {
+                    Ok(similarity) => similarity < request.focus_threshold,
+                    Err(error) => {
+                        tracing::warn!("failed to rate relevance of {final_url}: {error}");
+                        false
+                    }
                 }
             }
+            _ => false,
+        };
+        let labels = match (&thin, content_filter) {
+            (None, Some(filter)) if !off_topic && unwanted.is_none() => {
+                filter.classify(final_url.as_str(), &extracted.chunks).await
+            }
+            _ => Vec::new(),
+        };
+        if !labels.is_empty() {
+            tracing::debug!("{final_url} flagged as {}", labels.join(", "));
+            job.update(|progress| progress.pages_flagged_unsafe += 1);
+        }
+        if let Some(reason) = thin {
+            tracing::debug!("not indexing thin page {final_url}: {reason}");
+            job.emit(CrawlEvent::Skipped {
+                url: final_url.to_string(),
+                reason: "low_quality",
+            });
+            job.update(|progress| progress.pages_skipped_low_quality += 1);
+            stats.inc_skipped_low_quality();
+        } else if let Some(reason) = unwanted {
+            tracing::debug!("not indexing {final_url}: {reason}");
+            job.emit(CrawlEvent::Skipped {
+                url: final_url.to_string(),
+                reason: "keywords",
+            });
+            job.update(|progress| progress.pages_skipped_keywords += 1);
+        } else if off_topic {
+            job.emit(CrawlEvent::Skipped {
+                url: final_url.to_string(),
+                reason: "off_topic",
+            });
+            job.update(|progress| progress.pages_skipped_off_topic += 1);
+        } else if !labels.is_empty()
+            && content_filter
+                .as_ref()
+                .is_some_and(|filter| filter.action == FilterAction::Skip)
+        {
+            job.emit(CrawlEvent::Skipped {
+                url: final_url.to_string(),
+                reason: "unsafe",
+            });
+        } else {
+            let mut page_chunks = indexer
+                .fit_token_limit(extracted.chunks, chunking.max_tokens)
+                .await;
+            quality.apply(&mut page_chunks);
+            self.index_chunks(
+                context,
+                final_url,
+                html,
+                page_chunks,
+                !labels.is_empty(),
+                rel_canonical.as_ref(),
+            )
+            .await;
         }
 
-        let extracted = extract_page(&final_url, &html);
-        pages += 1;
-        stats.inc_crawled();
-        let has_chunks = !extracted.chunks.is_empty();
-        if let Err(error) = indexer.index_page(&extracted.chunks).await {
-            tracing::warn!("failed to index {final_url}: {error}");
-            failed += 1;
-            stats.inc_failed();
-        } else if has_chunks {
-            indexed += 1;
-            stats.inc_indexed();
+        // The seed's links are followed even when it is off topic itself,
+        // as it is often a hub page
+        if off_topic && depth > 0 {
+            (Vec::new(), Vec::new())
+        } else {
+            (extracted.pagination, extracted.links)
         }
+    }
 
-        for link in extracted.links {
-            if request.same_domain && !same_origin(&seed, &link) {
-                continue;
+    /// Index a page's chunks under its preferred URL variant, then hand it to
+    /// the page webhook, page events and the search cache
+    async fn index_chunks(
+        &self,
+        context: &JobContext<'_>,
+        final_url: &Url,
+        html: &str,
+        mut page_chunks: Vec<WebPageChunk>,
+        unsafe_content: bool,
+        rel_canonical: Option<&Url>,
+    ) {
+        let Self {
+            stats,
+            indexer,
+            archive,
+            search_cache,
+            page_events,
+            state,
+            ..
+        } = self;
+        let job = context.job;
+        let request = &job.request;
+        let collection = &context.collection;
+        let source_url = match state.canonical_url(collection, final_url, rel_canonical) {
+            Ok((source_url, Some(replaced))) => {
+                if let Err(error) = indexer.move_page(collection, &replaced, &source_url).await {
+                    tracing::warn!("failed to move {replaced} to {source_url}: {error:#}");
+                }
+                source_url
+            }
+            Ok((source_url, None)) => source_url,
+            Err(error) => {
+                tracing::warn!("failed to look up URL variants of {final_url}: {error}");
+                final_url.to_string()
             }
-            enqueue(&mut frontier, &mut queued, &visited, link, item.depth + 1);
-        }
-    }
-    tracing::info!(
-        "runner[{id}] finished crawl seed={} crawled={} indexed={} visited={} skipped_robots={} skipped_depth={} blocked={} failed={} max_pages={} max_depth={} same_domain={}",
-        seed,
-        pages,
-        indexed,
-        visited.len(),
-        skipped_robots,
-        skipped_depth,
-        blocked,
-        failed,
-        request.max_pages,
-        request.max_depth,
-        request.same_domain,
-    );
+        };
+        for chunk in &mut page_chunks {
+            chunk.source_url = source_url.clone();
+            chunk.unsafe_content = unsafe_content;
+            chunk.collection = request.collection.clone();
+        }
+        let chunks = page_chunks.len();
+        let archive_key = match archive {
+            Some(archive) => match archive.store(html).await {
+                Ok(key) => Some(key),
+                Err(error) => {
+                    tracing::warn!("failed to archive {final_url}: {error:#}");
+                    None
+                }
+            },
+            None => None,
+        };
+        let outcome = match indexer
+            .index_page(collection, &page_chunks, archive_key.as_deref())
+            .await
+        {
+            Ok(outcome) => outcome,
+            Err(error) => {
+                tracing::warn!("failed to index {final_url}: {error}");
+                job.emit(CrawlEvent::Error {
+                    url: final_url.to_string(),
+                    message: error.to_string(),
+                });
+                job.update(|progress| progress.pages_failed += 1);
+                job.record(|report| report.failed(FailureKind::Index));
+                stats.inc_failed(FailureKind::Index);
+                return;
+            }
+        };
+        job.record(|report| report.indexed(outcome));
+        if chunks == 0 {
+            return;
+        }
+        if let Some(page_webhook) = &context.page_webhook {
+            let queued = page_webhook.send(PageDelivery {
+                job_id: job.id.clone(),
+                url: source_url.clone(),
+                title: page_chunks[0].page_title.clone(),
+                outcome: outcome_name(outcome),
+                chunks: page_chunks.clone(),
+            });
+            if !queued {
+                job.update(|progress| progress.page_deliveries_dropped += 1);
+            }
+        }
+        page_events.page_indexed(PageIndexed {
+            url: source_url,
+            title: page_chunks[0].page_title.clone(),
+            chunk_count: chunks,
+            content_hash: page_version(&page_chunks),
+            outcome: outcome_name(outcome),
+            job_id: job.id.clone(),
+            collection: request.collection.clone(),
+            crawled_at: page_chunks[0].crawled_at,
+        });
+        job.emit(CrawlEvent::Indexed {
+            url: final_url.to_string(),
+            chunks,
+        });
+        job.update(|progress| progress.pages_indexed += 1);
+        stats.inc_indexed();
+        search_cache.page_indexed(final_url);
+    }
+
+    /// Keep an interrupted job's frontier and visited set for the next start
+    async fn checkpoint(
+        &self,
+        job: &CrawlJob,
+        frontier: &mut dyn Frontier,
+        visited: &VisitedSet,
+        budget: &PageBudget,
+    ) {
+        self.save_checkpoint(job, frontier, visited, budget).await;
+        job.finish(JobStatus::Interrupted);
+    }
+
+    async fn save_checkpoint(
+        &self,
+        job: &CrawlJob,
+        frontier: &mut dyn Frontier,
+        visited: &VisitedSet,
+        budget: &PageBudget,
+    ) {
+        job.set_budget(budget);
+        let saved = frontier
+            .checkpoint()
+            .and_then(|()| visited.checkpoint(&self.state, &job.id))
+            .and_then(|()| {
+                self.state
+                    .checkpoint_job(&job.id, job.created_at, &job.checkpoint_record(true))
+            });
+        let (frontier_size, visited_count) = (frontier.len().await, visited.len().await);
+        job.update(|progress| {
+            progress.frontier_size = frontier_size;
+            progress.visited = visited_count;
+        });
+        match saved {
+            Ok(()) => tracing::info!(
+                "runner[{}] checkpointed job {} with {frontier_size} URLs left",
+                self.id,
+                job.id,
+            ),
+            Err(error) => tracing::warn!("failed to checkpoint job {}: {error:#}", job.id),
+        }
+    }
 }
 
-fn enqueue(
-    frontier: &mut VecDeque<QueuedUrl>,
-    queued: &mut HashSet<String>,
-    visited: &HashSet<String>,
+/// How long `shutdown` waits for runners to finish their page, from
+/// `SPIDER_SHUTDOWN_GRACE_SECS`
+pub fn shutdown_grace_from_env() -> Duration {
+    env::var("SPIDER_SHUTDOWN_GRACE_SECS")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .map_or(DEFAULT_SHUTDOWN_GRACE, Duration::from_secs)
+}
+
+fn notify_completion(webhooks: &Arc<WebhookNotifier>, job: Arc<CrawlJob>) {
+    let Some(callback_url) = job.request.callback_url.clone() else {
+        return;
+    };
+    let webhooks = webhooks.clone();
+    tokio::spawn(async move {
+        if let Err(error) = webhooks.notify(&callback_url, &job.summary()).await {
+            tracing::warn!(
+                "webhook for job {} to {callback_url} failed: {error}",
+                job.id
+            );
+        }
+    });
+}
+
+/// The HTTP overrides of `request`: a timeout of 1 to 300 seconds, a body
+/// limit of 64 KiB to 64 MiB and at most 20 valid headers the crawler does
+/// not set itself
+fn request_options(request: &CrawlRequest) -> Result<RequestOptions, Problem> {
+    let timeout = match request.request_timeout_secs {
+        Some(secs) if !(1..=MAX_REQUEST_TIMEOUT_SECS).contains(&secs) => {
+            return Err(Problem::bad_request(
+                "request_timeout_secs_out_of_range",
+                format!("request_timeout_secs must be between 1 and {MAX_REQUEST_TIMEOUT_SECS}"),
+            ));
+        }
+        secs => secs.map(Duration::from_secs),
+    };
+    if request
+        .max_body_bytes
+        .is_some_and(|bytes| !(MIN_BODY_BYTES..=MAX_BODY_BYTES).contains(&bytes))
+    {
+        return Err(Problem::bad_request(
+            "max_body_bytes_out_of_range",
+            format!("max_body_bytes must be between {MIN_BODY_BYTES} and {MAX_BODY_BYTES}"),
+        ));
+    }
+    if request.headers.len() > MAX_EXTRA_HEADERS {
+        return Err(Problem::bad_request(
+            "invalid_headers",
+            format!("at most {MAX_EXTRA_HEADERS} headers"),
+        ));
+    }
+    let mut headers = HeaderMap::new();
+    for (name, value) in &request.headers {
+        let invalid = |detail: String| Problem::bad_request("invalid_headers", detail);
+        let header = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| invalid(format!("invalid header name {name:?}")))?;
+        if RESERVED_HEADERS.contains(&header.as_str()) {
+            return Err(invalid(format!("{header} cannot be overridden")));
+        }
+        let value = HeaderValue::from_str(value.trim())
+            .map_err(|_| invalid(format!("invalid value for {header}")))?;
+        headers.insert(header, value);
+    }
+    Ok(RequestOptions {
+        timeout,
+        max_html_bytes: request.max_body_bytes,
+        headers,
+    })
+}
+
+/// A per-crawl User-Agent must be a single header line that still names a
+/// robots.txt product token
+fn validate_user_agent(user_agent: &str) -> Result<(), Problem> {
+    let valid = !user_agent.trim().is_empty()
+        && user_agent.len() <= MAX_USER_AGENT_LEN
+        && !user_agent.chars().any(char::is_control)
+        && !product_token_of(user_agent).is_empty();
+    if valid {
+        Ok(())
+    } else {
+        Err(Problem::bad_request(
+            "invalid_user_agent",
+            format!(
+                "user_agent must be a product token such as \"ExampleBot/1.0 (+https://example.com/bot)\", at most {MAX_USER_AGENT_LEN} characters"
+            ),
+        ))
+    }
+}
+
+/// Sitemap pages not modified since they were last indexed. The seed is
+/// always fetched so new links are still found; if the index cannot be
+/// asked, every page counts as changed.
+async fn unchanged_pages(
+    indexer: &PageIndexer,
+    collection: &str,
+    seed: &Url,
+    pages: &[sitemap::DatedUrl],
+) -> HashSet<String> {
+    let dated = pages
+        .iter()
+        .filter(|(url, lastmod)| lastmod.is_some() && url != seed)
+        .map(|(url, _)| url.to_string())
+        .collect::<Vec<_>>();
+    let crawled = match indexer.last_crawled(collection, &dated).await {
+        Ok(crawled) => crawled,
+        Err(error) => {
+            tracing::warn!("incremental crawl of {seed} falls back to a full crawl: {error}");
+            return HashSet::new();
+        }
+    };
+    pages
+        .iter()
+        .filter_map(|(url, lastmod)| {
+            let crawled_at = crawled.get(url.as_str())?;
+            not_modified_since((*lastmod)?, *crawled_at).then(|| url.to_string())
+        })
+        .collect()
+}
+
+/// Whether a page whose sitemap `lastmod` is this is unchanged since it was
+/// crawled. A date without a time reads as midnight UTC, but the page may
+/// have changed any time that day, so it only counts for later days.
+fn not_modified_since(lastmod: i64, crawled_at: i64) -> bool {
+    const DAY: i64 = 24 * 60 * 60;
+    if lastmod.rem_euclid(DAY) == 0 {
+        lastmod.div_euclid(DAY) < crawled_at.div_euclid(DAY)
+    } else {
+        lastmod <= crawled_at
+    }
+}
+
+/// Returns whether the URL was queued
+async fn enqueue(
+    frontier: &mut dyn Frontier,
+    visited: &VisitedSet,
     url: Url,
     depth: usize,
-) {
-    if is_crawl_trap(&url) {
-        return;
+    referrer: Option<&str>,
+) -> bool {
+    if is_crawl_trap(&url) || visited.contains(url.as_str()).await {
+        return false;
     }
-    let key = url.to_string();
-    if !visited.contains(&key) && queued.insert(key) {
-        frontier.push_back(QueuedUrl { url, depth });
+    frontier.push(url, depth, referrer).await
+}
+
+fn record_broken_link(state: &StateStore, url: &str, source: &str, reason: &str) {
+    let host = Url::parse(source)
+        .ok()
+        .and_then(|source| source.host_str().map(str::to_string))
+        .unwrap_or_default();
+    if let Err(error) = state.record_broken_link(url, source, &host, reason, unix_now()) {
+        tracing::warn!("failed to record broken link {url}: {error}");
     }
 }
 
@@ -296,6 +1762,54 @@ fn is_crawl_trap(url: &Url) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain_profile::{DomainProfile, MIN_REQUEST_INTERVAL_MS};
+    use std::collections::BTreeMap;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn stops_and_starts_the_runners() {
+        let mut crawl_loop = CrawlLoop::new(
+            Arc::new(CrawlStats::default()),
+            Arc::new(CrawlJobs::new()),
+            Arc::new(PageIndexer::from_env().unwrap()),
+            None,
+            Arc::new(StateStore::in_memory().unwrap()),
+            "spider".to_string(),
+            "spider/1.0".to_string(),
+        );
+        assert_eq!(crawl_loop.lifecycle(), Lifecycle::Starting);
+        assert_eq!(crawl_loop.start().await.unwrap(), 0);
+        assert_eq!(crawl_loop.lifecycle(), Lifecycle::Running);
+        assert!(crawl_loop.runner_counts().0 > 0);
+
+        assert_eq!(crawl_loop.stop(), Lifecycle::Draining);
+        assert_eq!(
+            crawl_loop.start().await.unwrap_err().code,
+            "crawler_draining"
+        );
+        while crawl_loop.lifecycle() == Lifecycle::Draining {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(crawl_loop.lifecycle(), Lifecycle::Stopped);
+        assert_eq!(crawl_loop.runner_counts().0, 0);
+        assert_eq!(crawl_loop.stop(), Lifecycle::Stopped);
+
+        crawl_loop.start().await.unwrap();
+        assert_eq!(crawl_loop.lifecycle(), Lifecycle::Running);
+    }
+
+    #[test]
+    fn compares_date_only_lastmod_by_day() {
+        // 2026-01-02T00:00:00Z, as `2026-01-02` parses
+        let date = 1_767_312_000;
+        assert!(!not_modified_since(date, date + 12 * 60 * 60));
+        assert!(not_modified_since(date, date + 24 * 60 * 60));
+        assert!(!not_modified_since(date, date - 1));
+
+        let timed = date + 9 * 60 * 60;
+        assert!(not_modified_since(timed, timed + 60));
+        assert!(!not_modified_since(timed, timed - 60));
+    }
 
     #[test]
     fn rejects_common_crawl_traps() {
@@ -314,27 +1828,248 @@ mod tests {
     }
 
     #[test]
-    fn enqueue_skips_crawl_traps() {
-        let mut frontier = VecDeque::new();
-        let mut queued = HashSet::new();
-        let visited = HashSet::new();
+    fn validates_request_overrides() {
+        let request = |overrides: serde_json::Value| {
+            let mut body = serde_json::json!({ "url": "https://example.com/", "max_pages": 1 });
+            body.as_object_mut()
+                .unwrap()
+                .extend(overrides.as_object().unwrap().clone());
+            serde_json::from_value::<CrawlRequest>(body).unwrap()
+        };
+
+        let options = request_options(&request(serde_json::json!({
+            "request_timeout_secs": 60,
+            "max_body_bytes": 1048576,
+            "headers": { "Accept-Language": "de-DE", "X-Api-Key": " secret " }
+        })))
+        .unwrap();
+        assert_eq!(options.timeout, Some(Duration::from_secs(60)));
+        assert_eq!(options.max_html_bytes, Some(1_048_576));
+        assert_eq!(options.headers["accept-language"], "de-DE");
+        assert_eq!(options.headers["x-api-key"], "secret");
+        assert_eq!(
+            request_options(&request(serde_json::json!({}))).unwrap(),
+            RequestOptions::default()
+        );
+
+        for (overrides, code) in [
+            (
+                serde_json::json!({ "request_timeout_secs": 0 }),
+                "request_timeout_secs_out_of_range",
+            ),
+            (
+                serde_json::json!({ "max_body_bytes": 10 }),
+                "max_body_bytes_out_of_range",
+            ),
+            (
+                serde_json::json!({ "headers": { "Cookie": "a=b" } }),
+                "invalid_headers",
+            ),
+            (
+                serde_json::json!({ "headers": { "bad name": "x" } }),
+                "invalid_headers",
+            ),
+        ] {
+            assert_eq!(request_options(&request(overrides)).unwrap_err().code, code);
+        }
+    }
+
+    #[test]
+    fn scales_runners_with_distinct_pending_origins() {
+        let pending = [
+            "https://a.com",
+            "https://a.com",
+            "https://b.com",
+            "https://c.com",
+        ]
+        .map(String::from);
+        let busy = HashSet::from(["https://c.com".to_string()]);
+
+        assert_eq!(desired_runners(&pending, &busy, 1, 1, 8), 3);
+        assert_eq!(desired_runners(&pending, &busy, 1, 1, 2), 2);
+        assert_eq!(desired_runners(&[], &HashSet::new(), 0, 1, 8), 1);
+    }
+
+    #[tokio::test]
+    async fn enqueue_skips_crawl_traps() {
+        let store = Arc::new(StateStore::in_memory().unwrap());
+        let mut frontier = LocalFrontier::new(store, "job", 10);
+        let visited = VisitedSet::exact();
 
         enqueue(
             &mut frontier,
-            &mut queued,
             &visited,
             Url::parse("https://example.com/login").unwrap(),
             1,
-        );
+            None,
+        )
+        .await;
         enqueue(
             &mut frontier,
-            &mut queued,
             &visited,
             Url::parse("https://example.com/article").unwrap(),
             1,
+            Some("https://example.com/"),
+        )
+        .await;
+
+        assert_eq!(frontier.len().await, 1);
+        assert_eq!(
+            frontier.pop().await.unwrap().url.as_str(),
+            "https://example.com/article"
+        );
+    }
+
+    /// Serves `pages` as `(path, status, body)`, each after `delay`; other
+    /// paths answer 404 at once
+    async fn serve(pages: Vec<(&'static str, u16, String)>, delay: Duration) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let pages = Arc::new(pages);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let pages = pages.clone();
+                tokio::spawn(async move {
+                    let mut request = [0; 4096];
+                    let read = stream.read(&mut request).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&request[..read]);
+                    let path = request.split_whitespace().nth(1).unwrap_or("/");
+                    let (status, body) = match pages.iter().find(|(page, ..)| *page == path) {
+                        Some((_, status, body)) => {
+                            tokio::time::sleep(delay).await;
+                            (*status, body.clone())
+                        }
+                        None => (404, String::new()),
+                    };
+                    let response = format!(
+                        "HTTP/1.1 {status} Test\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        format!("http://{address}/")
+    }
+
+    fn links(paths: &[&str]) -> String {
+        paths
+            .iter()
+            .map(|path| format!("<a href=\"{path}\">{path}</a>"))
+            .collect()
+    }
+
+    /// A running runner allowed to fetch 127.0.0.1 every 100 ms, over HTTP
+    /// only; Qdrant and TEI need not be up for link checks
+    fn test_runner(state: Arc<StateStore>) -> CrawlRunner {
+        let crawl_loop = CrawlLoop::new(
+            Arc::new(CrawlStats::default()),
+            Arc::new(CrawlJobs::new()),
+            Arc::new(PageIndexer::from_env().unwrap()),
+            None,
+            state,
+            "spider".to_string(),
+            "spider/1.0".to_string(),
         );
+        crawl_loop
+            .profiles
+            .put(
+                "127.0.0.1",
+                DomainProfile {
+                    request_interval_ms: Some(MIN_REQUEST_INTERVAL_MS),
+                    render: RenderStrategy::Http,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let mut runner = crawl_loop.runner();
+        runner.visitor = Arc::new(runner.visitor.for_runner().with_private_networks(true));
+        *runner.lifecycle.lock().unwrap() = Lifecycle::Running;
+        runner
+    }
+
+    async fn linkcheck_job(seed: &str, overrides: serde_json::Value) -> Arc<CrawlJob> {
+        let mut body = serde_json::json!({ "url": seed, "max_pages": 10, "mode": "linkcheck" });
+        body.as_object_mut()
+            .unwrap()
+            .extend(overrides.as_object().unwrap().clone());
+        CrawlJobs::new()
+            .create(serde_json::from_value(body).unwrap())
+            .await
+    }
 
-        assert_eq!(frontier.len(), 1);
-        assert_eq!(frontier[0].url.as_str(), "https://example.com/article");
+    #[tokio::test]
+    async fn stops_at_max_pages_per_host() {
+        let seed = serve(
+            vec![
+                ("/", 200, links(&["/a", "/b", "/c"])),
+                ("/a", 200, links(&["/d"])),
+                ("/b", 200, String::new()),
+                ("/c", 200, String::new()),
+                ("/d", 200, String::new()),
+            ],
+            Duration::ZERO,
+        )
+        .await;
+        let runner = test_runner(Arc::new(StateStore::in_memory().unwrap()));
+        let job = linkcheck_job(&seed, serde_json::json!({ "max_pages_per_host": 2 })).await;
+
+        runner.crawl(&job).await;
+        assert_eq!(job.status(), JobStatus::Completed);
+        assert_eq!(job.progress().pages_crawled, 2);
+    }
+
+    #[tokio::test]
+    async fn truncates_jobs_at_max_duration_secs() {
+        let paths = ["/1", "/2", "/3", "/4", "/5", "/6"];
+        let mut pages = vec![("/", 200, links(&paths))];
+        pages.extend(paths.map(|path| (path, 200, String::new())));
+        let seed = serve(pages, Duration::from_millis(400)).await;
+        let runner = test_runner(Arc::new(StateStore::in_memory().unwrap()));
+        let job = linkcheck_job(&seed, serde_json::json!({ "max_duration_secs": 1 })).await;
+
+        let started = Instant::now();
+        runner.crawl(&job).await;
+        assert_eq!(job.status(), JobStatus::Truncated);
+        assert!(started.elapsed() < Duration::from_secs(3));
+        assert!((1..=paths.len()).contains(&job.progress().pages_crawled));
+    }
+
+    #[tokio::test]
+    async fn link_checks_record_statuses_without_indexing() {
+        let seed = serve(
+            vec![
+                ("/", 200, links(&["/ok", "/gone"])),
+                ("/ok", 200, "<p>Still here.</p>".to_string()),
+            ],
+            Duration::ZERO,
+        )
+        .await;
+        let state = Arc::new(StateStore::in_memory().unwrap());
+        let runner = test_runner(state.clone());
+        let job = linkcheck_job(&seed, serde_json::json!({})).await;
+
+        runner.crawl(&job).await;
+        assert_eq!(job.status(), JobStatus::Completed);
+        let report = job.report().unwrap();
+        assert_eq!(
+            report.pages_by_status,
+            BTreeMap::from([("200".to_string(), 2), ("404".to_string(), 1)])
+        );
+        assert_eq!(
+            (
+                report.new_pages,
+                report.updated_pages,
+                report.unchanged_pages
+            ),
+            (0, 0, 0)
+        );
+        assert_eq!(job.progress().pages_indexed, 0);
+        assert!(state.page_versions(&job.id).unwrap().is_empty());
+        let broken = state.broken_links("127.0.0.1", 10).unwrap();
+        assert_eq!(broken.len(), 1);
+        assert_eq!(broken[0].url, format!("{seed}gone"));
+        assert_eq!(broken[0].reason, "404");
+        assert_eq!(broken[0].sources, [seed]);
     }
 }
@@ -0,0 +1,105 @@
+//! Discovery crawls: seed crawl jobs from a third-party web search
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use shared_crawler_api::problem::Problem;
+
+use crate::seed_list::CrawlTemplate;
+
+const MAX_QUERY_CHARS: usize = 256;
+const MAX_SEEDS: usize = 50;
+
+#[derive(Debug, Deserialize)]
+pub struct DiscoverRequest {
+    pub query: String,
+    /// Number of search results to crawl (default: 10)
+    #[serde(default = "default_seeds")]
+    pub seeds: usize,
+    /// Every `CrawlRequest` field but `url`, for each seeded job
+    #[serde(flatten)]
+    pub crawl: Map<String, Value>,
+}
+
+fn default_seeds() -> usize {
+    10
+}
+
+impl DiscoverRequest {
+    pub fn validate(&self) -> Result<(), Problem> {
+        let query = self.query.trim();
+        if query.is_empty() {
+            return Err(Problem::bad_request(
+                "empty_query",
+                "query must not be empty",
+            ));
+        }
+        if query.chars().count() > MAX_QUERY_CHARS {
+            return Err(Problem::bad_request(
+                "query_too_long",
+                format!("query must be at most {MAX_QUERY_CHARS} characters"),
+            ));
+        }
+        if !(1..=MAX_SEEDS).contains(&self.seeds) {
+            return Err(Problem::bad_request(
+                "seeds_out_of_range",
+                format!("seeds must be between 1 and {MAX_SEEDS}"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Settings of the job seeded with each search result
+    pub fn crawl_template(&self) -> Result<CrawlTemplate, Problem> {
+        CrawlTemplate::from_json(self.crawl.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(body: serde_json::Value) -> DiscoverRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn validates_query_and_seed_count() {
+        let valid = request(serde_json::json!({"query": "rust web frameworks", "max_pages": 20}));
+        assert_eq!(valid.seeds, 10);
+        assert!(valid.validate().is_ok());
+
+        let empty = request(serde_json::json!({"query": "  ", "max_pages": 20}));
+        assert_eq!(empty.validate().unwrap_err().code, "empty_query");
+
+        let too_many = request(serde_json::json!({"query": "rust", "max_pages": 20, "seeds": 51}));
+        assert_eq!(too_many.validate().unwrap_err().code, "seeds_out_of_range");
+    }
+
+    #[test]
+    fn seeds_share_crawl_settings() {
+        let discover = request(serde_json::json!({
+            "query": "rust",
+            "max_pages": 5,
+            "same_domain": false,
+            "max_depth": 2,
+            "mode": "linkcheck",
+            "must_not_contain": ["casino"]
+        }));
+        let crawl = discover
+            .crawl_template()
+            .unwrap()
+            .crawl_request("https://www.rust-lang.org/".to_string());
+        assert_eq!(crawl.url, "https://www.rust-lang.org/");
+        assert_eq!(crawl.max_pages, 5);
+        assert!(!crawl.same_domain);
+        assert_eq!(crawl.max_depth, 2);
+        assert_eq!(crawl.mode, crate::CrawlMode::Linkcheck);
+        assert_eq!(crawl.must_not_contain, ["casino"]);
+
+        let without_budget = request(serde_json::json!({"query": "rust"}));
+        assert_eq!(
+            without_budget.crawl_template().unwrap_err().code,
+            "invalid_body"
+        );
+    }
+}
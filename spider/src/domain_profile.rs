@@ -0,0 +1,365 @@
+//! Per-domain crawl profiles
+//!
+//! A profile holds overrides for one host: how often it may be requested,
+//! whether its pages need the browser, which of its paths are worth crawling,
+//! cookies to send (e.g. a session for a members-only area) and chunk sizes.
+//! Profiles are kept in the state database, edited through
+//! `GET`/`PUT /domains/{host}/profile` and apply to every job that reaches
+//! the host, whatever its seed.
+//!
+//! `POST /domains/{host}/pause` holds back every fetch from the host, e.g.
+//! after its webmaster complained, until `POST /domains/{host}/resume`.
+//! Jobs keep the host's URLs queued meanwhile and crawl their other hosts.
+//! Paused hosts are kept in the state database too.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use shared_crawler_api::problem::Problem;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use url::{Host, Url};
+
+use crate::{index::ChunkOptions, state::StateStore};
+
+pub const MIN_REQUEST_INTERVAL_MS: u64 = 100;
+pub const MAX_REQUEST_INTERVAL_MS: u64 = 3_600_000;
+const MAX_INCLUDE_PATTERNS: usize = 100;
+const MAX_COOKIES: usize = 50;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderStrategy {
+    /// HTTP client, falling back to the browser for pages that look empty
+    #[default]
+    Auto,
+    /// HTTP client only
+    Http,
+    /// Headless browser for every page
+    Browser,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DomainProfile {
+    /// Minimum delay between requests to the host instead of 2 seconds
+    #[serde(default)]
+    pub request_interval_ms: Option<u64>,
+    #[serde(default)]
+    pub render: RenderStrategy,
+    /// Path patterns (`*` matches anything, a trailing `$` anchors the end);
+    /// when set, only matching pages of the host are crawled
+    #[serde(default)]
+    pub include_patterns: Vec<String>,
+    /// Sent with every request to the host
+    #[serde(default)]
+    pub cookies: BTreeMap<String, String>,
+    #[serde(default)]
+    pub chunk_target_chars: Option<usize>,
+    #[serde(default)]
+    pub chunk_max_chars: Option<usize>,
+    #[serde(default)]
+    pub chunk_overlap_chars: Option<usize>,
+}
+
+impl DomainProfile {
+    pub fn validate(&self) -> Result<(), Problem> {
+        if self
+            .request_interval_ms
+            .is_some_and(|ms| !(MIN_REQUEST_INTERVAL_MS..=MAX_REQUEST_INTERVAL_MS).contains(&ms))
+        {
+            return Err(Problem::bad_request(
+                "request_interval_out_of_range",
+                format!(
+                    "request_interval_ms must be between {MIN_REQUEST_INTERVAL_MS} and {MAX_REQUEST_INTERVAL_MS}"
+                ),
+            ));
+        }
+        if self.include_patterns.len() > MAX_INCLUDE_PATTERNS
+            || self
+                .include_patterns
+                .iter()
+                .any(|pattern| !pattern.starts_with('/'))
+        {
+            return Err(Problem::bad_request(
+                "invalid_include_patterns",
+                format!(
+                    "include_patterns must be at most {MAX_INCLUDE_PATTERNS} paths starting with /"
+                ),
+            ));
+        }
+        let token = |value: &str| {
+            !value.is_empty()
+                && !value
+                    .chars()
+                    .any(|c| c.is_control() || c.is_whitespace() || matches!(c, ';' | ',' | '"'))
+        };
+        if self.cookies.len() > MAX_COOKIES
+            || self
+                .cookies
+                .iter()
+                .any(|(name, value)| !token(name) || name.contains('=') || !token(value))
+        {
+            return Err(Problem::bad_request(
+                "invalid_cookies",
+                format!("cookies must be at most {MAX_COOKIES} name/value pairs without separators or whitespace"),
+            ));
+        }
+        ChunkOptions::default()
+            .with_overrides(
+                self.chunk_target_chars,
+                self.chunk_max_chars,
+                self.chunk_overlap_chars,
+            )
+            .map_err(|detail| Problem::bad_request("chunk_size_out_of_range", detail))?;
+        Ok(())
+    }
+
+    pub fn request_interval(&self) -> Option<Duration> {
+        self.request_interval_ms.map(Duration::from_millis)
+    }
+
+    /// Whether `url` matches the include patterns; no patterns include all
+    pub fn includes(&self, url: &Url) -> bool {
+        if self.include_patterns.is_empty() {
+            return true;
+        }
+        let path = match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        };
+        self.include_patterns
+            .iter()
+            .any(|pattern| matches_pattern(pattern, &path))
+    }
+
+    /// `Cookie` header value for the profile's cookies
+    pub fn cookie_header(&self) -> Option<String> {
+        (!self.cookies.is_empty()).then(|| {
+            self.cookies
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect::<Vec<_>>()
+                .join("; ")
+        })
+    }
+
+    /// `options` with the profile's chunk sizes, then a crawl's own
+    /// overrides, applied
+    pub fn chunking(
+        &self,
+        options: ChunkOptions,
+        target_chars: Option<usize>,
+        max_chars: Option<usize>,
+        overlap_chars: Option<usize>,
+    ) -> ChunkOptions {
+        options
+            .with_overrides(
+                self.chunk_target_chars,
+                self.chunk_max_chars,
+                self.chunk_overlap_chars,
+            )
+            .and_then(|profiled| profiled.with_overrides(target_chars, max_chars, overlap_chars))
+            .or_else(|_| options.with_overrides(target_chars, max_chars, overlap_chars))
+            .unwrap_or(options)
+    }
+}
+
+/// Robots.txt-style matching: `*` is any run of characters, `$` at the end
+/// anchors, and otherwise a pattern matches as a prefix
+fn matches_pattern(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+    let parts = parts.collect::<Vec<_>>();
+    for (index, part) in parts.iter().enumerate() {
+        let last = index + 1 == parts.len();
+        if last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+/// A lowercase host name or address, as used in profile URLs
+pub fn normalize_host(host: &str) -> Option<String> {
+    match Host::parse(host.trim()).ok()? {
+        Host::Domain(domain) => Some(domain.to_ascii_lowercase()),
+        address => Some(address.to_string()),
+    }
+}
+
+/// All profiles, cached in memory in front of the state database
+pub struct DomainProfiles {
+    store: Arc<StateStore>,
+    profiles: RwLock<HashMap<String, Arc<DomainProfile>>>,
+    /// Paused hosts and when they were paused
+    paused: RwLock<HashMap<String, i64>>,
+}
+
+impl DomainProfiles {
+    /// Profiles that no longer parse are skipped with a warning
+    pub fn load(store: Arc<StateStore>) -> Result<Self> {
+        let profiles = store
+            .domain_profiles()?
+            .into_iter()
+            .filter_map(
+                |(host, profile)| match serde_json::from_value::<DomainProfile>(profile) {
+                    Ok(profile) => Some((host, Arc::new(profile))),
+                    Err(error) => {
+                        tracing::warn!("ignoring stored profile of {host}: {error}");
+                        None
+                    }
+                },
+            )
+            .collect();
+        let paused = store.paused_hosts()?.into_iter().collect();
+        Ok(Self {
+            store,
+            profiles: RwLock::new(profiles),
+            paused: RwLock::new(paused),
+        })
+    }
+
+    pub fn get(&self, host: &str) -> Option<Arc<DomainProfile>> {
+        self.profiles.read().unwrap().get(host).cloned()
+    }
+
+    pub fn for_url(&self, url: &Url) -> Option<Arc<DomainProfile>> {
+        self.get(url.host_str()?)
+    }
+
+    pub fn put(&self, host: &str, profile: DomainProfile) -> Result<Arc<DomainProfile>> {
+        self.store
+            .set_domain_profile(host, &serde_json::to_value(&profile)?)?;
+        let profile = Arc::new(profile);
+        self.profiles
+            .write()
+            .unwrap()
+            .insert(host.to_string(), profile.clone());
+        Ok(profile)
+    }
+
+    /// Pause `host`; returns when it was paused, which a repeated call keeps
+    pub fn pause(&self, host: &str, now: i64) -> Result<i64> {
+        if let Some(&paused_at) = self.paused.read().unwrap().get(host) {
+            return Ok(paused_at);
+        }
+        self.store.pause_host(host, now)?;
+        self.paused.write().unwrap().insert(host.to_string(), now);
+        Ok(now)
+    }
+
+    /// Resume `host`; returns whether it was paused
+    pub fn resume(&self, host: &str) -> Result<bool> {
+        let resumed = self.store.resume_host(host)?;
+        self.paused.write().unwrap().remove(host);
+        Ok(resumed)
+    }
+
+    pub fn is_paused(&self, url: &Url) -> bool {
+        url.host_str().is_some_and(|host| self.is_host_paused(host))
+    }
+
+    pub fn is_host_paused(&self, host: &str) -> bool {
+        self.paused.read().unwrap().contains_key(host)
+    }
+
+    /// Paused hosts, sorted
+    pub fn paused(&self) -> BTreeMap<String, i64> {
+        self.paused
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(host, paused_at)| (host.clone(), *paused_at))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_include_patterns() {
+        let profile = DomainProfile {
+            include_patterns: vec!["/docs/".to_string(), "/blog/*/2024$".to_string()],
+            ..Default::default()
+        };
+        let includes = |url: &str| profile.includes(&Url::parse(url).unwrap());
+        assert!(includes("https://example.com/docs/intro"));
+        assert!(includes("https://example.com/blog/posts/2024"));
+        assert!(!includes("https://example.com/blog/posts/2024/old"));
+        assert!(!includes("https://example.com/shop"));
+        assert!(DomainProfile::default().includes(&Url::parse("https://example.com/x").unwrap()));
+    }
+
+    #[test]
+    fn validates_and_persists_profiles() {
+        let store = Arc::new(StateStore::in_memory().unwrap());
+        let profiles = DomainProfiles::load(store.clone()).unwrap();
+        let profile: DomainProfile = serde_json::from_value(serde_json::json!({
+            "request_interval_ms": 500,
+            "render": "browser",
+            "cookies": { "session": "abc" },
+            "chunk_target_chars": 400
+        }))
+        .unwrap();
+        assert!(profile.validate().is_ok());
+        assert_eq!(profile.cookie_header().as_deref(), Some("session=abc"));
+        assert_eq!(
+            profile
+                .chunking(ChunkOptions::default(), None, None, None)
+                .target_chars,
+            400
+        );
+        profiles.put("example.com", profile.clone()).unwrap();
+
+        let reloaded = DomainProfiles::load(store).unwrap();
+        assert_eq!(reloaded.get("example.com").as_deref(), Some(&profile));
+        assert!(reloaded.get("other.example").is_none());
+
+        let invalid = DomainProfile {
+            cookies: BTreeMap::from([("a b".to_string(), "x".to_string())]),
+            ..Default::default()
+        };
+        assert_eq!(invalid.validate().unwrap_err().code, "invalid_cookies");
+        assert_eq!(
+            normalize_host("Example.COM").as_deref(),
+            Some("example.com")
+        );
+    }
+
+    #[test]
+    fn pauses_hosts_across_restarts() {
+        let store = Arc::new(StateStore::in_memory().unwrap());
+        let profiles = DomainProfiles::load(store.clone()).unwrap();
+        let url = Url::parse("https://example.com/page").unwrap();
+        assert!(!profiles.is_paused(&url));
+        assert_eq!(profiles.pause("example.com", 10).unwrap(), 10);
+        assert_eq!(profiles.pause("example.com", 20).unwrap(), 10);
+        assert!(profiles.is_paused(&url));
+        assert!(!profiles.is_paused(&Url::parse("https://other.example/").unwrap()));
+
+        let reloaded = DomainProfiles::load(store).unwrap();
+        assert_eq!(
+            reloaded.paused(),
+            BTreeMap::from([("example.com".to_string(), 10)])
+        );
+        assert!(reloaded.resume("example.com").unwrap());
+        assert!(!reloaded.resume("example.com").unwrap());
+        assert!(!reloaded.is_paused(&url));
+    }
+}
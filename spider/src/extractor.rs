@@ -1,13 +1,14 @@
 use scraper::{Html, Selector};
+use url::Url;
 
-use crate::index::ContentBlock;
+use crate::{extractor_content::sanitize, index::ContentBlock};
 
 pub fn extract_title(document: &Html) -> String {
     let title_selector = Selector::parse("title").unwrap();
     document
         .select(&title_selector)
         .next()
-        .map(|el| el.text().collect::<String>().trim().to_string())
+        .map(|el| sanitize(&el.text().collect::<String>()))
         .unwrap_or_default()
 }
 
@@ -59,6 +60,78 @@ pub fn extract_description(document: &Html, content_blocks: &[ContentBlock]) ->
     }
 }
 
+/// The site's icon as the page declares it, preferring `rel="icon"` over
+/// `apple-touch-icon`, or `/favicon.ico` on the page's origin
+pub fn extract_favicon(document: &Html, url: &Url) -> Option<String> {
+    ["link[rel~=icon][href]", "link[rel~=apple-touch-icon][href]"]
+        .iter()
+        .find_map(|selector| {
+            let selector = Selector::parse(selector).unwrap();
+            document
+                .select(&selector)
+                .filter_map(|link| link.value().attr("href"))
+                .find_map(|href| resolve_asset(url, href))
+        })
+        .or_else(|| resolve_asset(url, "/favicon.ico"))
+}
+
+/// The page's preview image: `og:image`, or `twitter:image` without one
+pub fn extract_image(document: &Html, url: &Url) -> Option<String> {
+    [
+        "meta[property='og:image:secure_url'][content]",
+        "meta[property='og:image'][content]",
+        "meta[property='og:image:url'][content]",
+        "meta[name='twitter:image'][content]",
+    ]
+    .iter()
+    .find_map(|selector| {
+        let selector = Selector::parse(selector).unwrap();
+        document
+            .select(&selector)
+            .filter_map(|meta| meta.value().attr("content"))
+            .find_map(|content| resolve_asset(url, content))
+    })
+}
+
+/// Primary language subtag of the page, e.g. `en` for `lang="en-US"`, from
+/// `<html lang>` or a `Content-Language` meta tag
+pub fn extract_language(document: &Html) -> Option<String> {
+    [
+        ("html[lang]", "lang"),
+        ("meta[http-equiv='content-language' i][content]", "content"),
+    ]
+    .iter()
+    .find_map(|(selector, attribute)| {
+        let selector = Selector::parse(selector).unwrap();
+        document
+            .select(&selector)
+            .filter_map(|element| element.value().attr(attribute))
+            .find_map(primary_language)
+    })
+}
+
+/// `en` for `en-US`, `EN`, or `en_us, de`; `None` unless 2 or 3 letters
+fn primary_language(tag: &str) -> Option<String> {
+    let primary = tag.split([',', '-', '_']).next()?.trim();
+    (matches!(primary.len(), 2 | 3) && primary.chars().all(|c| c.is_ascii_alphabetic()))
+        .then(|| primary.to_ascii_lowercase())
+}
+
+/// The page's JSON-LD documents that parse
+pub fn json_ld(document: &Html) -> Vec<serde_json::Value> {
+    let script = Selector::parse(r#"script[type="application/ld+json"]"#).unwrap();
+    document
+        .select(&script)
+        .filter_map(|element| serde_json::from_str(&element.text().collect::<String>()).ok())
+        .collect()
+}
+
+/// `href` resolved against the page, if it is an HTTP(S) URL
+fn resolve_asset(url: &Url, href: &str) -> Option<String> {
+    let asset = url.join(href.trim()).ok()?;
+    matches!(asset.scheme(), "http" | "https").then(|| asset.to_string())
+}
+
 fn clean_description_text(text: &str) -> String {
     text.split_whitespace().collect::<Vec<_>>().join(" ")
 }
@@ -99,11 +172,11 @@ mod tests {
     fn fallback_description_skips_headings_and_short_noise() {
         let blocks = vec![
             ContentBlock {
-                heading: Some("WP:ABT".into()),
+                heading_path: vec!["WP:ABT".into()],
                 text: "Short".into(),
             },
             ContentBlock {
-                heading: Some("Wikipedia:About".into()),
+                heading_path: vec!["Wikipedia:About".into()],
                 text: "Wikipedia is a free online encyclopedia that anyone can edit.".into(),
             },
         ];
@@ -115,10 +188,53 @@ mod tests {
         );
     }
 
+    #[test]
+    fn resolves_favicons_and_preview_images() {
+        let url = Url::parse("https://example.com/blog/post").unwrap();
+        let document = Html::parse_document(
+            r#"<link rel="apple-touch-icon" href="/touch.png">
+            <link rel="shortcut icon" href="icons/favicon.png">
+            <meta property="og:image" content="data:image/png;base64,AAAA">
+            <meta name="twitter:image" content="//cdn.example.com/card.jpg">"#,
+        );
+        assert_eq!(
+            extract_favicon(&document, &url).as_deref(),
+            Some("https://example.com/blog/icons/favicon.png")
+        );
+        assert_eq!(
+            extract_image(&document, &url).as_deref(),
+            Some("https://cdn.example.com/card.jpg")
+        );
+
+        let bare = Html::parse_document("<html></html>");
+        assert_eq!(
+            extract_favicon(&bare, &url).as_deref(),
+            Some("https://example.com/favicon.ico")
+        );
+        assert_eq!(extract_image(&bare, &url), None);
+    }
+
+    #[test]
+    fn reads_primary_language_subtags() {
+        let language = |html: &str| extract_language(&Html::parse_document(html));
+        assert_eq!(
+            language(r#"<html lang="en-US"><body>x</body></html>"#).as_deref(),
+            Some("en")
+        );
+        assert_eq!(
+            language(
+                r#"<html lang=""><head><meta http-equiv="Content-Language" content="DE, en"></head></html>"#
+            )
+            .as_deref(),
+            Some("de")
+        );
+        assert_eq!(language(r#"<html lang="x-default"></html>"#), None);
+    }
+
     #[test]
     fn fallback_description_truncates() {
         let blocks = vec![ContentBlock {
-            heading: None,
+            heading_path: Vec::new(),
             text: "word ".repeat(100),
         }];
         let document = Html::parse_document("<html></html>");
@@ -1,51 +1,91 @@
 use scraper::{ElementRef, Html};
+use shared_crawler_api::text;
 
 use crate::index::ContentBlock;
 
+/// Open headings as `(level, text)`, outermost first
+type Headings = Vec<(u8, String)>;
+
 pub fn extract_content_blocks(document: &Html) -> Vec<ContentBlock> {
-    walk(document.root_element(), None).0
+    walk(document.root_element(), Vec::new()).0
 }
 
-fn walk(
-    element: ElementRef<'_>,
-    mut heading: Option<String>,
-) -> (Vec<ContentBlock>, Option<String>) {
+fn walk(element: ElementRef<'_>, mut headings: Headings) -> (Vec<ContentBlock>, Headings) {
     let mut blocks = Vec::new();
     for child in element.children().filter_map(ElementRef::wrap) {
         let name = child.value().name();
         if is_excluded(&child) {
             continue;
         }
-        if matches!(name, "h1" | "h2" | "h3" | "h4" | "h5" | "h6") {
+        if let Some(level) = heading_level(name) {
             let text = clean_text(&child);
             if !text.is_empty() {
-                heading = Some(text);
+                // A heading closes every open heading of the same or a deeper level
+                headings.retain(|(open, _)| *open < level);
+                headings.push((level, text));
             }
         } else if matches!(name, "p" | "li" | "pre" | "blockquote" | "td" | "th") {
             let text = clean_text(&child);
             if !text.is_empty() {
                 blocks.push(ContentBlock {
-                    heading: heading.clone(),
+                    heading_path: headings.iter().map(|(_, text)| text.clone()).collect(),
                     text,
                 });
             }
         } else {
-            let (nested, nested_heading) = walk(child, heading.clone());
+            let (nested, nested_headings) = walk(child, headings);
             blocks.extend(nested);
-            heading = nested_heading.or(heading);
+            headings = nested_headings;
         }
     }
-    (blocks, heading)
+    (blocks, headings)
+}
+
+fn heading_level(name: &str) -> Option<u8> {
+    match name {
+        "h1" => Some(1),
+        "h2" => Some(2),
+        "h3" => Some(3),
+        "h4" => Some(4),
+        "h5" => Some(5),
+        "h6" => Some(6),
+        _ => None,
+    }
 }
 
 fn clean_text(element: &ElementRef<'_>) -> String {
     let mut parts = Vec::new();
     collect_text(*element, &mut parts);
-    parts
-        .into_iter()
-        .flat_map(str::split_whitespace)
-        .collect::<Vec<_>>()
-        .join(" ")
+    sanitize(&parts.join(" "))
+}
+
+/// Text as it is embedded and indexed: entities left over from double
+/// escaping decoded, control and invisible characters (soft hyphens,
+/// zero-width spaces, bidi marks) and emoji removed, whitespace collapsed
+pub fn sanitize(raw: &str) -> String {
+    let decoded = text::clean(raw);
+    let visible = decoded
+        .chars()
+        .filter(|c| !is_invisible(*c))
+        .collect::<String>();
+    visible.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn is_invisible(c: char) -> bool {
+    (c.is_control() && !c.is_whitespace())
+        || matches!(
+            c,
+            '\u{ad}'
+                | '\u{200b}'..='\u{200f}'
+                | '\u{202a}'..='\u{202e}'
+                | '\u{2060}'..='\u{2069}'
+                | '\u{feff}'
+                // Variation selectors, emoji and their modifiers and tags
+                | '\u{fe00}'..='\u{fe0f}'
+                | '\u{2600}'..='\u{27bf}'
+                | '\u{1f000}'..='\u{1faff}'
+                | '\u{e0000}'..='\u{e007f}'
+        )
 }
 
 fn collect_text<'a>(element: ElementRef<'a>, parts: &mut Vec<&'a str>) {
@@ -92,7 +132,27 @@ mod tests {
             blocks.iter().map(|b| b.text.as_str()).collect::<Vec<_>>(),
             ["One", "Two", "Three"]
         );
-        assert!(blocks.iter().all(|b| b.heading.as_deref() == Some("Title")));
+        assert!(blocks.iter().all(|b| b.heading_path == ["Title"]));
+    }
+
+    #[test]
+    fn tracks_the_heading_hierarchy() {
+        let document = Html::parse_document(
+            "<h1>Guide</h1><h2>Install</h2><h3>Linux</h3><p>apt</p>\
+             <h3>macOS</h3><p>brew</p><h2>Usage</h2><p>run</p>",
+        );
+        let paths = extract_content_blocks(&document)
+            .into_iter()
+            .map(|block| block.heading_path.join(" › "))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "Guide › Install › Linux",
+                "Guide › Install › macOS",
+                "Guide › Usage"
+            ]
+        );
     }
 
     #[test]
@@ -104,4 +164,14 @@ mod tests {
 
         assert_eq!(blocks[0].text, "WP:SHORTCUTS");
     }
+
+    #[test]
+    fn decodes_entities_and_strips_invisible_characters() {
+        let document = Html::parse_document(
+            "<p>Ham&amp;nbsp;burg\u{ad}er\u{200b} &amp;amp; fries\u{7} \u{1f354}\u{fe0f}&nbsp;\u{1f44d}\u{1f3fd} today</p>",
+        );
+        let blocks = extract_content_blocks(&document);
+
+        assert_eq!(blocks[0].text, "Ham burger & fries today");
+    }
 }
@@ -0,0 +1,252 @@
+//! Per-job crawl frontiers
+//!
+//! A job's URLs wait in a `Frontier`. The `LocalFrontier` of a job on one
+//! instance holds up to `SPIDER_FRONTIER_MEMORY_LIMIT` URLs (default 10000)
+//! in memory. Beyond that, new URLs are appended to the SQLite state store
+//! and read back in batches once the in-memory queue drains, keeping FIFO
+//! order. At shutdown the in-memory URLs are written out too, so the job can
+//! resume from the store on the next start. Jobs that share a frontier
+//! across instances use `cluster::RedisFrontier` instead.
+
+use async_trait::async_trait;
+use std::{
+    collections::{HashSet, VecDeque},
+    env,
+    sync::Arc,
+};
+use url::Url;
+
+use crate::state::StateStore;
+
+const DEFAULT_MEMORY_LIMIT: usize = 10_000;
+const REFILL_BATCH: usize = 1_000;
+
+#[derive(Clone)]
+pub struct QueuedUrl {
+    pub url: Url,
+    pub depth: usize,
+    /// The page whose link queued it
+    pub referrer: Option<String>,
+}
+
+#[async_trait]
+pub trait Frontier: Send + Sync {
+    /// Queue a URL unless it is already waiting; returns whether it was added
+    async fn push(&mut self, url: Url, depth: usize, referrer: Option<&str>) -> bool;
+
+    async fn pop(&mut self) -> Option<QueuedUrl>;
+
+    async fn len(&self) -> usize;
+
+    async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    /// Keep the queued URLs for a job resumed on the next start
+    fn checkpoint(&mut self) -> anyhow::Result<()>;
+
+    /// Drop every queued URL this job owns
+    fn clear(&mut self);
+
+    /// The first `limit` URLs in fetch order
+    async fn next_urls(&self, limit: usize) -> Vec<String>;
+}
+
+pub struct LocalFrontier {
+    memory: VecDeque<QueuedUrl>,
+    /// URLs currently in `memory`; spilled URLs are deduplicated by SQLite
+    queued: HashSet<String>,
+    spilled: usize,
+    memory_limit: usize,
+    store: Arc<StateStore>,
+    job_id: String,
+}
+
+pub fn memory_limit_from_env() -> usize {
+    env::var("SPIDER_FRONTIER_MEMORY_LIMIT")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|limit| *limit > 0)
+        .unwrap_or(DEFAULT_MEMORY_LIMIT)
+}
+
+impl LocalFrontier {
+    pub fn new(store: Arc<StateStore>, job_id: &str, memory_limit: usize) -> Self {
+        Self {
+            memory: VecDeque::new(),
+            queued: HashSet::new(),
+            spilled: 0,
+            memory_limit: memory_limit.max(1),
+            store,
+            job_id: job_id.to_string(),
+        }
+    }
+
+    /// The frontier a `checkpoint` left in the store for `job_id`
+    pub fn resume(store: Arc<StateStore>, job_id: &str, memory_limit: usize) -> Self {
+        let mut frontier = Self::new(store, job_id, memory_limit);
+        frontier.spilled = frontier.store.frontier_len(job_id).unwrap_or_else(|error| {
+            tracing::warn!("failed to resume frontier of job {job_id}: {error}");
+            0
+        });
+        frontier
+    }
+
+    fn refill(&mut self) {
+        let batch = match self.store.pop_frontier(&self.job_id, REFILL_BATCH) {
+            Ok(batch) => batch,
+            Err(error) => {
+                tracing::warn!("frontier refill failed for job {}: {error}", self.job_id);
+                self.spilled = 0;
+                return;
+            }
+        };
+        if batch.is_empty() {
+            self.spilled = 0;
+            return;
+        }
+        self.spilled = self.spilled.saturating_sub(batch.len());
+        for (url, depth, referrer) in batch {
+            if let Ok(url) = Url::parse(&url) {
+                self.queued.insert(url.to_string());
+                self.memory.push_back(QueuedUrl {
+                    url,
+                    depth,
+                    referrer,
+                });
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Frontier for LocalFrontier {
+    /// Move every URL held in memory to the store, ahead of the spilled ones
+    fn checkpoint(&mut self) -> anyhow::Result<()> {
+        let urls = self
+            .memory
+            .iter()
+            .map(|item| (item.url.to_string(), item.depth, item.referrer.clone()))
+            .collect::<Vec<_>>();
+        self.store.unpop_frontier(&self.job_id, &urls)?;
+        self.spilled += urls.len();
+        self.memory.clear();
+        self.queued.clear();
+        Ok(())
+    }
+
+    async fn push(&mut self, url: Url, depth: usize, referrer: Option<&str>) -> bool {
+        let key = url.to_string();
+        if self.queued.contains(&key) {
+            return false;
+        }
+        // Once anything is spilled, later URLs follow it to disk to keep FIFO order.
+        if self.spilled == 0 && self.memory.len() < self.memory_limit {
+            self.queued.insert(key);
+            self.memory.push_back(QueuedUrl {
+                url,
+                depth,
+                referrer: referrer.map(str::to_string),
+            });
+            return true;
+        }
+        match self
+            .store
+            .push_frontier(&self.job_id, &key, depth, referrer)
+        {
+            Ok(added) => {
+                self.spilled += added as usize;
+                added
+            }
+            Err(error) => {
+                tracing::warn!("frontier spill failed, dropping {key}: {error}");
+                false
+            }
+        }
+    }
+
+    async fn pop(&mut self) -> Option<QueuedUrl> {
+        if self.memory.is_empty() && self.spilled > 0 {
+            self.refill();
+        }
+        let item = self.memory.pop_front()?;
+        self.queued.remove(item.url.as_str());
+        Some(item)
+    }
+
+    async fn len(&self) -> usize {
+        self.memory.len() + self.spilled
+    }
+
+    /// Drop every queued URL, including any spilled to disk
+    fn clear(&mut self) {
+        self.memory.clear();
+        self.queued.clear();
+        if self.spilled > 0 {
+            if let Err(error) = self.store.clear_job(&self.job_id) {
+                tracing::warn!("failed to clear frontier of job {}: {error}", self.job_id);
+            }
+            self.spilled = 0;
+        }
+    }
+
+    /// Only URLs held in memory, which come first
+    async fn next_urls(&self, limit: usize) -> Vec<String> {
+        self.memory
+            .iter()
+            .take(limit)
+            .map(|item| item.url.to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(page: usize) -> Url {
+        Url::parse(&format!("https://example.com/{page}")).unwrap()
+    }
+
+    async fn drain(frontier: &mut impl Frontier) -> Vec<String> {
+        let mut order = Vec::new();
+        while let Some(item) = frontier.pop().await {
+            order.push(item.url.path().to_string());
+        }
+        order
+    }
+
+    #[tokio::test]
+    async fn spills_past_the_memory_limit_and_keeps_fifo_order() {
+        let store = Arc::new(StateStore::in_memory().unwrap());
+        let mut frontier = LocalFrontier::new(store, "job", 2);
+        for page in 0..5 {
+            assert!(frontier.push(url(page), 1, None).await);
+        }
+        assert!(!frontier.push(url(0), 1, None).await);
+        assert!(!frontier.push(url(4), 1, None).await);
+        assert_eq!(frontier.len().await, 5);
+        assert_eq!(
+            frontier.next_urls(10).await,
+            ["https://example.com/0", "https://example.com/1"]
+        );
+
+        assert_eq!(drain(&mut frontier).await, ["/0", "/1", "/2", "/3", "/4"]);
+        assert!(frontier.is_empty().await);
+    }
+
+    #[tokio::test]
+    async fn resumes_a_checkpointed_frontier_in_order() {
+        let store = Arc::new(StateStore::in_memory().unwrap());
+        let mut frontier = LocalFrontier::new(store.clone(), "job", 2);
+        for page in 0..4 {
+            frontier.push(url(page), 1, None).await;
+        }
+        frontier.checkpoint().unwrap();
+        drop(frontier);
+
+        let mut resumed = LocalFrontier::resume(store, "job", 2);
+        assert_eq!(resumed.len().await, 4);
+        assert_eq!(drain(&mut resumed).await, ["/0", "/1", "/2", "/3"]);
+    }
+}
@@ -1,79 +1,257 @@
 use scraper::Html;
 use shared_crawler_api::WebPageChunk;
+use std::env;
 use url::Url;
 
 use crate::{
-    extractor::{extract_description, extract_title},
+    author::extract_author,
+    breadcrumb::extract_breadcrumb,
+    extractor::{
+        extract_description, extract_favicon, extract_image, extract_language, extract_title,
+    },
     extractor_content::extract_content_blocks,
-    web_visitor::extract_links,
+    pagination::pagination_links,
+    published::extract_published_at,
+    quality::PageMetrics,
+    sentences::split_sentences,
+    seo::SeoAudit,
+    tokens::DEFAULT_MAX_TOKENS,
+    web_visitor::{extract_links, ExtractedLink},
 };
 
-const TARGET_CHARS: usize = 800;
-const MAX_CHARS: usize = 1_200;
-const UNBROKEN_CHARS: usize = 450;
+const DEFAULT_TARGET_CHARS: usize = 800;
+const DEFAULT_MAX_CHARS: usize = 1_200;
+const DEFAULT_OVERLAP_CHARS: usize = 200;
+/// Headings kept per page for the `headings` field
+const MAX_PAGE_HEADINGS: usize = 64;
+/// Bounds for configured and per-crawl chunk sizes
+pub const MIN_CHUNK_CHARS: usize = 100;
+pub const MAX_CHUNK_CHARS: usize = 8_000;
+
+/// How pages are cut into chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkOptions {
+    /// A chunk is closed once it reaches this many characters
+    pub target_chars: usize,
+    /// Hard upper bound for a chunk
+    pub max_chars: usize,
+    /// Characters repeated from the end of a chunk at the start of the next one
+    /// in the same section (roughly 50 tokens by default)
+    pub overlap_chars: usize,
+    /// Chunks whose embedding input is longer in tokens of the embedding
+    /// model are split before indexing; `0` skips the check
+    pub max_tokens: usize,
+}
+
+impl ChunkOptions {
+    /// `CHUNK_TARGET_CHARS`, `CHUNK_MAX_CHARS` and `CHUNK_OVERLAP_CHARS`;
+    /// invalid combinations are clamped rather than rejected
+    pub fn from_env() -> Self {
+        let read = |key: &str, default: usize| {
+            env::var(key)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        let target_chars = read("CHUNK_TARGET_CHARS", DEFAULT_TARGET_CHARS)
+            .clamp(MIN_CHUNK_CHARS, MAX_CHUNK_CHARS);
+        let max_chars =
+            read("CHUNK_MAX_CHARS", DEFAULT_MAX_CHARS).clamp(target_chars, MAX_CHUNK_CHARS);
+        Self {
+            target_chars,
+            max_chars,
+            // An overlap must leave room for new text in every chunk
+            overlap_chars: read("CHUNK_OVERLAP_CHARS", DEFAULT_OVERLAP_CHARS).min(target_chars / 2),
+            max_tokens: read("CHUNK_MAX_TOKENS", DEFAULT_MAX_TOKENS),
+        }
+    }
+
+    /// These options with a crawl's overrides applied. A new target without a
+    /// new maximum keeps the default 3:2 ratio between the two, and the
+    /// overlap shrinks with the target unless it is set explicitly.
+    pub fn with_overrides(
+        self,
+        target_chars: Option<usize>,
+        max_chars: Option<usize>,
+        overlap_chars: Option<usize>,
+    ) -> Result<Self, String> {
+        let target = target_chars.unwrap_or(self.target_chars);
+        let max = max_chars.unwrap_or(if target_chars.is_some() {
+            (target * 3 / 2).min(MAX_CHUNK_CHARS)
+        } else {
+            self.max_chars.max(target)
+        });
+        let overlap = overlap_chars.unwrap_or(self.overlap_chars.min(target / 2));
+        if !(MIN_CHUNK_CHARS..=MAX_CHUNK_CHARS).contains(&target)
+            || !(MIN_CHUNK_CHARS..=MAX_CHUNK_CHARS).contains(&max)
+        {
+            return Err(format!(
+                "chunk sizes must be between {MIN_CHUNK_CHARS} and {MAX_CHUNK_CHARS} characters"
+            ));
+        }
+        if max < target {
+            return Err("chunk_max_chars must be at least chunk_target_chars".to_string());
+        }
+        if overlap > target / 2 {
+            return Err(
+                "chunk_overlap_chars must be at most half of chunk_target_chars".to_string(),
+            );
+        }
+        Ok(Self {
+            target_chars: target,
+            max_chars: max,
+            overlap_chars: overlap,
+            ..self
+        })
+    }
+
+    /// Long paragraphs are cut into pieces of at most this length, so a
+    /// piece still fits after the overlap carried into a new chunk
+    fn piece_chars(&self) -> usize {
+        self.target_chars - self.overlap_chars
+    }
+
+    /// Pieces of text without whitespace are cut at this length, well below
+    /// the maximum so they can share a chunk with their neighbours
+    fn unbroken_chars(&self) -> usize {
+        (self.max_chars * 3 / 8).min(self.piece_chars())
+    }
+}
+
+impl Default for ChunkOptions {
+    fn default() -> Self {
+        Self {
+            target_chars: DEFAULT_TARGET_CHARS,
+            max_chars: DEFAULT_MAX_CHARS,
+            overlap_chars: DEFAULT_OVERLAP_CHARS,
+            max_tokens: DEFAULT_MAX_TOKENS,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct ContentBlock {
-    pub heading: Option<String>,
+    /// Enclosing headings, outermost first
+    pub heading_path: Vec<String>,
     pub text: String,
 }
 
 pub struct ExtractedPage {
     pub chunks: Vec<WebPageChunk>,
-    pub links: Vec<Url>,
+    pub links: Vec<ExtractedLink>,
+    /// Next/previous pages of the same listing or article, also in `links`
+    pub pagination: Vec<Url>,
+    pub metrics: PageMetrics,
+    pub seo: SeoAudit,
 }
 
-pub fn extract_page(url: &Url, html: &str) -> ExtractedPage {
+pub fn extract_page(url: &Url, html: &str, options: &ChunkOptions) -> ExtractedPage {
     let document = Html::parse_document(html);
     let title = extract_title(&document);
     let blocks = extract_content_blocks(&document);
     let description = extract_description(&document, &blocks);
+    let metrics = PageMetrics::measure(&document, html.len(), &blocks);
     let crawled_at = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs() as i64;
-    let chunks = create_chunks(blocks, url.as_str(), &title, &description, crawled_at);
+    let published_at = extract_published_at(&document, crawled_at);
+    let author = extract_author(&document);
+    let favicon_url = extract_favicon(&document, url);
+    let image_url = extract_image(&document, url);
+    let breadcrumb = extract_breadcrumb(&document);
+    let headings = page_headings(&blocks);
+    let language = extract_language(&document);
+    let chunks = create_chunks(
+        blocks,
+        url.as_str(),
+        &title,
+        &description,
+        crawled_at,
+        options,
+    )
+    .into_iter()
+    .map(|chunk| {
+        chunk
+            .with_published_at(published_at)
+            .with_author(author.clone())
+            .with_images(favicon_url.clone(), image_url.clone())
+            .with_breadcrumb(breadcrumb.clone())
+            .with_headings(headings.clone())
+            .with_language(language.clone())
+    })
+    .collect();
     ExtractedPage {
         chunks,
         links: extract_links(&document, url),
+        pagination: pagination_links(&document, url),
+        metrics,
+        seo: SeoAudit::measure(&document, url),
     }
 }
 
+/// Pagination and other links of a page, without extracting its content
+pub fn page_links(url: &Url, html: &str) -> (Vec<Url>, Vec<ExtractedLink>) {
+    let document = Html::parse_document(html);
+    (
+        pagination_links(&document, url),
+        extract_links(&document, url),
+    )
+}
+
+/// Chunks never cross a heading section; within a section, each chunk after
+/// the first starts with the tail of the previous one so that a passage cut at
+/// a boundary stays readable in at least one chunk
 fn create_chunks(
     blocks: Vec<ContentBlock>,
     url: &str,
     title: &str,
     description: &str,
     crawled_at: i64,
+    options: &ChunkOptions,
 ) -> Vec<WebPageChunk> {
     let mut chunks = Vec::new();
-    let mut current = String::new();
-    let mut heading = None;
-
-    for block in blocks {
-        for piece in split_text(&block.text) {
-            let added = piece.chars().count() + usize::from(!current.is_empty());
-            if !current.is_empty() && current.chars().count() + added > MAX_CHARS {
-                push_chunk(
-                    &mut chunks,
-                    std::mem::take(&mut current),
-                    heading.clone(),
-                    url,
-                    title,
-                    description,
-                    crawled_at,
-                );
+    for (heading_path, texts) in sections(blocks) {
+        let mut current = String::new();
+        // Characters at the start of `current` repeated from the previous chunk
+        let mut carried = 0;
+        for piece in texts.iter().flat_map(|text| split_text(text, options)) {
+            let length = current.chars().count();
+            let added = piece.chars().count() + usize::from(length > 0);
+            if length > 0 && length + added > options.max_chars {
+                if length == carried {
+                    // The overlap alone would leave no room for the next piece
+                    current.clear();
+                } else {
+                    let chunk = std::mem::take(&mut current);
+                    current = overlap_tail(&chunk, options.overlap_chars);
+                    push_chunk(
+                        &mut chunks,
+                        chunk,
+                        &heading_path,
+                        url,
+                        title,
+                        description,
+                        crawled_at,
+                    );
+                    if current.chars().count() + piece.chars().count() + 1 > options.max_chars {
+                        current.clear();
+                    }
+                }
+                carried = current.chars().count();
             }
             if !current.is_empty() {
                 current.push(' ');
             }
             current.push_str(&piece);
-            heading = block.heading.clone().or(heading);
-            if current.chars().count() >= TARGET_CHARS {
+            if current.chars().count() >= options.target_chars {
+                let chunk = std::mem::take(&mut current);
+                current = overlap_tail(&chunk, options.overlap_chars);
+                carried = current.chars().count();
                 push_chunk(
                     &mut chunks,
-                    std::mem::take(&mut current),
-                    heading.clone(),
+                    chunk,
+                    &heading_path,
                     url,
                     title,
                     description,
@@ -81,30 +259,97 @@ fn create_chunks(
                 );
             }
         }
-    }
-    if !current.trim().is_empty() {
-        push_chunk(
-            &mut chunks,
-            current,
-            heading,
-            url,
-            title,
-            description,
-            crawled_at,
-        );
+        if current.chars().count() > carried {
+            push_chunk(
+                &mut chunks,
+                current,
+                &heading_path,
+                url,
+                title,
+                description,
+                crawled_at,
+            );
+        }
     }
     chunks
 }
 
-fn split_text(text: &str) -> Vec<String> {
-    if text.chars().count() <= MAX_CHARS {
+/// Consecutive blocks under the same headings, in document order
+fn sections(blocks: Vec<ContentBlock>) -> Vec<(Vec<String>, Vec<String>)> {
+    let mut sections: Vec<(Vec<String>, Vec<String>)> = Vec::new();
+    for block in blocks {
+        match sections.last_mut() {
+            Some((heading_path, texts)) if *heading_path == block.heading_path => {
+                texts.push(block.text)
+            }
+            _ => sections.push((block.heading_path, vec![block.text])),
+        }
+    }
+    sections
+}
+
+/// Headings of the page's content, each once, in document order
+fn page_headings(blocks: &[ContentBlock]) -> Vec<String> {
+    let mut headings: Vec<String> = Vec::new();
+    for heading in blocks.iter().flat_map(|block| &block.heading_path) {
+        if !headings.contains(heading) {
+            headings.push(heading.clone());
+        }
+    }
+    headings.truncate(MAX_PAGE_HEADINGS);
+    headings
+}
+
+/// The last `chars` characters of a chunk, starting at a word boundary when
+/// the text has one
+fn overlap_tail(chunk: &str, chars: usize) -> String {
+    if chars == 0 {
+        return String::new();
+    }
+    let length = chunk.chars().count();
+    if length <= chars {
+        return chunk.to_string();
+    }
+    let tail = chunk.chars().skip(length - chars).collect::<String>();
+    match tail.find(char::is_whitespace) {
+        Some(index) if chunk.contains(' ') => tail[index..].trim_start().to_string(),
+        _ => tail,
+    }
+}
+
+/// Cut a long paragraph into pieces of whole sentences where possible
+fn split_text(text: &str, options: &ChunkOptions) -> Vec<String> {
+    if text.chars().count() <= options.piece_chars() {
         return vec![text.to_string()];
     }
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    for sentence in split_sentences(text) {
+        if current.chars().count() + sentence.chars().count() > options.piece_chars() {
+            if !current.trim().is_empty() {
+                pieces.push(current.trim().to_string());
+            }
+            current.clear();
+        }
+        if sentence.chars().count() > options.piece_chars() {
+            pieces.extend(split_words(sentence, options));
+        } else {
+            current.push_str(sentence);
+        }
+    }
+    if !current.trim().is_empty() {
+        pieces.push(current.trim().to_string());
+    }
+    pieces
+}
+
+/// Cut a sentence longer than a piece at whitespace, or anywhere if it has none
+fn split_words(text: &str, options: &ChunkOptions) -> Vec<String> {
     let chars = text.chars().collect::<Vec<_>>();
     let mut pieces = Vec::new();
     let mut start = 0;
     while start < chars.len() {
-        let max_end = (start + MAX_CHARS).min(chars.len());
+        let max_end = (start + options.piece_chars()).min(chars.len());
         let end = if max_end == chars.len() {
             max_end
         } else {
@@ -112,7 +357,7 @@ fn split_text(text: &str) -> Vec<String> {
                 .rev()
                 .find(|index| chars[*index].is_whitespace())
                 .filter(|index| *index > start)
-                .unwrap_or((start + UNBROKEN_CHARS).min(chars.len()))
+                .unwrap_or((start + options.unbroken_chars()).min(chars.len()))
         };
         pieces.push(
             chars[start..end]
@@ -133,7 +378,7 @@ fn split_text(text: &str) -> Vec<String> {
 fn push_chunk(
     chunks: &mut Vec<WebPageChunk>,
     content: String,
-    heading: Option<String>,
+    heading_path: &[String],
     url: &str,
     title: &str,
     description: &str,
@@ -142,18 +387,21 @@ fn push_chunk(
     if content.trim().is_empty() {
         return;
     }
-    chunks.push(WebPageChunk::new(
-        content.trim().to_string(),
-        heading,
-        url.to_string(),
-        title.to_string(),
-        description.to_string(),
-        Vec::new(),
-        Vec::new(),
-        0.0,
-        0.0,
-        crawled_at,
-    ));
+    chunks.push(
+        WebPageChunk::new(
+            content.trim().to_string(),
+            heading_path.last().cloned(),
+            url.to_string(),
+            title.to_string(),
+            description.to_string(),
+            Vec::new(),
+            Vec::new(),
+            0.0,
+            0.0,
+            crawled_at,
+        )
+        .with_heading_path(heading_path.to_vec()),
+    );
 }
 
 #[cfg(test)]
@@ -169,31 +417,128 @@ mod tests {
         ] {
             let chunks = create_chunks(
                 vec![ContentBlock {
-                    heading: None,
+                    heading_path: Vec::new(),
                     text,
                 }],
                 "https://example.com",
                 "title",
                 "",
                 0,
+                &ChunkOptions::default(),
             );
             assert!(!chunks.is_empty());
             assert!(chunks
                 .iter()
-                .all(|chunk| chunk.chunk_content.chars().count() <= MAX_CHARS));
+                .all(|chunk| chunk.chunk_content.chars().count() <= DEFAULT_MAX_CHARS));
+        }
+    }
+
+    #[test]
+    fn splits_by_section_and_overlaps_within_one() {
+        let block = |heading: &str, text: String| ContentBlock {
+            heading_path: vec![heading.to_string()],
+            text,
+        };
+        let long = (0..300)
+            .map(|n| format!("w{n}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let chunks = create_chunks(
+            vec![
+                block("Intro", "short intro".to_string()),
+                block("Body", long),
+            ],
+            "https://example.com",
+            "title",
+            "",
+            0,
+            &ChunkOptions {
+                overlap_chars: 40,
+                ..ChunkOptions::default()
+            },
+        );
+
+        assert_eq!(chunks[0].chunk_content, "short intro");
+        assert!(chunks[1..]
+            .iter()
+            .all(|chunk| chunk.chunk_heading.as_deref() == Some("Body")));
+        assert!(chunks.len() > 2);
+        for pair in chunks[1..].windows(2) {
+            let tail = overlap_tail(&pair[0].chunk_content, 40);
+            assert!(!tail.is_empty());
+            assert!(pair[1].chunk_content.starts_with(&tail));
         }
     }
 
+    #[test]
+    fn collects_page_headings_once_in_order() {
+        let block = |heading_path: &[&str]| ContentBlock {
+            heading_path: heading_path
+                .iter()
+                .map(|heading| heading.to_string())
+                .collect(),
+            text: "text".to_string(),
+        };
+        let blocks = [
+            block(&[]),
+            block(&["Guide"]),
+            block(&["Guide", "Install"]),
+            block(&["Guide", "Install"]),
+            block(&["Guide", "Usage"]),
+        ];
+        assert_eq!(page_headings(&blocks), ["Guide", "Install", "Usage"]);
+    }
+
+    #[test]
+    fn applies_and_validates_overrides() {
+        let base = ChunkOptions::default();
+        assert_eq!(base.with_overrides(None, None, None), Ok(base));
+
+        let small = base.with_overrides(Some(300), None, None).unwrap();
+        assert_eq!((small.max_chars, small.overlap_chars), (450, 150));
+        let chunks = create_chunks(
+            vec![ContentBlock {
+                heading_path: Vec::new(),
+                text: "word ".repeat(400),
+            }],
+            "https://example.com",
+            "title",
+            "",
+            0,
+            &small,
+        );
+        assert!(chunks.len() > 5);
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.chunk_content.chars().count() <= 450));
+
+        assert!(base.with_overrides(Some(50), None, None).is_err());
+        assert!(base.with_overrides(Some(1_000), Some(900), None).is_err());
+        assert!(base.with_overrides(None, None, Some(500)).is_err());
+    }
+
+    #[test]
+    fn splits_long_paragraphs_between_sentences() {
+        let sentence = format!("{} e.g. {}. ", "a".repeat(100), "b".repeat(100));
+        let pieces = split_text(&sentence.repeat(4), &ChunkOptions::default());
+        assert_eq!(pieces.len(), 2);
+        assert!(pieces.iter().all(|piece| piece.ends_with('.')));
+    }
+
     #[test]
     fn unbroken_text_uses_small_splits() {
-        let pieces = split_text(&"界".repeat(1300));
-        assert_eq!(pieces[0].chars().count(), UNBROKEN_CHARS);
+        let pieces = split_text(&"界".repeat(1300), &ChunkOptions::default());
+        assert_eq!(pieces[0].chars().count(), 450);
     }
 
     #[test]
     fn keeps_fetched_url_when_canonical_points_to_root() {
         let base = Url::parse("https://example.com/subpage").unwrap();
-        let page = extract_page(&base, r#"<link rel="canonical" href="/"><p>content</p>"#);
+        let page = extract_page(
+            &base,
+            r#"<link rel="canonical" href="/"><p>content</p>"#,
+            &ChunkOptions::default(),
+        );
         assert_eq!(page.chunks[0].source_url, "https://example.com/subpage");
     }
 }
@@ -0,0 +1,610 @@
+//! Crawl job registry
+//!
+//! Every accepted crawl request becomes a job with an ID, live progress
+//! counters and a broadcast channel of events that UIs can subscribe to.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use shared_crawler_api::request_id;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::{broadcast, RwLock};
+use uuid::Uuid;
+
+use crate::{
+    report::{CrawlReport, ReportRecorder},
+    CrawlRequest,
+};
+
+const EVENT_BUFFER: usize = 256;
+const MAX_FINISHED_JOBS: usize = 1_000;
+const MAX_RECORDED_ERRORS: usize = 20;
+/// Frontier URLs kept per running job for `GET /queue`
+pub const NEXT_URLS_PREVIEW: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    /// Stopped at `max_duration_secs` with URLs left in the frontier
+    Truncated,
+    Failed,
+    /// Checkpointed by a shutdown, to be resumed on the next start
+    Interrupted,
+    /// Checkpointed because every URL left is on a paused host; queued again
+    /// once one of them is resumed
+    Parked,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Completed | Self::Truncated | Self::Failed)
+    }
+}
+
+/// Counters for a single crawl job
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobProgress {
+    pub pages_crawled: usize,
+    pub pages_indexed: usize,
+    pub pages_failed: usize,
+    pub pages_skipped_robots: usize,
+    pub pages_skipped_depth: usize,
+    pub pages_skipped_low_quality: usize,
+    /// Below the `focus_threshold` of a focused crawl
+    pub pages_skipped_off_topic: usize,
+    /// Failing the crawl's `must_contain` / `must_not_contain` terms
+    pub pages_skipped_keywords: usize,
+    /// Classified as spam or adult content, whether skipped or flagged
+    pub pages_flagged_unsafe: usize,
+    pub pages_blocked: usize,
+    /// Fetched by some job within `skip_crawled_within_hours`
+    pub pages_skipped_recent: usize,
+    /// Sitemap URLs an incremental crawl skipped as not modified
+    pub pages_unchanged: usize,
+    /// Pages not posted to `page_callback_url` because its queue was full
+    pub page_deliveries_dropped: usize,
+    pub frontier_size: usize,
+    pub visited: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum CrawlEvent {
+    Fetched {
+        url: String,
+    },
+    Indexed {
+        url: String,
+        chunks: usize,
+    },
+    Skipped {
+        url: String,
+        reason: &'static str,
+    },
+    Error {
+        url: String,
+        message: String,
+    },
+    Progress(JobProgress),
+    /// Every URL left is on one of `hosts`, which are paused; the job goes
+    /// on once one is resumed
+    Parked {
+        hosts: Vec<String>,
+        progress: JobProgress,
+    },
+    Finished {
+        status: JobStatus,
+        progress: JobProgress,
+    },
+}
+
+impl CrawlEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Fetched { .. } => "fetched",
+            Self::Indexed { .. } => "indexed",
+            Self::Skipped { .. } => "skipped",
+            Self::Error { .. } => "error",
+            Self::Progress(_) => "progress",
+            Self::Parked { .. } => "parked",
+            Self::Finished { .. } => "finished",
+        }
+    }
+}
+
+/// What a job has spent of `max_pages` and `max_pages_per_host`, kept in its
+/// checkpoint; paginated pages count towards neither
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PageBudget {
+    pub pages: usize,
+    pub host_pages: HashMap<String, usize>,
+}
+
+pub struct CrawlJob {
+    pub id: String,
+    pub request: CrawlRequest,
+    pub created_at: i64,
+    /// `X-Request-Id` of the request that queued the job, or a new one
+    pub request_id: String,
+    /// Continues from a frontier and visited set checkpointed at shutdown
+    pub resumed: bool,
+    status: Mutex<JobStatus>,
+    /// Paused hosts a parked job waits for
+    parked_on: Mutex<Vec<String>>,
+    progress: Mutex<JobProgress>,
+    /// As of the last checkpoint
+    budget: Mutex<PageBudget>,
+    timings: Mutex<JobTimings>,
+    errors: Mutex<Vec<String>>,
+    next_urls: Mutex<Vec<String>>,
+    report: Mutex<ReportRecorder>,
+    events: broadcast::Sender<CrawlEvent>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct JobTimings {
+    started_at: Option<SystemTime>,
+    finished_at: Option<SystemTime>,
+}
+
+/// Final outcome of a job, delivered to `callback_url`
+#[derive(Debug, Clone, Serialize)]
+pub struct JobSummary {
+    pub job_id: String,
+    pub url: String,
+    pub status: JobStatus,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    pub duration_ms: u64,
+    pub progress: JobProgress,
+    pub errors: Vec<String>,
+}
+
+/// Serializable view of a job for the status endpoints
+#[derive(Debug, Clone, Serialize)]
+pub struct JobSnapshot {
+    pub id: String,
+    pub url: String,
+    pub status: JobStatus,
+    pub created_at: i64,
+    pub progress: JobProgress,
+}
+
+impl CrawlJob {
+    fn new(request: CrawlRequest) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            request,
+            created_at: unix_now(),
+            request_id: request_id::current().unwrap_or_else(|| request_id::from_header(None)),
+            resumed: false,
+            status: Mutex::new(JobStatus::Queued),
+            parked_on: Mutex::new(Vec::new()),
+            progress: Mutex::new(JobProgress::default()),
+            budget: Mutex::new(PageBudget::default()),
+            timings: Mutex::new(JobTimings::default()),
+            errors: Mutex::new(Vec::new()),
+            next_urls: Mutex::new(Vec::new()),
+            report: Mutex::new(ReportRecorder::default()),
+            events: broadcast::channel(EVENT_BUFFER).0,
+        }
+    }
+
+    pub fn status(&self) -> JobStatus {
+        *self.status.lock().unwrap()
+    }
+
+    pub fn progress(&self) -> JobProgress {
+        self.progress.lock().unwrap().clone()
+    }
+
+    pub fn snapshot(&self) -> JobSnapshot {
+        JobSnapshot {
+            id: self.id.clone(),
+            url: self.request.url.clone(),
+            status: self.status(),
+            created_at: self.created_at,
+            progress: self.progress(),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<CrawlEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn emit(&self, event: CrawlEvent) {
+        match &event {
+            CrawlEvent::Error { url, message } => {
+                let mut errors = self.errors.lock().unwrap();
+                if errors.len() < MAX_RECORDED_ERRORS {
+                    errors.push(format!("{url}: {message}"));
+                }
+                self.record(|report| report.error(message));
+            }
+            CrawlEvent::Skipped {
+                url,
+                reason: "robots",
+            } => self.record(|report| report.skipped_robots(url)),
+            _ => {}
+        }
+        // No subscribers is the common case; events are best-effort.
+        let _ = self.events.send(event);
+    }
+
+    pub fn start(&self) {
+        *self.status.lock().unwrap() = JobStatus::Running;
+        self.timings.lock().unwrap().started_at = Some(SystemTime::now());
+        self.emit(CrawlEvent::Progress(self.progress()));
+    }
+
+    /// Apply a change to the counters and broadcast the new totals
+    pub fn update(&self, change: impl FnOnce(&mut JobProgress)) {
+        let progress = {
+            let mut progress = self.progress.lock().unwrap();
+            change(&mut progress);
+            progress.clone()
+        };
+        self.emit(CrawlEvent::Progress(progress));
+    }
+
+    /// Remember the head of the frontier, in fetch order
+    pub fn set_next_urls(&self, urls: impl IntoIterator<Item = String>) {
+        *self.next_urls.lock().unwrap() = urls.into_iter().take(NEXT_URLS_PREVIEW).collect();
+    }
+
+    pub fn next_urls(&self) -> Vec<String> {
+        self.next_urls.lock().unwrap().clone()
+    }
+
+    /// Mark the job parked until one of `hosts` is resumed; it has not
+    /// finished, so subscribers stay attached
+    pub fn park(&self, hosts: impl IntoIterator<Item = String>) {
+        let hosts = hosts.into_iter().collect::<Vec<_>>();
+        *self.parked_on.lock().unwrap() = hosts.clone();
+        *self.status.lock().unwrap() = JobStatus::Parked;
+        self.next_urls.lock().unwrap().clear();
+        self.emit(CrawlEvent::Parked {
+            hosts,
+            progress: self.progress(),
+        });
+    }
+
+    /// Claim a parked job whose hosts are not all `paused` any more, so it is
+    /// queued again exactly once
+    pub fn unpark(&self, paused: impl Fn(&str) -> bool) -> bool {
+        let mut status = self.status.lock().unwrap();
+        if *status != JobStatus::Parked || self.parked_on.lock().unwrap().iter().all(|h| paused(h))
+        {
+            return false;
+        }
+        *status = JobStatus::Queued;
+        true
+    }
+
+    pub fn finish(&self, status: JobStatus) {
+        *self.status.lock().unwrap() = status;
+        self.next_urls.lock().unwrap().clear();
+        self.timings.lock().unwrap().finished_at = Some(SystemTime::now());
+        self.emit(CrawlEvent::Finished {
+            status,
+            progress: self.progress(),
+        });
+    }
+
+    /// What is kept of the job in the history once it has finished
+    pub fn history_record(&self) -> serde_json::Value {
+        let mut record = serde_json::to_value(self.summary()).unwrap_or_default();
+        record["created_at"] = self.created_at.into();
+        record["request"] = serde_json::to_value(&self.request).unwrap_or_default();
+        // Extra headers often carry credentials
+        if let Some(headers) = record["request"]["headers"].as_object_mut() {
+            for value in headers.values_mut() {
+                *value = "[redacted]".into();
+            }
+        }
+        record
+    }
+
+    /// Pages spent as of the last checkpoint, where a resumed crawl goes on
+    pub fn budget(&self) -> PageBudget {
+        self.budget.lock().unwrap().clone()
+    }
+
+    pub fn set_budget(&self, budget: &PageBudget) {
+        *self.budget.lock().unwrap() = budget.clone();
+    }
+
+    /// What a shutdown keeps of the job to resume it; `started` once its
+    /// frontier and visited set are checkpointed too
+    pub fn checkpoint_record(&self, started: bool) -> Value {
+        serde_json::json!({
+            "request": self.request,
+            "request_id": self.request_id,
+            "progress": self.progress(),
+            "budget": self.budget(),
+            "started": started,
+        })
+    }
+
+    /// Add to the figures behind the job's report
+    pub fn record(&self, change: impl FnOnce(&mut ReportRecorder)) {
+        change(&mut self.report.lock().unwrap());
+    }
+
+    /// The crawl report, once the job has finished
+    pub fn report(&self) -> Option<CrawlReport> {
+        self.status()
+            .is_finished()
+            .then(|| self.report.lock().unwrap().report(self.summary()))
+    }
+
+    pub fn summary(&self) -> JobSummary {
+        let timings = *self.timings.lock().unwrap();
+        let duration = match (timings.started_at, timings.finished_at) {
+            (Some(started), Some(finished)) => finished.duration_since(started).unwrap_or_default(),
+            _ => Default::default(),
+        };
+        JobSummary {
+            job_id: self.id.clone(),
+            url: self.request.url.clone(),
+            status: self.status(),
+            started_at: timings.started_at.map(unix_secs),
+            finished_at: timings.finished_at.map(unix_secs),
+            duration_ms: duration.as_millis() as u64,
+            progress: self.progress(),
+            errors: self.errors.lock().unwrap().clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CrawlJobs {
+    jobs: RwLock<HashMap<String, Arc<CrawlJob>>>,
+}
+
+impl CrawlJobs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn create(&self, request: CrawlRequest) -> Arc<CrawlJob> {
+        let job = Arc::new(CrawlJob::new(request));
+        let mut jobs = self.jobs.write().await;
+        prune_finished(&mut jobs);
+        jobs.insert(job.id.clone(), job.clone());
+        job
+    }
+
+    /// Bring back a job checkpointed by the previous process, queued again
+    pub async fn resume(&self, id: &str, created_at: i64, record: &Value) -> Result<Arc<CrawlJob>> {
+        let mut job = CrawlJob::new(serde_json::from_value(record["request"].clone())?);
+        job.id = id.to_string();
+        job.created_at = created_at;
+        if let Some(request_id) = record["request_id"].as_str() {
+            job.request_id = request_id.to_string();
+        }
+        job.resumed = record["started"].as_bool().unwrap_or(false);
+        if job.resumed {
+            let progress: JobProgress =
+                serde_json::from_value(record["progress"].clone()).unwrap_or_default();
+            // Checkpoints from before the budget was kept
+            let budget = serde_json::from_value(record["budget"].clone()).unwrap_or(PageBudget {
+                pages: progress.pages_crawled,
+                host_pages: HashMap::new(),
+            });
+            *job.progress.get_mut().unwrap() = progress;
+            *job.budget.get_mut().unwrap() = budget;
+        }
+        let job = Arc::new(job);
+        self.jobs.write().await.insert(job.id.clone(), job.clone());
+        Ok(job)
+    }
+
+    pub async fn get(&self, id: &str) -> Option<Arc<CrawlJob>> {
+        self.jobs.read().await.get(id).cloned()
+    }
+
+    /// Jobs waiting for a paused host, oldest first
+    pub async fn parked(&self) -> Vec<Arc<CrawlJob>> {
+        self.with_status(JobStatus::Parked).await
+    }
+
+    /// Jobs currently being crawled, oldest first
+    pub async fn running(&self) -> Vec<Arc<CrawlJob>> {
+        self.with_status(JobStatus::Running).await
+    }
+
+    async fn with_status(&self, status: JobStatus) -> Vec<Arc<CrawlJob>> {
+        let mut running = self
+            .jobs
+            .read()
+            .await
+            .values()
+            .filter(|job| job.status() == status)
+            .cloned()
+            .collect::<Vec<_>>();
+        running.sort_by_key(|job| job.created_at);
+        running
+    }
+}
+
+fn prune_finished(jobs: &mut HashMap<String, Arc<CrawlJob>>) {
+    let mut finished = jobs
+        .values()
+        .filter(|job| job.status().is_finished())
+        .map(|job| (job.created_at, job.id.clone()))
+        .collect::<Vec<_>>();
+    if finished.len() < MAX_FINISHED_JOBS {
+        return;
+    }
+    finished.sort();
+    for (_, id) in finished.iter().take(finished.len() + 1 - MAX_FINISHED_JOBS) {
+        jobs.remove(id);
+    }
+}
+
+pub fn unix_now() -> i64 {
+    unix_secs(SystemTime::now())
+}
+
+fn unix_secs(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> CrawlRequest {
+        serde_json::from_value(serde_json::json!({
+            "url": "https://example.com/",
+            "max_pages": 1
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn broadcasts_progress_and_finish() {
+        let jobs = CrawlJobs::new();
+        let job = jobs.create(request()).await;
+        let mut events = job.subscribe();
+
+        job.start();
+        job.update(|progress| progress.pages_crawled += 1);
+        job.finish(JobStatus::Completed);
+
+        assert!(matches!(events.recv().await, Ok(CrawlEvent::Progress(_))));
+        match events.recv().await.unwrap() {
+            CrawlEvent::Progress(progress) => assert_eq!(progress.pages_crawled, 1),
+            event => panic!("unexpected event {}", event.name()),
+        }
+        assert!(matches!(
+            events.recv().await,
+            Ok(CrawlEvent::Finished {
+                status: JobStatus::Completed,
+                ..
+            })
+        ));
+        assert_eq!(
+            jobs.get(&job.id).await.unwrap().status(),
+            JobStatus::Completed
+        );
+    }
+
+    #[tokio::test]
+    async fn unparks_once_a_host_is_resumed() {
+        let jobs = CrawlJobs::new();
+        let job = jobs.create(request()).await;
+        job.start();
+        let mut events = job.subscribe();
+        job.park(["a.example".to_string(), "b.example".to_string()]);
+        assert!(!job.status().is_finished());
+        assert!(job.summary().finished_at.is_none());
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            CrawlEvent::Parked { hosts, .. } if hosts == ["a.example", "b.example"]
+        ));
+        assert_eq!(jobs.parked().await.len(), 1);
+
+        assert!(!job.unpark(|_| true));
+        assert!(job.unpark(|host| host == "a.example"));
+        assert_eq!(job.status(), JobStatus::Queued);
+        // Claimed already
+        assert!(!job.unpark(|_| false));
+        assert!(jobs.parked().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn summarizes_finished_job_with_errors() {
+        let job = CrawlJobs::new().create(request()).await;
+        job.start();
+        job.emit(CrawlEvent::Error {
+            url: "https://example.com/a".to_string(),
+            message: "HTTP 500".to_string(),
+        });
+        job.finish(JobStatus::Completed);
+
+        let summary = job.summary();
+        assert_eq!(summary.status, JobStatus::Completed);
+        assert!(summary.started_at.is_some() && summary.finished_at.is_some());
+        assert_eq!(summary.errors, ["https://example.com/a: HTTP 500"]);
+    }
+
+    #[tokio::test]
+    async fn resumes_checkpointed_jobs() {
+        let job = CrawlJobs::new().create(request()).await;
+        job.start();
+        job.update(|progress| progress.pages_crawled = 3);
+        // One of the three was a paginated page
+        let budget = PageBudget {
+            pages: 2,
+            host_pages: HashMap::from([("example.com".to_string(), 2)]),
+        };
+        job.set_budget(&budget);
+        job.finish(JobStatus::Interrupted);
+        let record = job.checkpoint_record(true);
+
+        let jobs = CrawlJobs::new();
+        let resumed = jobs.resume(&job.id, job.created_at, &record).await.unwrap();
+        assert!(resumed.resumed && !JobStatus::Interrupted.is_finished());
+        assert_eq!(resumed.status(), JobStatus::Queued);
+        assert_eq!(resumed.request_id, job.request_id);
+        assert_eq!(resumed.progress().pages_crawled, 3);
+        assert_eq!(resumed.budget(), budget);
+        assert!(jobs.get(&job.id).await.is_some());
+
+        let mut legacy = record.clone();
+        legacy.as_object_mut().unwrap().remove("budget");
+        let resumed = jobs.resume(&job.id, job.created_at, &legacy).await.unwrap();
+        assert_eq!(resumed.budget().pages, 3);
+    }
+
+    #[tokio::test]
+    async fn lists_running_jobs_with_frontier_preview() {
+        let jobs = CrawlJobs::new();
+        let queued = jobs.create(request()).await;
+        let running = jobs.create(request()).await;
+        running.start();
+        running.set_next_urls((0..200).map(|page| format!("https://example.com/{page}")));
+
+        let listed = jobs.running().await;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, running.id);
+        assert_eq!(listed[0].next_urls().len(), NEXT_URLS_PREVIEW);
+        assert_eq!(queued.status(), JobStatus::Queued);
+
+        running.finish(JobStatus::Completed);
+        assert!(running.next_urls().is_empty());
+        assert!(jobs.running().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn prunes_oldest_finished_jobs() {
+        let jobs = CrawlJobs::new();
+        let first = jobs.create(request()).await;
+        first.finish(JobStatus::Completed);
+        {
+            let mut map = jobs.jobs.write().await;
+            for _ in 1..MAX_FINISHED_JOBS {
+                let job = Arc::new(CrawlJob::new(request()));
+                job.finish(JobStatus::Completed);
+                map.insert(job.id.clone(), job);
+            }
+        }
+        let running = jobs.create(request()).await;
+
+        assert_eq!(jobs.jobs.read().await.len(), MAX_FINISHED_JOBS);
+        assert!(jobs.get(&running.id).await.is_some());
+    }
+}
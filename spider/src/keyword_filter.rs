@@ -0,0 +1,118 @@
+//! Keyword gating of a crawl's index
+//!
+//! `must_contain` and `must_not_contain` on a crawl request are matched
+//! against each page's title, description and extracted text, ignoring case
+//! and whitespace differences. A page is indexed only if it contains every
+//! `must_contain` term and none of the `must_not_contain` ones; pages that
+//! fail are still traversed for links.
+
+use shared_crawler_api::{problem::Problem, text, WebPageChunk};
+
+const MAX_TERMS: usize = 50;
+const MAX_TERM_CHARS: usize = 100;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeywordFilter {
+    must_contain: Vec<String>,
+    must_not_contain: Vec<String>,
+}
+
+impl KeywordFilter {
+    pub fn new(must_contain: &[String], must_not_contain: &[String]) -> Result<Self, Problem> {
+        Ok(Self {
+            must_contain: normalize_terms("must_contain", must_contain)?,
+            must_not_contain: normalize_terms("must_not_contain", must_not_contain)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.must_contain.is_empty() && self.must_not_contain.is_empty()
+    }
+
+    /// Why a page is not indexed, if it is not
+    pub fn rejects(&self, chunks: &[WebPageChunk]) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut page = chunks
+            .first()
+            .map(|chunk| format!("{}\n{}\n", chunk.page_title, chunk.description))
+            .unwrap_or_default();
+        for chunk in chunks {
+            page.push_str(&chunk.chunk_content);
+            page.push('\n');
+        }
+        let page = text::clean(&page).to_lowercase();
+        if let Some(term) = self.must_contain.iter().find(|term| !page.contains(*term)) {
+            Some(format!("missing \"{term}\""))
+        } else {
+            self.must_not_contain
+                .iter()
+                .find(|term| page.contains(*term))
+                .map(|term| format!("contains \"{term}\""))
+        }
+    }
+}
+
+fn normalize_terms(field: &str, terms: &[String]) -> Result<Vec<String>, Problem> {
+    if terms.len() > MAX_TERMS {
+        return Err(Problem::bad_request(
+            "invalid_keywords",
+            format!("{field} takes at most {MAX_TERMS} terms"),
+        ));
+    }
+    terms
+        .iter()
+        .map(|term| {
+            let term = text::clean(term).to_lowercase();
+            if term.is_empty() || term.chars().count() > MAX_TERM_CHARS {
+                Err(Problem::bad_request(
+                    "invalid_keywords",
+                    format!("{field} terms must be 1 to {MAX_TERM_CHARS} characters"),
+                ))
+            } else {
+                Ok(term)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(content: &str) -> Vec<WebPageChunk> {
+        vec![WebPageChunk::new(
+            content.to_string(),
+            None,
+            "https://example.com/".to_string(),
+            "Tokio guide".to_string(),
+            String::new(),
+            vec![],
+            vec![],
+            0.0,
+            0.0,
+            0,
+        )]
+    }
+
+    #[test]
+    fn requires_all_wanted_and_no_unwanted_terms() {
+        let filter = KeywordFilter::new(
+            &["tokio".to_string(), "Async  Runtime".to_string()],
+            &["sponsored".to_string()],
+        )
+        .unwrap();
+
+        assert_eq!(filter.rejects(&page("An async\nruntime for Rust")), None);
+        assert_eq!(
+            filter.rejects(&page("A runtime for Rust")),
+            Some("missing \"async runtime\"".to_string())
+        );
+        assert_eq!(
+            filter.rejects(&page("Async runtime (Sponsored)")),
+            Some("contains \"sponsored\"".to_string())
+        );
+        assert!(KeywordFilter::new(&[" ".to_string()], &[]).is_err());
+    }
+}
@@ -1,39 +1,112 @@
+use crate::archive::HtmlArchive;
+use crate::backup::{BackupRequest, OperationKind, Operations, RestoreRequest};
+use crate::crawl_diff::CrawlDiff;
 use crate::crawl_loop::CrawlLoop;
+use crate::discover::DiscoverRequest;
+use crate::domain_profile::{normalize_host, DomainProfile, DomainProfiles};
+use crate::jobs::{unix_now, CrawlEvent, CrawlJob, CrawlJobs, NEXT_URLS_PREVIEW};
 use crate::qdrant::PageIndexer;
+use crate::reindex::{ReindexRequest, Reindexer};
+use crate::seed_list::{CrawlTemplate, SeedListBody, SeedListQuery};
+use crate::seo::SeoReport;
+use crate::state::{BrokenLink, StateStore};
 use crate::stats::CrawlStats;
+use crate::third_party_search::{SearchProvider, ThirdPartySearch};
 use actix_cors::Cors;
-use actix_web::{web, App, HttpResponse, HttpServer, Responder};
-use serde::Deserialize;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::{from_fn, Next};
+use actix_web::{web, App, HttpMessage, HttpRequest, HttpResponse, HttpServer, Responder};
+use futures::stream;
+use serde::{Deserialize, Serialize};
+use shared_crawler_api::caches::FlushRequest;
+use shared_crawler_api::csv;
+use shared_crawler_api::health::{ComponentHealth, ReadinessReport};
+use shared_crawler_api::listen::Listen;
+use shared_crawler_api::problem::{Problem, PROBLEM_CONTENT_TYPE};
+use shared_crawler_api::request_id;
+use shared_crawler_api::tenant;
 use shared_crawler_api::util_fns::load_env;
+use shared_crawler_api::QDRANT_COLLECTION_NAME;
+use std::collections::BTreeMap;
 use std::env;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
+use tracing::Instrument;
 
+pub mod archive;
+pub mod author;
+pub mod backup;
+pub mod blob_store;
+pub mod breadcrumb;
+pub mod cache_invalidation;
+pub mod cluster;
+pub mod content_filter;
+pub mod crawl_diff;
 pub mod crawl_loop;
+pub mod discover;
+pub mod domain_profile;
 pub mod extractor;
 pub mod extractor_content;
+pub mod frontier;
 pub mod index;
+pub mod jobs;
+pub mod keyword_filter;
+pub mod page_events;
+pub mod pagination;
+pub mod private_network;
+pub mod published;
 pub mod qdrant;
+pub mod qdrant_retry;
+pub mod quality;
+pub mod reindex;
+pub mod report;
 pub mod robots;
+pub mod s3;
+pub mod seed_list;
+pub mod sentences;
+pub mod seo;
 pub mod sitemap;
+pub mod state;
 pub mod stats;
+pub mod third_party_search;
+pub mod tokens;
+pub mod url_variants;
+pub mod visited;
 pub mod web_visitor;
 pub mod web_visitor_browser;
+pub mod webhook;
 
 const REQUEST_TIMEOUT_SECS: u64 = 30;
+const READINESS_TIMEOUT: Duration = Duration::from_secs(3);
+const MAX_HISTORY_LIMIT: usize = 500;
+const MAX_REPORT_LIMIT: usize = 1000;
 
 struct AppState {
     crawl_loop: Arc<Mutex<CrawlLoop>>,
     stats: Arc<CrawlStats>,
+    jobs: Arc<CrawlJobs>,
+    indexer: Arc<PageIndexer>,
+    search_provider: Option<Arc<SearchProvider>>,
+    state: Arc<StateStore>,
+    profiles: Arc<DomainProfiles>,
+    backups: Arc<Operations>,
+    reindexer: Arc<Reindexer>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct CrawlRequest {
     pub url: String,
     pub max_pages: usize,
     // same domain, per default true
     #[serde(default = "default_same_domain")]
     pub same_domain: bool, // if true, only crawl pages from the same root domain will be crawled
+    /// `index` (default) or `linkcheck`
+    #[serde(default)]
+    pub mode: CrawlMode,
     /// Force browser-based crawling for all pages (bypasses HTTP client)
     #[serde(default)]
     pub use_browser: bool,
@@ -43,9 +116,102 @@ pub struct CrawlRequest {
     /// Timeout in milliseconds for wait_for_selector (default: 5000)
     #[serde(default = "default_wait_timeout")]
     pub wait_timeout_ms: u64,
+    /// Browser only: scroll to the bottom up to this many times before
+    /// capturing, stopping early once the page stops growing, so infinite
+    /// feeds and lazy-loaded listings render more than their first screen
+    #[serde(default)]
+    pub scroll_count: usize,
     /// Maximum crawl depth (default: 10)
     #[serde(default = "default_max_depth")]
     pub max_depth: usize,
+    /// URL that receives a signed JSON summary when the job finishes
+    #[serde(default)]
+    pub callback_url: Option<String>,
+    /// URL that receives each page's chunks as they are indexed
+    #[serde(default)]
+    pub page_callback_url: Option<String>,
+    /// Track visited URLs in a Bloom filter with this false-positive rate
+    /// instead of an exact in-memory set; meant for very large crawls
+    #[serde(default)]
+    pub bloom_fp_rate: Option<f64>,
+    /// Pages crawled from any one host, so a `same_domain=false` crawl
+    /// spreads `max_pages` over the hosts it discovers
+    #[serde(default)]
+    pub max_pages_per_host: Option<usize>,
+    /// Wall-clock limit; the job stops after the page in flight and is
+    /// marked `truncated`
+    #[serde(default)]
+    pub max_duration_secs: Option<u64>,
+    /// User-Agent for this crawl instead of `CRAWLER_USER_AGENT`; its product
+    /// token is also the one matched against robots.txt
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// HTTP timeout for this crawl's requests instead of
+    /// `REQUEST_TIMEOUT_SECS`
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+    /// Largest HTML body fetched instead of the default 5 MiB
+    #[serde(default)]
+    pub max_body_bytes: Option<usize>,
+    /// Extra headers sent with every HTTP request of this crawl
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Topic of a focused crawl: pages whose content embeds less similar to
+    /// it than `focus_threshold` are neither indexed nor followed
+    #[serde(default)]
+    pub focus_query: Option<String>,
+    #[serde(default = "default_focus_threshold")]
+    pub focus_threshold: f32,
+    /// Skip pages any job of the same tenant fetched less than this many
+    /// hours ago instead of `SKIP_CRAWLED_WITHIN_HOURS`; `0` fetches
+    /// everything
+    #[serde(default)]
+    pub skip_crawled_within_hours: Option<u64>,
+    /// Terms a page must all contain to be indexed
+    #[serde(default)]
+    pub must_contain: Vec<String>,
+    /// Terms that keep a page out of the index
+    #[serde(default)]
+    pub must_not_contain: Vec<String>,
+    /// Chunk size overrides, e.g. for an embedding model with a shorter or
+    /// longer context than the default one; unset values come from `CHUNK_*`
+    #[serde(default)]
+    pub chunk_target_chars: Option<usize>,
+    #[serde(default)]
+    pub chunk_max_chars: Option<usize>,
+    #[serde(default)]
+    pub chunk_overlap_chars: Option<usize>,
+    /// Pages reached through pagination links, which are followed without
+    /// counting against `max_pages` or `max_depth`; unset uses
+    /// `PAGINATION_MAX_PAGES`
+    #[serde(default)]
+    pub max_pagination_pages: Option<usize>,
+    /// Skip sitemap URLs whose `lastmod` is not newer than their indexed
+    /// `crawled_at`, so a refresh only fetches what changed
+    #[serde(default)]
+    pub incremental: bool,
+    /// Index into this tenant's own collection instead of the shared one
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Label stored on this job's pages, so searches can be limited to it
+    #[serde(default)]
+    pub collection: Option<String>,
+    /// Name of a frontier and visited set kept in Redis, which jobs of the
+    /// same name on other spider instances work through together; needs
+    /// `SPIDER_REDIS_URL`
+    #[serde(default)]
+    pub shared_frontier: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CrawlMode {
+    /// Extract, filter and index every page
+    #[default]
+    Index,
+    /// Only fetch pages and follow their links, recording response statuses
+    /// and broken links; links to other sites are checked but not followed
+    Linkcheck,
 }
 
 fn default_same_domain() -> bool {
@@ -60,6 +226,10 @@ fn default_max_depth() -> usize {
     10
 }
 
+fn default_focus_threshold() -> f32 {
+    0.8
+}
+
 async fn health_check() -> impl Responder {
     HttpResponse::Ok().json(serde_json::json!({
         "status": "ok",
@@ -67,23 +237,137 @@ async fn health_check() -> impl Responder {
     }))
 }
 
+/// Readiness probe: 503 unless Qdrant and TEI both answer
+async fn readiness(app_state: web::Data<AppState>) -> impl Responder {
+    let (qdrant, tei) = tokio::join!(
+        probe(app_state.indexer.check_qdrant()),
+        probe(app_state.indexer.check_embeddings()),
+    );
+    let report = ReadinessReport::new([("qdrant", qdrant), ("tei", tei)]);
+    if report.is_ready() {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::ServiceUnavailable().json(report)
+    }
+}
+
+async fn probe(check: impl Future<Output = anyhow::Result<()>>) -> ComponentHealth {
+    let started = Instant::now();
+    let result = match tokio::time::timeout(READINESS_TIMEOUT, check).await {
+        Ok(result) => result.map_err(|error| error.to_string()),
+        Err(_) => Err("timed out".to_string()),
+    };
+    ComponentHealth::from_result(result, started.elapsed())
+}
+
 /// Status endpoint returning crawler metrics
 async fn status(app_state: web::Data<AppState>) -> impl Responder {
     let stats = app_state.stats.snapshot();
-    let queue_size = {
+    let (lifecycle, queue_size, (runners, busy_runners), throttled_origins) = {
         let loop_lock = app_state.crawl_loop.lock().await;
-        loop_lock.queue_size().await
+        (
+            loop_lock.lifecycle(),
+            loop_lock.queue_size().await,
+            loop_lock.runner_counts(),
+            loop_lock.throttled_origins().await,
+        )
     };
 
     HttpResponse::Ok().json(serde_json::json!({
         "status": "ok",
+        "lifecycle": lifecycle,
         "queue_size": queue_size,
+        "runners": runners,
+        "busy_runners": busy_runners,
         "pages_crawled": stats.pages_crawled,
         "pages_indexed": stats.pages_indexed,
         "pages_failed": stats.pages_failed,
         "pages_skipped_robots": stats.pages_skipped_robots,
         "pages_skipped_depth": stats.pages_skipped_depth,
+        "pages_skipped_low_quality": stats.pages_skipped_low_quality,
         "retries_attempted": stats.retries_attempted,
+        "responses_by_protocol": {
+            "http1": stats.responses_http1,
+            "http2": stats.responses_http2,
+            "http3": stats.responses_http3,
+        },
+        "failures_by_kind": stats.failures_by_kind,
+        "throttled_origins": throttled_origins,
+        "paused_hosts": app_state.profiles.paused(),
+        "qdrant": app_state.indexer.qdrant_stats(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct QueueQuery {
+    /// Frontier URLs to list per running job (default: 10)
+    #[serde(default = "default_queue_limit")]
+    limit: usize,
+}
+
+fn default_queue_limit() -> usize {
+    10
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    /// Unix seconds; only jobs finished at or after this are listed
+    #[serde(default)]
+    since: i64,
+    #[serde(default = "default_history_limit")]
+    limit: usize,
+}
+
+fn default_history_limit() -> usize {
+    50
+}
+
+/// Finished jobs from the state database, newest first
+async fn crawl_history(
+    query: web::Query<HistoryQuery>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    if query.limit == 0 || query.limit > MAX_HISTORY_LIMIT {
+        return problem_response(Problem::bad_request(
+            "limit_out_of_range",
+            format!("limit must be between 1 and {MAX_HISTORY_LIMIT}"),
+        ));
+    }
+    match app_state.state.job_history(query.since, query.limit) {
+        Ok(crawls) => HttpResponse::Ok().json(serde_json::json!({ "crawls": crawls })),
+        Err(error) => problem_response(Problem::internal("history_unavailable", error.to_string())),
+    }
+}
+
+/// Pending jobs, and the frontier size and next URLs of every running job
+async fn queue(query: web::Query<QueueQuery>, app_state: web::Data<AppState>) -> HttpResponse {
+    if query.limit > NEXT_URLS_PREVIEW {
+        return problem_response(Problem::bad_request(
+            "limit_out_of_range",
+            format!("limit must be at most {NEXT_URLS_PREVIEW}"),
+        ));
+    }
+    let pending = {
+        let loop_lock = app_state.crawl_loop.lock().await;
+        loop_lock.pending().await
+    };
+    let running = app_state.jobs.running().await;
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "pending": pending.iter().map(|job| job.snapshot()).collect::<Vec<_>>(),
+        "running": running
+            .iter()
+            .map(|job| {
+                let mut next_urls = job.next_urls();
+                next_urls.truncate(query.limit);
+                serde_json::json!({
+                    "id": job.id,
+                    "url": job.request.url,
+                    "frontier_size": job.progress().frontier_size,
+                    "next_urls": next_urls,
+                })
+            })
+            .collect::<Vec<_>>(),
     }))
 }
 
@@ -95,31 +379,793 @@ async fn crawl(
     let url = req.url.clone();
 
     // enqueue the crawl request into the shared CrawlLoop
-    {
+    let job = {
         let loop_lock = app_state.crawl_loop.lock().await;
-        if let Err(error) = loop_lock.add_crawl_request(req).await {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "success": false,
-                "message": error
-            }));
+        match loop_lock.add_crawl_request(req).await {
+            Ok(job) => job,
+            Err(problem) => return problem_response(problem),
         }
-    }
+    };
 
     HttpResponse::Ok().json(serde_json::json!({
         "success": true,
         "message": format!("Queued crawl for {url}"),
+        "job_id": job.id,
     }))
 }
 
+/// Run a web search and queue a crawl job for each result
+async fn discover(
+    discover_req: web::Json<DiscoverRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let req = discover_req.into_inner();
+    let template = match req.validate().and_then(|()| req.crawl_template()) {
+        Ok(template) => template,
+        Err(problem) => return problem_response(problem),
+    };
+    let Some(provider) = app_state.search_provider.clone() else {
+        return problem_response(Problem::new(
+            503,
+            "discovery_unavailable",
+            "no web search provider is configured",
+        ));
+    };
+    let hits = match provider.search(req.query.trim(), req.seeds).await {
+        Ok(hits) => hits,
+        Err(error) => {
+            return problem_response(Problem::new(
+                502,
+                "search_provider_failed",
+                format!("{} search failed: {error}", provider.name()),
+            ))
+        }
+    };
+
+    let mut jobs = Vec::new();
+    let mut skipped = Vec::new();
+    // Locked per hit, as for seed lists: each check resolves a host
+    for hit in hits {
+        let added = app_state
+            .crawl_loop
+            .lock()
+            .await
+            .add_crawl_request(template.crawl_request(hit.url.clone()))
+            .await;
+        match added {
+            Ok(job) => jobs.push(serde_json::json!({
+                "job_id": job.id,
+                "url": hit.url,
+                "title": hit.title,
+            })),
+            Err(problem) => skipped.push(serde_json::json!({
+                "url": hit.url,
+                "code": problem.code,
+            })),
+        }
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "query": req.query,
+        "provider": provider.name(),
+        "jobs": jobs,
+        "skipped": skipped,
+    }))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RobotsFlushRequest {
+    /// Origins such as `https://example.com`; all entries are flushed when omitted
+    #[serde(default)]
+    origins: Option<Vec<String>>,
+}
+
+/// Drop cached robots.txt files so the next request refetches them
+async fn flush_robots(
+    body: Option<web::Json<RobotsFlushRequest>>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let request = body.map(web::Json::into_inner).unwrap_or_default();
+    let flushed = {
+        let loop_lock = app_state.crawl_loop.lock().await;
+        loop_lock.flush_robots(request.origins.as_deref()).await
+    };
+    HttpResponse::Ok().json(serde_json::json!({ "flushed": flushed }))
+}
+
+/// Stop taking crawl jobs; running ones finish their page and are checkpointed
+async fn stop_crawler(app_state: web::Data<AppState>) -> HttpResponse {
+    let lifecycle = app_state.crawl_loop.lock().await.stop();
+    HttpResponse::Ok().json(serde_json::json!({ "lifecycle": lifecycle }))
+}
+
+/// Start taking crawl jobs again, resuming the ones `stop` checkpointed first
+async fn start_crawler(app_state: web::Data<AppState>) -> HttpResponse {
+    let mut loop_lock = app_state.crawl_loop.lock().await;
+    match loop_lock.start().await {
+        Ok(resumed) => HttpResponse::Ok().json(serde_json::json!({
+            "lifecycle": loop_lock.lifecycle(),
+            "resumed_jobs": resumed,
+        })),
+        Err(problem) => problem_response(problem),
+    }
+}
+
+/// Caches `/admin/caches` reports and flushes
+const CACHES: [&str; 2] = ["robots", "fetched_pages"];
+
+/// Entries and hit rates of the robots.txt cache and of the URLs remembered
+/// for `skip_crawled_within_hours`
+async fn cache_stats(app_state: web::Data<AppState>) -> HttpResponse {
+    let robots = app_state.crawl_loop.lock().await.robots_stats().await;
+    match app_state.state.fetch_stats() {
+        Ok(fetched_pages) => HttpResponse::Ok().json(serde_json::json!({
+            "robots": robots,
+            "fetched_pages": fetched_pages,
+        })),
+        Err(error) => problem_response(Problem::internal("history_unavailable", error.to_string())),
+    }
+}
+
+async fn flush_caches(
+    body: Option<web::Json<FlushRequest>>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let request = body.map(web::Json::into_inner).unwrap_or_default();
+    let caches = match request.select(&CACHES) {
+        Ok(caches) => caches,
+        Err(problem) => return problem_response(problem),
+    };
+    let mut flushed = BTreeMap::new();
+    for cache in caches {
+        let removed = match cache {
+            "robots" => app_state.crawl_loop.lock().await.flush_robots(None).await,
+            _ => match app_state.state.clear_fetches() {
+                Ok(removed) => removed,
+                Err(error) => {
+                    return problem_response(Problem::internal(
+                        "history_unavailable",
+                        error.to_string(),
+                    ));
+                }
+            },
+        };
+        flushed.insert(cache, removed);
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "flushed": flushed }))
+}
+
+#[derive(Debug, Deserialize)]
+struct BackupsQuery {
+    #[serde(default)]
+    tenant: Option<String>,
+}
+
+/// Snapshots of the shared or a tenant's collection, and recent backup and
+/// restore operations
+async fn list_backups(
+    query: web::Query<BackupsQuery>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let collection = match tenant::collection_name(query.tenant.as_deref()) {
+        Ok(collection) => collection,
+        Err(problem) => return problem_response(problem),
+    };
+    match app_state.indexer.snapshots(&collection).await {
+        Ok(snapshots) => HttpResponse::Ok().json(serde_json::json!({
+            "collection": collection,
+            "snapshots": snapshots,
+            "operations": app_state.backups.recent(),
+        })),
+        Err(error) => problem_response(Problem::internal(
+            "snapshots_unavailable",
+            format!("{error:#}"),
+        )),
+    }
+}
+
+/// Progress of one backup or restore
+async fn backup_operation(id: web::Path<String>, app_state: web::Data<AppState>) -> HttpResponse {
+    match app_state.backups.get(&id) {
+        Some(operation) => HttpResponse::Ok().json(operation),
+        None => problem_response(Problem::not_found(
+            "operation_not_found",
+            format!("no backup or restore operation {id}"),
+        )),
+    }
+}
+
+/// Start writing a snapshot of the shared or a tenant's collection
+async fn start_backup(
+    body: Option<web::Json<BackupRequest>>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let request = body.map(web::Json::into_inner).unwrap_or_default();
+    let collection = match tenant::collection_name(request.tenant.as_deref()) {
+        Ok(collection) => collection,
+        Err(problem) => return problem_response(problem),
+    };
+    let Some(operation) =
+        app_state
+            .backups
+            .start(OperationKind::Backup, &collection, None, unix_now())
+    else {
+        return problem_response(operation_running());
+    };
+    let (indexer, backups, id) = (
+        app_state.indexer.clone(),
+        app_state.backups.clone(),
+        operation.id.clone(),
+    );
+    tokio::spawn(
+        request_id::inherit(async move {
+            let result = indexer
+                .create_snapshot(&collection)
+                .await
+                .map_err(|error| format!("{error:#}"));
+            backups.finish(&id, result, unix_now());
+        })
+        .in_current_span(),
+    );
+    HttpResponse::Accepted().json(operation)
+}
+
+/// Start replacing a collection with one of its snapshots; refused while
+/// crawl jobs could write to the index
+async fn start_restore(
+    body: web::Json<RestoreRequest>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let collection = match tenant::collection_name(body.tenant.as_deref()) {
+        Ok(collection) => collection,
+        Err(problem) => return problem_response(problem),
+    };
+    let crawling = {
+        let loop_lock = app_state.crawl_loop.lock().await;
+        loop_lock.queue_size().await > 0 || loop_lock.runner_counts().1 > 0
+    };
+    if crawling {
+        return problem_response(Problem::new(
+            409,
+            "crawls_running",
+            "wait for running and queued crawl jobs to finish before restoring",
+        ));
+    }
+    let snapshot = match app_state.indexer.snapshots(&collection).await {
+        Ok(snapshots) => snapshots
+            .into_iter()
+            .find(|snapshot| snapshot.name == body.snapshot),
+        Err(error) => {
+            return problem_response(Problem::internal(
+                "snapshots_unavailable",
+                format!("{error:#}"),
+            ));
+        }
+    };
+    let Some(snapshot) = snapshot else {
+        return problem_response(Problem::not_found(
+            "snapshot_not_found",
+            format!("{collection} has no snapshot {}", body.snapshot),
+        ));
+    };
+    let Some(operation) = app_state.backups.start(
+        OperationKind::Restore,
+        &collection,
+        Some(snapshot.clone()),
+        unix_now(),
+    ) else {
+        return problem_response(operation_running());
+    };
+    let (indexer, backups, id) = (
+        app_state.indexer.clone(),
+        app_state.backups.clone(),
+        operation.id.clone(),
+    );
+    tokio::spawn(
+        request_id::inherit(async move {
+            let result = indexer
+                .restore_snapshot(&collection, &snapshot.name)
+                .await
+                .map(|()| snapshot)
+                .map_err(|error| format!("{error:#}"));
+            backups.finish(&id, result, unix_now());
+        })
+        .in_current_span(),
+    );
+    HttpResponse::Accepted().json(operation)
+}
+
+/// Start rebuilding the shared or a tenant's collection with the current
+/// chunking settings and the current or a new embedding model
+async fn start_reindex(
+    body: Option<web::Json<ReindexRequest>>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let request = body.map(web::Json::into_inner).unwrap_or_default();
+    let collection = match tenant::collection_name(request.tenant.as_deref())
+        .and_then(|collection| request.validate().map(|()| collection))
+    {
+        Ok(collection) => collection,
+        Err(problem) => return problem_response(problem),
+    };
+    if !request.drop_versions && app_state.indexer.keeps_versions(&collection).await {
+        return problem_response(Problem::new(
+            409,
+            "versions_would_be_lost",
+            "the collection keeps page versions, which a reindex drops; set drop_versions to reindex anyway",
+        ));
+    }
+    match app_state.reindexer.start(collection, request) {
+        Some(progress) => HttpResponse::Accepted().json(progress),
+        None => problem_response(Problem::new(
+            409,
+            "reindex_running",
+            "a reindex is already running",
+        )),
+    }
+}
+
+/// Progress of the running or last reindex
+async fn reindex_progress(app_state: web::Data<AppState>) -> HttpResponse {
+    match app_state.reindexer.progress() {
+        Some(progress) => HttpResponse::Ok().json(progress),
+        None => problem_response(Problem::not_found(
+            "reindex_not_found",
+            "no reindex has run since the spider started",
+        )),
+    }
+}
+
+fn operation_running() -> Problem {
+    Problem::new(
+        409,
+        "operation_running",
+        "another backup or restore is still running",
+    )
+}
+
+fn profile_host(host: &str) -> Result<String, Problem> {
+    normalize_host(host).ok_or_else(|| {
+        Problem::bad_request("invalid_host", "host must be a host name or IP address")
+    })
+}
+
+/// The crawl profile of a host
+async fn get_domain_profile(
+    host: web::Path<String>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let host = match profile_host(&host) {
+        Ok(host) => host,
+        Err(problem) => return problem_response(problem),
+    };
+    match app_state.profiles.get(&host) {
+        Some(profile) => HttpResponse::Ok().json(profile.as_ref()),
+        None => problem_response(Problem::not_found(
+            "profile_not_found",
+            format!("no crawl profile for {host}"),
+        )),
+    }
+}
+
+/// Create or replace the crawl profile of a host; jobs pick it up with
+/// their next request to the host
+async fn put_domain_profile(
+    host: web::Path<String>,
+    profile: web::Json<DomainProfile>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let host = match profile_host(&host) {
+        Ok(host) => host,
+        Err(problem) => return problem_response(problem),
+    };
+    let profile = profile.into_inner();
+    if let Err(problem) = profile.validate() {
+        return problem_response(problem);
+    }
+    match app_state.profiles.put(&host, profile) {
+        Ok(profile) => HttpResponse::Ok().json(profile.as_ref()),
+        Err(error) => problem_response(Problem::internal("profile_not_saved", error.to_string())),
+    }
+}
+
+/// Hold back every fetch from a host, in all jobs, until it is resumed
+async fn pause_domain(host: web::Path<String>, app_state: web::Data<AppState>) -> HttpResponse {
+    let host = match profile_host(&host) {
+        Ok(host) => host,
+        Err(problem) => return problem_response(problem),
+    };
+    match app_state.profiles.pause(&host, unix_now()) {
+        Ok(paused_at) => HttpResponse::Ok().json(serde_json::json!({
+            "host": host,
+            "paused": true,
+            "paused_at": paused_at,
+        })),
+        Err(error) => problem_response(Problem::internal("profile_not_saved", error.to_string())),
+    }
+}
+
+/// Let jobs fetch from a paused host again
+async fn resume_domain(host: web::Path<String>, app_state: web::Data<AppState>) -> HttpResponse {
+    let host = match profile_host(&host) {
+        Ok(host) => host,
+        Err(problem) => return problem_response(problem),
+    };
+    match app_state.profiles.resume(&host) {
+        Ok(true) => {
+            let unparked = app_state.crawl_loop.lock().await.unpark().await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "host": host,
+                "paused": false,
+                "unparked_jobs": unparked,
+            }))
+        }
+        Ok(false) => problem_response(Problem::not_found(
+            "host_not_paused",
+            format!("{host} is not paused"),
+        )),
+        Err(error) => problem_response(Problem::internal("profile_not_saved", error.to_string())),
+    }
+}
+
+/// Queue one crawl job per URL of an uploaded or server-side seed list
+async fn crawl_seeds(
+    req: HttpRequest,
+    query: web::Query<SeedListQuery>,
+    body: web::Bytes,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let (text, template) = if req.content_type() == "application/json" {
+        let body = match serde_json::from_slice::<SeedListBody>(&body) {
+            Ok(body) => body,
+            Err(error) => {
+                return problem_response(Problem::bad_request("invalid_body", error.to_string()))
+            }
+        };
+        (body.urls.join("\n"), CrawlTemplate::from_json(body.crawl))
+    } else {
+        match String::from_utf8(body.to_vec()) {
+            Ok(text) => (text, CrawlTemplate::from_query(req.query_string())),
+            Err(_) => {
+                return problem_response(Problem::bad_request(
+                    "invalid_body",
+                    "seed list must be UTF-8 text",
+                ))
+            }
+        }
+    };
+    let template = match template {
+        Ok(template) => template,
+        Err(problem) => return problem_response(problem),
+    };
+    let text = if !text.is_empty() {
+        text
+    } else if let Some(path) = &query.path {
+        match seed_list::read_seed_file(path).await {
+            Ok(text) => text,
+            Err(problem) => return problem_response(problem),
+        }
+    } else {
+        return problem_response(Problem::bad_request(
+            "empty_seed_list",
+            "upload a seed list or pass a path",
+        ));
+    };
+    let seeds = match seed_list::parse_seed_list(&text) {
+        Ok(seeds) => seeds,
+        Err(problem) => return problem_response(problem),
+    };
+
+    let mut jobs = Vec::new();
+    let mut skipped = Vec::new();
+    // Locked per seed: checking a seed resolves its host, and a long list
+    // must not hold up other requests until all of it is queued
+    for seed in seeds {
+        let added = app_state
+            .crawl_loop
+            .lock()
+            .await
+            .add_crawl_request(template.crawl_request(seed.clone()))
+            .await;
+        match added {
+            Ok(job) => jobs.push(serde_json::json!({ "job_id": job.id, "url": seed })),
+            Err(problem) => skipped.push(serde_json::json!({ "url": seed, "code": problem.code })),
+        }
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "jobs": jobs,
+        "skipped": skipped,
+    }))
+}
+
+async fn crawl_job(path: web::Path<String>, app_state: web::Data<AppState>) -> impl Responder {
+    match app_state.jobs.get(&path).await {
+        Some(job) => HttpResponse::Ok().json(job.snapshot()),
+        None => job_not_found(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ReportQuery {
+    /// `json` (default) or `html`
+    #[serde(default)]
+    format: Option<String>,
+}
+
+/// Crawl report of a finished job, as JSON or a standalone HTML page
+async fn crawl_report(
+    path: web::Path<String>,
+    query: web::Query<ReportQuery>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let Some(job) = app_state.jobs.get(&path).await else {
+        return job_not_found();
+    };
+    let Some(report) = job.report() else {
+        return problem_response(Problem::new(
+            409,
+            "job_not_finished",
+            "the report is available once the job has finished",
+        ));
+    };
+    match query.format.as_deref() {
+        None | Some("json") => HttpResponse::Ok().json(report),
+        Some("html") => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(report.to_html()),
+        Some(_) => problem_response(Problem::bad_request(
+            "invalid_report_format",
+            "format must be json or html",
+        )),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DomainReportQuery {
+    domain: String,
+    /// Entries to list (default: 100)
+    #[serde(default = "default_report_limit")]
+    limit: usize,
+    /// `json` or `csv`; unset follows the `Accept` header
+    #[serde(default)]
+    format: Option<String>,
+}
+
+impl DomainReportQuery {
+    /// The normalized domain, and whether the report is wanted as CSV
+    fn validate(&self, req: &HttpRequest) -> Result<(String, bool), Problem> {
+        let domain = profile_host(&self.domain)?;
+        if self.limit == 0 || self.limit > MAX_REPORT_LIMIT {
+            return Err(Problem::bad_request(
+                "limit_out_of_range",
+                format!("limit must be between 1 and {MAX_REPORT_LIMIT}"),
+            ));
+        }
+        let csv = match self.format.as_deref() {
+            None => csv::accepts(
+                req.headers()
+                    .get(actix_web::http::header::ACCEPT)
+                    .and_then(|value| value.to_str().ok()),
+            ),
+            Some("json") => false,
+            Some("csv") => true,
+            Some(_) => {
+                return Err(Problem::bad_request(
+                    "invalid_report_format",
+                    "format must be json or csv",
+                ))
+            }
+        };
+        Ok((domain, csv))
+    }
+}
+
+fn csv_response(body: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(csv::CONTENT_TYPE)
+        .body(body)
+}
+
+fn default_report_limit() -> usize {
+    100
+}
+
+/// URLs that answered 404 or 410 or did not resolve, with the pages of the
+/// domain and its subdomains that link to them
+async fn broken_links(
+    req: HttpRequest,
+    query: web::Query<DomainReportQuery>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let (domain, csv) = match query.validate(&req) {
+        Ok(validated) => validated,
+        Err(problem) => return problem_response(problem),
+    };
+    match app_state.state.broken_links(&domain, query.limit) {
+        Ok(links) if csv => csv_response(BrokenLink::csv(&links)),
+        Ok(links) => HttpResponse::Ok().json(serde_json::json!({
+            "domain": domain,
+            "broken_links": links,
+        })),
+        Err(error) => problem_response(Problem::internal("report_unavailable", error.to_string())),
+    }
+}
+
+/// SEO findings of the audited pages of the domain and its subdomains
+async fn seo_report(
+    req: HttpRequest,
+    query: web::Query<DomainReportQuery>,
+    app_state: web::Data<AppState>,
+) -> HttpResponse {
+    let (domain, csv) = match query.validate(&req) {
+        Ok(validated) => validated,
+        Err(problem) => return problem_response(problem),
+    };
+    match app_state.state.seo_pages(&domain) {
+        Ok(pages) => {
+            let report = SeoReport::build(&domain, pages, query.limit);
+            if csv {
+                csv_response(report.to_csv())
+            } else {
+                HttpResponse::Ok().json(report)
+            }
+        }
+        Err(error) => problem_response(Problem::internal("report_unavailable", error.to_string())),
+    }
+}
+
+/// New, removed and changed pages since the previous crawl of the seed's host
+async fn crawl_diff(path: web::Path<String>, app_state: web::Data<AppState>) -> HttpResponse {
+    match CrawlDiff::load(&app_state.state, &path) {
+        Ok(Some(diff)) => HttpResponse::Ok().json(diff),
+        Ok(None) => match app_state.jobs.get(&path).await {
+            Some(job) if job.request.mode == CrawlMode::Linkcheck => {
+                problem_response(Problem::new(
+                    409,
+                    "no_diff_for_linkcheck",
+                    "link checks do not record page versions",
+                ))
+            }
+            Some(_) => problem_response(Problem::new(
+                409,
+                "job_not_finished",
+                "the diff is available once the job has finished",
+            )),
+            None => job_not_found(),
+        },
+        Err(error) => problem_response(Problem::internal("history_unavailable", error.to_string())),
+    }
+}
+
+/// Server-sent events stream of a job's progress, closed once the job finishes
+async fn crawl_events(path: web::Path<String>, app_state: web::Data<AppState>) -> HttpResponse {
+    let Some(job) = app_state.jobs.get(&path).await else {
+        return job_not_found();
+    };
+    let receiver = job.subscribe();
+    let initial = if job.status().is_finished() {
+        CrawlEvent::Finished {
+            status: job.status(),
+            progress: job.progress(),
+        }
+    } else {
+        CrawlEvent::Progress(job.progress())
+    };
+    let events = stream::unfold(
+        (Some(initial), Some(receiver), job),
+        |(pending, receiver, job)| async move {
+            let mut receiver = receiver?;
+            let event = match pending {
+                Some(event) => event,
+                None => next_event(&mut receiver, &job).await,
+            };
+            let done = matches!(event, CrawlEvent::Finished { .. });
+            let frame = sse_frame(&event);
+            Some((
+                Ok::<_, actix_web::Error>(frame),
+                (None, (!done).then_some(receiver), job),
+            ))
+        },
+    );
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events)
+}
+
+async fn next_event(
+    receiver: &mut tokio::sync::broadcast::Receiver<CrawlEvent>,
+    job: &CrawlJob,
+) -> CrawlEvent {
+    use tokio::sync::broadcast::error::RecvError;
+    match receiver.recv().await {
+        Ok(event) => event,
+        // Slow consumers skip ahead to the current totals.
+        Err(RecvError::Lagged(_)) => CrawlEvent::Progress(job.progress()),
+        Err(RecvError::Closed) => CrawlEvent::Finished {
+            status: job.status(),
+            progress: job.progress(),
+        },
+    }
+}
+
+fn sse_frame(event: &CrawlEvent) -> web::Bytes {
+    let data = serde_json::to_string(event).unwrap_or_default();
+    web::Bytes::from(format!("event: {}\ndata: {data}\n\n", event.name()))
+}
+
+fn job_not_found() -> HttpResponse {
+    problem_response(Problem::not_found("job_not_found", "unknown crawl job"))
+}
+
+/// Tag the request, its log lines and the calls it makes with its
+/// `X-Request-Id`, and return the ID in the response
+async fn tag_request(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let id = request_id::from_header(
+        req.headers()
+            .get(request_id::HEADER)
+            .and_then(|value| value.to_str().ok()),
+    );
+    let span = tracing::info_span!("request", request_id = %id);
+    let (method, path) = (req.method().clone(), req.path().to_string());
+    let mut response = request_id::scope(id.clone(), next.call(req))
+        .instrument(span.clone())
+        .await?;
+    if response.status().is_server_error() {
+        span.in_scope(|| tracing::warn!("{method} {path} failed with {}", response.status()));
+    }
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static("x-request-id"), value);
+    }
+    Ok(response)
+}
+
+/// `X-Request-Id` of the request or crawl job being handled, to forward
+/// on calls made for it
+pub fn forwarded_request_id() -> reqwest::header::HeaderMap {
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(value) =
+        request_id::current().and_then(|id| reqwest::header::HeaderValue::from_str(&id).ok())
+    {
+        headers.insert(request_id::HEADER, value);
+    }
+    headers
+}
+
+fn problem_response(problem: Problem) -> HttpResponse {
+    let status = actix_web::http::StatusCode::from_u16(problem.status)
+        .unwrap_or(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR);
+    HttpResponse::build(status)
+        .content_type(PROBLEM_CONTENT_TYPE)
+        .json(problem)
+}
+
+fn query_error(error: actix_web::error::QueryPayloadError, _: &HttpRequest) -> actix_web::Error {
+    let response = problem_response(Problem::bad_request(
+        "invalid_query_parameters",
+        error.to_string(),
+    ));
+    actix_web::error::InternalError::from_response(error, response).into()
+}
+
+fn json_error(error: actix_web::error::JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    let response = problem_response(Problem::bad_request("invalid_body", error.to_string()));
+    actix_web::error::InternalError::from_response(error, response).into()
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     tracing_subscriber::fmt::init();
 
     load_env();
 
-    let host = env::var("SPIDER_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-    let port = env::var("SPIDER_PORT").unwrap_or_else(|_| "8001".to_string());
-    let bind_address = format!("{}:{}", host, port);
+    let listen = Listen::from_env("SPIDER", 8001)?;
     let product_token =
         env::var("CRAWLER_PRODUCT_TOKEN").expect("CRAWLER_PRODUCT_TOKEN must be configured");
     let user_agent = env::var("CRAWLER_USER_AGENT").expect("CRAWLER_USER_AGENT must be configured");
@@ -130,48 +1176,125 @@ async fn main() -> std::io::Result<()> {
 
     let indexer = Arc::new(PageIndexer::from_env().expect("failed to create Qdrant client"));
     indexer
-        .ensure_collection()
+        .ensure_collection(QDRANT_COLLECTION_NAME)
         .await
         .expect("Qdrant collection creation failed");
 
-    println!("🚀 Starting Crawler server on http://{}", bind_address);
+    println!("🚀 Starting Crawler server on {listen}");
     println!("📝 Routes:");
     println!("   GET  /health         - Health check");
+    println!("   GET  /health/ready   - Qdrant and TEI readiness");
     println!("   GET  /status         - Crawler status and metrics");
+    println!("   GET  /queue          - Pending jobs and upcoming frontier URLs");
     println!("   POST /crawl          - Crawl a URL");
+    println!("   POST /crawl/seeds    - Crawl every URL of a seed list");
+    println!("   GET  /crawls         - History of finished crawl jobs");
+    println!("   GET  /crawl/{{id}}     - Crawl job status");
+    println!("   GET  /crawl/{{id}}/events - Crawl progress (server-sent events)");
+    println!("   GET  /crawl/{{id}}/report - Report of a finished crawl (JSON or HTML)");
+    println!("   GET  /crawl/{{id}}/diff - Pages changed since the previous crawl of the host");
+    println!("   POST /discover       - Crawl the web search results for a query");
+    println!("   GET  /domains/{{host}}/profile - Crawl profile of a host");
+    println!("   PUT  /domains/{{host}}/profile - Set the crawl profile of a host");
+    println!("   POST /domains/{{host}}/pause - Stop fetching from a host");
+    println!("   POST /domains/{{host}}/resume - Fetch from a paused host again");
+    println!("   POST /admin/stop     - Stop taking crawl jobs");
+    println!("   POST /admin/start    - Start taking crawl jobs again");
+    println!("   POST /admin/robots/flush - Drop cached robots.txt files");
+    println!("   GET  /admin/caches   - Cache sizes and hit rates");
+    println!("   POST /admin/caches/flush - Empty caches");
+    println!("   POST /admin/backup   - Snapshot the index");
+    println!("   POST /admin/restore  - Restore the index from a snapshot");
+    println!("   GET  /admin/backups  - Snapshots and backup progress");
+    println!("   POST /admin/reindex  - Rebuild the index with current settings");
     println!();
-    let stats = Arc::new(CrawlStats::new());
+    let search_provider = match SearchProvider::from_env(&user_agent) {
+        Ok(provider) => {
+            println!("🔎 Discovery search provider: {}", provider.name());
+            Some(Arc::new(provider))
+        }
+        Err(error) => {
+            tracing::warn!("discovery disabled: {error:#}");
+            None
+        }
+    };
+    let archive = HtmlArchive::from_env()
+        .expect("invalid HTML archive configuration")
+        .map(Arc::new);
+    let state = Arc::new(StateStore::from_env().expect("failed to open crawl state database"));
+    state
+        .clear_jobs()
+        .expect("failed to reset crawl state database");
+    let stats = Arc::new(match state.saved_stats() {
+        Ok(Some(snapshot)) => CrawlStats::restore(&snapshot),
+        Ok(None) => CrawlStats::new(),
+        Err(error) => {
+            tracing::warn!("failed to load saved crawl stats: {error}");
+            CrawlStats::new()
+        }
+    });
+    let jobs = Arc::new(CrawlJobs::new());
 
-    let mut crawl_loop = CrawlLoop::new(stats.clone(), indexer, product_token, user_agent);
-    crawl_loop.run();
+    let reindexer = Arc::new(Reindexer::new(indexer.clone(), archive.clone()));
+    let mut crawl_loop = CrawlLoop::new(
+        stats.clone(),
+        jobs.clone(),
+        indexer.clone(),
+        archive,
+        state.clone(),
+        product_token,
+        user_agent,
+    );
+    let resumed = crawl_loop
+        .start()
+        .await
+        .map_err(|problem| std::io::Error::other(problem.detail))?;
+    if resumed > 0 {
+        println!("⏯️  Resuming {resumed} crawl jobs interrupted by the last shutdown");
+    }
+    let profiles = crawl_loop.domain_profiles();
     let crawl_loop = Arc::new(Mutex::new(crawl_loop));
 
     let app_state = web::Data::new(AppState {
         crawl_loop: crawl_loop.clone(),
         stats: stats.clone(),
+        jobs,
+        indexer,
+        search_provider,
+        state,
+        profiles,
+        backups: Arc::new(Operations::default()),
+        reindexer,
     });
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         let cors = if allowed_origins.trim() == "*" {
             // Allow any origin
             Cors::default()
                 .allow_any_origin()
                 .allow_any_method()
                 .allow_any_header()
-                .expose_headers(vec![actix_web::http::header::CONTENT_TYPE])
+                .expose_headers(vec![
+                    actix_web::http::header::CONTENT_TYPE,
+                    HeaderName::from_static("x-request-id"),
+                ])
                 .max_age(3600)
         } else {
             // Parse allowed origins from comma-separated list
             let origins: Vec<&str> = allowed_origins.split(',').map(|s| s.trim()).collect();
 
             let mut cors = Cors::default()
-                .allowed_methods(vec!["GET", "POST", "OPTIONS"])
+                .allowed_methods(vec!["GET", "POST", "PUT", "OPTIONS"])
                 .allowed_headers(vec![
                     actix_web::http::header::CONTENT_TYPE,
                     actix_web::http::header::ACCEPT,
                     actix_web::http::header::AUTHORIZATION,
+                    HeaderName::from_static("x-request-id"),
+                ])
+                .expose_headers(vec![
+                    actix_web::http::header::CONTENT_TYPE,
+                    HeaderName::from_static("x-request-id"),
                 ])
-                .expose_headers(vec![actix_web::http::header::CONTENT_TYPE])
                 .max_age(3600);
 
             // Add each origin
@@ -184,12 +1307,55 @@ async fn main() -> std::io::Result<()> {
 
         App::new()
             .wrap(cors)
+            .wrap(from_fn(tag_request))
             .app_data(app_state.clone())
+            .app_data(web::JsonConfig::default().error_handler(json_error))
+            .app_data(web::QueryConfig::default().error_handler(query_error))
+            .app_data(web::PayloadConfig::new(seed_list::MAX_UPLOAD_BYTES))
             .route("/health", web::get().to(health_check))
+            .route("/health/ready", web::get().to(readiness))
             .route("/status", web::get().to(status))
+            .route("/queue", web::get().to(queue))
             .route("/crawl", web::post().to(crawl))
-    })
-    .bind(&bind_address)?
+            .route("/crawl/seeds", web::post().to(crawl_seeds))
+            .route("/crawls", web::get().to(crawl_history))
+            .route("/crawl/{id}", web::get().to(crawl_job))
+            .route("/crawl/{id}/events", web::get().to(crawl_events))
+            .route("/crawl/{id}/report", web::get().to(crawl_report))
+            .route("/crawl/{id}/diff", web::get().to(crawl_diff))
+            .route("/reports/broken-links", web::get().to(broken_links))
+            .route("/reports/seo", web::get().to(seo_report))
+            .route("/discover", web::post().to(discover))
+            .route("/domains/{host}/profile", web::get().to(get_domain_profile))
+            .route("/domains/{host}/profile", web::put().to(put_domain_profile))
+            .route("/domains/{host}/pause", web::post().to(pause_domain))
+            .route("/domains/{host}/resume", web::post().to(resume_domain))
+            .route("/admin/stop", web::post().to(stop_crawler))
+            .route("/admin/start", web::post().to(start_crawler))
+            .route("/admin/robots/flush", web::post().to(flush_robots))
+            .route("/admin/caches", web::get().to(cache_stats))
+            .route("/admin/caches/flush", web::post().to(flush_caches))
+            .route("/admin/backup", web::post().to(start_backup))
+            .route("/admin/restore", web::post().to(start_restore))
+            .route("/admin/backups", web::get().to(list_backups))
+            .route("/admin/backups/{id}", web::get().to(backup_operation))
+            .route("/admin/reindex", web::post().to(start_reindex))
+            .route("/admin/reindex", web::get().to(reindex_progress))
+    });
+    let served = match listen {
+        Listen::Http(address) => server.bind(address)?,
+        Listen::Https(address, tls) => server.bind_rustls_0_23(address, *tls)?,
+        #[cfg(unix)]
+        Listen::Unix(path) => server.bind_uds(path)?,
+    }
     .run()
-    .await
+    .await;
+    // The server has stopped on SIGTERM or SIGINT; drain the crawl jobs
+    println!("⏸️  Draining crawl jobs");
+    crawl_loop
+        .lock()
+        .await
+        .shutdown(crawl_loop::shutdown_grace_from_env())
+        .await;
+    served
 }
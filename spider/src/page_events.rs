@@ -0,0 +1,134 @@
+//! Stream of indexed pages for downstream pipelines
+//!
+//! With `SPIDER_NATS_URL` set, every page written to the index is published
+//! as a JSON event on the NATS subject `SPIDER_EVENTS_SUBJECT` (default
+//! `spider.pages.indexed`), so alerting, analytics or secondary indexes can
+//! follow crawl output as it happens. Pages whose content did not change are
+//! published too, with `outcome` `unchanged`. Events are sent at most once:
+//! while NATS cannot be reached they are dropped with a warning, and crawling
+//! goes on. Kafka consumers can read the subject through a NATS-Kafka bridge.
+
+use async_nats::Client;
+use serde::Serialize;
+use std::{env, sync::Arc, time::Duration};
+use tokio::sync::OnceCell;
+
+use crate::report::IndexOutcome;
+
+pub const DEFAULT_SUBJECT: &str = "spider.pages.indexed";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PageIndexed {
+    pub url: String,
+    pub title: String,
+    pub chunk_count: usize,
+    /// Version of the page's content, equal across crawls while it is unchanged
+    pub content_hash: String,
+    pub outcome: &'static str,
+    pub job_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+    pub crawled_at: i64,
+}
+
+pub fn outcome_name(outcome: IndexOutcome) -> &'static str {
+    match outcome {
+        IndexOutcome::New => "new",
+        IndexOutcome::Updated => "updated",
+        IndexOutcome::Unchanged => "unchanged",
+        IndexOutcome::Empty => "empty",
+    }
+}
+
+pub struct PageEventPublisher {
+    url: Option<String>,
+    subject: String,
+    /// Connected on the first event
+    client: OnceCell<Client>,
+}
+
+impl PageEventPublisher {
+    pub fn new(url: Option<String>, subject: String) -> Self {
+        Self {
+            url,
+            subject,
+            client: OnceCell::new(),
+        }
+    }
+
+    /// Reads `SPIDER_NATS_URL` and `SPIDER_EVENTS_SUBJECT`; events are
+    /// disabled when the URL is unset
+    pub fn from_env() -> Self {
+        let url = env::var("SPIDER_NATS_URL")
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty());
+        let subject = env::var("SPIDER_EVENTS_SUBJECT")
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| DEFAULT_SUBJECT.to_string());
+        Self::new(url, subject)
+    }
+
+    /// Publish `event` in the background
+    pub fn page_indexed(self: &Arc<Self>, event: PageIndexed) {
+        if self.url.is_none() {
+            return;
+        }
+        let publisher = self.clone();
+        tokio::spawn(async move {
+            if let Err(error) = publisher.publish(&event).await {
+                tracing::warn!("failed to publish indexed page {}: {error:#}", event.url);
+            }
+        });
+    }
+
+    async fn publish(&self, event: &PageIndexed) -> anyhow::Result<()> {
+        let client = self
+            .client
+            .get_or_try_init(|| async {
+                let url = self.url.as_deref().unwrap_or_default();
+                async_nats::ConnectOptions::new()
+                    .connection_timeout(CONNECT_TIMEOUT)
+                    .connect(url)
+                    .await
+            })
+            .await?;
+        let payload = serde_json::to_vec(event)?;
+        client.publish(self.subject.clone(), payload.into()).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_indexed_page_events() {
+        let event = PageIndexed {
+            url: "https://example.com/docs".to_string(),
+            title: "Docs".to_string(),
+            chunk_count: 3,
+            content_hash: "abc123".to_string(),
+            outcome: outcome_name(IndexOutcome::Updated),
+            job_id: "job-1".to_string(),
+            collection: None,
+            crawled_at: 1_792_152_000,
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "url": "https://example.com/docs",
+                "title": "Docs",
+                "chunk_count": 3,
+                "content_hash": "abc123",
+                "outcome": "updated",
+                "job_id": "job-1",
+                "crawled_at": 1_792_152_000,
+            })
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Pagination detection
+//!
+//! Archives, search-style listings and multi-page articles link to their
+//! other pages with `rel="next"`/`rel="prev"`, a "next" or "load more" link,
+//! or a URL that differs only in a page number (`?page=3`, `/page/3/`). Those
+//! links are followed at the depth of the page they continue and count
+//! against the job's pagination budget instead of `max_pages`.
+
+use scraper::{Html, Selector};
+use std::env;
+use url::Url;
+
+use crate::web_visitor::{resolve_link, same_origin};
+
+pub const DEFAULT_MAX_PAGINATION_PAGES: usize = 20;
+
+/// Query parameters holding a page number or offset; `normalize_url` keeps
+/// these when their value is numeric and drops every other parameter
+pub const PAGE_PARAMS: &[&str] = &[
+    "page", "paged", "pg", "pagenum", "page_num", "start", "offset",
+];
+
+/// Link texts of next-page and "load more" links, lowercase
+const NEXT_TEXTS: &[&str] = &[
+    "next",
+    "next page",
+    "next »",
+    "load more",
+    "show more",
+    "more results",
+    "older posts",
+    "older entries",
+    "weiter",
+    "nächste",
+    "nächste seite",
+    "mehr laden",
+    "»",
+    "›",
+];
+
+/// `PAGINATION_MAX_PAGES`, the default per-job pagination budget
+pub fn max_pages_from_env() -> usize {
+    env::var("PAGINATION_MAX_PAGES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_MAX_PAGINATION_PAGES)
+}
+
+pub fn is_page_param(key: &str, value: &str) -> bool {
+    PAGE_PARAMS.contains(&key.to_ascii_lowercase().as_str())
+        && !value.is_empty()
+        && value.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+/// Other pages of the listing or article at `url`, same origin only
+pub fn pagination_links(document: &Html, url: &Url) -> Vec<Url> {
+    let rel = Selector::parse(
+        "link[rel~=next][href], link[rel~=prev][href], a[rel~=next][href], a[rel~=prev][href]",
+    )
+    .unwrap();
+    let anchors = Selector::parse("a[href]").unwrap();
+    let mut links = document
+        .select(&rel)
+        .filter_map(|element| element.value().attr("href"))
+        .filter_map(|href| resolve_link(url, href))
+        .collect::<Vec<_>>();
+    for anchor in document.select(&anchors) {
+        let text = anchor
+            .text()
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+        if let Some(link) = anchor
+            .value()
+            .attr("href")
+            .and_then(|href| resolve_link(url, href))
+        {
+            if NEXT_TEXTS.contains(&text.as_str()) || is_page_of(&link, url) {
+                links.push(link);
+            }
+        }
+    }
+    let mut seen = std::collections::HashSet::new();
+    links.retain(|link| link != url && same_origin(link, url) && seen.insert(link.to_string()));
+    links
+}
+
+/// Whether `link` is a numbered page of the same listing as `page`
+fn is_page_of(link: &Url, page: &Url) -> bool {
+    page_number(link).is_some() && listing_path(link) == listing_path(page)
+}
+
+fn page_number(url: &Url) -> Option<u64> {
+    url.query_pairs()
+        .find(|(key, value)| is_page_param(key, value))
+        .and_then(|(_, value)| value.parse().ok())
+        .or_else(|| path_page(url).map(|(_, number)| number))
+}
+
+/// `/blog/page/3/` → `("/blog", 3)`
+fn path_page(url: &Url) -> Option<(&str, u64)> {
+    let path = url.path().trim_end_matches('/');
+    let (rest, number) = path.rsplit_once('/')?;
+    let number = number.parse().ok()?;
+    let prefix = rest
+        .strip_suffix("/page")
+        .or((rest == "/page").then_some(""))?;
+    Some((prefix, number))
+}
+
+fn listing_path(url: &Url) -> &str {
+    path_page(url)
+        .map(|(prefix, _)| prefix)
+        .unwrap_or(url.path())
+        .trim_end_matches('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links(page: &str, html: &str) -> Vec<String> {
+        pagination_links(&Html::parse_document(html), &Url::parse(page).unwrap())
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn finds_rel_next_and_next_links() {
+        let html = r#"<head><link rel="next" href="/article/2"></head>
+            <body><a href="/about">About</a>
+            <a href="/older">Older posts</a>
+            <a rel="prev" href="https://other.example/1">Previous</a></body>"#;
+        assert_eq!(
+            links("https://example.com/article", html),
+            ["https://example.com/article/2", "https://example.com/older"]
+        );
+    }
+
+    #[test]
+    fn finds_numbered_pages_of_the_same_listing() {
+        let html = r#"<a href="?page=2&amp;sort=new">2</a> <a href="/blog/page/3/">3</a>
+            <a href="/shop?page=2">Shop</a> <a href="/blog?id=7">Post</a>"#;
+        assert_eq!(
+            links("https://example.com/blog", html),
+            [
+                "https://example.com/blog?page=2",
+                "https://example.com/blog/page/3/"
+            ]
+        );
+        assert!(is_page_of(
+            &Url::parse("https://example.com/page/2").unwrap(),
+            &Url::parse("https://example.com/").unwrap()
+        ));
+    }
+}
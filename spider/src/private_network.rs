@@ -0,0 +1,185 @@
+//! Private address space protection
+//!
+//! Unless `ALLOW_PRIVATE_NETWORKS` is enabled, the crawler refuses to fetch
+//! from loopback, RFC 1918, link-local (which includes the cloud metadata
+//! endpoint 169.254.169.254), CGNAT and IPv6 unique-local addresses. Host
+//! names are checked as the HTTP client resolves them, so a name that turns
+//! private after the job was accepted (DNS rebinding) is refused as well.
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::{
+    env,
+    error::Error,
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+use url::{Host, Url};
+
+/// `ALLOW_PRIVATE_NETWORKS`, off by default
+pub fn allowed_from_env() -> bool {
+    env::var("ALLOW_PRIVATE_NETWORKS")
+        .is_ok_and(|value| matches!(value.trim(), "1" | "true" | "yes"))
+}
+
+/// A host that only resolves to addresses the crawler may not reach
+#[derive(Debug)]
+pub struct PrivateAddress(pub String);
+
+impl fmt::Display for PrivateAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} resolves to a private address", self.0)
+    }
+}
+
+impl Error for PrivateAddress {}
+
+pub fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_private_v4(ip),
+        IpAddr::V6(ip) => is_private_v6(ip),
+    }
+}
+
+fn is_private_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        // "this network", CGNAT (100.64.0.0/10) and the reserved 240.0.0.0/4
+        || a == 0
+        || (a == 100 && (b & 0xc0) == 64)
+        || a >= 240
+}
+
+fn is_private_v6(ip: Ipv6Addr) -> bool {
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_private_v4(v4);
+    }
+    let segments = ip.segments();
+    // NAT64 (64:ff9b::/96) reaches whatever IPv4 address it embeds
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [.., a, b, c, d] = ip.octets();
+        return is_private_v4(Ipv4Addr::new(a, b, c, d));
+    }
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || (segments[0] & 0xfe00) == 0xfc00
+        || (segments[0] & 0xffc0) == 0xfe80
+}
+
+/// The literal address of `url`, if its host is one and it is private
+pub fn private_literal(url: &Url) -> Option<IpAddr> {
+    let ip = match url.host()? {
+        Host::Ipv4(ip) => IpAddr::V4(ip),
+        Host::Ipv6(ip) => IpAddr::V6(ip),
+        Host::Domain(_) => return None,
+    };
+    is_private(ip).then_some(ip)
+}
+
+/// Refuse `url` if its host is a private address or resolves only to
+/// private addresses. A host that does not resolve at all passes; fetching
+/// it will fail on its own.
+pub async fn check(url: &Url) -> Result<(), PrivateAddress> {
+    let host = url.host_str().unwrap_or_default();
+    if private_literal(url).is_some() {
+        return Err(PrivateAddress(host.to_string()));
+    }
+    if !matches!(url.host(), Some(Host::Domain(_))) {
+        return Ok(());
+    }
+    let port = url.port_or_known_default().unwrap_or(80);
+    let Ok(addrs) = tokio::net::lookup_host((host, port)).await else {
+        return Ok(());
+    };
+    let addrs = addrs.collect::<Vec<_>>();
+    if !addrs.is_empty() && addrs.iter().all(|addr| is_private(addr.ip())) {
+        return Err(PrivateAddress(host.to_string()));
+    }
+    Ok(())
+}
+
+/// The private host a request failed on, if the resolver refused it
+pub fn blocked_host(error: &(dyn Error + 'static)) -> Option<String> {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        if let Some(blocked) = error.downcast_ref::<PrivateAddress>() {
+            return Some(blocked.0.clone());
+        }
+        source = error.source();
+    }
+    None
+}
+
+/// System DNS resolution with private addresses removed
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .collect::<Vec<SocketAddr>>();
+            let public = addrs
+                .iter()
+                .copied()
+                .filter(|addr| !is_private(addr.ip()))
+                .collect::<Vec<_>>();
+            if public.is_empty() && !addrs.is_empty() {
+                return Err(Box::new(PrivateAddress(host)) as Box<dyn Error + Send + Sync>);
+            }
+            Ok(Box::new(public.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_private_addresses() {
+        for ip in [
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "127.0.0.1",
+            "169.254.169.254",
+            "100.100.100.200",
+            "0.0.0.0",
+            "::1",
+            "fd00:ec2::254",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(is_private(ip.parse().unwrap()), "{ip} should be private");
+        }
+        for ip in [
+            "93.184.216.34",
+            "172.32.0.1",
+            "100.128.0.1",
+            "2606:4700::1111",
+        ] {
+            assert!(!is_private(ip.parse().unwrap()), "{ip} should be public");
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_private_hosts() {
+        let url = |value: &str| Url::parse(value).unwrap();
+        assert!(check(&url("http://127.0.0.1:8080/")).await.is_err());
+        assert!(check(&url("http://[::1]/")).await.is_err());
+        assert!(check(&url("http://localhost/")).await.is_err());
+        assert!(check(&url("http://93.184.216.34/")).await.is_ok());
+        assert_eq!(
+            private_literal(&url("http://169.254.169.254/latest/meta-data/")),
+            Some("169.254.169.254".parse().unwrap())
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! Publish dates of crawled pages
+//!
+//! A page's `published_at` comes from the first of these that holds a date:
+//! `article:published_time` and the other common date meta tags, an element
+//! marked `itemprop="datePublished"`, then the first `<time datetime>`,
+//! preferring one inside `<article>`. Dates without a time zone are read as
+//! UTC. Dates after the crawl (beyond a day of clock skew) or before the web
+//! existed are ignored, as they are template placeholders more often than not.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use scraper::{Html, Selector};
+
+/// Meta tags holding the publish date, by `property`, `name` or `itemprop`,
+/// most specific first; compared lowercase
+const DATE_META: &[&str] = &[
+    "article:published_time",
+    "og:published_time",
+    "datepublished",
+    "publishdate",
+    "publish-date",
+    "pubdate",
+    "parsely-pub-date",
+    "sailthru.date",
+    "dc.date.issued",
+    "dcterms.issued",
+    "dc.date",
+    "dcterms.date",
+    "date",
+];
+
+/// 1991-01-01, before which no page was published
+const EARLIEST: i64 = 662_688_000;
+const CLOCK_SKEW_SECS: i64 = 86_400;
+
+/// When the page says it was published, as Unix seconds
+pub fn extract_published_at(document: &Html, crawled_at: i64) -> Option<i64> {
+    let plausible = |date: i64| (EARLIEST..=crawled_at + CLOCK_SKEW_SECS).contains(&date);
+    let meta = Selector::parse("meta[content]").unwrap();
+    let metas = document
+        .select(&meta)
+        .filter_map(|element| {
+            let value = element.value();
+            let key = value
+                .attr("property")
+                .or_else(|| value.attr("name"))
+                .or_else(|| value.attr("itemprop"))?
+                .to_ascii_lowercase();
+            Some((key, value.attr("content")?))
+        })
+        .collect::<Vec<_>>();
+    let from_meta = DATE_META.iter().find_map(|wanted| {
+        metas
+            .iter()
+            .filter(|(key, _)| key == wanted)
+            .find_map(|(_, content)| parse_date(content).filter(|date| plausible(*date)))
+    });
+    if from_meta.is_some() {
+        return from_meta;
+    }
+
+    let selectors = [
+        "[itemprop=datePublished][datetime]",
+        "article time[datetime]",
+        "time[datetime]",
+    ];
+    selectors.iter().find_map(|selector| {
+        let selector = Selector::parse(selector).unwrap();
+        document
+            .select(&selector)
+            .filter_map(|element| element.value().attr("datetime"))
+            .find_map(|value| parse_date(value).filter(|date| plausible(*date)))
+    })
+}
+
+/// RFC 3339 and its common relaxations, RFC 2822, or a bare date
+fn parse_date(value: &str) -> Option<i64> {
+    let value = value.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(value)
+        .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f%z"))
+        .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f%z"))
+        .or_else(|_| DateTime::parse_from_rfc2822(value))
+    {
+        return Some(date.timestamp());
+    }
+    for format in [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+    ] {
+        if let Ok(date) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(date.and_utc().timestamp());
+        }
+    }
+    let date = NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d").ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc().timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefers_meta_tags_then_time_elements() {
+        let crawled_at = 1_767_225_600; // 2026-01-01
+        let published_at =
+            |html: &str| extract_published_at(&Html::parse_document(html), crawled_at);
+        assert_eq!(
+            published_at(
+                r#"<meta name="date" content="2020-01-01">
+                <meta property="article:published_time" content="2024-05-01T12:00:00+02:00">"#
+            ),
+            Some(1_714_557_600)
+        );
+        assert_eq!(
+            published_at(
+                r#"<time datetime="2025-12-31">Today</time>
+                <article><time datetime="2024-05-01 10:00">May 1</time></article>"#
+            ),
+            Some(1_714_557_600)
+        );
+        assert_eq!(
+            published_at(r#"<meta name="pubdate" content="Wed, 01 May 2024 10:00:00 GMT">"#),
+            Some(1_714_557_600)
+        );
+        // Placeholders and unparseable dates
+        assert_eq!(
+            published_at(
+                r#"<meta name="date" content="2099-01-01"><time datetime="soon">x</time>"#
+            ),
+            None
+        );
+    }
+}
@@ -1,28 +1,68 @@
 use anyhow::{Context, Result};
 use qdrant_client::{
     qdrant::{
-        Condition, CreateCollectionBuilder, CreateFieldIndexCollectionBuilder, DeletePointsBuilder,
-        Distance, DocumentBuilder, FieldType, Filter, Modifier, NamedVectors, PointStruct,
-        SparseIndexConfigBuilder, SparseVectorParamsBuilder, SparseVectorsConfigBuilder,
-        UpsertPointsBuilder, VectorParamsBuilder, VectorsConfigBuilder,
+        alias_operations::Action, collections_client::CollectionsClient, vector_output,
+        vectors_output, AliasOperations, ChangeAliases, Condition, CountPointsBuilder, CreateAlias,
+        CreateAliasBuilder, CreateCollectionBuilder, CreateFieldIndexCollectionBuilder,
+        DeleteAlias, DeletePointsBuilder, Distance, DocumentBuilder, FieldType, Filter, Modifier,
+        NamedVectors, PayloadIncludeSelector, PointStruct, Range, ScrollPointsBuilder,
+        SetPayloadPointsBuilder, SnapshotDescription, SparseIndexConfigBuilder,
+        SparseVectorParamsBuilder, SparseVectorsConfigBuilder, UpsertPointsBuilder, Vector,
+        VectorParamsBuilder, VectorsConfigBuilder, VectorsOutput,
     },
     Payload, Qdrant,
 };
 use reqwest::Client;
 use serde::Serialize;
 use sha2::{Digest, Sha256};
-use shared_crawler_api::{WebPageChunk, QDRANT_COLLECTION_NAME};
-use std::{collections::HashMap, env};
+use shared_crawler_api::{
+    text::{self, LexicalOptions},
+    WebPageChunk,
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    env,
+    sync::{Arc, Mutex},
+};
+use url::Url;
 use uuid::Uuid;
 
+use crate::{
+    backup::Snapshot,
+    qdrant_retry::{QdrantRetry, RetryStats},
+    report::IndexOutcome,
+    tokens::{split_to_token_limit, Token, TokenizeRequest},
+};
+
 const BM25_MODEL: &str = "qdrant/bm25";
+/// Sparse vector of a page's headings, missing in collections created before
+/// it was added until they are reindexed
+const HEADINGS_VECTOR: &str = "headings_bm25";
 // ponytail: one chunk per TEI request avoids max-batch-token 422s; raise after TEI limits are tuned.
 const EMBED_BATCH_SIZE: usize = 1;
+/// URLs per `last_crawled` scroll
+const LOOKUP_BATCH_SIZE: usize = 256;
+/// Opening text of a page compared with a crawl's focus query
+const FOCUS_PASSAGE_CHARS: usize = 2_000;
 
 pub struct PageIndexer {
     qdrant: Qdrant,
+    retry: Arc<QdrantRetry>,
     http: Client,
     tei_url: String,
+    /// Size of the dense vectors the model behind `tei_url` returns
+    dimensions: usize,
+    dedup_chunks: bool,
+    keep_versions: bool,
+    lexical: LexicalOptions,
+    /// Collections known to exist with their payload indexes
+    ensured: Mutex<HashSet<String>>,
+    /// Ensured collections without the headings vector
+    without_headings: Mutex<HashSet<String>>,
+    /// Qdrant's REST API, for what gRPC does not offer
+    rest_url: String,
+    /// Qdrant's snapshots directory, as seen by Qdrant
+    snapshots_path: String,
 }
 
 impl PageIndexer {
@@ -31,121 +71,822 @@ impl PageIndexer {
             env::var("QDRANT_URL").unwrap_or_else(|_| "http://localhost:6334".to_string());
         Ok(Self {
             qdrant: Qdrant::from_url(&qdrant_url).build()?,
+            retry: Arc::new(QdrantRetry::from_env()),
             http: Client::new(),
             tei_url: env::var("TEI_URL").unwrap_or_else(|_| "http://localhost:8080".to_string()),
+            dimensions: shared_crawler_api::embedding_dimensions(),
+            dedup_chunks: env::var("CHUNK_DEDUP").map_or(true, |value| value != "false"),
+            keep_versions: env::var("KEEP_PAGE_VERSIONS")
+                .is_ok_and(|value| matches!(value.trim(), "1" | "true" | "yes")),
+            lexical: LexicalOptions::from_env(),
+            ensured: Mutex::new(HashSet::new()),
+            without_headings: Mutex::new(HashSet::new()),
+            rest_url: env::var("QDRANT_REST_URL")
+                .unwrap_or_else(|_| "http://localhost:6333".to_string()),
+            snapshots_path: env::var("QDRANT_SNAPSHOTS_PATH")
+                .unwrap_or_else(|_| "/qdrant/snapshots".to_string()),
         })
     }
 
-    pub async fn ensure_collection(&self) -> Result<()> {
+    /// This indexer with another embedding model, for moving a collection
+    /// to it; the model's vector size is asked from its TEI
+    pub async fn with_embeddings(&self, tei_url: &str) -> Result<Self> {
+        let mut indexer = Self {
+            qdrant: self.qdrant.clone(),
+            retry: self.retry.clone(),
+            http: self.http.clone(),
+            tei_url: tei_url.to_string(),
+            dimensions: 0,
+            dedup_chunks: self.dedup_chunks,
+            keep_versions: self.keep_versions,
+            lexical: self.lexical,
+            ensured: Mutex::new(HashSet::new()),
+            without_headings: Mutex::new(HashSet::new()),
+            rest_url: self.rest_url.clone(),
+            snapshots_path: self.snapshots_path.clone(),
+        };
+        let probe = indexer
+            .embed(&["query: dimensions".to_string()])
+            .await
+            .with_context(|| format!("embedding model at {tei_url} is not reachable"))?;
+        indexer.dimensions = probe.first().map_or(0, Vec::len);
+        anyhow::ensure!(
+            indexer.dimensions > 0,
+            "{tei_url} returned an empty embedding"
+        );
+        Ok(indexer)
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// Retries and circuit breaker state of this indexer's Qdrant calls
+    pub fn qdrant_stats(&self) -> RetryStats {
+        self.retry.stats()
+    }
+
+    pub async fn check_qdrant(&self) -> Result<()> {
+        self.qdrant.health_check().await?;
+        Ok(())
+    }
+
+    pub async fn check_embeddings(&self) -> Result<()> {
+        self.http
+            .get(format!("{}/health", self.tei_url.trim_end_matches('/')))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Create `collection` with its payload indexes unless this indexer
+    /// already did, so a tenant's collection exists before its first crawl.
+    /// A new collection is `<collection>_v0` behind an alias called
+    /// `collection`, so a reindex can switch it without a gap.
+    pub async fn ensure_collection(&self, collection: &str) -> Result<()> {
+        self.ensure(collection, true).await
+    }
+
+    /// Create a reindex's `target` as a plain collection, for
+    /// [`Self::replace_collection`] to point an alias at
+    pub async fn ensure_target(&self, target: &str) -> Result<()> {
+        self.ensure(target, false).await
+    }
+
+    async fn ensure(&self, collection: &str, aliased: bool) -> Result<()> {
+        if self.ensured.lock().unwrap().contains(collection) {
+            return Ok(());
+        }
+        if !self.exists(collection).await? {
+            if aliased {
+                let first = first_version(collection);
+                self.create_collection(&first).await?;
+                self.retry
+                    .call(|| {
+                        self.qdrant
+                            .create_alias(CreateAliasBuilder::new(first.as_str(), collection))
+                    })
+                    .await?;
+            } else {
+                self.create_collection(collection).await?;
+            }
+        } else if !self.has_headings_vector(collection).await? {
+            tracing::warn!(
+                "collection {collection} has no {HEADINGS_VECTOR} vector; reindex it so headings are searched"
+            );
+            self.without_headings
+                .lock()
+                .unwrap()
+                .insert(collection.to_string());
+        }
+        // Also run for existing collections, so payload indexes added later exist
+        for (field, kind) in [
+            ("source_url", FieldType::Keyword),
+            ("page_version", FieldType::Keyword),
+            ("crawled_at", FieldType::Integer),
+            ("published_at", FieldType::Integer),
+            ("author", FieldType::Keyword),
+            ("language", FieldType::Keyword),
+            ("chunk_index", FieldType::Integer),
+            ("host", FieldType::Keyword),
+            ("content_hash", FieldType::Keyword),
+            ("unsafe", FieldType::Bool),
+            ("version_number", FieldType::Integer),
+            ("version_since", FieldType::Integer),
+            ("superseded_at", FieldType::Integer),
+            ("collection", FieldType::Keyword),
+        ] {
+            self.retry
+                .call(|| {
+                    self.qdrant.create_field_index(
+                        CreateFieldIndexCollectionBuilder::new(collection, field, kind).wait(true),
+                    )
+                })
+                .await?;
+        }
+        self.ensured.lock().unwrap().insert(collection.to_string());
+        Ok(())
+    }
+
+    async fn has_headings_vector(&self, collection: &str) -> Result<bool> {
+        let info = self
+            .retry
+            .call(|| self.qdrant.collection_info(collection))
+            .await?;
+        Ok(info
+            .result
+            .and_then(|info| info.config)
+            .and_then(|config| config.params)
+            .and_then(|params| params.sparse_vectors_config)
+            .is_some_and(|sparse| sparse.map.contains_key(HEADINGS_VECTOR)))
+    }
+
+    /// Whether `name` is a collection or, after a reindex, an alias of one
+    async fn exists(&self, name: &str) -> Result<bool> {
         if self
-            .qdrant
-            .collection_exists(QDRANT_COLLECTION_NAME)
+            .retry
+            .call(|| self.qdrant.collection_exists(name))
             .await?
         {
-            return Ok(());
+            return Ok(true);
         }
+        let aliases = self
+            .retry
+            .call(|| self.qdrant.list_aliases())
+            .await?
+            .aliases;
+        Ok(aliases.iter().any(|alias| alias.alias_name == name))
+    }
+
+    async fn create_collection(&self, collection: &str) -> Result<()> {
         let mut dense = VectorsConfigBuilder::default();
         dense.add_named_vector_params(
             "dense",
-            VectorParamsBuilder::new(384, Distance::Cosine).on_disk(true),
+            VectorParamsBuilder::new(self.dimensions as u64, Distance::Cosine).on_disk(true),
         );
         let sparse_params = SparseVectorParamsBuilder::default()
             .modifier(Modifier::Idf)
             .index(SparseIndexConfigBuilder::default().on_disk(true));
         let mut sparse = SparseVectorsConfigBuilder::default();
         sparse.add_named_vector_params("title_bm25", sparse_params.clone());
+        sparse.add_named_vector_params(HEADINGS_VECTOR, sparse_params.clone());
         sparse.add_named_vector_params("body_bm25", sparse_params);
-        self.qdrant
-            .create_collection(
-                CreateCollectionBuilder::new(QDRANT_COLLECTION_NAME)
-                    .vectors_config(dense)
-                    .sparse_vectors_config(sparse)
-                    .on_disk_payload(true),
-            )
-            .await?;
-        for (field, kind) in [
-            ("source_url", FieldType::Keyword),
-            ("page_version", FieldType::Keyword),
-            ("crawled_at", FieldType::Integer),
-            ("chunk_index", FieldType::Integer),
-        ] {
-            self.qdrant
-                .create_field_index(
-                    CreateFieldIndexCollectionBuilder::new(QDRANT_COLLECTION_NAME, field, kind)
-                        .wait(true),
+        self.retry
+            .call(|| {
+                self.qdrant.create_collection(
+                    CreateCollectionBuilder::new(collection)
+                        .vectors_config(dense.clone())
+                        .sparse_vectors_config(sparse.clone())
+                        .on_disk_payload(true),
                 )
-                .await?;
-        }
+            })
+            .await?;
         Ok(())
     }
 
-    pub async fn index_page(&self, chunks: &[WebPageChunk]) -> Result<()> {
+    /// Index a page's chunks, replacing any earlier version of the page or,
+    /// with `KEEP_PAGE_VERSIONS`, marking it superseded; `archive_key` points
+    /// at the raw HTML in the archive. Chunks whose content is already
+    /// indexed for another page of the same host, such as footers and cookie
+    /// notices, are skipped unless `CHUNK_DEDUP=false`.
+    pub async fn index_page(
+        &self,
+        collection: &str,
+        chunks: &[WebPageChunk],
+        archive_key: Option<&str>,
+    ) -> Result<IndexOutcome> {
         if chunks.is_empty() {
-            return Ok(());
+            return Ok(IndexOutcome::Empty);
         }
         let source_url = &chunks[0].source_url;
         let version = page_version(chunks);
-        let documents = chunks
+        let crawled_at = chunks[0].crawled_at;
+        let (outcome, version_number, version_since) =
+            match self.indexed_version(collection, source_url).await? {
+                None => (IndexOutcome::New, 1, crawled_at),
+                Some(previous) if previous.page_version == version => {
+                    (IndexOutcome::Unchanged, previous.number, previous.since)
+                }
+                Some(previous) => (IndexOutcome::Updated, previous.number + 1, crawled_at),
+            };
+        let host = Url::parse(source_url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+        let hashes = chunks.iter().map(content_hash).collect::<Vec<_>>();
+        let elsewhere = if self.dedup_chunks {
+            self.hashes_on_other_pages(collection, &host, source_url, &hashes)
+                .await?
+        } else {
+            HashSet::new()
+        };
+        let positions = if self.dedup_chunks {
+            unique_chunks(&hashes, &elsewhere)
+        } else {
+            (0..chunks.len()).collect()
+        };
+        // Original positions are kept so point ids stay stable
+        let kept = positions
+            .into_iter()
+            .map(|index| (index, (&chunks[index], &hashes[index])))
+            .collect::<Vec<_>>();
+        if kept.len() < chunks.len() {
+            tracing::debug!(
+                "skipping {} duplicate chunks of {source_url}",
+                chunks.len() - kept.len()
+            );
+        }
+
+        let documents = kept
             .iter()
-            .map(|chunk| {
-                format!(
-                    "passage: {}\n{}\n{}",
-                    chunk.page_title,
-                    chunk.chunk_heading.as_deref().unwrap_or(""),
-                    chunk.chunk_content
-                )
-            })
+            .map(|(_, (chunk, _))| format!("{}{}", passage_prefix(chunk), chunk.chunk_content))
             .collect::<Vec<_>>();
         let dense = self.embed(&documents).await?;
-        if dense.len() != chunks.len() || dense.iter().any(|vector| vector.len() != 384) {
+        if dense.len() != kept.len() || dense.iter().any(|vector| vector.len() != self.dimensions) {
             anyhow::bail!("TEI returned invalid embedding dimensions");
         }
 
-        let points = chunks
-            .iter()
+        let with_headings = !self.without_headings.lock().unwrap().contains(collection);
+        let lexical = self.lexical.for_language(chunks[0].language.as_deref());
+        let points = kept
+            .into_iter()
             .zip(dense)
-            .enumerate()
-            .map(|(index, (chunk, dense))| {
-                let title = format!(
-                    "{}\n{}",
-                    chunk.page_title,
-                    chunk.chunk_heading.as_deref().unwrap_or("")
-                );
+            .map(|((index, (chunk, hash)), dense)| {
+                let title = format!("{}\n{}", chunk.page_title, chunk.heading_context());
                 let body = format!("{}\n{}", chunk.description, chunk.chunk_content);
                 let mut payload = chunk.to_payload_json();
                 let object = payload.as_object_mut().unwrap();
                 object.insert("page_version".to_string(), version.clone().into());
                 object.insert("chunk_index".to_string(), (index as i64).into());
+                object.insert("version_number".to_string(), version_number.into());
+                object.insert("version_since".to_string(), version_since.into());
+                object.insert("host".to_string(), host.clone().into());
+                object.insert("content_hash".to_string(), hash.clone().into());
+                if let Some(key) = archive_key {
+                    object.insert("archive_key".to_string(), key.into());
+                }
+                let mut vectors = NamedVectors::default()
+                    .add_vector("dense", dense)
+                    .add_vector("title_bm25", bm25_document(&title, &lexical))
+                    .add_vector("body_bm25", bm25_document(&body, &lexical));
+                if with_headings {
+                    vectors = vectors.add_vector(
+                        HEADINGS_VECTOR,
+                        bm25_document(&chunk.headings.join("\n"), &lexical),
+                    );
+                }
                 PointStruct::new(
                     point_id(source_url, &version, index),
-                    NamedVectors::default()
-                        .add_vector("dense", dense)
-                        .add_vector("title_bm25", bm25_document(title))
-                        .add_vector("body_bm25", bm25_document(body)),
+                    vectors,
                     Payload::try_from(payload).unwrap(),
                 )
             })
             .collect::<Vec<_>>();
 
-        self.qdrant
-            .upsert_points(UpsertPointsBuilder::new(QDRANT_COLLECTION_NAME, points).wait(true))
+        if !points.is_empty() {
+            self.retry
+                .call(|| {
+                    self.qdrant.upsert_points(
+                        UpsertPointsBuilder::new(collection, points.clone()).wait(true),
+                    )
+                })
+                .await?;
+        }
+        if self.keep_versions {
+            let mut stale = stale_version_filter(source_url, &version);
+            stale.must.push(current_version());
+            self.retry
+                .call(|| {
+                    self.qdrant.set_payload(
+                        SetPayloadPointsBuilder::new(
+                            collection,
+                            Payload::try_from(serde_json::json!({ "superseded_at": crawled_at }))
+                                .unwrap(),
+                        )
+                        .points_selector(stale.clone())
+                        .wait(true),
+                    )
+                })
+                .await?;
+        } else {
+            self.retry
+                .call(|| {
+                    self.qdrant.delete_points(
+                        DeletePointsBuilder::new(collection)
+                            .points(stale_version_filter(source_url, &version))
+                            .wait(true),
+                    )
+                })
+                .await?;
+        }
+        Ok(outcome)
+    }
+
+    /// Move every stored version of the page indexed under `from` to `to`,
+    /// keeping its vectors and version numbers, after the page's preferred
+    /// URL variant changed
+    pub async fn move_page(&self, collection: &str, from: &str, to: &str) -> Result<()> {
+        let filter = Filter::must([Condition::matches("source_url", from.to_string())]);
+        let mut offset = None;
+        loop {
+            let mut scroll = ScrollPointsBuilder::new(collection)
+                .filter(filter.clone())
+                .limit(256)
+                .with_payload(true)
+                .with_vectors(true);
+            if let Some(offset) = offset {
+                scroll = scroll.offset(offset);
+            }
+            let response = self
+                .retry
+                .call(|| self.qdrant.scroll(scroll.clone()))
+                .await?;
+            let points = response
+                .result
+                .into_iter()
+                .map(|point| {
+                    let mut payload = payload_json(point.payload);
+                    let version = payload
+                        .get("page_version")
+                        .and_then(|version| version.as_str())
+                        .unwrap_or_default()
+                        .to_string();
+                    let index = payload
+                        .get("chunk_index")
+                        .and_then(|index| index.as_u64())
+                        .unwrap_or(0) as usize;
+                    payload["source_url"] = to.into();
+                    PointStruct::new(
+                        point_id(to, &version, index),
+                        stored_vectors(point.vectors),
+                        Payload::try_from(payload).unwrap(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            if !points.is_empty() {
+                self.retry
+                    .call(|| {
+                        self.qdrant.upsert_points(
+                            UpsertPointsBuilder::new(collection, points.clone()).wait(true),
+                        )
+                    })
+                    .await?;
+            }
+            offset = response.next_page_offset;
+            if offset.is_none() {
+                break;
+            }
+        }
+        self.retry
+            .call(|| {
+                self.qdrant.delete_points(
+                    DeletePointsBuilder::new(collection)
+                        .points(filter.clone())
+                        .wait(true),
+                )
+            })
             .await?;
-        self.qdrant
-            .delete_points(
-                DeletePointsBuilder::new(QDRANT_COLLECTION_NAME)
-                    .points(stale_version_filter(source_url, &version))
-                    .wait(true),
+        Ok(())
+    }
+
+    /// Whether `collection` keeps earlier page versions: `KEEP_PAGE_VERSIONS`
+    /// is on or superseded versions are stored. Falls back to the setting
+    /// when Qdrant cannot be asked.
+    pub async fn keeps_versions(&self, collection: &str) -> bool {
+        if self.keep_versions {
+            return true;
+        }
+        let superseded = self
+            .qdrant
+            .count(
+                CountPointsBuilder::new(collection)
+                    .filter(Filter::must_not([current_version()]))
+                    .exact(false),
             )
+            .await;
+        match superseded {
+            Ok(response) => response.result.is_some_and(|result| result.count > 0),
+            Err(error) => {
+                tracing::warn!("cannot count superseded versions in {collection}: {error}");
+                false
+            }
+        }
+    }
+
+    /// Pages with a current version in `collection`, by URL; with `since`,
+    /// only those crawled at or after it
+    pub async fn indexed_pages(
+        &self,
+        collection: &str,
+        since: Option<i64>,
+    ) -> Result<BTreeMap<String, StoredPage>> {
+        let mut filter = Filter::must([current_version()]);
+        if let Some(since) = since {
+            filter.must.push(Condition::range(
+                "crawled_at",
+                Range {
+                    gte: Some(since as f64),
+                    ..Default::default()
+                },
+            ));
+        }
+        let mut pages = BTreeMap::new();
+        let mut offset = None;
+        loop {
+            let mut scroll = ScrollPointsBuilder::new(collection)
+                .filter(filter.clone())
+                .limit(1_000)
+                .with_payload(PayloadIncludeSelector::new(
+                    [
+                        "source_url",
+                        "archive_key",
+                        "crawled_at",
+                        "unsafe",
+                        "collection",
+                    ]
+                    .map(String::from)
+                    .to_vec(),
+                ))
+                .with_vectors(false);
+            if let Some(offset) = offset {
+                scroll = scroll.offset(offset);
+            }
+            let response = self
+                .retry
+                .call(|| self.qdrant.scroll(scroll.clone()))
+                .await?;
+            for point in response.result {
+                let payload = payload_json(point.payload);
+                if let Some(url) = payload.get("source_url").and_then(|url| url.as_str()) {
+                    pages
+                        .entry(url.to_string())
+                        .or_insert_with(|| StoredPage::from_payload(&payload));
+                }
+            }
+            offset = response.next_page_offset;
+            if offset.is_none() {
+                return Ok(pages);
+            }
+        }
+    }
+
+    /// The current chunks of a page in `collection`, in page order
+    pub async fn page_chunks(
+        &self,
+        collection: &str,
+        source_url: &str,
+    ) -> Result<Vec<WebPageChunk>> {
+        let response = self
+            .retry
+            .call(|| {
+                self.qdrant.scroll(
+                    ScrollPointsBuilder::new(collection)
+                        .filter(Filter::must([
+                            Condition::matches("source_url", source_url.to_string()),
+                            current_version(),
+                        ]))
+                        .limit(10_000)
+                        .with_payload(true)
+                        .with_vectors(false),
+                )
+            })
             .await?;
+        let mut chunks = response
+            .result
+            .into_iter()
+            .filter_map(|point| {
+                let payload = payload_json(point.payload);
+                let index = payload
+                    .get("chunk_index")
+                    .and_then(|index| index.as_i64())
+                    .unwrap_or(0);
+                WebPageChunk::from_payload_json(&payload).map(|chunk| (index, chunk))
+            })
+            .collect::<Vec<_>>();
+        chunks.sort_by_key(|(index, _)| *index);
+        Ok(chunks.into_iter().map(|(_, chunk)| chunk).collect())
+    }
+
+    /// Serve the alias `name` from `target` and delete the collection that
+    /// served it before. A collection created before collections were kept
+    /// behind aliases is refused: its name cannot become an alias without
+    /// deleting it first, which would leave searches without a collection.
+    pub async fn replace_collection(&self, name: &str, target: &str) -> Result<()> {
+        let previous = self
+            .retry
+            .call(|| self.qdrant.list_aliases())
+            .await?
+            .aliases
+            .into_iter()
+            .find(|alias| alias.alias_name == name)
+            .map(|alias| alias.collection_name);
+        let Some(previous) = previous else {
+            anyhow::bail!(
+                "{name} is a collection, not an alias; delete it and alias {target} as {name} to switch over"
+            );
+        };
+        self.swap_alias(name, target).await?;
+        self.retry
+            .call(|| self.qdrant.delete_collection(&previous))
+            .await?;
+        self.ensured.lock().unwrap().remove(name);
+        self.without_headings.lock().unwrap().remove(name);
+        Ok(())
+    }
+
+    /// Point the alias `name` at `target` with one request, so it never
+    /// resolves to nothing; the client's alias calls send one action each
+    async fn swap_alias(&self, name: &str, target: &str) -> Result<()> {
+        let mut client = CollectionsClient::connect(self.qdrant.config.uri.clone()).await?;
+        let mut request = tonic::Request::new(alias_swap(name, target));
+        if let Some(api_key) = &self.qdrant.config.api_key {
+            request.metadata_mut().insert("api-key", api_key.parse()?);
+        }
+        client.update_aliases(request).await?;
         Ok(())
     }
 
+    /// The collection an alias names, or `name` itself; snapshots belong to
+    /// the collection, not to its alias
+    async fn resolve(&self, name: &str) -> Result<String> {
+        let aliases = self
+            .retry
+            .call(|| self.qdrant.list_aliases())
+            .await?
+            .aliases;
+        Ok(aliases
+            .into_iter()
+            .find(|alias| alias.alias_name == name)
+            .map_or_else(|| name.to_string(), |alias| alias.collection_name))
+    }
+
+    /// Snapshots of `collection` on the Qdrant node, newest first
+    pub async fn snapshots(&self, collection: &str) -> Result<Vec<Snapshot>> {
+        let collection = self.resolve(collection).await?;
+        let response = self
+            .retry
+            .call(|| self.qdrant.list_snapshots(&collection))
+            .await?;
+        let mut snapshots = response
+            .snapshot_descriptions
+            .into_iter()
+            .map(snapshot)
+            .collect::<Vec<_>>();
+        snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.created_at));
+        Ok(snapshots)
+    }
+
+    /// Write a snapshot of `collection` to Qdrant's snapshots directory
+    pub async fn create_snapshot(&self, collection: &str) -> Result<Snapshot> {
+        let collection = self.resolve(collection).await?;
+        self.retry
+            .call(|| self.qdrant.create_snapshot(&collection))
+            .await?
+            .snapshot_description
+            .map(snapshot)
+            .context("Qdrant did not describe the snapshot")
+    }
+
+    /// Replace `collection` with the points of its snapshot `name`
+    pub async fn restore_snapshot(&self, collection: &str, name: &str) -> Result<()> {
+        let collection = self.resolve(collection).await?;
+        let location = format!(
+            "file://{}/{collection}/{name}",
+            self.snapshots_path.trim_end_matches('/')
+        );
+        self.http
+            .put(format!(
+                "{}/collections/{collection}/snapshots/recover?wait=true",
+                self.rest_url.trim_end_matches('/')
+            ))
+            .headers(crate::forwarded_request_id())
+            .json(&serde_json::json!({ "location": location, "priority": "snapshot" }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Split chunks whose embedding input exceeds `max_tokens` tokens of the
+    /// embedding model; `0` disables the check. If TEI cannot tokenize, the
+    /// chunks are kept as they are.
+    pub async fn fit_token_limit(
+        &self,
+        chunks: Vec<WebPageChunk>,
+        max_tokens: usize,
+    ) -> Vec<WebPageChunk> {
+        if max_tokens == 0 || chunks.is_empty() {
+            return chunks;
+        }
+        let prefixes = chunks.iter().map(passage_prefix).collect::<Vec<_>>();
+        let documents = chunks
+            .iter()
+            .zip(&prefixes)
+            .map(|(chunk, prefix)| format!("{prefix}{}", chunk.chunk_content))
+            .collect::<Vec<_>>();
+        let tokens = match self.tokenize(&documents).await {
+            Ok(tokens) if tokens.len() == chunks.len() => tokens,
+            Ok(_) => {
+                tracing::warn!("TEI returned the wrong number of tokenizations");
+                return chunks;
+            }
+            Err(error) => {
+                tracing::warn!(
+                    "failed to tokenize chunks of {}: {error:#}",
+                    chunks[0].source_url
+                );
+                return chunks;
+            }
+        };
+        chunks
+            .into_iter()
+            .zip(prefixes)
+            .zip(tokens)
+            .flat_map(|((chunk, prefix), tokens)| {
+                split_to_token_limit(chunk, prefix.len(), &tokens, max_tokens)
+            })
+            .collect()
+    }
+
+    async fn tokenize(&self, inputs: &[String]) -> Result<Vec<Vec<Token>>> {
+        self.http
+            .post(format!("{}/tokenize", self.tei_url.trim_end_matches('/')))
+            .headers(crate::forwarded_request_id())
+            .json(&TokenizeRequest {
+                inputs,
+                add_special_tokens: true,
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("invalid TEI tokenize response")
+    }
+
+    /// Which of `hashes` are already indexed for other pages of `host`
+    async fn hashes_on_other_pages(
+        &self,
+        collection: &str,
+        host: &str,
+        source_url: &str,
+        hashes: &[String],
+    ) -> Result<HashSet<String>> {
+        let response = self
+            .retry
+            .call(|| {
+                self.qdrant.scroll(
+                    ScrollPointsBuilder::new(collection)
+                        .filter(Filter {
+                            must: vec![
+                                Condition::matches("host", host.to_string()),
+                                Condition::matches("content_hash", hashes.to_vec()),
+                                current_version(),
+                            ],
+                            must_not: vec![Condition::matches(
+                                "source_url",
+                                source_url.to_string(),
+                            )],
+                            ..Default::default()
+                        })
+                        .limit(hashes.len() as u32)
+                        .with_payload(true)
+                        .with_vectors(false),
+                )
+            })
+            .await?;
+        Ok(response
+            .result
+            .into_iter()
+            .filter_map(|point| point.payload.get("content_hash").cloned())
+            .filter_map(|hash| hash.into_json().as_str().map(str::to_string))
+            .collect())
+    }
+
+    /// `crawled_at` of the indexed URLs among `urls`
+    pub async fn last_crawled(
+        &self,
+        collection: &str,
+        urls: &[String],
+    ) -> Result<HashMap<String, i64>> {
+        let mut crawled = HashMap::new();
+        for batch in urls.chunks(LOOKUP_BATCH_SIZE) {
+            let response = self
+                .retry
+                .call(|| {
+                    self.qdrant.scroll(
+                        ScrollPointsBuilder::new(collection)
+                            .filter(Filter::must([
+                                Condition::matches("source_url", batch.to_vec()),
+                                Condition::matches("chunk_index", 0i64),
+                                current_version(),
+                            ]))
+                            .limit(batch.len() as u32)
+                            .with_payload(PayloadIncludeSelector::new(vec![
+                                "source_url".to_string(),
+                                "crawled_at".to_string(),
+                            ]))
+                            .with_vectors(false),
+                    )
+                })
+                .await?;
+            for point in response.result {
+                let field = |key: &str| {
+                    point
+                        .payload
+                        .get(key)
+                        .cloned()
+                        .map(|value| value.into_json())
+                };
+                if let (Some(url), Some(at)) = (field("source_url"), field("crawled_at")) {
+                    if let (Some(url), Some(at)) = (url.as_str(), at.as_i64()) {
+                        crawled.insert(url.to_string(), at);
+                    }
+                }
+            }
+        }
+        Ok(crawled)
+    }
+
+    /// The version of a URL that is currently indexed
+    async fn indexed_version(
+        &self,
+        collection: &str,
+        source_url: &str,
+    ) -> Result<Option<IndexedVersion>> {
+        let response = self
+            .retry
+            .call(|| {
+                self.qdrant.scroll(
+                    ScrollPointsBuilder::new(collection)
+                        .filter(Filter::must([
+                            Condition::matches("source_url", source_url.to_string()),
+                            current_version(),
+                        ]))
+                        .limit(1)
+                        .with_payload(true)
+                        .with_vectors(false),
+                )
+            })
+            .await?;
+        Ok(response
+            .result
+            .into_iter()
+            .next()
+            .and_then(|point| IndexedVersion::from_payload(&payload_json(point.payload))))
+    }
+
+    /// BM25 input, normalized the way the API normalizes queries
+    /// Embedding of a crawl's `focus_query`
+    pub async fn embed_query(&self, query: &str) -> Result<Vec<f32>> {
+        self.embed(&[format!("query: {query}")])
+            .await?
+            .pop()
+            .context("TEI returned no embedding")
+    }
+
+    /// Cosine similarity of a page's title, description and opening text to
+    /// an `embed_query` vector; `0` for a page without content
+    pub async fn relevance(&self, query: &[f32], chunks: &[WebPageChunk]) -> Result<f32> {
+        let Some(passage) = focus_passage(chunks) else {
+            return Ok(0.0);
+        };
+        let page = self
+            .embed(&[passage])
+            .await?
+            .pop()
+            .context("TEI returned no embedding")?;
+        Ok(cosine(query, &page))
+    }
+
     async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
         let mut embeddings = Vec::with_capacity(inputs.len());
         for inputs in inputs.chunks(EMBED_BATCH_SIZE) {
             let mut batch = self
                 .http
                 .post(format!("{}/embed", self.tei_url.trim_end_matches('/')))
+                .headers(crate::forwarded_request_id())
                 .json(&EmbedRequest { inputs })
                 .send()
                 .await?
@@ -159,17 +900,128 @@ impl PageIndexer {
     }
 }
 
-fn bm25_document(text: String) -> qdrant_client::qdrant::Document {
-    DocumentBuilder::new(text, BM25_MODEL)
+/// Everything embedded ahead of a chunk's content
+/// BM25 vector of `text`, normalized as the API normalizes queries in the
+/// same language
+fn bm25_document(text: &str, lexical: &LexicalOptions) -> qdrant_client::qdrant::Document {
+    DocumentBuilder::new(text::lexical(text, lexical), BM25_MODEL)
         .options(HashMap::from([("language".to_string(), "none".into())]))
         .build()
 }
 
-fn page_version(chunks: &[WebPageChunk]) -> String {
+fn passage_prefix(chunk: &WebPageChunk) -> String {
+    format!(
+        "passage: {}\n{}\n",
+        chunk.page_title,
+        chunk.heading_context()
+    )
+}
+
+fn focus_passage(chunks: &[WebPageChunk]) -> Option<String> {
+    let first = chunks.first()?;
+    let mut passage = format!("passage: {}\n{}\n", first.page_title, first.description);
+    for chunk in chunks {
+        passage.push_str(&chunk.chunk_content);
+        passage.push('\n');
+        if passage.len() >= FOCUS_PASSAGE_CHARS {
+            break;
+        }
+    }
+    Some(passage.chars().take(FOCUS_PASSAGE_CHARS).collect())
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot = a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+/// Hash of a chunk's text with whitespace normalized, shared by identical
+/// boilerplate on different pages
+fn content_hash(chunk: &WebPageChunk) -> String {
+    let text = chunk
+        .chunk_content
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+/// What a reindex keeps of an indexed page besides its content
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StoredPage {
+    pub archive_key: Option<String>,
+    pub crawled_at: i64,
+    pub unsafe_content: bool,
+    pub collection: Option<String>,
+}
+
+impl StoredPage {
+    fn from_payload(payload: &serde_json::Value) -> Self {
+        let text = |key: &str| {
+            payload
+                .get(key)
+                .and_then(|value| value.as_str())
+                .map(str::to_string)
+        };
+        Self {
+            archive_key: text("archive_key"),
+            crawled_at: payload
+                .get("crawled_at")
+                .and_then(|value| value.as_i64())
+                .unwrap_or(0),
+            unsafe_content: payload
+                .get("unsafe")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false),
+            collection: text("collection"),
+        }
+    }
+}
+
+fn snapshot(description: SnapshotDescription) -> Snapshot {
+    Snapshot {
+        name: description.name,
+        created_at: description.creation_time.map_or(0, |time| time.seconds),
+        size_bytes: description.size,
+    }
+}
+
+/// The collection behind a new collection's alias
+fn first_version(collection: &str) -> String {
+    format!("{collection}_v0")
+}
+
+/// Moving the alias `name` to `target`, as one change Qdrant applies at once
+fn alias_swap(name: &str, target: &str) -> ChangeAliases {
+    let actions = [
+        Action::from(DeleteAlias {
+            alias_name: name.to_string(),
+        }),
+        Action::from(CreateAlias::from(CreateAliasBuilder::new(target, name))),
+    ];
+    ChangeAliases {
+        actions: actions
+            .into_iter()
+            .map(|action| AliasOperations {
+                action: Some(action),
+            })
+            .collect(),
+        timeout: None,
+    }
+}
+
+/// Content hash of a page's chunks
+pub fn page_version(chunks: &[WebPageChunk]) -> String {
     let mut hasher = Sha256::new();
     for chunk in chunks {
         hasher.update(chunk.page_title.as_bytes());
-        hasher.update(chunk.chunk_heading.as_deref().unwrap_or("").as_bytes());
+        hasher.update(chunk.heading_context().as_bytes());
         hasher.update(chunk.description.as_bytes());
         hasher.update(chunk.chunk_content.as_bytes());
         hasher.update([0]);
@@ -177,6 +1029,12 @@ fn page_version(chunks: &[WebPageChunk]) -> String {
     format!("{:x}", hasher.finalize())
 }
 
+/// Chunks of the version of a page that is currently indexed; superseded
+/// versions kept by `KEEP_PAGE_VERSIONS` carry `superseded_at`
+fn current_version() -> Condition {
+    Condition::is_empty("superseded_at")
+}
+
 fn stale_version_filter(source_url: &str, version: &str) -> Filter {
     Filter {
         must: vec![Condition::matches("source_url", source_url.to_string())],
@@ -185,6 +1043,74 @@ fn stale_version_filter(source_url: &str, version: &str) -> Filter {
     }
 }
 
+/// Payload fields of the indexed version of a page
+#[derive(Debug, PartialEq)]
+struct IndexedVersion {
+    page_version: String,
+    /// Counts from 1 per URL; pages indexed before versions were numbered
+    /// are version 1
+    number: i64,
+    /// When this content was first crawled, as later re-crawls that find it
+    /// unchanged only move `crawled_at`
+    since: i64,
+}
+
+impl IndexedVersion {
+    fn from_payload(payload: &serde_json::Value) -> Option<Self> {
+        Some(Self {
+            page_version: payload.get("page_version")?.as_str()?.to_string(),
+            number: payload
+                .get("version_number")
+                .and_then(|number| number.as_i64())
+                .unwrap_or(1),
+            since: payload
+                .get("version_since")
+                .or_else(|| payload.get("crawled_at"))
+                .and_then(|since| since.as_i64())
+                .unwrap_or(0),
+        })
+    }
+}
+
+/// Positions of the chunks to index: the first chunk of each content hash,
+/// unless that hash is already indexed for another page of the host
+fn unique_chunks(hashes: &[String], elsewhere: &HashSet<String>) -> Vec<usize> {
+    let mut seen = HashSet::new();
+    hashes
+        .iter()
+        .enumerate()
+        .filter(|(_, hash)| !elsewhere.contains(*hash) && seen.insert(*hash))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Vectors read back from Qdrant, to be written under another point id
+fn stored_vectors(vectors: Option<VectorsOutput>) -> NamedVectors {
+    let mut named = NamedVectors::default();
+    if let Some(vectors_output::VectorsOptions::Vectors(output)) =
+        vectors.and_then(|vectors| vectors.vectors_options)
+    {
+        for (name, vector) in output.vectors {
+            let vector: Vector = match vector.into_vector() {
+                vector_output::Vector::Dense(dense) => dense.into(),
+                vector_output::Vector::Sparse(sparse) => sparse.into(),
+                vector_output::Vector::MultiDense(multi) => multi.into(),
+            };
+            named = named.add_vector(name, vector);
+        }
+    }
+    named
+}
+
+fn payload_json(payload: HashMap<String, qdrant_client::qdrant::Value>) -> serde_json::Value {
+    serde_json::Value::Object(
+        payload
+            .into_iter()
+            .map(|(key, value)| (key, value.into_json()))
+            .collect(),
+    )
+}
+
 fn point_id(url: &str, version: &str, index: usize) -> String {
     Uuid::new_v5(
         &Uuid::NAMESPACE_URL,
@@ -203,6 +1129,31 @@ mod tests {
     use super::*;
     use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
+    #[test]
+    fn swaps_aliases_in_one_change() {
+        assert_eq!(first_version("web_pages__docs"), "web_pages__docs_v0");
+        assert!(shared_crawler_api::tenant::collection_name(Some("docs_v0")).is_err());
+
+        let change = alias_swap("web_pages", "web_pages_reindex_17");
+        let actions = change
+            .actions
+            .into_iter()
+            .map(|operation| operation.action.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            actions,
+            vec![
+                Action::DeleteAlias(DeleteAlias {
+                    alias_name: "web_pages".into()
+                }),
+                Action::CreateAlias(CreateAlias {
+                    collection_name: "web_pages_reindex_17".into(),
+                    alias_name: "web_pages".into()
+                }),
+            ]
+        );
+    }
+
     #[test]
     fn page_versions_and_ids_are_stable() {
         let chunk = WebPageChunk::new(
@@ -225,6 +1176,109 @@ mod tests {
         assert_eq!(filter.must_not.len(), 1);
     }
 
+    #[test]
+    fn content_hashes_ignore_whitespace() {
+        let chunk = |content: &str| {
+            WebPageChunk::new(
+                content.into(),
+                None,
+                "https://example.com".into(),
+                "title".into(),
+                String::new(),
+                vec![],
+                vec![],
+                0.0,
+                0.0,
+                0,
+            )
+        };
+        assert_eq!(
+            content_hash(&chunk("cookie  notice")),
+            content_hash(&chunk("  cookie\nnotice\n"))
+        );
+        assert_ne!(
+            content_hash(&chunk("cookie notice")),
+            content_hash(&chunk("cookienotice"))
+        );
+    }
+
+    #[test]
+    fn skips_chunks_indexed_for_other_pages_of_the_host() {
+        let hashes = ["footer", "intro", "footer", "body"].map(String::from);
+        assert_eq!(unique_chunks(&hashes, &HashSet::new()), [0, 1, 3]);
+        let elsewhere = HashSet::from(["footer".to_string()]);
+        assert_eq!(unique_chunks(&hashes, &elsewhere), [1, 3]);
+    }
+
+    #[test]
+    fn reads_indexed_versions_from_payloads() {
+        let numbered = serde_json::json!({
+            "page_version": "b",
+            "version_number": 3,
+            "version_since": 200,
+            "crawled_at": 300
+        });
+        assert_eq!(
+            IndexedVersion::from_payload(&numbered),
+            Some(IndexedVersion {
+                page_version: "b".to_string(),
+                number: 3,
+                since: 200
+            })
+        );
+        let legacy = serde_json::json!({ "page_version": "a", "crawled_at": 100 });
+        let legacy = IndexedVersion::from_payload(&legacy).unwrap();
+        assert_eq!((legacy.number, legacy.since), (1, 100));
+        assert_eq!(IndexedVersion::from_payload(&serde_json::json!({})), None);
+    }
+
+    #[test]
+    fn keeps_page_metadata_for_a_reindex() {
+        let payload = serde_json::json!({
+            "archive_key": "ab/cdef",
+            "crawled_at": 300,
+            "unsafe": true,
+            "collection": "docs"
+        });
+        assert_eq!(
+            StoredPage::from_payload(&payload),
+            StoredPage {
+                archive_key: Some("ab/cdef".to_string()),
+                crawled_at: 300,
+                unsafe_content: true,
+                collection: Some("docs".to_string()),
+            }
+        );
+        assert_eq!(
+            StoredPage::from_payload(&serde_json::json!({})),
+            StoredPage::default()
+        );
+    }
+
+    #[test]
+    fn focus_passage_is_the_opening_of_the_page() {
+        let chunk = |content: &str| {
+            WebPageChunk::new(
+                content.to_string(),
+                None,
+                "https://example.com/".to_string(),
+                "Tokio".to_string(),
+                "Async runtime".to_string(),
+                vec![],
+                vec![],
+                0.0,
+                0.0,
+                0,
+            )
+        };
+        assert_eq!(focus_passage(&[]), None);
+        let passage = focus_passage(&[chunk("Tasks"), chunk(&"x".repeat(3_000))]).unwrap();
+        assert!(passage.starts_with("passage: Tokio\nAsync runtime\nTasks\nxxx"));
+        assert_eq!(passage.chars().count(), FOCUS_PASSAGE_CHARS);
+        assert!((cosine(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine(&[1.0, 0.0], &[0.0, 0.0]), 0.0);
+    }
+
     #[tokio::test]
     async fn embeds_in_client_sized_batches_and_preserves_order() {
         let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
@@ -285,8 +1339,17 @@ mod tests {
 
         let indexer = PageIndexer {
             qdrant: Qdrant::from_url("http://127.0.0.1:6334").build().unwrap(),
+            retry: Arc::new(QdrantRetry::new(1, 1, std::time::Duration::ZERO)),
             http: Client::new(),
             tei_url: format!("http://{address}"),
+            dimensions: 384,
+            dedup_chunks: true,
+            keep_versions: false,
+            lexical: LexicalOptions::default(),
+            ensured: Mutex::new(HashSet::new()),
+            without_headings: Mutex::new(HashSet::new()),
+            rest_url: String::new(),
+            snapshots_path: String::new(),
         };
         let embeddings = indexer.embed(&inputs).await.unwrap();
 
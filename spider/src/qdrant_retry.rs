@@ -0,0 +1,231 @@
+//! Retries and a circuit breaker around Qdrant calls
+//!
+//! A call that fails with a transient error (Qdrant unreachable, overloaded
+//! or timing out) is tried again up to `QDRANT_RETRY_ATTEMPTS` times in all
+//! (default 4), after exponential backoff from 200 ms with jitter, or after
+//! the delay Qdrant asks for when it is rate limiting. Once
+//! `QDRANT_BREAKER_THRESHOLD` calls in a row (default 5) failed that way, the
+//! breaker opens and calls fail at once for `QDRANT_BREAKER_COOLDOWN_SECS`
+//! (default 30), so an outage fails pages quickly instead of holding every
+//! runner in backoff. After the cooldown calls go through again, and the
+//! first failure opens the breaker anew. `GET /status` reports the counters.
+
+use anyhow::Result;
+use qdrant_client::QdrantError;
+use serde::Serialize;
+use std::{
+    env,
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use tonic::Code;
+
+const BASE_DELAY_MS: u64 = 200;
+/// Longest wait between attempts, also when Qdrant asks for more
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RetryStats {
+    pub calls: usize,
+    /// Attempts after the first one of a call
+    pub retries: usize,
+    /// Calls that still failed after their last attempt
+    pub failures: usize,
+    /// Calls refused while the breaker was open
+    pub rejected: usize,
+    pub breaker_open: bool,
+}
+
+pub struct QdrantRetry {
+    max_attempts: usize,
+    breaker_threshold: usize,
+    breaker_cooldown: Duration,
+    /// Calls in a row that failed with a transient error
+    consecutive_failures: AtomicUsize,
+    open_until: Mutex<Option<Instant>>,
+    calls: AtomicUsize,
+    retries: AtomicUsize,
+    failures: AtomicUsize,
+    rejected: AtomicUsize,
+}
+
+impl QdrantRetry {
+    pub fn new(max_attempts: usize, breaker_threshold: usize, breaker_cooldown: Duration) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            breaker_threshold: breaker_threshold.max(1),
+            breaker_cooldown,
+            consecutive_failures: AtomicUsize::new(0),
+            open_until: Mutex::new(None),
+            calls: AtomicUsize::new(0),
+            retries: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+            rejected: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let read = |key: &str, default: usize| {
+            env::var(key)
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(default)
+        };
+        Self::new(
+            read("QDRANT_RETRY_ATTEMPTS", 4),
+            read("QDRANT_BREAKER_THRESHOLD", 5),
+            Duration::from_secs(read("QDRANT_BREAKER_COOLDOWN_SECS", 30) as u64),
+        )
+    }
+
+    /// Run `call`, trying again while it fails with a transient error
+    pub async fn call<T, F, Fut>(&self, mut call: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, QdrantError>>,
+    {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        if let Some(until) = *self.open_until.lock().unwrap() {
+            if Instant::now() < until {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                anyhow::bail!("Qdrant is unavailable; circuit breaker open");
+            }
+        }
+        let mut attempt = 0;
+        loop {
+            let error = match call().await {
+                Ok(value) => {
+                    self.consecutive_failures.store(0, Ordering::Relaxed);
+                    *self.open_until.lock().unwrap() = None;
+                    return Ok(value);
+                }
+                Err(error) => error,
+            };
+            let Some(delay) = retry_delay(&error, attempt) else {
+                // Qdrant answered, so it is up; the request itself was wrong
+                self.consecutive_failures.store(0, Ordering::Relaxed);
+                self.failures.fetch_add(1, Ordering::Relaxed);
+                return Err(error.into());
+            };
+            attempt += 1;
+            if attempt >= self.max_attempts {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+                let failed = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+                if failed >= self.breaker_threshold {
+                    *self.open_until.lock().unwrap() = Some(Instant::now() + self.breaker_cooldown);
+                    tracing::warn!(
+                        "{failed} Qdrant calls failed in a row; pausing calls for {:?}",
+                        self.breaker_cooldown
+                    );
+                }
+                return Err(error.into());
+            }
+            self.retries.fetch_add(1, Ordering::Relaxed);
+            tracing::debug!("retrying Qdrant call in {delay:?}: {error}");
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    pub fn stats(&self) -> RetryStats {
+        RetryStats {
+            calls: self.calls.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            breaker_open: self
+                .open_until
+                .lock()
+                .unwrap()
+                .is_some_and(|until| Instant::now() < until),
+        }
+    }
+}
+
+/// How long to wait before trying again after `error`, if it is transient
+fn retry_delay(error: &QdrantError, attempt: usize) -> Option<Duration> {
+    match error {
+        QdrantError::ResourceExhaustedError {
+            retry_after_seconds,
+            ..
+        } => Some(Duration::from_secs(*retry_after_seconds).min(MAX_DELAY)),
+        QdrantError::ResponseError { status }
+            if matches!(
+                status.code(),
+                Code::Unavailable
+                    | Code::DeadlineExceeded
+                    | Code::ResourceExhausted
+                    | Code::Aborted
+                    | Code::Cancelled
+                    | Code::Unknown
+            ) =>
+        {
+            Some(backoff(attempt))
+        }
+        QdrantError::Io(_) => Some(backoff(attempt)),
+        _ => None,
+    }
+}
+
+fn backoff(attempt: usize) -> Duration {
+    let base = BASE_DELAY_MS << attempt.min(6);
+    let jitter = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|value| value.subsec_millis() as u64 % (base / 2 + 1))
+        .unwrap_or(0);
+    Duration::from_millis(base + jitter).min(MAX_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Status;
+
+    #[tokio::test]
+    async fn retries_transient_errors_and_opens_the_breaker() {
+        let retry = QdrantRetry::new(2, 2, Duration::from_secs(60));
+        let mut attempts = 0;
+        let value = retry
+            .call(|| {
+                attempts += 1;
+                let result = if attempts == 1 {
+                    Err(Status::unavailable("restarting").into())
+                } else {
+                    Ok(7)
+                };
+                async move { result }
+            })
+            .await
+            .unwrap();
+        assert_eq!((value, attempts), (7, 2));
+
+        let invalid = retry
+            .call(|| async { Err::<(), _>(Status::invalid_argument("bad filter").into()) })
+            .await;
+        assert!(invalid.is_err());
+        assert_eq!(retry.stats().retries, 1);
+
+        for _ in 0..2 {
+            let down = retry
+                .call(|| async { Err::<(), _>(Status::unavailable("down").into()) })
+                .await;
+            assert!(down.is_err());
+        }
+        let stats = retry.stats();
+        assert!(stats.breaker_open);
+        assert_eq!((stats.retries, stats.failures), (3, 3));
+
+        let mut called = false;
+        let rejected = retry
+            .call(|| {
+                called = true;
+                async { Ok(()) }
+            })
+            .await;
+        assert!(rejected.is_err() && !called);
+        assert_eq!(retry.stats().rejected, 1);
+    }
+}
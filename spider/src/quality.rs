@@ -0,0 +1,253 @@
+//! Content quality
+//!
+//! Pages are first checked for thin content: too few words, too little text
+//! for their markup, or mostly link text (tag clouds, login pages, empty
+//! category listings). Such pages are not indexed, though their links are
+//! still followed.
+//!
+//! Indexed chunks then get a quality score in their `score` field. A chunk of
+//! ordinary length from an ordinary host scores `0`. Configured domain boosts
+//! are added for matching hosts and their subdomains, and chunks shorter than
+//! a threshold lose up to a fixed penalty in proportion to how short they
+//! are. The search API adds the score to its ranking.
+
+use scraper::{Html, Selector};
+use shared_crawler_api::WebPageChunk;
+use std::env;
+use unicode_segmentation::UnicodeSegmentation;
+use url::Url;
+
+use crate::index::ContentBlock;
+
+const DEFAULT_MIN_CHUNK_CHARS: usize = 200;
+const DEFAULT_SHORT_CHUNK_PENALTY: f64 = 0.5;
+const DEFAULT_MIN_WORDS: usize = 30;
+const DEFAULT_MIN_TEXT_RATIO: f64 = 0.01;
+const DEFAULT_MAX_LINK_DENSITY: f64 = 0.8;
+
+/// Thin-content signals of one page
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageMetrics {
+    /// Words of extracted content
+    pub word_count: usize,
+    /// Extracted content bytes per byte of HTML
+    pub text_ratio: f64,
+    /// Share of the body's visible characters that are link text
+    pub link_density: f64,
+}
+
+impl PageMetrics {
+    pub fn measure(document: &Html, html_len: usize, blocks: &[ContentBlock]) -> Self {
+        let content_bytes = blocks.iter().map(|block| block.text.len()).sum::<usize>();
+        let visible_chars = |text: &mut dyn Iterator<Item = &str>| {
+            text.flat_map(str::chars)
+                .filter(|c| !c.is_whitespace())
+                .count()
+        };
+        let body = Selector::parse("body").unwrap();
+        let links = Selector::parse("body a[href]").unwrap();
+        let body_chars = document
+            .select(&body)
+            .map(|body| visible_chars(&mut body.text()))
+            .sum::<usize>();
+        let link_chars = document
+            .select(&links)
+            .map(|link| visible_chars(&mut link.text()))
+            .sum::<usize>();
+        Self {
+            word_count: blocks
+                .iter()
+                .map(|block| block.text.unicode_words().count())
+                .sum(),
+            text_ratio: content_bytes as f64 / html_len.max(1) as f64,
+            link_density: link_chars as f64 / body_chars.max(1) as f64,
+        }
+    }
+}
+
+/// Thresholds below which a page is not indexed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageQualityRules {
+    pub min_words: usize,
+    pub min_text_ratio: f64,
+    pub max_link_density: f64,
+}
+
+impl PageQualityRules {
+    /// `PAGE_MIN_WORDS`, `PAGE_MIN_TEXT_RATIO` and `PAGE_MAX_LINK_DENSITY`
+    pub fn from_env() -> Self {
+        let read = |key: &str, default: f64| {
+            env::var(key)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            min_words: env::var("PAGE_MIN_WORDS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_MIN_WORDS),
+            min_text_ratio: read("PAGE_MIN_TEXT_RATIO", DEFAULT_MIN_TEXT_RATIO),
+            max_link_density: read("PAGE_MAX_LINK_DENSITY", DEFAULT_MAX_LINK_DENSITY),
+        }
+    }
+
+    /// Why a page is too thin to index, if it is
+    pub fn rejects(&self, metrics: &PageMetrics) -> Option<String> {
+        if metrics.word_count < self.min_words {
+            Some(format!("{} words", metrics.word_count))
+        } else if metrics.text_ratio < self.min_text_ratio {
+            Some(format!("text-to-HTML ratio {:.3}", metrics.text_ratio))
+        } else if metrics.link_density > self.max_link_density {
+            Some(format!("link density {:.2}", metrics.link_density))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityRules {
+    /// `(domain, boost)`, most specific domain first
+    domain_boosts: Vec<(String, f64)>,
+    min_chunk_chars: usize,
+    short_chunk_penalty: f64,
+}
+
+impl QualityRules {
+    /// `QUALITY_DOMAIN_BOOSTS` (e.g. `wikipedia.org=0.5,example.net=-0.3`),
+    /// `QUALITY_MIN_CHUNK_CHARS` and `QUALITY_SHORT_CHUNK_PENALTY`
+    pub fn from_env() -> Self {
+        Self::new(
+            &env::var("QUALITY_DOMAIN_BOOSTS").unwrap_or_default(),
+            env::var("QUALITY_MIN_CHUNK_CHARS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_MIN_CHUNK_CHARS),
+            env::var("QUALITY_SHORT_CHUNK_PENALTY")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_SHORT_CHUNK_PENALTY),
+        )
+    }
+
+    /// Boosts that do not parse are ignored with a warning
+    pub fn new(domain_boosts: &str, min_chunk_chars: usize, short_chunk_penalty: f64) -> Self {
+        let mut domain_boosts = domain_boosts
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                let parsed = entry.split_once('=').and_then(|(domain, boost)| {
+                    let domain = domain.trim().trim_start_matches('.').to_ascii_lowercase();
+                    let boost = boost.trim().parse::<f64>().ok()?;
+                    (!domain.is_empty() && boost.is_finite()).then_some((domain, boost))
+                });
+                if parsed.is_none() {
+                    tracing::warn!("ignoring invalid QUALITY_DOMAIN_BOOSTS entry {entry:?}");
+                }
+                parsed
+            })
+            .collect::<Vec<_>>();
+        domain_boosts.sort_by_key(|(domain, _)| std::cmp::Reverse(domain.len()));
+        Self {
+            domain_boosts,
+            min_chunk_chars,
+            short_chunk_penalty,
+        }
+    }
+
+    pub fn score(&self, chunk: &WebPageChunk) -> f64 {
+        let host = Url::parse(&chunk.source_url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+            .unwrap_or_default();
+        let boost = self
+            .domain_boosts
+            .iter()
+            .find(|(domain, _)| {
+                host == *domain
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|prefix| prefix.ends_with('.'))
+            })
+            .map_or(0.0, |(_, boost)| *boost);
+        let chars = chunk.chunk_content.chars().count();
+        let shortfall = if chars < self.min_chunk_chars {
+            1.0 - chars as f64 / self.min_chunk_chars as f64
+        } else {
+            0.0
+        };
+        boost - self.short_chunk_penalty * shortfall
+    }
+
+    pub fn apply(&self, chunks: &mut [WebPageChunk]) {
+        for chunk in chunks {
+            chunk.score = self.score(chunk);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(url: &str, content: &str) -> WebPageChunk {
+        WebPageChunk::new(
+            content.to_string(),
+            None,
+            url.to_string(),
+            String::new(),
+            String::new(),
+            Vec::new(),
+            Vec::new(),
+            0.0,
+            0.0,
+            0,
+        )
+    }
+
+    #[test]
+    fn measures_and_rejects_thin_pages() {
+        let rules = PageQualityRules {
+            min_words: 5,
+            min_text_ratio: 0.01,
+            max_link_density: 0.5,
+        };
+        let measure = |html: &str| {
+            let document = Html::parse_document(html);
+            let blocks = crate::extractor_content::extract_content_blocks(&document);
+            PageMetrics::measure(&document, html.len(), &blocks)
+        };
+
+        let article = measure("<p>One two three four five six <a href='/x'>seven</a>.</p>");
+        assert_eq!(article.word_count, 7);
+        assert!(rules.rejects(&article).is_none());
+
+        let login = measure("<h1>Login</h1><p>Sign in</p><form><input name='user'></form>");
+        assert_eq!(rules.rejects(&login).as_deref(), Some("2 words"));
+
+        let tags = measure(
+            "<p><a href='/a'>alpha</a> <a href='/b'>beta</a> <a href='/c'>gamma</a> \
+             <a href='/d'>delta</a> <a href='/e'>epsilon</a> and more</p>",
+        );
+        assert!(tags.link_density > 0.5);
+        assert!(rules.rejects(&tags).unwrap().starts_with("link density"));
+    }
+
+    #[test]
+    fn boosts_domains_and_penalizes_short_chunks() {
+        let rules = QualityRules::new("wikipedia.org=0.5, de.wikipedia.org=0.2,bad,x=y", 100, 0.5);
+        let long = "a".repeat(100);
+
+        assert_eq!(rules.score(&chunk("https://example.com/", &long)), 0.0);
+        assert_eq!(rules.score(&chunk("https://en.wikipedia.org/", &long)), 0.5);
+        assert_eq!(rules.score(&chunk("https://de.wikipedia.org/", &long)), 0.2);
+        assert_eq!(rules.score(&chunk("https://notwikipedia.org/", &long)), 0.0);
+        assert_eq!(
+            rules.score(&chunk("https://example.com/", &"a".repeat(50))),
+            -0.25
+        );
+        assert_eq!(rules.score(&chunk("https://example.com/", "")), -0.5);
+    }
+}
@@ -0,0 +1,338 @@
+//! Full reindex into a fresh collection
+//!
+//! `POST /admin/reindex` rebuilds the shared or a tenant's collection with
+//! the current settings, for when chunking or the embedding model changed.
+//! Pages whose raw HTML is in the archive are extracted and chunked again;
+//! the others keep their stored chunks and are only embedded again. Pages
+//! are written to `<collection>_reindex_<unix time>` while searches keep
+//! using the old collection. Pages crawled in the meantime are copied again
+//! in a final pass, then a single Qdrant alias change switches the
+//! collection's name over to the new one and the old collection is deleted.
+//! Version numbers start over and superseded versions are not carried over,
+//! so a collection that keeps versions is only reindexed when the request
+//! sets `drop_versions`.
+//!
+//! With `tei_url`, the pages are embedded by another model instead, for
+//! switching models without a gap in search: the old vectors keep serving
+//! until every page has a new one. `max_pages_per_second` keeps the job from
+//! starving crawls of TEI and Qdrant capacity.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use shared_crawler_api::{problem::Problem, request_id};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::Instrument;
+use url::Url;
+
+use crate::{
+    archive::HtmlArchive,
+    index::{extract_page, ChunkOptions},
+    jobs::unix_now,
+    qdrant::{PageIndexer, StoredPage},
+    quality::QualityRules,
+};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReindexRequest {
+    /// Reindex this tenant's collection instead of the shared one
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// TEI of the embedding model to move to, instead of the one at `TEI_URL`
+    #[serde(default)]
+    pub tei_url: Option<String>,
+    #[serde(default)]
+    pub max_pages_per_second: Option<f64>,
+    /// Reindex even though the page history of a collection that keeps
+    /// versions is lost
+    #[serde(default)]
+    pub drop_versions: bool,
+}
+
+impl ReindexRequest {
+    pub fn validate(&self) -> Result<(), Problem> {
+        if let Some(tei_url) = &self.tei_url {
+            if !Url::parse(tei_url).is_ok_and(|url| matches!(url.scheme(), "http" | "https")) {
+                return Err(Problem::bad_request(
+                    "invalid_tei_url",
+                    "tei_url must be an http or https URL",
+                ));
+            }
+        }
+        if let Some(rate) = self.max_pages_per_second {
+            if !rate.is_finite() || rate <= 0.0 {
+                return Err(Problem::bad_request(
+                    "invalid_max_pages_per_second",
+                    "max_pages_per_second must be greater than 0",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReindexStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReindexProgress {
+    /// The name searches use
+    pub collection: String,
+    /// The collection being written, which serves `collection` once done
+    pub target: String,
+    pub status: ReindexStatus,
+    /// The model the pages are embedded with, when not the one at `TEI_URL`
+    pub tei_url: Option<String>,
+    /// Size of the new vectors, once the model was asked for it
+    pub dimensions: Option<usize>,
+    pub max_pages_per_second: Option<f64>,
+    pub pages_total: usize,
+    pub pages_done: usize,
+    /// Pages extracted and chunked again from their archived HTML
+    pub pages_rechunked: usize,
+    pub pages_failed: usize,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub error: Option<String>,
+}
+
+pub struct Reindexer {
+    indexer: Arc<PageIndexer>,
+    archive: Option<Arc<HtmlArchive>>,
+    /// The running or last reindex
+    progress: Mutex<Option<ReindexProgress>>,
+}
+
+impl Reindexer {
+    pub fn new(indexer: Arc<PageIndexer>, archive: Option<Arc<HtmlArchive>>) -> Self {
+        Self {
+            indexer,
+            archive,
+            progress: Mutex::new(None),
+        }
+    }
+
+    pub fn progress(&self) -> Option<ReindexProgress> {
+        self.progress.lock().unwrap().clone()
+    }
+
+    /// Start reindexing `collection` in the background, unless a reindex is
+    /// already running
+    pub fn start(
+        self: &Arc<Self>,
+        collection: String,
+        request: ReindexRequest,
+    ) -> Option<ReindexProgress> {
+        let started_at = unix_now();
+        let started = {
+            let mut progress = self.progress.lock().unwrap();
+            if progress
+                .as_ref()
+                .is_some_and(|progress| progress.status == ReindexStatus::Running)
+            {
+                return None;
+            }
+            let started = ReindexProgress {
+                target: target_name(&collection, started_at),
+                collection,
+                status: ReindexStatus::Running,
+                tei_url: request.tei_url,
+                dimensions: None,
+                max_pages_per_second: request.max_pages_per_second,
+                pages_total: 0,
+                pages_done: 0,
+                pages_rechunked: 0,
+                pages_failed: 0,
+                started_at,
+                finished_at: None,
+                error: None,
+            };
+            *progress = Some(started.clone());
+            started
+        };
+        let reindexer = self.clone();
+        let (collection, target) = (started.collection.clone(), started.target.clone());
+        let (tei_url, rate) = (started.tei_url.clone(), started.max_pages_per_second);
+        tokio::spawn(
+            request_id::inherit(async move {
+                let result = reindexer
+                    .run(&collection, &target, tei_url.as_deref(), rate, started_at)
+                    .await;
+                if let Err(error) = &result {
+                    tracing::warn!("reindex of {collection} failed: {error:#}");
+                }
+                reindexer.update(|progress| {
+                    progress.finished_at = Some(unix_now());
+                    match result {
+                        Ok(()) => progress.status = ReindexStatus::Completed,
+                        Err(error) => {
+                            progress.status = ReindexStatus::Failed;
+                            progress.error = Some(format!("{error:#}"));
+                        }
+                    }
+                });
+            })
+            .in_current_span(),
+        );
+        Some(started)
+    }
+
+    async fn run(
+        &self,
+        collection: &str,
+        target: &str,
+        tei_url: Option<&str>,
+        max_pages_per_second: Option<f64>,
+        started_at: i64,
+    ) -> Result<()> {
+        let copy = CopyJob {
+            collection,
+            target,
+            writer: match tei_url {
+                Some(tei_url) => Arc::new(self.indexer.with_embeddings(tei_url).await?),
+                None => self.indexer.clone(),
+            },
+            chunking: ChunkOptions::from_env(),
+            quality: QualityRules::from_env(),
+            interval: max_pages_per_second.map(|rate| Duration::from_secs_f64(1.0 / rate)),
+        };
+        self.update(|progress| progress.dimensions = Some(copy.writer.dimensions()));
+        copy.writer.ensure_target(target).await?;
+        let pages = self.indexer.indexed_pages(collection, None).await?;
+        self.update(|progress| progress.pages_total = pages.len());
+        self.copy_pages(&copy, pages).await;
+        // Pages crawled while the first pass ran
+        let recent = self
+            .indexer
+            .indexed_pages(collection, Some(started_at))
+            .await?;
+        self.update(|progress| progress.pages_total += recent.len());
+        self.copy_pages(&copy, recent).await;
+        let failed = self.progress().map_or(0, |progress| progress.pages_failed);
+        if failed > 0 {
+            anyhow::bail!(
+                "{failed} pages could not be reindexed; {target} is not searched and can be deleted"
+            );
+        }
+        self.indexer.replace_collection(collection, target).await
+    }
+
+    async fn copy_pages(&self, copy: &CopyJob<'_>, pages: BTreeMap<String, StoredPage>) {
+        for (url, page) in pages {
+            let page_started = Instant::now();
+            match self.copy_page(copy, &url, &page).await {
+                Ok(rechunked) => self.update(|progress| {
+                    progress.pages_done += 1;
+                    progress.pages_rechunked += usize::from(rechunked);
+                }),
+                Err(error) => {
+                    tracing::warn!("failed to reindex {url}: {error:#}");
+                    self.update(|progress| progress.pages_failed += 1);
+                }
+            }
+            if let Some(interval) = copy.interval {
+                tokio::time::sleep(interval.saturating_sub(page_started.elapsed())).await;
+            }
+        }
+    }
+
+    /// Write one page to `target`; `true` if it was chunked again from its
+    /// archived HTML
+    async fn copy_page(&self, copy: &CopyJob<'_>, url: &str, page: &StoredPage) -> Result<bool> {
+        let html = match (&self.archive, &page.archive_key) {
+            (Some(archive), Some(key)) => archive.load(key).await?,
+            _ => None,
+        };
+        let rechunked = html.is_some();
+        let mut chunks = match html {
+            Some(html) => {
+                let chunks = extract_page(&Url::parse(url)?, &html, &copy.chunking).chunks;
+                let mut chunks = copy
+                    .writer
+                    .fit_token_limit(chunks, copy.chunking.max_tokens)
+                    .await;
+                copy.quality.apply(&mut chunks);
+                chunks
+            }
+            None => self.indexer.page_chunks(copy.collection, url).await?,
+        };
+        for chunk in &mut chunks {
+            chunk.crawled_at = page.crawled_at;
+            chunk.unsafe_content = page.unsafe_content;
+            chunk.collection = page.collection.clone();
+        }
+        copy.writer
+            .index_page(copy.target, &chunks, page.archive_key.as_deref())
+            .await?;
+        Ok(rechunked)
+    }
+
+    fn update(&self, change: impl FnOnce(&mut ReindexProgress)) {
+        if let Some(progress) = self.progress.lock().unwrap().as_mut() {
+            change(progress);
+        }
+    }
+}
+
+/// What one reindex writes where
+struct CopyJob<'a> {
+    collection: &'a str,
+    target: &'a str,
+    /// Embeds and writes the new collection's pages
+    writer: Arc<PageIndexer>,
+    chunking: ChunkOptions,
+    quality: QualityRules,
+    /// Shortest time per page under `max_pages_per_second`
+    interval: Option<Duration>,
+}
+
+/// Tenant names have no `_`, so the name cannot be another tenant's
+fn target_name(collection: &str, now: i64) -> String {
+    format!("{collection}_reindex_{now}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_crawler_api::tenant;
+
+    #[test]
+    fn target_names_stay_out_of_the_tenant_namespace() {
+        let shared = tenant::collection_name(None).unwrap();
+        assert_eq!(target_name(&shared, 17), "web_pages_reindex_17");
+        let docs = tenant::collection_name(Some("docs")).unwrap();
+        assert_eq!(target_name(&docs, 17), "web_pages__docs_reindex_17");
+        assert!(tenant::collection_name(Some("docs_reindex_17")).is_err());
+    }
+
+    #[test]
+    fn validates_model_migration_settings() {
+        let request = |tei_url: Option<&str>, rate: Option<f64>| ReindexRequest {
+            tenant: None,
+            tei_url: tei_url.map(str::to_string),
+            max_pages_per_second: rate,
+            drop_versions: false,
+        };
+        assert!(request(Some("http://tei-e5-large"), Some(2.5))
+            .validate()
+            .is_ok());
+        assert_eq!(
+            request(Some("tei:8080"), None).validate().unwrap_err().code,
+            "invalid_tei_url"
+        );
+        for rate in [0.0, -1.0, f64::NAN] {
+            assert_eq!(
+                request(None, Some(rate)).validate().unwrap_err().code,
+                "invalid_max_pages_per_second"
+            );
+        }
+    }
+}
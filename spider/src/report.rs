@@ -0,0 +1,305 @@
+//! Per-job crawl report
+//!
+//! Runners record fetch outcomes while a job runs; once it finishes the
+//! figures are combined with the job summary and served by
+//! `GET /crawl/{id}/report`, as JSON or as a standalone HTML page.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::jobs::{JobStatus, JobSummary};
+
+const TOP_ERRORS: usize = 10;
+const MAX_ROBOTS_URLS: usize = 100;
+
+/// Why a page failed, counted in `GET /status` and job reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FailureKind {
+    Dns,
+    Tls,
+    Timeout,
+    /// Refused, reset or otherwise broken connections
+    Connection,
+    ClientError,
+    ServerError,
+    Redirect,
+    /// Not HTML or over the body size limit
+    Content,
+    /// Invalid URLs and undecodable bodies
+    Parse,
+    Browser,
+    /// Embedding or writing to Qdrant
+    Index,
+    Other,
+}
+
+impl FailureKind {
+    pub const ALL: [Self; 12] = [
+        Self::Dns,
+        Self::Tls,
+        Self::Timeout,
+        Self::Connection,
+        Self::ClientError,
+        Self::ServerError,
+        Self::Redirect,
+        Self::Content,
+        Self::Parse,
+        Self::Browser,
+        Self::Index,
+        Self::Other,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Dns => "dns",
+            Self::Tls => "tls",
+            Self::Timeout => "timeout",
+            Self::Connection => "connection",
+            Self::ClientError => "http_4xx",
+            Self::ServerError => "http_5xx",
+            Self::Redirect => "redirect",
+            Self::Content => "content",
+            Self::Parse => "parse",
+            Self::Browser => "browser",
+            Self::Index => "index",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// What indexing a page changed in the search index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexOutcome {
+    New,
+    Updated,
+    Unchanged,
+    /// No content chunks, nothing was written
+    Empty,
+}
+
+/// Figures collected while a job runs
+#[derive(Debug, Default)]
+pub struct ReportRecorder {
+    pages_by_status: BTreeMap<String, usize>,
+    errors: HashMap<String, usize>,
+    failures_by_kind: BTreeMap<&'static str, usize>,
+    skipped_robots: Vec<String>,
+    skipped_robots_total: usize,
+    new_pages: usize,
+    updated_pages: usize,
+    unchanged_pages: usize,
+    bytes_fetched: u64,
+}
+
+impl ReportRecorder {
+    /// A response, keyed by HTTP status or `browser` for rendered pages
+    pub fn fetched(&mut self, status: impl ToString, bytes: usize) {
+        *self.pages_by_status.entry(status.to_string()).or_insert(0) += 1;
+        self.bytes_fetched += bytes as u64;
+    }
+
+    pub fn error(&mut self, message: &str) {
+        *self.errors.entry(message.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn failed(&mut self, kind: FailureKind) {
+        *self.failures_by_kind.entry(kind.as_str()).or_insert(0) += 1;
+    }
+
+    pub fn skipped_robots(&mut self, url: &str) {
+        self.skipped_robots_total += 1;
+        if self.skipped_robots.len() < MAX_ROBOTS_URLS {
+            self.skipped_robots.push(url.to_string());
+        }
+    }
+
+    pub fn indexed(&mut self, outcome: IndexOutcome) {
+        match outcome {
+            IndexOutcome::New => self.new_pages += 1,
+            IndexOutcome::Updated => self.updated_pages += 1,
+            IndexOutcome::Unchanged => self.unchanged_pages += 1,
+            IndexOutcome::Empty => {}
+        }
+    }
+
+    pub fn report(&self, summary: JobSummary) -> CrawlReport {
+        let mut top_errors = self
+            .errors
+            .iter()
+            .map(|(message, count)| ErrorCount {
+                message: message.clone(),
+                count: *count,
+            })
+            .collect::<Vec<_>>();
+        top_errors.sort_by(|a, b| b.count.cmp(&a.count).then(a.message.cmp(&b.message)));
+        top_errors.truncate(TOP_ERRORS);
+        CrawlReport {
+            summary,
+            pages_by_status: self.pages_by_status.clone(),
+            top_errors,
+            failures_by_kind: self.failures_by_kind.clone(),
+            skipped_robots: self.skipped_robots.clone(),
+            skipped_robots_total: self.skipped_robots_total,
+            new_pages: self.new_pages,
+            updated_pages: self.updated_pages,
+            unchanged_pages: self.unchanged_pages,
+            bytes_fetched: self.bytes_fetched,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorCount {
+    pub message: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CrawlReport {
+    #[serde(flatten)]
+    pub summary: JobSummary,
+    pub pages_by_status: BTreeMap<String, usize>,
+    pub top_errors: Vec<ErrorCount>,
+    /// Failed pages per `FailureKind`
+    pub failures_by_kind: BTreeMap<&'static str, usize>,
+    /// First 100 URLs disallowed by robots.txt
+    pub skipped_robots: Vec<String>,
+    pub skipped_robots_total: usize,
+    pub new_pages: usize,
+    pub updated_pages: usize,
+    pub unchanged_pages: usize,
+    pub bytes_fetched: u64,
+}
+
+impl CrawlReport {
+    pub fn to_html(&self) -> String {
+        let summary = &self.summary;
+        let mut html = format!(
+            "<!doctype html>\n<html><head><meta charset=\"utf-8\"><title>Crawl report {id}</title>\
+             <style>body{{font-family:sans-serif;margin:2rem}}table{{border-collapse:collapse}}\
+             td,th{{border:1px solid #ccc;padding:.25rem .5rem;text-align:left}}</style></head><body>\n\
+             <h1>Crawl report</h1>\n<table>\n",
+            id = escape(&summary.job_id),
+        );
+        let duration = format!("{:.1} s", summary.duration_ms as f64 / 1000.0);
+        for (label, value) in [
+            ("Job", summary.job_id.clone()),
+            ("Seed", summary.url.clone()),
+            ("Status", status_name(summary.status)),
+            ("Duration", duration),
+            ("Bytes fetched", self.bytes_fetched.to_string()),
+            ("Pages crawled", summary.progress.pages_crawled.to_string()),
+            ("New pages", self.new_pages.to_string()),
+            ("Updated pages", self.updated_pages.to_string()),
+            ("Unchanged pages", self.unchanged_pages.to_string()),
+        ] {
+            html.push_str(&format!(
+                "<tr><th>{}</th><td>{}</td></tr>\n",
+                escape(label),
+                escape(&value)
+            ));
+        }
+        html.push_str("</table>\n<h2>Pages by status</h2>\n<table>\n");
+        html.push_str(&row(&["Status", "Pages"], "th"));
+        for (status, count) in &self.pages_by_status {
+            html.push_str(&row(&[status, &count.to_string()], "td"));
+        }
+        html.push_str("</table>\n<h2>Failures by kind</h2>\n<table>\n");
+        html.push_str(&row(&["Kind", "Pages"], "th"));
+        for (kind, count) in &self.failures_by_kind {
+            html.push_str(&row(&[kind, &count.to_string()], "td"));
+        }
+        html.push_str("</table>\n<h2>Top errors</h2>\n<table>\n");
+        html.push_str(&row(&["Error", "Count"], "th"));
+        for error in &self.top_errors {
+            html.push_str(&row(&[&error.message, &error.count.to_string()], "td"));
+        }
+        html.push_str(&format!(
+            "</table>\n<h2>Skipped by robots.txt ({})</h2>\n<ul>\n",
+            self.skipped_robots_total
+        ));
+        for url in &self.skipped_robots {
+            html.push_str(&format!("<li>{}</li>\n", escape(url)));
+        }
+        html.push_str("</ul>\n</body></html>\n");
+        html
+    }
+}
+
+fn status_name(status: JobStatus) -> String {
+    serde_json::to_value(status)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn row(cells: &[&str], tag: &str) -> String {
+    let cells = cells
+        .iter()
+        .map(|cell| format!("<{tag}>{}</{tag}>", escape(cell)))
+        .collect::<String>();
+    format!("<tr>{cells}</tr>\n")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::JobProgress;
+
+    fn summary() -> JobSummary {
+        JobSummary {
+            job_id: "job".to_string(),
+            url: "https://example.com/".to_string(),
+            status: JobStatus::Completed,
+            started_at: Some(0),
+            finished_at: Some(2),
+            duration_ms: 2_000,
+            progress: JobProgress::default(),
+            errors: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn aggregates_statuses_and_ranks_errors() {
+        let mut recorder = ReportRecorder::default();
+        recorder.fetched(200, 10);
+        recorder.fetched(200, 5);
+        recorder.fetched("browser", 1);
+        recorder.error("HTTP 404 Not Found");
+        recorder.error("timed out");
+        recorder.error("timed out");
+        recorder.indexed(IndexOutcome::New);
+        recorder.indexed(IndexOutcome::Empty);
+        recorder.failed(FailureKind::Timeout);
+        recorder.failed(FailureKind::Timeout);
+        recorder.failed(FailureKind::ClientError);
+
+        let report = recorder.report(summary());
+        assert_eq!(report.pages_by_status["200"], 2);
+        assert_eq!(report.pages_by_status["browser"], 1);
+        assert_eq!(report.bytes_fetched, 16);
+        assert_eq!(report.top_errors[0].message, "timed out");
+        assert_eq!(report.top_errors[0].count, 2);
+        assert_eq!((report.new_pages, report.updated_pages), (1, 0));
+        assert_eq!(
+            report.failures_by_kind,
+            BTreeMap::from([("http_4xx", 1), ("timeout", 2)])
+        );
+    }
+
+    #[test]
+    fn renders_escaped_html() {
+        let mut recorder = ReportRecorder::default();
+        recorder.skipped_robots("https://example.com/?a=<b>");
+        let html = recorder.report(summary()).to_html();
+        assert!(html.contains("https://example.com/?a=&lt;b&gt;"));
+        assert!(html.contains("<th>Status</th><td>completed</td>"));
+    }
+}
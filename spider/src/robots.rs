@@ -1,7 +1,16 @@
+//! robots.txt cache
+//!
+//! Entries live for `ROBOTS_CACHE_TTL_SECS` (default 24 h) after a successful
+//! fetch and `ROBOTS_FAILURE_TTL_SECS` (default 5 min) after a failure. Once
+//! three quarters of an entry's lifetime has passed, the next lookup still
+//! uses it but refreshes it in the background.
+
 use robotstxt::{parse_robotstxt, DefaultMatcher, RobotsParseHandler};
+use shared_crawler_api::caches::{CacheCounters, CacheStats};
 use std::{
-    collections::HashMap,
-    sync::Arc,
+    collections::{HashMap, HashSet},
+    env,
+    sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 use tokio::sync::RwLock;
@@ -13,6 +22,37 @@ const ROBOTS_MAX_BYTES: usize = 512 * 1024;
 const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
 const FAILURE_TTL: Duration = Duration::from_secs(5 * 60);
 
+#[derive(Debug, Clone, Copy)]
+pub struct RobotsTtl {
+    pub success: Duration,
+    pub failure: Duration,
+}
+
+impl Default for RobotsTtl {
+    fn default() -> Self {
+        Self {
+            success: CACHE_TTL,
+            failure: FAILURE_TTL,
+        }
+    }
+}
+
+impl RobotsTtl {
+    pub fn from_env() -> Self {
+        let read = |key: &str, default: Duration| {
+            env::var(key)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default)
+        };
+        Self {
+            success: read("ROBOTS_CACHE_TTL_SECS", CACHE_TTL),
+            failure: read("ROBOTS_FAILURE_TTL_SECS", FAILURE_TTL),
+        }
+    }
+}
+
 #[derive(Clone)]
 struct CacheEntry {
     body: Option<String>,
@@ -27,6 +67,10 @@ impl CacheEntry {
         self.fetched_at.elapsed() < self.ttl
     }
 
+    fn due_for_refresh(&self) -> bool {
+        self.fetched_at.elapsed() >= self.ttl / 4 * 3
+    }
+
     fn allowed(&self, product_token: &str, url: &Url) -> bool {
         match &self.body {
             Some(body) => DefaultMatcher::default().one_agent_allowed_by_robots(
@@ -44,22 +88,62 @@ pub struct RobotsPolicy {
     pub sitemaps: Vec<Url>,
 }
 
+/// The robots.txt product token of a User-Agent, e.g. `ExampleBot` for
+/// `ExampleBot/2.1 (+https://example.com/bot)`
+pub fn product_token_of(user_agent: &str) -> &str {
+    user_agent
+        .split(|c: char| c == '/' || c.is_whitespace())
+        .next()
+        .unwrap_or_default()
+}
+
 pub struct RobotsCache {
     cache: RwLock<HashMap<String, CacheEntry>>,
-    visitor: Arc<WebVisitorImpl>,
-    product_token: String,
+    refreshing: Mutex<HashSet<String>>,
+    ttl: RobotsTtl,
+    lookups: CacheCounters,
 }
 
 impl RobotsCache {
-    pub fn new(visitor: Arc<WebVisitorImpl>, product_token: String) -> Self {
+    pub fn new(ttl: RobotsTtl) -> Self {
         Self {
             cache: RwLock::new(HashMap::new()),
-            visitor,
-            product_token,
+            refreshing: Mutex::new(HashSet::new()),
+            ttl,
+            lookups: CacheCounters::default(),
+        }
+    }
+
+    pub async fn stats(&self) -> CacheStats {
+        self.lookups.stats(self.cache.read().await.len())
+    }
+
+    /// Forget cached robots.txt files, for the given origins or all of them;
+    /// returns the number of entries removed
+    pub async fn flush(&self, origins: Option<&[String]>) -> usize {
+        let mut cache = self.cache.write().await;
+        match origins {
+            Some(origins) => origins
+                .iter()
+                .filter(|origin| cache.remove(origin.trim_end_matches('/')).is_some())
+                .count(),
+            None => {
+                let flushed = cache.len();
+                cache.clear();
+                self.lookups.reset();
+                flushed
+            }
         }
     }
 
-    pub async fn policy(&self, url: &Url) -> RobotsPolicy {
+    /// Whether `product_token` may fetch `url`; robots.txt is fetched with
+    /// `visitor`, so with the same User-Agent as the crawl itself
+    pub async fn policy(
+        self: &Arc<Self>,
+        url: &Url,
+        visitor: &Arc<WebVisitorImpl>,
+        product_token: &str,
+    ) -> RobotsPolicy {
         let Some(key) = origin(url) else {
             return RobotsPolicy {
                 allowed: false,
@@ -73,31 +157,63 @@ impl RobotsCache {
             .get(&key)
             .filter(|entry| entry.fresh())
         {
+            self.lookups.record(true);
+            if entry.due_for_refresh() {
+                self.refresh_in_background(&key, visitor);
+            }
             return RobotsPolicy {
-                allowed: entry.allowed(&self.product_token, url),
+                allowed: entry.allowed(product_token, url),
                 sitemaps: entry.sitemaps.clone(),
             };
         }
 
-        let stale = self.cache.read().await.get(&key).cloned();
+        self.lookups.record(false);
+        let entry = self.fetch(&key, visitor).await;
+        let allowed = entry.allowed(product_token, url);
+        let sitemaps = entry.sitemaps.clone();
+        self.cache.write().await.insert(key, entry);
+        RobotsPolicy { allowed, sitemaps }
+    }
+
+    fn refresh_in_background(self: &Arc<Self>, key: &str, visitor: &Arc<WebVisitorImpl>) {
+        if !self.refreshing.lock().unwrap().insert(key.to_string()) {
+            return;
+        }
+        let cache = self.clone();
+        let visitor = visitor.clone();
+        let key = key.to_string();
+        tokio::spawn(async move {
+            let entry = cache.fetch(&key, &visitor).await;
+            cache.cache.write().await.insert(key.clone(), entry);
+            cache.refreshing.lock().unwrap().remove(&key);
+        });
+    }
+
+    async fn fetch(&self, key: &str, visitor: &WebVisitorImpl) -> CacheEntry {
+        let stale = self.cache.read().await.get(key).cloned();
         let robots_url = format!("{key}/robots.txt");
-        let entry = match self
-            .visitor
+        match visitor
             .fetch_resource(&robots_url, ROBOTS_MAX_BYTES, 5)
             .await
         {
-            Ok(result) => Self::entry_from_response(result, stale),
-            Err(_) => stale
-                .filter(|entry| entry.body.is_some())
-                .unwrap_or_else(Self::disallow),
-        };
-        let allowed = entry.allowed(&self.product_token, url);
-        let sitemaps = entry.sitemaps.clone();
-        self.cache.write().await.insert(key, entry);
-        RobotsPolicy { allowed, sitemaps }
+            Ok(result) => self.entry_from_response(result, stale),
+            Err(_) => self.fallback(stale),
+        }
+    }
+
+    /// A previously fetched file outlives transient failures; otherwise disallow for a while
+    fn fallback(&self, stale: Option<CacheEntry>) -> CacheEntry {
+        match stale.filter(|entry| entry.body.is_some()) {
+            Some(entry) => CacheEntry {
+                fetched_at: Instant::now(),
+                ttl: self.ttl.failure,
+                ..entry
+            },
+            None => self.disallow(),
+        }
     }
 
-    fn entry_from_response(result: FetchResult, stale: Option<CacheEntry>) -> CacheEntry {
+    fn entry_from_response(&self, result: FetchResult, stale: Option<CacheEntry>) -> CacheEntry {
         if result.status.is_success() {
             let body = String::from_utf8_lossy(&result.body).into_owned();
             let sitemaps = extract_sitemaps(&body);
@@ -106,7 +222,7 @@ impl RobotsCache {
                 allow_all: false,
                 sitemaps,
                 fetched_at: Instant::now(),
-                ttl: CACHE_TTL,
+                ttl: self.ttl.success,
             }
         } else if result.status.is_client_error() {
             CacheEntry {
@@ -114,22 +230,20 @@ impl RobotsCache {
                 allow_all: true,
                 sitemaps: Vec::new(),
                 fetched_at: Instant::now(),
-                ttl: CACHE_TTL,
+                ttl: self.ttl.success,
             }
         } else {
-            stale
-                .filter(|entry| entry.body.is_some())
-                .unwrap_or_else(Self::disallow)
+            self.fallback(stale)
         }
     }
 
-    fn disallow() -> CacheEntry {
+    fn disallow(&self) -> CacheEntry {
         CacheEntry {
             body: None,
             allow_all: false,
             sitemaps: Vec::new(),
             fetched_at: Instant::now(),
-            ttl: FAILURE_TTL,
+            ttl: self.ttl.failure,
         }
     }
 }
@@ -180,15 +294,55 @@ mod tests {
         assert_eq!(entry.sitemaps.len(), 1);
     }
 
+    fn cache() -> RobotsCache {
+        RobotsCache::new(RobotsTtl::default())
+    }
+
+    #[test]
+    fn derives_product_token_from_user_agent() {
+        assert_eq!(
+            product_token_of("ExampleBot/2.1 (+https://example.com/bot)"),
+            "ExampleBot"
+        );
+        assert_eq!(product_token_of("ExampleBot"), "ExampleBot");
+    }
+
     #[test]
     fn cache_expiration_and_error_policy() {
+        let cache = cache();
         let expired = CacheEntry {
             fetched_at: Instant::now() - CACHE_TTL,
             ttl: CACHE_TTL,
-            ..RobotsCache::disallow()
+            ..cache.disallow()
         };
         assert!(!expired.fresh());
-        assert!(!RobotsCache::disallow()
+        assert!(!cache
+            .disallow()
             .allowed("TestBot", &Url::parse("https://example.com/").unwrap()));
+
+        let aging = CacheEntry {
+            fetched_at: Instant::now() - CACHE_TTL / 5 * 4,
+            ttl: CACHE_TTL,
+            ..cache.disallow()
+        };
+        assert!(aging.fresh() && aging.due_for_refresh());
+        assert_eq!(cache.disallow().ttl, FAILURE_TTL);
+    }
+
+    #[tokio::test]
+    async fn flushes_selected_or_all_origins() {
+        let cache = cache();
+        {
+            let mut entries = cache.cache.write().await;
+            for origin in ["https://a.example", "https://b.example"] {
+                entries.insert(origin.to_string(), cache.disallow());
+            }
+        }
+        assert_eq!(
+            cache.flush(Some(&["https://a.example/".to_string()])).await,
+            1
+        );
+        assert_eq!(cache.flush(None).await, 1);
+        assert!(cache.cache.read().await.is_empty());
     }
 }
@@ -0,0 +1,204 @@
+//! Seed lists: one crawl job per URL from an uploaded or server-side text file
+//!
+//! Lists are newline separated; blank lines and `#` comments are ignored.
+//! Server-side lists are only read from `SEED_LIST_DIR`. Every job gets the
+//! same settings, a `CrawlTemplate` taken from the query string or from a JSON
+//! body that lists the seeds itself.
+
+use actix_web::web;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use shared_crawler_api::problem::Problem;
+use std::{
+    collections::HashSet,
+    env,
+    path::{Path, PathBuf},
+};
+use url::form_urlencoded;
+
+use crate::CrawlRequest;
+
+pub const MAX_SEEDS: usize = 10_000;
+/// Upload limit for `POST /crawl/seeds` bodies
+pub const MAX_UPLOAD_BYTES: usize = 4 * 1024 * 1024;
+
+/// Query parameters of `POST /crawl/seeds` besides the job settings
+#[derive(Debug, Deserialize)]
+pub struct SeedListQuery {
+    /// File name inside `SEED_LIST_DIR`, used when no list is uploaded
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+/// JSON body of `POST /crawl/seeds`: the seeds and every `CrawlRequest`
+/// field but `url`
+#[derive(Debug, Deserialize)]
+pub struct SeedListBody {
+    #[serde(default)]
+    pub urls: Vec<String>,
+    #[serde(flatten)]
+    pub crawl: Map<String, Value>,
+}
+
+/// Settings shared by the jobs of a seed list or discovery crawl: a
+/// `CrawlRequest` whose `url` each job replaces
+#[derive(Debug, Clone)]
+pub struct CrawlTemplate(CrawlRequest);
+
+impl CrawlTemplate {
+    /// From the fields of a JSON `CrawlRequest`; a `url` among them is ignored
+    pub fn from_json(mut fields: Map<String, Value>) -> Result<Self, Problem> {
+        fields.insert("url".to_string(), Value::String(String::new()));
+        serde_json::from_value(Value::Object(fields))
+            .map(Self)
+            .map_err(|error| Problem::bad_request("invalid_body", error.to_string()))
+    }
+
+    /// From query parameters named like the `CrawlRequest` fields; lists and
+    /// maps such as `headers` and `must_contain` need a JSON body
+    pub fn from_query(query: &str) -> Result<Self, Problem> {
+        let query = form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(form_urlencoded::parse(query.as_bytes()).filter(|(key, _)| key != "url"))
+            .append_pair("url", "")
+            .finish();
+        web::Query::<CrawlRequest>::from_query(&query)
+            .map(|request| Self(request.into_inner()))
+            .map_err(|error| Problem::bad_request("invalid_query_parameters", error.to_string()))
+    }
+
+    pub fn crawl_request(&self, url: String) -> CrawlRequest {
+        CrawlRequest {
+            url,
+            ..self.0.clone()
+        }
+    }
+}
+
+pub fn parse_seed_list(text: &str) -> Result<Vec<String>, Problem> {
+    let mut seen = HashSet::new();
+    let seeds = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter(|line| seen.insert(*line))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if seeds.is_empty() {
+        return Err(Problem::bad_request(
+            "empty_seed_list",
+            "the seed list contains no URLs",
+        ));
+    }
+    if seeds.len() > MAX_SEEDS {
+        return Err(Problem::bad_request(
+            "too_many_seeds",
+            format!("a seed list may contain at most {MAX_SEEDS} URLs"),
+        ));
+    }
+    Ok(seeds)
+}
+
+/// Read a seed list from `SEED_LIST_DIR`, refusing paths that escape it
+pub async fn read_seed_file(path: &str) -> Result<String, Problem> {
+    let Some(dir) = env::var("SEED_LIST_DIR")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+    else {
+        return Err(Problem::bad_request(
+            "seed_files_disabled",
+            "SEED_LIST_DIR is not configured; upload the list instead",
+        ));
+    };
+    let file = resolve(Path::new(dir.trim()), path).await?;
+    tokio::fs::read_to_string(&file)
+        .await
+        .map_err(|error| Problem::bad_request("seed_file_unreadable", error.to_string()))
+}
+
+async fn resolve(dir: &Path, path: &str) -> Result<PathBuf, Problem> {
+    let not_found = || Problem::not_found("seed_file_not_found", format!("no seed list {path:?}"));
+    let dir = tokio::fs::canonicalize(dir)
+        .await
+        .map_err(|_| not_found())?;
+    let file = tokio::fs::canonicalize(dir.join(path))
+        .await
+        .map_err(|_| not_found())?;
+    if !file.starts_with(&dir) {
+        return Err(not_found());
+    }
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CrawlMode;
+
+    #[test]
+    fn parses_lines_skipping_comments_and_duplicates() {
+        let seeds = parse_seed_list(
+            "# news\nhttps://example.com\n\n  https://example.org  \r\nhttps://example.com\n",
+        )
+        .unwrap();
+        assert_eq!(seeds, ["https://example.com", "https://example.org"]);
+        assert_eq!(
+            parse_seed_list("# nothing\n\n").unwrap_err().code,
+            "empty_seed_list"
+        );
+    }
+
+    #[test]
+    fn templates_carry_every_crawl_setting() {
+        let template = CrawlTemplate::from_query(
+            "max_pages=5&mode=linkcheck&request_timeout_secs=3&focus_query=rust&url=x&path=a.txt",
+        )
+        .unwrap();
+        let request = template.crawl_request("https://example.com".to_string());
+        assert_eq!(request.url, "https://example.com");
+        assert_eq!(request.max_pages, 5);
+        assert_eq!(request.mode, CrawlMode::Linkcheck);
+        assert_eq!(request.request_timeout_secs, Some(3));
+        assert_eq!(request.focus_query.as_deref(), Some("rust"));
+        assert_eq!(
+            CrawlTemplate::from_query("max_pages=many")
+                .unwrap_err()
+                .code,
+            "invalid_query_parameters"
+        );
+
+        let body: SeedListBody = serde_json::from_value(serde_json::json!({
+            "urls": ["https://example.com"],
+            "max_pages": 5,
+            "headers": {"Authorization": "Bearer token"},
+            "must_contain": ["rust"],
+            "shared_frontier": "docs",
+        }))
+        .unwrap();
+        let request = CrawlTemplate::from_json(body.crawl)
+            .unwrap()
+            .crawl_request(body.urls[0].clone());
+        assert_eq!(request.headers["Authorization"], "Bearer token");
+        assert_eq!(request.must_contain, ["rust"]);
+        assert_eq!(request.shared_frontier.as_deref(), Some("docs"));
+        assert_eq!(
+            CrawlTemplate::from_json(Map::new()).unwrap_err().code,
+            "invalid_body"
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_paths_outside_the_seed_dir() {
+        let dir = env::temp_dir().join(format!("seed-list-{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        tokio::fs::write(dir.join("sites.txt"), "https://example.com\n")
+            .await
+            .unwrap();
+
+        assert!(resolve(&dir, "sites.txt").await.is_ok());
+        assert_eq!(
+            resolve(&dir, "../../etc/passwd").await.unwrap_err().code,
+            "seed_file_not_found"
+        );
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
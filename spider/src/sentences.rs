@@ -0,0 +1,69 @@
+//! Sentence segmentation
+//!
+//! Unicode sentence boundaries (UAX #29) already keep "3.14" and "e.g. this"
+//! together and split CJK text at `。！？`; on top of that, a period after a
+//! known abbreviation or a single-letter initial does not end a sentence.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Lowercase, without the final period
+const ABBREVIATIONS: &[&str] = &[
+    "approx", "bzw", "ca", "cf", "co", "corp", "d.h", "dept", "dr", "e.g", "etc", "fig", "i.e",
+    "inc", "jr", "ltd", "mr", "mrs", "ms", "no", "nr", "p", "pp", "prof", "sr", "st", "usw", "vgl",
+    "vol", "vs", "z.b",
+];
+
+/// The sentences of a text in order, each with its trailing whitespace
+pub fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    for (offset, sentence) in text.split_sentence_bound_indices() {
+        let end = offset + sentence.len();
+        if !ends_with_abbreviation(sentence) || end == text.len() {
+            sentences.push(&text[start..end]);
+            start = end;
+        }
+    }
+    sentences
+}
+
+fn ends_with_abbreviation(sentence: &str) -> bool {
+    let Some(word) = sentence
+        .trim_end()
+        .strip_suffix('.')
+        .and_then(|rest| rest.split_whitespace().last())
+    else {
+        return false;
+    };
+    let word = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+    let mut chars = word.chars();
+    let initial = matches!((chars.next(), chars.next()), (Some(c), None) if c.is_uppercase());
+    initial || ABBREVIATIONS.contains(&word.to_lowercase().as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_abbreviations_and_decimals_together() {
+        assert_eq!(
+            split_sentences(
+                "Pi is about 3.14. Ask Dr. Smith, e.g. by mail. J. R. Tolkien wrote it."
+            ),
+            [
+                "Pi is about 3.14. ",
+                "Ask Dr. Smith, e.g. by mail. ",
+                "J. R. Tolkien wrote it."
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_cjk_sentences() {
+        assert_eq!(
+            split_sentences("今天天气很好。我们去公园吧！好的"),
+            ["今天天气很好。", "我们去公园吧！", "好的"]
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! SEO audit of crawled pages
+//!
+//! Every page an index crawl fetches is checked for the basics search
+//! engines look at: title length, a meta description, exactly one `<h1>` and
+//! a single, valid canonical link on the page's own site. The latest audit
+//! of each URL is kept in the state database, and `GET /reports/seo?domain=`
+//! lists the pages with findings. Duplicate titles are found across the
+//! domain's audited pages when the report is built.
+
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use shared_crawler_api::csv::CsvWriter;
+use std::collections::{BTreeMap, HashMap};
+use url::Url;
+
+use crate::extractor::extract_title;
+
+/// Titles outside this range are cut off or say too little in results
+const MIN_TITLE_CHARS: usize = 30;
+const MAX_TITLE_CHARS: usize = 60;
+
+pub const DUPLICATE_TITLE: &str = "duplicate_title";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeoAudit {
+    pub title: String,
+    pub title_chars: usize,
+    pub has_description: bool,
+    pub h1_count: usize,
+    /// The resolved canonical URL, if the page names exactly one
+    pub canonical: Option<String>,
+    pub findings: Vec<String>,
+}
+
+impl SeoAudit {
+    pub fn measure(document: &Html, url: &Url) -> Self {
+        let description = Selector::parse("meta[name='description']").unwrap();
+        let h1 = Selector::parse("h1").unwrap();
+        let canonical = Selector::parse("link[rel~='canonical']").unwrap();
+
+        let title = extract_title(document);
+        let title_chars = title.chars().count();
+        let has_description = document.select(&description).any(|meta| {
+            meta.value()
+                .attr("content")
+                .is_some_and(|content| !content.trim().is_empty())
+        });
+        let h1_count = document.select(&h1).count();
+        let canonicals = document
+            .select(&canonical)
+            .map(|link| link.value().attr("href").unwrap_or_default())
+            .collect::<Vec<_>>();
+
+        let mut findings = Vec::new();
+        match title_chars {
+            0 => findings.push("title_missing"),
+            chars if chars < MIN_TITLE_CHARS => findings.push("title_too_short"),
+            chars if chars > MAX_TITLE_CHARS => findings.push("title_too_long"),
+            _ => {}
+        }
+        if !has_description {
+            findings.push("description_missing");
+        }
+        match h1_count {
+            0 => findings.push("h1_missing"),
+            1 => {}
+            _ => findings.push("h1_multiple"),
+        }
+        let canonical = match canonicals.as_slice() {
+            [] => None,
+            [href] => match url.join(href.trim()) {
+                Ok(canonical) if !href.trim().is_empty() => {
+                    if canonical.host_str() != url.host_str() {
+                        findings.push("canonical_other_host");
+                    }
+                    Some(canonical.to_string())
+                }
+                _ => {
+                    findings.push("canonical_invalid");
+                    None
+                }
+            },
+            _ => {
+                findings.push("canonical_multiple");
+                None
+            }
+        };
+
+        Self {
+            title,
+            title_chars,
+            has_description,
+            h1_count,
+            canonical,
+            findings: findings.into_iter().map(str::to_string).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SeoPage {
+    pub url: String,
+    pub audited_at: i64,
+    #[serde(flatten)]
+    pub audit: SeoAudit,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SeoReport {
+    pub domain: String,
+    pub pages_audited: usize,
+    pub pages_with_findings: usize,
+    /// Pages per finding
+    pub findings: BTreeMap<String, usize>,
+    /// Pages with findings, by URL, up to the requested limit
+    pub pages: Vec<SeoPage>,
+}
+
+impl SeoReport {
+    /// Report over the latest audits of a domain's pages, flagging titles
+    /// that more than one of them uses
+    pub fn build(domain: &str, mut pages: Vec<SeoPage>, limit: usize) -> Self {
+        let mut titles: HashMap<String, usize> = HashMap::new();
+        for page in &pages {
+            if !page.audit.title.is_empty() {
+                *titles.entry(page.audit.title.to_lowercase()).or_default() += 1;
+            }
+        }
+        let mut findings = BTreeMap::new();
+        for page in &mut pages {
+            if titles
+                .get(&page.audit.title.to_lowercase())
+                .is_some_and(|&count| count > 1)
+            {
+                page.audit.findings.push(DUPLICATE_TITLE.to_string());
+            }
+            for finding in &page.audit.findings {
+                *findings.entry(finding.clone()).or_default() += 1;
+            }
+        }
+        let pages_audited = pages.len();
+        pages.retain(|page| !page.audit.findings.is_empty());
+        pages.sort_by(|a, b| a.url.cmp(&b.url));
+        let pages_with_findings = pages.len();
+        pages.truncate(limit);
+        Self {
+            domain: domain.to_string(),
+            pages_audited,
+            pages_with_findings,
+            findings,
+            pages,
+        }
+    }
+
+    /// The listed pages, one row each, findings separated by `;`
+    pub fn to_csv(&self) -> String {
+        let mut csv = CsvWriter::new(&[
+            "url",
+            "audited_at",
+            "title",
+            "title_chars",
+            "has_description",
+            "h1_count",
+            "canonical",
+            "findings",
+        ]);
+        for page in &self.pages {
+            let audit = &page.audit;
+            csv.row([
+                page.url.clone(),
+                page.audited_at.to_string(),
+                audit.title.clone(),
+                audit.title_chars.to_string(),
+                audit.has_description.to_string(),
+                audit.h1_count.to_string(),
+                audit.canonical.clone().unwrap_or_default(),
+                audit.findings.join(";"),
+            ]);
+        }
+        csv.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audit(html: &str) -> SeoAudit {
+        let url = Url::parse("https://example.com/guide").unwrap();
+        SeoAudit::measure(&Html::parse_document(html), &url)
+    }
+
+    #[test]
+    fn flags_missing_and_malformed_elements() {
+        let good = audit(
+            r#"<html><head><title>A complete guide to crawling the web politely</title>
+            <meta name="description" content="How to crawl.">
+            <link rel="canonical" href="/guide"></head>
+            <body><h1>Guide</h1></body></html>"#,
+        );
+        assert_eq!(good.findings, Vec::<String>::new());
+        assert_eq!(good.canonical.as_deref(), Some("https://example.com/guide"));
+
+        let bad = audit(
+            r#"<html><head><title>Guide</title>
+            <link rel="canonical" href="https://mirror.example.org/guide"></head>
+            <body><h1>One</h1><h1>Two</h1></body></html>"#,
+        );
+        assert_eq!(
+            bad.findings,
+            [
+                "title_too_short",
+                "description_missing",
+                "h1_multiple",
+                "canonical_other_host"
+            ]
+        );
+        assert_eq!(
+            audit("<p>no head</p>").findings,
+            ["title_missing", "description_missing", "h1_missing"]
+        );
+    }
+
+    #[test]
+    fn reports_duplicate_titles_across_pages() {
+        let page = |url: &str, title: &str| SeoPage {
+            url: url.to_string(),
+            audited_at: 0,
+            audit: SeoAudit {
+                title: title.to_string(),
+                title_chars: 40,
+                has_description: true,
+                h1_count: 1,
+                canonical: None,
+                findings: Vec::new(),
+            },
+        };
+        let report = SeoReport::build(
+            "example.com",
+            vec![
+                page("https://example.com/b", "Welcome to Example"),
+                page("https://example.com/a", "welcome to example"),
+                page("https://example.com/c", "Pricing"),
+            ],
+            1,
+        );
+        assert_eq!(report.pages_audited, 3);
+        assert_eq!(report.pages_with_findings, 2);
+        assert_eq!(report.findings[DUPLICATE_TITLE], 2);
+        assert_eq!(report.pages.len(), 1);
+        assert_eq!(report.pages[0].url, "https://example.com/a");
+    }
+}
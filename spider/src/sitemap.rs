@@ -14,12 +14,14 @@ const MAX_DEPTH: usize = 3;
 const MAX_FILES: usize = 20;
 const MAX_DECOMPRESSED_BYTES: usize = 10 * 1024 * 1024;
 
+/// Same-origin page URLs from the seed's sitemaps, newest first, with their
+/// `lastmod` as a Unix timestamp when the sitemap has one
 pub async fn discover(
     visitor: Arc<WebVisitorImpl>,
     seed: &Url,
     declared: Vec<Url>,
     max_pages: usize,
-) -> Vec<Url> {
+) -> Vec<DatedUrl> {
     let initial = if declared.is_empty() {
         seed.join("/sitemap.xml").into_iter().collect()
     } else {
@@ -55,7 +57,7 @@ pub async fn discover(
         };
         found_pages.retain(|(page, _)| same_origin(seed, page));
         found_pages.sort_by_key(|page| std::cmp::Reverse(page.1));
-        pages.extend(found_pages.into_iter().map(|(url, _)| url));
+        pages.extend(found_pages);
         pages.truncate(limit);
 
         if depth < MAX_DEPTH {
@@ -68,13 +70,13 @@ pub async fn discover(
     let mut seen = HashSet::new();
     pages
         .into_iter()
-        .filter_map(|url| normalize_url(url.as_str()))
-        .filter(|url| seen.insert(url.to_string()))
+        .filter_map(|(url, lastmod)| Some((normalize_url(url.as_str())?, lastmod)))
+        .filter(|(url, _)| seen.insert(url.to_string()))
         .take(limit)
         .collect()
 }
 
-type DatedUrl = (Url, Option<i64>);
+pub type DatedUrl = (Url, Option<i64>);
 
 fn parse_response(url: &Url, result: FetchResult) -> Result<(Vec<DatedUrl>, Vec<DatedUrl>)> {
     let gzip = url.path().ends_with(".gz")
@@ -0,0 +1,1045 @@
+//! SQLite-backed crawl state that should not live in memory
+//!
+//! The database lives at `SPIDER_STATE_PATH` (default `spider-state.sqlite3` in
+//! the working directory). Calls are short, indexed statements and run inline
+//! on the calling task. Per-job rows are dropped on restart; the history of
+//! finished jobs, the page versions they saw, when each URL was last fetched,
+//! the broken links found, the latest SEO audit of each page, the
+//! per-domain crawl profiles, the paused hosts and the URL each page variant
+//! is indexed under are kept.
+
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use serde_json::Value;
+use shared_crawler_api::{
+    caches::{CacheCounters, CacheStats},
+    csv::CsvWriter,
+};
+use std::{env, path::Path, sync::Mutex};
+
+use url::Url;
+
+use crate::{
+    crawl_diff::PageVersions,
+    seo::{SeoAudit, SeoPage},
+    url_variants::{prefers, variant_key},
+};
+
+/// A URL that answered 404 or 410 or whose host did not resolve
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BrokenLink {
+    pub url: String,
+    /// HTTP status or `dns`, as last seen
+    pub reason: String,
+    pub found_at: i64,
+    /// Pages linking to it
+    pub sources: Vec<String>,
+}
+
+impl BrokenLink {
+    /// One row per broken URL and linking page
+    pub fn csv(links: &[Self]) -> String {
+        let mut csv = CsvWriter::new(&["url", "reason", "found_at", "source"]);
+        for link in links {
+            for source in &link.sources {
+                csv.row([
+                    link.url.as_str(),
+                    link.reason.as_str(),
+                    &link.found_at.to_string(),
+                    source.as_str(),
+                ]);
+            }
+        }
+        csv.finish()
+    }
+}
+
+/// Longest `skip_crawled_within_hours`; older fetch times are deleted
+pub const MAX_SKIP_CRAWLED_WITHIN_HOURS: u64 = 30 * 24;
+
+/// `SKIP_CRAWLED_WITHIN_HOURS`: pages any job fetched this recently are not
+/// fetched again; `0` (the default) turns this off
+pub fn skip_crawled_within_hours_from_env() -> u64 {
+    env::var("SKIP_CRAWLED_WITHIN_HOURS")
+        .ok()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .unwrap_or(0)
+        .min(MAX_SKIP_CRAWLED_WITHIN_HOURS)
+}
+
+const DEFAULT_PATH: &str = "spider-state.sqlite3";
+const SECS_PER_HOUR: i64 = 3_600;
+
+const SCHEMA: &str = "
+    PRAGMA journal_mode = WAL;
+    PRAGMA synchronous = NORMAL;
+    CREATE TABLE IF NOT EXISTS visited (
+        job_id TEXT NOT NULL,
+        url TEXT NOT NULL,
+        PRIMARY KEY (job_id, url)
+    ) WITHOUT ROWID;
+    CREATE TABLE IF NOT EXISTS frontier (
+        id INTEGER PRIMARY KEY,
+        job_id TEXT NOT NULL,
+        url TEXT NOT NULL,
+        depth INTEGER NOT NULL,
+        referrer TEXT,
+        UNIQUE (job_id, url)
+    );
+    CREATE TABLE IF NOT EXISTS job_history (
+        job_id TEXT PRIMARY KEY,
+        finished_at INTEGER NOT NULL,
+        record TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS job_history_finished_at ON job_history (finished_at);
+    CREATE TABLE IF NOT EXISTS crawl_pages (
+        job_id TEXT NOT NULL,
+        url TEXT NOT NULL,
+        page_version TEXT,
+        PRIMARY KEY (job_id, url)
+    ) WITHOUT ROWID;
+    CREATE TABLE IF NOT EXISTS crawls (
+        job_id TEXT PRIMARY KEY,
+        host TEXT NOT NULL,
+        finished_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS crawls_host ON crawls (host, finished_at);
+    CREATE TABLE IF NOT EXISTS fetched_pages (
+        url TEXT PRIMARY KEY,
+        fetched_at INTEGER NOT NULL
+    ) WITHOUT ROWID;
+    CREATE INDEX IF NOT EXISTS fetched_pages_at ON fetched_pages (fetched_at);
+    CREATE TABLE IF NOT EXISTS broken_links (
+        url TEXT NOT NULL,
+        source TEXT NOT NULL,
+        source_host TEXT NOT NULL,
+        reason TEXT NOT NULL,
+        found_at INTEGER NOT NULL,
+        PRIMARY KEY (url, source)
+    ) WITHOUT ROWID;
+    CREATE INDEX IF NOT EXISTS broken_links_host ON broken_links (source_host, url);
+    CREATE TABLE IF NOT EXISTS seo_pages (
+        url TEXT PRIMARY KEY,
+        host TEXT NOT NULL,
+        audit TEXT NOT NULL,
+        audited_at INTEGER NOT NULL
+    ) WITHOUT ROWID;
+    CREATE INDEX IF NOT EXISTS seo_pages_host ON seo_pages (host);
+    CREATE TABLE IF NOT EXISTS domain_profiles (
+        host TEXT PRIMARY KEY,
+        profile TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS paused_hosts (
+        host TEXT PRIMARY KEY,
+        paused_at INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS url_variants (
+        collection TEXT NOT NULL,
+        variant_key TEXT NOT NULL,
+        source_url TEXT NOT NULL,
+        PRIMARY KEY (collection, variant_key)
+    ) WITHOUT ROWID;
+    CREATE TABLE IF NOT EXISTS checkpoints (
+        job_id TEXT PRIMARY KEY,
+        created_at INTEGER NOT NULL,
+        record TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS saved_stats (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        snapshot TEXT NOT NULL
+    );
+";
+
+pub struct StateStore {
+    conn: Mutex<Connection>,
+    /// `fetched_within` lookups, hits being recent fetches
+    fetch_lookups: CacheCounters,
+}
+
+impl StateStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let path = env::var("SPIDER_STATE_PATH")
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_PATH.to_string());
+        Self::open(path)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        // Databases created before frontier rows kept the linking page
+        let has_referrer = conn
+            .prepare("SELECT 1 FROM pragma_table_info('frontier') WHERE name = 'referrer'")?
+            .exists([])?;
+        if !has_referrer {
+            conn.execute_batch("ALTER TABLE frontier ADD COLUMN referrer TEXT")?;
+        }
+        Ok(Self {
+            conn: Mutex::new(conn),
+            fetch_lookups: CacheCounters::default(),
+        })
+    }
+
+    /// Record a visited URL; returns `false` if it was already recorded
+    pub fn mark_visited(&self, job_id: &str, url: &str) -> Result<bool> {
+        let inserted = self.conn.lock().unwrap().execute(
+            "INSERT OR IGNORE INTO visited (job_id, url) VALUES (?1, ?2)",
+            params![job_id, url],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    pub fn is_visited(&self, job_id: &str, url: &str) -> Result<bool> {
+        let found = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT 1 FROM visited WHERE job_id = ?1 AND url = ?2",
+                params![job_id, url],
+                |_| Ok(()),
+            )
+            .optional()?;
+        Ok(found.is_some())
+    }
+
+    /// Only jobs checkpointed at shutdown survive a restart, so other rows
+    /// left by a previous process are orphans
+    pub fn clear_jobs(&self) -> Result<()> {
+        self.conn.lock().unwrap().execute_batch(
+            "DELETE FROM visited WHERE job_id NOT IN (SELECT job_id FROM checkpoints);
+             DELETE FROM frontier WHERE job_id NOT IN (SELECT job_id FROM checkpoints);
+             DELETE FROM crawl_pages WHERE job_id NOT IN (SELECT job_id FROM crawls)
+                 AND job_id NOT IN (SELECT job_id FROM checkpoints);",
+        )?;
+        Ok(())
+    }
+
+    /// Record every URL of a job's visited set at once
+    pub fn mark_all_visited<'a>(
+        &self,
+        job_id: &str,
+        urls: impl IntoIterator<Item = &'a str>,
+    ) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut insert =
+                tx.prepare("INSERT OR IGNORE INTO visited (job_id, url) VALUES (?1, ?2)")?;
+            for url in urls {
+                insert.execute(params![job_id, url])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn visited_urls(&self, job_id: &str) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare("SELECT url FROM visited WHERE job_id = ?1")?;
+        let urls = statement
+            .query_map(params![job_id], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(urls)
+    }
+
+    /// Put URLs back in front of a job's spilled frontier, keeping their order
+    pub fn unpop_frontier(
+        &self,
+        job_id: &str,
+        urls: &[(String, usize, Option<String>)],
+    ) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let first: i64 = tx.query_row("SELECT COALESCE(MIN(id), 1) FROM frontier", [], |row| {
+            row.get(0)
+        })?;
+        {
+            let mut insert = tx.prepare(
+                "INSERT OR IGNORE INTO frontier (id, job_id, url, depth, referrer)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            let start = first - urls.len() as i64;
+            for (offset, (url, depth, referrer)) in urls.iter().enumerate() {
+                insert.execute(params![
+                    start + offset as i64,
+                    job_id,
+                    url,
+                    *depth as i64,
+                    referrer
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn frontier_len(&self, job_id: &str) -> Result<usize> {
+        let count: i64 = self.conn.lock().unwrap().query_row(
+            "SELECT COUNT(*) FROM frontier WHERE job_id = ?1",
+            params![job_id],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Keep a job interrupted by a shutdown to resume it on the next start
+    pub fn checkpoint_job(&self, job_id: &str, created_at: i64, record: &Value) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO checkpoints (job_id, created_at, record) VALUES (?1, ?2, ?3)",
+            params![job_id, created_at, record.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Checkpointed jobs as `(job_id, created_at, record)`, oldest first
+    pub fn checkpoints(&self) -> Result<Vec<(String, i64, Value)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement =
+            conn.prepare("SELECT job_id, created_at, record FROM checkpoints ORDER BY created_at")?;
+        let checkpoints = statement
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get::<_, String>(2)?))
+            })?
+            .map(|row| {
+                let (job_id, created_at, record) = row?;
+                Ok((job_id, created_at, serde_json::from_str(&record)?))
+            })
+            .collect();
+        checkpoints
+    }
+
+    pub fn remove_checkpoint(&self, job_id: &str) -> Result<()> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM checkpoints WHERE job_id = ?1", params![job_id])?;
+        Ok(())
+    }
+
+    /// Keep the crawler's counters for the next start
+    pub fn save_stats(&self, snapshot: &Value) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO saved_stats (id, snapshot) VALUES (1, ?1)",
+            params![snapshot.to_string()],
+        )?;
+        Ok(())
+    }
+
+    pub fn saved_stats(&self) -> Result<Option<Value>> {
+        let snapshot = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT snapshot FROM saved_stats WHERE id = 1", [], |row| {
+                row.get::<_, String>(0)
+            })
+            .optional()?;
+        Ok(snapshot
+            .map(|snapshot| serde_json::from_str(&snapshot))
+            .transpose()?)
+    }
+
+    /// Append a URL to a job's spilled frontier; returns `false` if it is already there
+    pub fn push_frontier(
+        &self,
+        job_id: &str,
+        url: &str,
+        depth: usize,
+        referrer: Option<&str>,
+    ) -> Result<bool> {
+        let inserted = self.conn.lock().unwrap().execute(
+            "INSERT OR IGNORE INTO frontier (job_id, url, depth, referrer) VALUES (?1, ?2, ?3, ?4)",
+            params![job_id, url, depth as i64, referrer],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// Remove and return up to `limit` of a job's oldest spilled URLs as
+    /// `(url, depth, referrer)`
+    pub fn pop_frontier(
+        &self,
+        job_id: &str,
+        limit: usize,
+    ) -> Result<Vec<(String, usize, Option<String>)>> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let rows = {
+            let mut statement = tx.prepare(
+                "SELECT id, url, depth, referrer FROM frontier WHERE job_id = ?1
+                 ORDER BY id LIMIT ?2",
+            )?;
+            let rows = statement
+                .query_map(params![job_id, limit as i64], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            rows
+        };
+        if let Some((last, _, _, _)) = rows.last() {
+            tx.execute(
+                "DELETE FROM frontier WHERE job_id = ?1 AND id <= ?2",
+                params![job_id, last],
+            )?;
+        }
+        tx.commit()?;
+        Ok(rows
+            .into_iter()
+            .map(|(_, url, depth, referrer)| (url, depth as usize, referrer))
+            .collect())
+    }
+
+    /// Keep the JSON record of a finished job
+    pub fn record_job(&self, job_id: &str, finished_at: i64, record: &Value) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO job_history (job_id, finished_at, record) VALUES (?1, ?2, ?3)",
+            params![job_id, finished_at, record.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Records of jobs finished at or after `since` (Unix seconds), newest first
+    pub fn job_history(&self, since: i64, limit: usize) -> Result<Vec<Value>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(
+            "SELECT record FROM job_history WHERE finished_at >= ?1
+             ORDER BY finished_at DESC, job_id LIMIT ?2",
+        )?;
+        let records = statement
+            .query_map(params![since, limit as i64], |row| row.get::<_, String>(0))?
+            .map(|record| Ok(serde_json::from_str(&record?)?))
+            .collect();
+        records
+    }
+
+    /// Record the content hash of a page a job fetched; `None` for a page it
+    /// skipped as unchanged
+    pub fn record_page(&self, job_id: &str, url: &str, version: Option<&str>) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO crawl_pages (job_id, url, page_version) VALUES (?1, ?2, ?3)",
+            params![job_id, url, version],
+        )?;
+        Ok(())
+    }
+
+    /// Mark a job's pages as a finished crawl of `host`
+    pub fn record_crawl(&self, job_id: &str, host: &str, finished_at: i64) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO crawls (job_id, host, finished_at) VALUES (?1, ?2, ?3)",
+            params![job_id, host, finished_at],
+        )?;
+        Ok(())
+    }
+
+    /// Host and finish time of a finished crawl
+    pub fn crawl_snapshot(&self, job_id: &str) -> Result<Option<(String, i64)>> {
+        Ok(self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT host, finished_at FROM crawls WHERE job_id = ?1",
+                params![job_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?)
+    }
+
+    /// The latest other crawl of `host` finished at or before `finished_at`
+    pub fn previous_crawl(
+        &self,
+        host: &str,
+        finished_at: i64,
+        job_id: &str,
+    ) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT job_id FROM crawls WHERE host = ?1 AND finished_at <= ?2 AND job_id != ?3
+                 ORDER BY finished_at DESC, job_id DESC LIMIT 1",
+                params![host, finished_at, job_id],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    pub fn page_versions(&self, job_id: &str) -> Result<PageVersions> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement =
+            conn.prepare("SELECT url, page_version FROM crawl_pages WHERE job_id = ?1")?;
+        let versions = statement
+            .query_map(params![job_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(versions)
+    }
+
+    /// Remember that `url` was fetched at `fetched_at`, by any job, and
+    /// forget fetches too old for any `skip_crawled_within_hours`
+    pub fn record_fetch(&self, url: &str, fetched_at: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO fetched_pages (url, fetched_at) VALUES (?1, ?2)",
+            params![url, fetched_at],
+        )?;
+        conn.execute(
+            "DELETE FROM fetched_pages WHERE fetched_at <= ?1",
+            params![fetched_at - MAX_SKIP_CRAWLED_WITHIN_HOURS as i64 * SECS_PER_HOUR],
+        )?;
+        Ok(())
+    }
+
+    /// Whether any job fetched `url` less than `hours` before `now`
+    pub fn fetched_within(&self, url: &str, hours: u64, now: i64) -> Result<bool> {
+        let since = now - hours as i64 * SECS_PER_HOUR;
+        let found = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT 1 FROM fetched_pages WHERE url = ?1 AND fetched_at > ?2",
+                params![url, since],
+                |_| Ok(()),
+            )
+            .optional()?;
+        self.fetch_lookups.record(found.is_some());
+        Ok(found.is_some())
+    }
+
+    /// The URLs remembered as fetched, with how often a lookup skipped one
+    pub fn fetch_stats(&self) -> Result<CacheStats> {
+        let entries: i64 = self.conn.lock().unwrap().query_row(
+            "SELECT COUNT(*) FROM fetched_pages",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(self.fetch_lookups.stats(entries as usize))
+    }
+
+    /// Forget every fetch, so `skip_crawled_within_hours` skips nothing until
+    /// pages are fetched again; returns the number of URLs forgotten
+    pub fn clear_fetches(&self) -> Result<usize> {
+        let cleared = self
+            .conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM fetched_pages", [])?;
+        self.fetch_lookups.reset();
+        Ok(cleared)
+    }
+
+    /// Record that `source` links to `url`, which is gone or does not resolve
+    pub fn record_broken_link(
+        &self,
+        url: &str,
+        source: &str,
+        source_host: &str,
+        reason: &str,
+        found_at: i64,
+    ) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO broken_links (url, source, source_host, reason, found_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![url, source, source_host, reason, found_at],
+        )?;
+        Ok(())
+    }
+
+    /// Forget a URL that was fetched successfully again
+    pub fn clear_broken_link(&self, url: &str) -> Result<()> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM broken_links WHERE url = ?1", params![url])?;
+        Ok(())
+    }
+
+    /// Up to `limit` broken URLs linked from pages of `domain` or its
+    /// subdomains, with every linking page, ordered by URL
+    pub fn broken_links(&self, domain: &str, limit: usize) -> Result<Vec<BrokenLink>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(
+            "SELECT url, source, reason, found_at FROM broken_links
+             WHERE (source_host = ?1 OR substr(source_host, -length(?1) - 1) = '.' || ?1)
+               AND url IN (SELECT DISTINCT url FROM broken_links
+                           WHERE source_host = ?1
+                              OR substr(source_host, -length(?1) - 1) = '.' || ?1
+                           ORDER BY url LIMIT ?2)
+             ORDER BY url, source",
+        )?;
+        let rows = statement
+            .query_map(params![domain, limit as i64], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, i64>(3)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let mut links: Vec<BrokenLink> = Vec::new();
+        for (url, source, reason, found_at) in rows {
+            match links.last_mut() {
+                Some(link) if link.url == url => {
+                    link.sources.push(source);
+                    if found_at > link.found_at {
+                        (link.reason, link.found_at) = (reason, found_at);
+                    }
+                }
+                _ => links.push(BrokenLink {
+                    url,
+                    reason,
+                    found_at,
+                    sources: vec![source],
+                }),
+            }
+        }
+        Ok(links)
+    }
+
+    /// Replace the SEO audit of a page
+    pub fn record_seo_audit(
+        &self,
+        url: &str,
+        host: &str,
+        audit: &SeoAudit,
+        audited_at: i64,
+    ) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO seo_pages (url, host, audit, audited_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![url, host, serde_json::to_string(audit)?, audited_at],
+        )?;
+        Ok(())
+    }
+
+    /// Latest SEO audits of the pages of `domain` and its subdomains
+    pub fn seo_pages(&self, domain: &str) -> Result<Vec<SeoPage>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(
+            "SELECT url, audit, audited_at FROM seo_pages
+             WHERE host = ?1 OR substr(host, -length(?1) - 1) = '.' || ?1",
+        )?;
+        let rows = statement
+            .query_map(params![domain], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.into_iter()
+            .map(|(url, audit, audited_at)| {
+                Ok(SeoPage {
+                    url,
+                    audited_at,
+                    audit: serde_json::from_str(&audit)?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn set_domain_profile(&self, host: &str, profile: &Value) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO domain_profiles (host, profile) VALUES (?1, ?2)",
+            params![host, profile.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Every stored profile as `(host, profile)`
+    pub fn domain_profiles(&self) -> Result<Vec<(String, Value)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare("SELECT host, profile FROM domain_profiles")?;
+        let profiles = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .map(|row| {
+                let (host, profile) = row?;
+                Ok((host, serde_json::from_str(&profile)?))
+            })
+            .collect();
+        profiles
+    }
+
+    pub fn pause_host(&self, host: &str, paused_at: i64) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR IGNORE INTO paused_hosts (host, paused_at) VALUES (?1, ?2)",
+            params![host, paused_at],
+        )?;
+        Ok(())
+    }
+
+    /// Returns whether the host was paused
+    pub fn resume_host(&self, host: &str) -> Result<bool> {
+        let removed = self
+            .conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM paused_hosts WHERE host = ?1", params![host])?;
+        Ok(removed > 0)
+    }
+
+    /// Every paused host as `(host, paused_at)`
+    pub fn paused_hosts(&self) -> Result<Vec<(String, i64)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare("SELECT host, paused_at FROM paused_hosts")?;
+        let hosts = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(hosts)
+    }
+
+    /// The URL `url`'s page is indexed under in `collection`, given the
+    /// page's rel=canonical: the preferred variant crawled so far. When
+    /// `url` or its rel=canonical beats the variant it was indexed under, the
+    /// mapping moves and that variant is returned second, so its points can
+    /// follow.
+    pub fn canonical_url(
+        &self,
+        collection: &str,
+        url: &Url,
+        rel_canonical: Option<&Url>,
+    ) -> Result<(String, Option<String>)> {
+        let key = variant_key(url);
+        let mut candidate = url;
+        if let Some(canonical) = rel_canonical {
+            if variant_key(canonical) == key && prefers(canonical, candidate, rel_canonical) {
+                candidate = canonical;
+            }
+        }
+        let conn = self.conn.lock().unwrap();
+        let current: Option<String> = conn
+            .query_row(
+                "SELECT source_url FROM url_variants WHERE collection = ?1 AND variant_key = ?2",
+                params![collection, key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let replaced = match current {
+            Some(current) => match Url::parse(&current) {
+                Ok(parsed) if !prefers(candidate, &parsed, rel_canonical) => {
+                    return Ok((current, None));
+                }
+                _ => Some(current),
+            },
+            None => None,
+        };
+        conn.execute(
+            "INSERT OR REPLACE INTO url_variants (collection, variant_key, source_url)
+             VALUES (?1, ?2, ?3)",
+            params![collection, key, candidate.as_str()],
+        )?;
+        Ok((candidate.to_string(), replaced))
+    }
+
+    /// Drop every row belonging to a finished job
+    pub fn clear_job(&self, job_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM visited WHERE job_id = ?1", params![job_id])?;
+        conn.execute("DELETE FROM frontier WHERE job_id = ?1", params![job_id])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexes_url_variants_under_the_preferred_one() {
+        let store = StateStore::in_memory().unwrap();
+        let url = |url: &str| Url::parse(url).unwrap();
+        let first = "http://www.example.com/a/";
+        assert_eq!(
+            store.canonical_url("web_pages", &url(first), None).unwrap(),
+            (first.to_string(), None)
+        );
+        assert_eq!(
+            store
+                .canonical_url("web_pages", &url("https://www.example.com/a"), None)
+                .unwrap(),
+            (
+                "https://www.example.com/a".to_string(),
+                Some(first.to_string())
+            )
+        );
+        assert_eq!(
+            store
+                .canonical_url("web_pages", &url("http://example.com/a"), None)
+                .unwrap(),
+            ("https://www.example.com/a".to_string(), None)
+        );
+        let canonical = url("https://example.com/a");
+        assert_eq!(
+            store
+                .canonical_url("web_pages", &url(first), Some(&canonical))
+                .unwrap(),
+            (
+                "https://example.com/a".to_string(),
+                Some("https://www.example.com/a".to_string())
+            )
+        );
+        assert_eq!(
+            store
+                .canonical_url("web_pages__docs", &url("http://example.com/a"), None)
+                .unwrap(),
+            ("http://example.com/a".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn tracks_visited_urls_per_job() {
+        let store = StateStore::in_memory().unwrap();
+        assert!(store.mark_visited("a", "https://example.com/").unwrap());
+        assert!(!store.mark_visited("a", "https://example.com/").unwrap());
+        assert!(store.is_visited("a", "https://example.com/").unwrap());
+        assert!(!store.is_visited("b", "https://example.com/").unwrap());
+
+        store.clear_job("a").unwrap();
+        assert!(!store.is_visited("a", "https://example.com/").unwrap());
+    }
+
+    #[test]
+    fn keeps_checkpointed_jobs_across_restarts() {
+        let store = StateStore::in_memory().unwrap();
+        store
+            .mark_all_visited("kept", ["https://example.com/"])
+            .unwrap();
+        store
+            .mark_visited("orphan", "https://example.com/")
+            .unwrap();
+        store
+            .push_frontier("kept", "https://example.com/c", 2, None)
+            .unwrap();
+        store
+            .unpop_frontier(
+                "kept",
+                &[
+                    ("https://example.com/a".to_string(), 1, None),
+                    ("https://example.com/b".to_string(), 1, None),
+                ],
+            )
+            .unwrap();
+        let record = serde_json::json!({ "request": { "url": "https://example.com/" } });
+        store.checkpoint_job("kept", 10, &record).unwrap();
+        store.clear_jobs().unwrap();
+
+        assert_eq!(
+            store.checkpoints().unwrap(),
+            [("kept".to_string(), 10, record)]
+        );
+        assert_eq!(
+            store.visited_urls("kept").unwrap(),
+            ["https://example.com/"]
+        );
+        assert!(store.visited_urls("orphan").unwrap().is_empty());
+        assert_eq!(store.frontier_len("kept").unwrap(), 3);
+        let order = store
+            .pop_frontier("kept", 10)
+            .unwrap()
+            .into_iter()
+            .map(|(url, _, _)| url)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            [
+                "https://example.com/a",
+                "https://example.com/b",
+                "https://example.com/c"
+            ]
+        );
+        store.remove_checkpoint("kept").unwrap();
+        assert!(store.checkpoints().unwrap().is_empty());
+    }
+
+    #[test]
+    fn remembers_fetches_across_jobs_and_restarts() {
+        let store = StateStore::in_memory().unwrap();
+        let url = "https://example.com/";
+        store.record_fetch(url, 10_000).unwrap();
+        store.clear_jobs().unwrap();
+
+        assert!(store.fetched_within(url, 1, 10_000 + 3_599).unwrap());
+        assert!(!store.fetched_within(url, 1, 10_000 + 3_600).unwrap());
+        assert!(!store
+            .fetched_within("https://example.com/other", 1, 10_000)
+            .unwrap());
+        let stats = store.fetch_stats().unwrap();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 2));
+
+        assert_eq!(store.clear_fetches().unwrap(), 1);
+        assert!(!store.fetched_within(url, 1, 10_000).unwrap());
+        assert_eq!(store.fetch_stats().unwrap().entries, 0);
+    }
+
+    #[test]
+    fn forgets_fetches_older_than_the_longest_window() {
+        let store = StateStore::in_memory().unwrap();
+        let window = MAX_SKIP_CRAWLED_WITHIN_HOURS as i64 * SECS_PER_HOUR;
+        store.record_fetch("https://example.com/old", 0).unwrap();
+        store.record_fetch("https://example.com/kept", 1).unwrap();
+        store
+            .record_fetch("https://example.com/new", window)
+            .unwrap();
+
+        assert_eq!(store.fetch_stats().unwrap().entries, 2);
+        assert!(store
+            .fetched_within(
+                "https://example.com/kept",
+                MAX_SKIP_CRAWLED_WITHIN_HOURS,
+                window
+            )
+            .unwrap());
+    }
+
+    #[test]
+    fn lists_job_history_since_newest_first() {
+        let store = StateStore::in_memory().unwrap();
+        for (job_id, finished_at) in [("a", 100), ("b", 200), ("c", 300)] {
+            let record = serde_json::json!({ "job_id": job_id });
+            store.record_job(job_id, finished_at, &record).unwrap();
+        }
+        store.clear_jobs().unwrap();
+
+        let history = store.job_history(200, 10).unwrap();
+        assert_eq!(
+            history,
+            [
+                serde_json::json!({ "job_id": "c" }),
+                serde_json::json!({ "job_id": "b" })
+            ]
+        );
+        assert_eq!(store.job_history(0, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn pops_spilled_frontier_in_insertion_order() {
+        let store = StateStore::in_memory().unwrap();
+        for page in 0..5 {
+            let url = format!("https://example.com/{page}");
+            assert!(store.push_frontier("a", &url, page, None).unwrap());
+        }
+        assert!(!store
+            .push_frontier("a", "https://example.com/0", 9, None)
+            .unwrap());
+        store
+            .push_frontier("b", "https://example.org/", 0, Some("https://example.net/"))
+            .unwrap();
+
+        let batch = store.pop_frontier("a", 3).unwrap();
+        assert_eq!(
+            batch,
+            [
+                ("https://example.com/0".to_string(), 0, None),
+                ("https://example.com/1".to_string(), 1, None),
+                ("https://example.com/2".to_string(), 2, None),
+            ]
+        );
+        assert_eq!(store.pop_frontier("a", 10).unwrap().len(), 2);
+        assert!(store.pop_frontier("a", 10).unwrap().is_empty());
+        assert_eq!(
+            store.pop_frontier("b", 10).unwrap()[0].2.as_deref(),
+            Some("https://example.net/")
+        );
+    }
+
+    #[test]
+    fn groups_broken_links_by_url_for_a_domain() {
+        let store = StateStore::in_memory().unwrap();
+        let gone = "https://example.com/gone";
+        store
+            .record_broken_link(gone, "https://example.com/a", "example.com", "404", 10)
+            .unwrap();
+        store
+            .record_broken_link(
+                gone,
+                "https://blog.example.com/b",
+                "blog.example.com",
+                "410",
+                20,
+            )
+            .unwrap();
+        store
+            .record_broken_link(
+                "https://nx.example/",
+                "https://example.com/a",
+                "example.com",
+                "dns",
+                10,
+            )
+            .unwrap();
+        store
+            .record_broken_link(gone, "https://other.org/", "other.org", "404", 10)
+            .unwrap();
+        store
+            .record_broken_link(gone, "https://blog.axb.org/", "blog.axb.org", "404", 10)
+            .unwrap();
+
+        let links = store.broken_links("example.com", 10).unwrap();
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].url, gone);
+        assert_eq!(links[0].reason, "410");
+        assert_eq!(
+            links[0].sources,
+            ["https://blog.example.com/b", "https://example.com/a"]
+        );
+        assert_eq!(store.broken_links("example.com", 1).unwrap().len(), 1);
+        // `_` and `%` in a domain are no wildcards
+        assert_eq!(store.broken_links("a_b.org", 10).unwrap(), []);
+        assert_eq!(store.broken_links("%.org", 10).unwrap(), []);
+
+        store.clear_broken_link(gone).unwrap();
+        assert_eq!(store.broken_links("other.org", 10).unwrap(), []);
+    }
+
+    #[test]
+    fn lists_seo_audits_of_a_domain_and_its_subdomains() {
+        let store = StateStore::in_memory().unwrap();
+        let audit = SeoAudit {
+            title: "Example".into(),
+            title_chars: 7,
+            has_description: true,
+            h1_count: 1,
+            canonical: None,
+            findings: vec![],
+        };
+        for (url, host) in [
+            ("https://example.com/", "example.com"),
+            ("https://blog.example.com/", "blog.example.com"),
+            ("https://badexample.com/", "badexample.com"),
+            ("https://blog.axb.org/", "blog.axb.org"),
+        ] {
+            store.record_seo_audit(url, host, &audit, 10).unwrap();
+        }
+
+        let mut urls = store
+            .seo_pages("example.com")
+            .unwrap()
+            .into_iter()
+            .map(|page| page.url)
+            .collect::<Vec<_>>();
+        urls.sort();
+        assert_eq!(urls, ["https://blog.example.com/", "https://example.com/"]);
+        // `_` and `%` in a domain are no wildcards
+        assert!(store.seo_pages("a_b.org").unwrap().is_empty());
+        assert!(store.seo_pages("%.org").unwrap().is_empty());
+    }
+}
@@ -2,8 +2,14 @@
 //!
 //! Provides thread-safe atomic counters for monitoring crawler performance.
 
+use reqwest::Version;
 use serde::Serialize;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::report::FailureKind;
 
 /// Thread-safe statistics counters for the crawler
 #[derive(Default)]
@@ -13,7 +19,14 @@ pub struct CrawlStats {
     pub pages_failed: AtomicUsize,
     pub pages_skipped_robots: AtomicUsize,
     pub pages_skipped_depth: AtomicUsize,
+    pub pages_skipped_low_quality: AtomicUsize,
     pub retries_attempted: AtomicUsize,
+    /// Page responses received over HTTP/1.x, HTTP/2 and HTTP/3
+    pub responses_http1: AtomicUsize,
+    pub responses_http2: AtomicUsize,
+    pub responses_http3: AtomicUsize,
+    /// Failed pages, indexed like `FailureKind::ALL`
+    failures: [AtomicUsize; FailureKind::ALL.len()],
 }
 
 impl CrawlStats {
@@ -21,6 +34,32 @@ impl CrawlStats {
         Self::default()
     }
 
+    /// Counters continuing from a `snapshot` saved by an earlier process
+    pub fn restore(snapshot: &serde_json::Value) -> Self {
+        let count = |value: &serde_json::Value| value.as_u64().unwrap_or(0) as usize;
+        let read = |value: &serde_json::Value| AtomicUsize::new(count(value));
+        let stats = Self {
+            pages_crawled: read(&snapshot["pages_crawled"]),
+            pages_indexed: read(&snapshot["pages_indexed"]),
+            pages_failed: read(&snapshot["pages_failed"]),
+            pages_skipped_robots: read(&snapshot["pages_skipped_robots"]),
+            pages_skipped_depth: read(&snapshot["pages_skipped_depth"]),
+            pages_skipped_low_quality: read(&snapshot["pages_skipped_low_quality"]),
+            retries_attempted: read(&snapshot["retries_attempted"]),
+            responses_http1: read(&snapshot["responses_http1"]),
+            responses_http2: read(&snapshot["responses_http2"]),
+            responses_http3: read(&snapshot["responses_http3"]),
+            ..Self::default()
+        };
+        for (kind, failures) in FailureKind::ALL.iter().zip(&stats.failures) {
+            failures.store(
+                count(&snapshot["failures_by_kind"][kind.as_str()]),
+                Ordering::Relaxed,
+            );
+        }
+        stats
+    }
+
     pub fn inc_crawled(&self) {
         self.pages_crawled.fetch_add(1, Ordering::Relaxed);
     }
@@ -29,8 +68,9 @@ impl CrawlStats {
         self.pages_indexed.fetch_add(1, Ordering::Relaxed);
     }
 
-    pub fn inc_failed(&self) {
+    pub fn inc_failed(&self, kind: FailureKind) {
         self.pages_failed.fetch_add(1, Ordering::Relaxed);
+        self.failures[kind as usize].fetch_add(1, Ordering::Relaxed);
     }
 
     pub fn inc_skipped_robots(&self) {
@@ -41,6 +81,22 @@ impl CrawlStats {
         self.pages_skipped_depth.fetch_add(1, Ordering::Relaxed);
     }
 
+    pub fn inc_skipped_low_quality(&self) {
+        self.pages_skipped_low_quality
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_protocol(&self, version: Version) {
+        let counter = if version == Version::HTTP_2 {
+            &self.responses_http2
+        } else if version == Version::HTTP_3 {
+            &self.responses_http3
+        } else {
+            &self.responses_http1
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
     /// Get a snapshot of current stats
     pub fn snapshot(&self) -> StatsSnapshot {
         StatsSnapshot {
@@ -49,7 +105,17 @@ impl CrawlStats {
             pages_failed: self.pages_failed.load(Ordering::Relaxed),
             pages_skipped_robots: self.pages_skipped_robots.load(Ordering::Relaxed),
             pages_skipped_depth: self.pages_skipped_depth.load(Ordering::Relaxed),
+            pages_skipped_low_quality: self.pages_skipped_low_quality.load(Ordering::Relaxed),
             retries_attempted: self.retries_attempted.load(Ordering::Relaxed),
+            responses_http1: self.responses_http1.load(Ordering::Relaxed),
+            responses_http2: self.responses_http2.load(Ordering::Relaxed),
+            responses_http3: self.responses_http3.load(Ordering::Relaxed),
+            failures_by_kind: FailureKind::ALL
+                .iter()
+                .zip(&self.failures)
+                .map(|(kind, count)| (kind.as_str(), count.load(Ordering::Relaxed)))
+                .filter(|(_, count)| *count > 0)
+                .collect(),
         }
     }
 }
@@ -62,5 +128,10 @@ pub struct StatsSnapshot {
     pub pages_failed: usize,
     pub pages_skipped_robots: usize,
     pub pages_skipped_depth: usize,
+    pub pages_skipped_low_quality: usize,
     pub retries_attempted: usize,
+    pub responses_http1: usize,
+    pub responses_http2: usize,
+    pub responses_http3: usize,
+    pub failures_by_kind: BTreeMap<&'static str, usize>,
 }
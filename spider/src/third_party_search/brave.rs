@@ -0,0 +1,190 @@
+//! Brave Web Search API
+//!
+//! Requires `BRAVE_SEARCH_API_KEY`; `BRAVE_SAFESEARCH` is `off`, `moderate`
+//! (default) or `strict`.
+
+use anyhow::{bail, Context, Result};
+use reqwest::Client;
+use serde::Deserialize;
+use std::{env, time::Duration};
+
+use super::{SearchHit, ThirdPartySearch};
+
+const ENDPOINT: &str = "https://api.search.brave.com/res/v1/web/search";
+/// Brave returns at most 20 results per request and accepts page offsets 0..=9;
+/// `offset` counts pages of `count` results, so every request asks for a full page
+const PAGE_SIZE: usize = 20;
+const MAX_PAGES: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SafeSearch {
+    Off,
+    Moderate,
+    Strict,
+}
+
+impl SafeSearch {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Moderate => "moderate",
+            Self::Strict => "strict",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "off" => Some(Self::Off),
+            "moderate" => Some(Self::Moderate),
+            "strict" => Some(Self::Strict),
+            _ => None,
+        }
+    }
+}
+
+pub struct BraveSearch {
+    http: Client,
+    api_key: String,
+    safe_search: SafeSearch,
+}
+
+#[derive(Deserialize)]
+struct BraveResponse {
+    #[serde(default)]
+    web: Option<BraveWeb>,
+}
+
+#[derive(Deserialize)]
+struct BraveWeb {
+    #[serde(default)]
+    results: Vec<BraveResult>,
+}
+
+#[derive(Deserialize)]
+struct BraveResult {
+    url: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    description: String,
+}
+
+impl BraveSearch {
+    pub fn new(user_agent: &str, api_key: String, safe_search: SafeSearch) -> Self {
+        let http = Client::builder()
+            .user_agent(user_agent)
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("failed to create Brave Search HTTP client");
+        Self {
+            http,
+            api_key,
+            safe_search,
+        }
+    }
+
+    pub fn from_env(user_agent: &str) -> Result<Self> {
+        let api_key = env::var("BRAVE_SEARCH_API_KEY")
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .context("BRAVE_SEARCH_API_KEY must be configured")?;
+        let safe_search = match env::var("BRAVE_SAFESEARCH") {
+            Ok(value) => match SafeSearch::parse(&value) {
+                Some(safe_search) => safe_search,
+                None => bail!("BRAVE_SAFESEARCH must be off, moderate or strict"),
+            },
+            Err(_) => SafeSearch::Moderate,
+        };
+        Ok(Self::new(user_agent, api_key, safe_search))
+    }
+
+    async fn page(&self, query: &str, page: usize) -> Result<Vec<SearchHit>> {
+        let response = self
+            .http
+            .get(ENDPOINT)
+            .header("X-Subscription-Token", &self.api_key)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .query(&[
+                ("q", query),
+                ("count", &PAGE_SIZE.to_string()),
+                ("offset", &page.to_string()),
+                ("safesearch", self.safe_search.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<BraveResponse>()
+            .await?;
+        Ok(hits(response))
+    }
+}
+
+impl ThirdPartySearch for BraveSearch {
+    async fn search(&self, query: &str, count: usize) -> Result<Vec<SearchHit>> {
+        let mut hits = Vec::new();
+        for page in 0..MAX_PAGES {
+            if hits.len() >= count {
+                break;
+            }
+            let results = self.page(query, page).await?;
+            let exhausted = results.len() < PAGE_SIZE;
+            for hit in results {
+                super::push_unique(&mut hits, hit);
+            }
+            if exhausted {
+                break;
+            }
+        }
+        hits.truncate(count);
+        Ok(hits)
+    }
+}
+
+fn hits(response: BraveResponse) -> Vec<SearchHit> {
+    response
+        .web
+        .map(|web| {
+            web.results
+                .into_iter()
+                .map(|result| SearchHit {
+                    url: result.url,
+                    title: result.title,
+                    snippet: result.description,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_web_results() {
+        let response: BraveResponse = serde_json::from_str(
+            r#"{"type":"search","web":{"results":[
+                {"title":"Rust","url":"https://www.rust-lang.org/","description":"..."},
+                {"title":"Docs","url":"https://doc.rust-lang.org/"}
+            ]}}"#,
+        )
+        .unwrap();
+        let hits = hits(response);
+        assert_eq!(
+            hits.iter().map(|hit| hit.url.as_str()).collect::<Vec<_>>(),
+            ["https://www.rust-lang.org/", "https://doc.rust-lang.org/"]
+        );
+        assert_eq!(hits[0].title, "Rust");
+        assert_eq!(hits[1].snippet, "");
+
+        let empty: BraveResponse = serde_json::from_str(r#"{"type":"search"}"#).unwrap();
+        assert!(super::hits(empty).is_empty());
+    }
+
+    #[test]
+    fn parses_safe_search_levels() {
+        assert_eq!(SafeSearch::parse(" Strict "), Some(SafeSearch::Strict));
+        assert_eq!(SafeSearch::parse("off"), Some(SafeSearch::Off));
+        assert_eq!(SafeSearch::parse("medium"), None);
+    }
+}
@@ -0,0 +1,131 @@
+//! DuckDuckGo HTML results, a key-free fallback for seed discovery
+
+use anyhow::Result;
+use reqwest::Client;
+use scraper::{Html, Selector};
+use std::time::Duration;
+use url::Url;
+
+use super::{SearchHit, ThirdPartySearch};
+
+const ENDPOINT: &str = "https://html.duckduckgo.com/html/";
+/// The HTML endpoint serves roughly 30 results per page; stay polite and stop after a few
+const PAGE_SIZE: usize = 30;
+const MAX_PAGES: usize = 3;
+
+pub struct DuckDuckGoSearch {
+    http: Client,
+}
+
+impl DuckDuckGoSearch {
+    pub fn new(user_agent: &str) -> Self {
+        let http = Client::builder()
+            .user_agent(user_agent)
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("failed to create DuckDuckGo HTTP client");
+        Self { http }
+    }
+}
+
+impl ThirdPartySearch for DuckDuckGoSearch {
+    async fn search(&self, query: &str, count: usize) -> Result<Vec<SearchHit>> {
+        let mut hits = Vec::new();
+        for page in 0..MAX_PAGES {
+            if hits.len() >= count {
+                break;
+            }
+            let html = self
+                .http
+                .post(ENDPOINT)
+                .form(&[("q", query), ("s", &(page * PAGE_SIZE).to_string())])
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+            let results = parse_hits(&html);
+            if results.is_empty() {
+                break;
+            }
+            for hit in results {
+                super::push_unique(&mut hits, hit);
+            }
+        }
+        hits.truncate(count);
+        Ok(hits)
+    }
+}
+
+fn parse_hits(html: &str) -> Vec<SearchHit> {
+    let document = Html::parse_document(html);
+    let result_selector = Selector::parse(".result").unwrap();
+    let link_selector = Selector::parse("a.result__a[href]").unwrap();
+    let snippet_selector = Selector::parse(".result__snippet").unwrap();
+    document
+        .select(&result_selector)
+        .filter_map(|result| {
+            let link = result.select(&link_selector).next()?;
+            let url = unwrap_redirect(link.value().attr("href")?)?;
+            let snippet = result
+                .select(&snippet_selector)
+                .next()
+                .map(|snippet| text(snippet.text()))
+                .unwrap_or_default();
+            Some(SearchHit {
+                url,
+                title: text(link.text()),
+                snippet,
+            })
+        })
+        .collect()
+}
+
+fn text<'a>(parts: impl Iterator<Item = &'a str>) -> String {
+    parts
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Result links point at `//duckduckgo.com/l/?uddg=<target>`; ads and internal links are dropped
+fn unwrap_redirect(href: &str) -> Option<String> {
+    let url = Url::parse("https://duckduckgo.com").ok()?.join(href).ok()?;
+    let target = if url.host_str()?.ends_with("duckduckgo.com") {
+        let (_, target) = url.query_pairs().find(|(key, _)| key == "uddg")?;
+        Url::parse(&target).ok()?
+    } else {
+        url
+    };
+    matches!(target.scheme(), "http" | "https").then(|| target.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_result_links() {
+        let html = r##"
+            <div class="result">
+              <a class="result__a" href="//duckduckgo.com/l/?uddg=https%3A%2F%2Fwww.rust-lang.org%2F&amp;rut=abc">Rust <b>Programming</b></a>
+              <a class="result__snippet" href="#">A language empowering
+                everyone</a>
+            </div>
+            <div class="result">
+              <a class="result__a" href="https://tokio.rs/">Tokio</a>
+            </div>
+            <div class="result result--ad">
+              <a class="result__a" href="//duckduckgo.com/y.js?ad_provider=x">Ad</a>
+            </div>
+            <a class="result__url" href="https://ignored.example/">ignored</a>
+        "##;
+        let hits = parse_hits(html);
+        assert_eq!(
+            hits.iter().map(|hit| hit.url.as_str()).collect::<Vec<_>>(),
+            ["https://www.rust-lang.org/", "https://tokio.rs/"]
+        );
+        assert_eq!(hits[0].title, "Rust Programming");
+        assert_eq!(hits[0].snippet, "A language empowering everyone");
+    }
+}
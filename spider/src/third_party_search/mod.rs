@@ -0,0 +1,92 @@
+//! Web search providers used to discover crawl seeds
+//!
+//! `DISCOVERY_SEARCH_PROVIDER` picks the provider (`brave`, `searxng` or
+//! `duckduckgo`). When unset, Brave is used if `BRAVE_SEARCH_API_KEY` is
+//! configured, then SearxNG if `SEARXNG_URL` is, else DuckDuckGo.
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::{env, future::Future};
+
+pub mod brave;
+pub mod duckduckgo;
+pub mod searxng;
+
+use brave::BraveSearch;
+use duckduckgo::DuckDuckGoSearch;
+use searxng::SearxngSearch;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchHit {
+    pub url: String,
+    pub title: String,
+    pub snippet: String,
+}
+
+pub trait ThirdPartySearch {
+    /// Up to `count` hits for `query`, best match first
+    fn search(
+        &self,
+        query: &str,
+        count: usize,
+    ) -> impl Future<Output = Result<Vec<SearchHit>>> + Send;
+}
+
+/// The configured provider
+pub enum SearchProvider {
+    Brave(BraveSearch),
+    Searxng(SearxngSearch),
+    DuckDuckGo(DuckDuckGoSearch),
+}
+
+impl SearchProvider {
+    pub fn from_env(user_agent: &str) -> Result<Self> {
+        let name = env::var("DISCOVERY_SEARCH_PROVIDER")
+            .ok()
+            .map(|value| value.trim().to_ascii_lowercase())
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| default_provider().to_string());
+        Ok(match name.as_str() {
+            "brave" => Self::Brave(BraveSearch::from_env(user_agent)?),
+            "searxng" => Self::Searxng(SearxngSearch::from_env(user_agent)?),
+            "duckduckgo" => Self::DuckDuckGo(DuckDuckGoSearch::new(user_agent)),
+            other => bail!("unknown DISCOVERY_SEARCH_PROVIDER {other:?}"),
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Brave(_) => "brave",
+            Self::Searxng(_) => "searxng",
+            Self::DuckDuckGo(_) => "duckduckgo",
+        }
+    }
+}
+
+impl ThirdPartySearch for SearchProvider {
+    async fn search(&self, query: &str, count: usize) -> Result<Vec<SearchHit>> {
+        match self {
+            Self::Brave(provider) => provider.search(query, count).await,
+            Self::Searxng(provider) => provider.search(query, count).await,
+            Self::DuckDuckGo(provider) => provider.search(query, count).await,
+        }
+    }
+}
+
+fn default_provider() -> &'static str {
+    let configured = |key: &str| env::var(key).is_ok_and(|value| !value.trim().is_empty());
+    if configured("BRAVE_SEARCH_API_KEY") {
+        "brave"
+    } else if configured("SEARXNG_URL") {
+        "searxng"
+    } else {
+        "duckduckgo"
+    }
+}
+
+/// Keep the first hit for each URL
+fn push_unique(hits: &mut Vec<SearchHit>, hit: SearchHit) {
+    if !hits.iter().any(|existing| existing.url == hit.url) {
+        hits.push(hit);
+    }
+}
@@ -0,0 +1,113 @@
+//! Self-hosted SearxNG instances via their JSON API
+//!
+//! The instance must allow the `json` output format (`search.formats` in
+//! its `settings.yml`).
+
+use anyhow::{Context, Result};
+use reqwest::Client;
+use serde::Deserialize;
+use std::{env, time::Duration};
+
+use super::{SearchHit, ThirdPartySearch};
+
+const MAX_PAGES: usize = 5;
+
+pub struct SearxngSearch {
+    http: Client,
+    base_url: String,
+}
+
+#[derive(Deserialize)]
+struct SearxngResponse {
+    #[serde(default)]
+    results: Vec<SearxngResult>,
+}
+
+#[derive(Deserialize)]
+struct SearxngResult {
+    url: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    content: String,
+}
+
+impl SearxngSearch {
+    pub fn new(user_agent: &str, base_url: &str) -> Self {
+        let http = Client::builder()
+            .user_agent(user_agent)
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("failed to create SearxNG HTTP client");
+        Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn from_env(user_agent: &str) -> Result<Self> {
+        let base_url = env::var("SEARXNG_URL")
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .context("SEARXNG_URL must be configured")?;
+        Ok(Self::new(user_agent, base_url.trim()))
+    }
+}
+
+impl ThirdPartySearch for SearxngSearch {
+    async fn search(&self, query: &str, count: usize) -> Result<Vec<SearchHit>> {
+        let mut hits = Vec::new();
+        for page in 1..=MAX_PAGES {
+            if hits.len() >= count {
+                break;
+            }
+            let response = self
+                .http
+                .get(format!("{}/search", self.base_url))
+                .query(&[
+                    ("q", query),
+                    ("format", "json"),
+                    ("pageno", &page.to_string()),
+                ])
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<SearxngResponse>()
+                .await?;
+            if response.results.is_empty() {
+                break;
+            }
+            for result in response.results {
+                let hit = SearchHit {
+                    url: result.url,
+                    title: result.title,
+                    snippet: result.content,
+                };
+                super::push_unique(&mut hits, hit);
+            }
+        }
+        hits.truncate(count);
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_json_results() {
+        let response: SearxngResponse = serde_json::from_str(
+            r#"{"query":"rust","number_of_results":0,"results":[
+                {"url":"https://www.rust-lang.org/","title":"Rust","engine":"brave"}
+            ],"answers":[],"suggestions":[]}"#,
+        )
+        .unwrap();
+        assert_eq!(response.results[0].url, "https://www.rust-lang.org/");
+        assert_eq!(response.results[0].title, "Rust");
+        assert_eq!(
+            SearxngSearch::new("test", "http://searx.local/").base_url,
+            "http://searx.local"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Token limits measured with the embedding model's own tokenizer
+//!
+//! Chunks are sized in characters, which undercounts tokens for code, CJK
+//! text and long URLs. Before indexing, every embedding input is run through
+//! TEI's `/tokenize`, which uses the tokenizer of the model TEI serves, and
+//! chunks that would exceed `CHUNK_MAX_TOKENS` are split at token boundaries
+//! instead of being silently truncated by the model.
+
+use serde::{Deserialize, Serialize};
+use shared_crawler_api::WebPageChunk;
+
+/// Input limit of `intfloat/multilingual-e5-small`
+pub const DEFAULT_MAX_TOKENS: usize = 512;
+
+/// One token of a TEI `/tokenize` response; special tokens have no offsets
+#[derive(Debug, Clone, Deserialize)]
+pub struct Token {
+    #[serde(default)]
+    pub special: bool,
+    /// Byte offsets into the input
+    pub start: Option<usize>,
+    pub stop: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct TokenizeRequest<'a> {
+    pub inputs: &'a [String],
+    pub add_special_tokens: bool,
+}
+
+/// Split a chunk whose embedding input (`prefix` followed by the chunk
+/// content) has more than `max_tokens` tokens. `tokens` is the tokenization
+/// of that input; the prefix and special tokens are charged to every part.
+pub fn split_to_token_limit(
+    chunk: WebPageChunk,
+    prefix: usize,
+    tokens: &[Token],
+    max_tokens: usize,
+) -> Vec<WebPageChunk> {
+    if tokens.len() <= max_tokens {
+        return vec![chunk];
+    }
+    // Content token offsets, relative to the chunk content
+    let content = tokens
+        .iter()
+        .filter(|token| !token.special)
+        .filter_map(|token| Some((token.start?.checked_sub(prefix)?, token.stop? - prefix)))
+        .collect::<Vec<_>>();
+    let budget = max_tokens
+        .saturating_sub(tokens.len() - content.len())
+        .max(1);
+
+    let text = &chunk.chunk_content;
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut tokens = content.as_slice();
+    while !tokens.is_empty() {
+        let end = if tokens.len() <= budget {
+            text.len()
+        } else {
+            let stop = tokens[budget - 1].1;
+            let half = tokens[budget / 2].0;
+            if text[stop..].starts_with(char::is_whitespace) {
+                stop
+            } else {
+                // Prefer a word boundary in the second half of the part
+                text[..stop]
+                    .rfind(char::is_whitespace)
+                    .filter(|space| *space > half && *space > start)
+                    .unwrap_or(stop)
+            }
+        };
+        let end = floor_char_boundary(text, end.max(start));
+        let part = text[start..end].trim();
+        if !part.is_empty() {
+            parts.push(part.to_string());
+        }
+        let consumed = tokens.iter().take_while(|(_, stop)| *stop <= end).count();
+        // Never stall on a token that straddles the cut
+        tokens = &tokens[consumed.max(1)..];
+        start = tokens
+            .first()
+            .map_or(text.len(), |(start, _)| (*start).max(end));
+    }
+
+    parts
+        .into_iter()
+        .map(|content| WebPageChunk {
+            chunk_content: content,
+            ..chunk.clone()
+        })
+        .collect()
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whitespace "tokenizer" with a leading special token, like TEI's output
+    fn tokenize(input: &str) -> Vec<Token> {
+        let mut tokens = vec![Token {
+            special: true,
+            start: None,
+            stop: None,
+        }];
+        let mut offset = 0;
+        for word in input.split(' ') {
+            tokens.push(Token {
+                special: false,
+                start: Some(offset),
+                stop: Some(offset + word.len()),
+            });
+            offset += word.len() + 1;
+        }
+        tokens
+    }
+
+    fn chunk(content: &str) -> WebPageChunk {
+        WebPageChunk::new(
+            content.to_string(),
+            None,
+            "https://example.com/".to_string(),
+            "title".to_string(),
+            String::new(),
+            Vec::new(),
+            Vec::new(),
+            0.0,
+            0.0,
+            0,
+        )
+    }
+
+    #[test]
+    fn keeps_chunks_within_the_limit() {
+        let prefix = "passage: title\n\n";
+        let content = "a b c";
+        let tokens = tokenize(&format!("{prefix}{content}"));
+        let parts = split_to_token_limit(chunk(content), prefix.len(), &tokens, 10);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].chunk_content, content);
+    }
+
+    #[test]
+    fn splits_oversized_chunks_at_token_boundaries() {
+        let prefix = "passage: ";
+        let content = (0..20)
+            .map(|n| format!("w{n}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let tokens = tokenize(&format!("{prefix}{content}"));
+        // 1 special + 1 prefix token leave 6 tokens of content per part
+        let parts = split_to_token_limit(chunk(&content), prefix.len(), &tokens, 8);
+
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0].chunk_content, "w0 w1 w2 w3 w4 w5");
+        assert_eq!(parts[3].chunk_content, "w18 w19");
+        assert_eq!(
+            parts
+                .iter()
+                .map(|part| part.chunk_content.as_str())
+                .collect::<Vec<_>>()
+                .join(" "),
+            content
+        );
+        assert!(parts
+            .iter()
+            .all(|part| part.source_url == "https://example.com/"));
+    }
+}
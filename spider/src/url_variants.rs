@@ -0,0 +1,107 @@
+//! Collapsing URL variants of one page at indexing time
+//!
+//! `http://www.example.com/a/` and `https://example.com/a` are usually the
+//! same page. Variants share a key that ignores the scheme, a leading `www.`,
+//! a trailing slash and the fragment, and are indexed under one `source_url`
+//! per key: the https variant, then the one the page names as its
+//! rel=canonical, then the one without `www.`. The state store keeps the
+//! mapping per collection and moves it when a better variant is crawled, so
+//! the page is not indexed twice and its ranking signals are not split.
+
+use std::cmp::Reverse;
+use url::Url;
+
+/// `example.com/a?b=1` for every variant of `https://example.com/a?b=1`
+pub fn variant_key(url: &Url) -> String {
+    let host = url.host_str().unwrap_or_default();
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    let port = url
+        .port()
+        .map(|port| format!(":{port}"))
+        .unwrap_or_default();
+    let path = match url.path().trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    };
+    let query = url
+        .query()
+        .map(|query| format!("?{query}"))
+        .unwrap_or_default();
+    format!("{host}{port}{path}{query}")
+}
+
+/// Whether `candidate` should replace `current` as the `source_url` of their
+/// variant key, given the page's rel=canonical. Ties go to the shorter URL,
+/// then the lexically smaller one, so the choice does not depend on the order
+/// variants are crawled in.
+pub fn prefers(candidate: &Url, current: &Url, rel_canonical: Option<&Url>) -> bool {
+    rank(candidate, rel_canonical) > rank(current, rel_canonical)
+}
+
+fn rank<'a>(
+    url: &'a Url,
+    rel_canonical: Option<&Url>,
+) -> (bool, bool, bool, Reverse<usize>, Reverse<&'a str>) {
+    (
+        url.scheme() == "https",
+        rel_canonical == Some(url),
+        !url.host_str().unwrap_or_default().starts_with("www."),
+        Reverse(url.as_str().len()),
+        Reverse(url.as_str()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collapses_scheme_www_and_trailing_slash() {
+        let key = |url: &str| variant_key(&Url::parse(url).unwrap());
+        assert_eq!(key("http://www.example.com/a/"), "example.com/a");
+        assert_eq!(key("https://example.com/a#top"), "example.com/a");
+        assert_eq!(key("https://EXAMPLE.com"), "example.com/");
+        assert_eq!(key("http://www.example.com/?page=2"), "example.com/?page=2");
+        assert_ne!(
+            key("https://example.com:8443/a"),
+            key("https://example.com/a")
+        );
+        assert_ne!(
+            key("https://docs.example.com/a"),
+            key("https://example.com/a")
+        );
+    }
+
+    #[test]
+    fn prefers_https_then_rel_canonical_then_no_www() {
+        let url = |url: &str| Url::parse(url).unwrap();
+        let prefers = |a: &str, b: &str, canonical: Option<&str>| {
+            prefers(&url(a), &url(b), canonical.map(url).as_ref())
+        };
+        assert!(prefers(
+            "https://www.example.com/a",
+            "http://example.com/a",
+            None
+        ));
+        assert!(prefers(
+            "https://www.example.com/a",
+            "https://example.com/a",
+            Some("https://www.example.com/a")
+        ));
+        assert!(prefers(
+            "https://example.com/a",
+            "https://www.example.com/a",
+            None
+        ));
+        assert!(prefers(
+            "https://example.com/a",
+            "https://example.com/a/",
+            None
+        ));
+        assert!(!prefers(
+            "https://example.com/a",
+            "https://example.com/a",
+            None
+        ));
+    }
+}
@@ -0,0 +1,246 @@
+//! Per-job visited-URL sets
+//!
+//! Small crawls keep every URL in a `HashSet`. Large crawls can opt into a
+//! Bloom filter whose memory is fixed up front; a filter hit is confirmed
+//! against the SQLite state store so false positives never skip a page.
+//! Either kind is saved to the store when a shutdown interrupts its job.
+//! Jobs of a `shared_frontier` share one set in Redis instead.
+
+use std::{
+    collections::HashSet,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::Arc,
+};
+
+use crate::{cluster::SharedVisited, state::StateStore};
+
+/// Expected URLs per requested page; visited sets also hold redirect targets and skipped URLs
+const URLS_PER_PAGE: usize = 4;
+const MIN_CAPACITY: usize = 1_024;
+
+pub enum VisitedSet {
+    Exact(HashSet<String>),
+    Bloom {
+        filter: BloomFilter,
+        store: Arc<StateStore>,
+        job_id: String,
+        len: usize,
+    },
+    Shared(Box<SharedVisited>),
+}
+
+impl VisitedSet {
+    pub fn exact() -> Self {
+        Self::Exact(HashSet::new())
+    }
+
+    /// Bloom filter sized for a crawl of `max_pages` pages
+    pub fn bloom(
+        store: Arc<StateStore>,
+        job_id: &str,
+        max_pages: usize,
+        false_positive_rate: f64,
+    ) -> Self {
+        let capacity = max_pages.saturating_mul(URLS_PER_PAGE).max(MIN_CAPACITY);
+        Self::Bloom {
+            filter: BloomFilter::new(capacity, false_positive_rate),
+            store,
+            job_id: job_id.to_string(),
+            len: 0,
+        }
+    }
+
+    pub async fn contains(&self, url: &str) -> bool {
+        match self {
+            Self::Exact(urls) => urls.contains(url),
+            Self::Bloom {
+                filter,
+                store,
+                job_id,
+                ..
+            } => {
+                filter.might_contain(url)
+                    && store.is_visited(job_id, url).unwrap_or_else(|error| {
+                        tracing::warn!("visited lookup failed for {url}: {error}");
+                        false
+                    })
+            }
+            Self::Shared(visited) => visited.contains(url).await,
+        }
+    }
+
+    pub async fn insert(&mut self, url: String) {
+        match self {
+            Self::Exact(urls) => {
+                urls.insert(url);
+            }
+            Self::Bloom {
+                filter,
+                store,
+                job_id,
+                len,
+            } => {
+                filter.insert(&url);
+                match store.mark_visited(job_id, &url) {
+                    Ok(true) => *len += 1,
+                    Ok(false) => {}
+                    Err(error) => tracing::warn!("failed to record visited {url}: {error}"),
+                }
+            }
+            Self::Shared(visited) => visited.insert(&url).await,
+        }
+    }
+
+    pub async fn len(&self) -> usize {
+        match self {
+            Self::Exact(urls) => urls.len(),
+            Self::Bloom { len, .. } => *len,
+            Self::Shared(visited) => visited.count().await,
+        }
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    /// Save the URLs to the store for a later `restore`
+    pub fn checkpoint(&self, store: &StateStore, job_id: &str) -> anyhow::Result<()> {
+        match self {
+            Self::Exact(urls) => store.mark_all_visited(job_id, urls.iter().map(String::as_str)),
+            // Every URL is already in the store or in Redis
+            Self::Bloom { .. } | Self::Shared(_) => Ok(()),
+        }
+    }
+
+    /// Reload the URLs a `checkpoint` saved for `job_id`
+    pub fn restore(&mut self, store: &StateStore, job_id: &str) -> anyhow::Result<()> {
+        let urls = store.visited_urls(job_id)?;
+        match self {
+            Self::Exact(set) => set.extend(urls),
+            Self::Bloom { filter, len, .. } => {
+                for url in &urls {
+                    filter.insert(url);
+                }
+                *len += urls.len();
+            }
+            Self::Shared(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Release any state kept outside this process
+    pub fn clear(&mut self) {
+        match self {
+            Self::Exact(urls) => urls.clear(),
+            Self::Bloom {
+                filter,
+                store,
+                job_id,
+                len,
+            } => {
+                filter.clear();
+                *len = 0;
+                if let Err(error) = store.clear_job(job_id) {
+                    tracing::warn!("failed to clear visited state for job {job_id}: {error}");
+                }
+            }
+            // Other instances may still be crawling; the set expires
+            Self::Shared(_) => {}
+        }
+    }
+}
+
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    pub fn new(capacity: usize, false_positive_rate: f64) -> Self {
+        let capacity = capacity.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-capacity * false_positive_rate.ln() / (ln2 * ln2))
+            .ceil()
+            .max(64.0) as usize;
+        let hashes = ((bits as f64 / capacity) * ln2).round().clamp(1.0, 32.0) as u32;
+        Self {
+            bits: vec![0; bits.div_ceil(64)],
+            hashes,
+        }
+    }
+
+    pub fn insert(&mut self, item: &str) {
+        for bit in self.positions(item) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    pub fn might_contain(&self, item: &str) -> bool {
+        self.positions(item)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    pub fn clear(&mut self) {
+        self.bits.fill(0);
+    }
+
+    /// Kirsch–Mitzenmacher double hashing
+    fn positions(&self, item: &str) -> impl Iterator<Item = usize> {
+        let size = (self.bits.len() * 64) as u64;
+        let first = hash(item, 0);
+        let second = hash(item, 1) | 1;
+        (0..self.hashes as u64)
+            .map(move |round| (first.wrapping_add(round.wrapping_mul(second)) % size) as usize)
+    }
+}
+
+fn hash(item: &str, seed: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    item.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bloom_filter_stays_near_target_false_positive_rate() {
+        let mut filter = BloomFilter::new(10_000, 0.01);
+        for page in 0..10_000 {
+            filter.insert(&format!("https://example.com/{page}"));
+        }
+        assert!(
+            (0..10_000).all(|page| filter.might_contain(&format!("https://example.com/{page}")))
+        );
+
+        let false_positives = (0..10_000)
+            .filter(|page| filter.might_contain(&format!("https://example.org/{page}")))
+            .count();
+        assert!(false_positives < 300, "{false_positives} false positives");
+    }
+
+    #[tokio::test]
+    async fn bloom_mode_confirms_hits_against_the_store() {
+        let store = Arc::new(StateStore::in_memory().unwrap());
+        // A filter this small answers "maybe" for nearly everything.
+        let mut visited = VisitedSet::Bloom {
+            filter: BloomFilter::new(1, 0.5),
+            store: store.clone(),
+            job_id: "job".to_string(),
+            len: 0,
+        };
+        for page in 0..50 {
+            visited.insert(format!("https://example.com/{page}")).await;
+        }
+        visited.insert("https://example.com/0".to_string()).await;
+
+        assert_eq!(visited.len().await, 50);
+        assert!(visited.contains("https://example.com/7").await);
+        assert!(!visited.contains("https://example.com/other").await);
+
+        visited.clear();
+        assert!(!store.is_visited("job", "https://example.com/7").unwrap());
+    }
+}
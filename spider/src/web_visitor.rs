@@ -5,59 +5,191 @@ use governor::{
     Quota, RateLimiter,
 };
 use reqwest::{
-    header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE, LOCATION, RETRY_AFTER},
-    Client, StatusCode,
+    header::{
+        HeaderMap, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, LOCATION, RETRY_AFTER, SET_COOKIE,
+        USER_AGENT,
+    },
+    Client, StatusCode, Version,
 };
 use scraper::{Html, Selector};
+use serde::Serialize;
 use std::{
-    collections::HashMap,
-    fmt,
+    collections::{BTreeMap, HashMap},
+    env, fmt,
     num::NonZeroU32,
     sync::Arc,
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 use tokio::sync::{Mutex, OwnedMutexGuard};
 use url::Url;
 
-use crate::REQUEST_TIMEOUT_SECS;
+use crate::{
+    cluster::Cluster, domain_profile::DomainProfiles, pagination, private_network,
+    report::FailureKind, REQUEST_TIMEOUT_SECS,
+};
 
 const MAX_ATTEMPTS: usize = 3;
 pub const MAX_HTML_BYTES: usize = 5 * 1024 * 1024;
+const MAX_COOKIES_PER_HOST: usize = 50;
+const DEFAULT_REQUEST_INTERVAL: Duration = Duration::from_secs(2);
+const THROTTLE_BASE_DELAY: Duration = Duration::from_secs(2);
+const MAX_THROTTLE_DELAY: Duration = Duration::from_secs(300);
 
 type OriginLimiter = RateLimiter<NotKeyed, InMemoryState, DefaultClock>;
 
 struct OriginState {
     active: Arc<Mutex<()>>,
     limiter: OriginLimiter,
+    interval: Duration,
+    throttle: std::sync::Mutex<Throttle>,
+}
+
+impl OriginState {
+    fn new(interval: Duration, active: Arc<Mutex<()>>, throttle: Throttle) -> Self {
+        Self {
+            active,
+            limiter: RateLimiter::direct(
+                Quota::with_period(interval)
+                    .unwrap()
+                    .allow_burst(NonZeroU32::new(1).unwrap()),
+            ),
+            interval,
+            throttle: std::sync::Mutex::new(throttle),
+        }
+    }
+}
+
+/// Extra politeness for an origin answering 429/503: every overload response
+/// doubles the delay between requests, every successful one halves it again
+#[derive(Debug, Default)]
+struct Throttle {
+    strikes: u32,
+    resume_at: Option<Instant>,
+}
+
+impl Throttle {
+    fn delay(&self) -> Duration {
+        if self.strikes == 0 {
+            return Duration::ZERO;
+        }
+        THROTTLE_BASE_DELAY
+            .saturating_mul(1 << self.strikes.min(16))
+            .min(MAX_THROTTLE_DELAY)
+    }
+
+    fn record(&mut self, overloaded: bool, retry_after: Option<Duration>) {
+        if overloaded {
+            self.strikes += 1;
+        } else {
+            self.strikes = self.strikes.saturating_sub(1);
+        }
+        if self.strikes == 0 {
+            self.resume_at = None;
+            return;
+        }
+        let wait = self.delay().max(retry_after.unwrap_or_default());
+        self.resume_at = Some(Instant::now() + wait.min(MAX_THROTTLE_DELAY));
+    }
+}
+
+/// An origin currently slowed down by adaptive throttling
+#[derive(Debug, Clone, Serialize)]
+pub struct ThrottledOrigin {
+    pub origin: String,
+    pub strikes: u32,
+    pub delay_ms: u64,
+    pub resume_in_ms: u64,
+}
+
+/// Exclusive access to an origin; report the response through it
+pub struct OriginPermit {
+    _active: OwnedMutexGuard<()>,
+    state: Arc<OriginState>,
+}
+
+impl OriginPermit {
+    fn record(&self, status: StatusCode, headers: &HeaderMap) {
+        let overloaded =
+            status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE;
+        let retry_after = overloaded.then(|| retry_after(headers)).flatten();
+        self.state
+            .throttle
+            .lock()
+            .unwrap()
+            .record(overloaded, retry_after);
+    }
 }
 
 #[derive(Clone, Default)]
 pub struct OriginScheduler {
     origins: Arc<Mutex<HashMap<String, Arc<OriginState>>>>,
+    /// Spaces requests to an origin across spider instances too
+    cluster: Option<Arc<Cluster>>,
 }
 
 impl OriginScheduler {
-    async fn acquire(&self, url: &Url) -> OwnedMutexGuard<()> {
+    pub fn with_cluster(self, cluster: Option<Arc<Cluster>>) -> Self {
+        Self { cluster, ..self }
+    }
+
+    /// Origins with a raised delay, most throttled first
+    pub async fn throttled(&self) -> Vec<ThrottledOrigin> {
+        let now = Instant::now();
+        let mut throttled = self
+            .origins
+            .lock()
+            .await
+            .iter()
+            .filter_map(|(origin, state)| {
+                let throttle = state.throttle.lock().unwrap();
+                (throttle.strikes > 0).then(|| ThrottledOrigin {
+                    origin: origin.clone(),
+                    strikes: throttle.strikes,
+                    delay_ms: throttle.delay().as_millis() as u64,
+                    resume_in_ms: throttle
+                        .resume_at
+                        .map(|at| at.saturating_duration_since(now).as_millis() as u64)
+                        .unwrap_or_default(),
+                })
+            })
+            .collect::<Vec<_>>();
+        throttled.sort_by(|a, b| b.strikes.cmp(&a.strikes).then(a.origin.cmp(&b.origin)));
+        throttled
+    }
+
+    /// `interval` overrides the default delay between requests, e.g. from a
+    /// domain profile; a changed interval keeps the origin's throttle state
+    async fn acquire(&self, url: &Url, interval: Option<Duration>) -> OriginPermit {
         let origin = origin(url).expect("validated URL has an origin");
+        let interval = interval.unwrap_or(DEFAULT_REQUEST_INTERVAL);
         let state = {
             let mut origins = self.origins.lock().await;
-            origins
-                .entry(origin)
-                .or_insert_with(|| {
-                    Arc::new(OriginState {
-                        active: Arc::new(Mutex::new(())),
-                        limiter: RateLimiter::direct(
-                            Quota::with_period(Duration::from_secs(2))
-                                .unwrap()
-                                .allow_burst(NonZeroU32::new(1).unwrap()),
-                        ),
-                    })
-                })
-                .clone()
+            let state = origins.entry(origin.clone()).or_insert_with(|| {
+                Arc::new(OriginState::new(
+                    interval,
+                    Arc::new(Mutex::new(())),
+                    Throttle::default(),
+                ))
+            });
+            if state.interval != interval {
+                let throttle = std::mem::take(&mut *state.throttle.lock().unwrap());
+                *state = Arc::new(OriginState::new(interval, state.active.clone(), throttle));
+            }
+            state.clone()
         };
-        let guard = state.active.clone().lock_owned().await;
+        let active = state.active.clone().lock_owned().await;
         state.limiter.until_ready().await;
-        guard
+        let resume_at = state.throttle.lock().unwrap().resume_at;
+        if let Some(wait) = resume_at.map(|at| at.saturating_duration_since(Instant::now())) {
+            tokio::time::sleep(wait).await;
+        }
+        if let Some(cluster) = &self.cluster {
+            cluster.wait_for_origin(&origin, interval).await;
+        }
+        OriginPermit {
+            _active: active,
+            state,
+        }
     }
 }
 
@@ -68,6 +200,8 @@ pub struct FetchResult {
     pub headers: HeaderMap,
     pub content_type: Option<mime::Mime>,
     pub body: Vec<u8>,
+    /// HTTP version the response came over
+    pub version: Version,
 }
 
 #[derive(Debug)]
@@ -79,6 +213,74 @@ pub enum FetchError {
     Http(StatusCode),
     Request(reqwest::Error),
     Redirect(String),
+    PrivateAddress(String),
+    Browser(String),
+}
+
+impl FetchError {
+    pub fn kind(&self) -> FailureKind {
+        match self {
+            Self::InvalidUrl(_) => FailureKind::Parse,
+            Self::UnsupportedContentType(_) | Self::BodyTooLarge => FailureKind::Content,
+            Self::Blocked(_) => FailureKind::ClientError,
+            Self::Http(status) if status.is_client_error() => FailureKind::ClientError,
+            Self::Http(status) if status.is_server_error() => FailureKind::ServerError,
+            Self::Http(_) => FailureKind::Other,
+            Self::Request(error) => request_failure_kind(error),
+            Self::Redirect(_) => FailureKind::Redirect,
+            Self::PrivateAddress(_) => FailureKind::Other,
+            Self::Browser(_) => FailureKind::Browser,
+        }
+    }
+
+    /// Why a link to the URL counts as broken: it is gone or its host does
+    /// not resolve. Other failures may be passing.
+    pub fn broken_reason(&self) -> Option<&'static str> {
+        match self {
+            Self::Http(StatusCode::NOT_FOUND) => Some("404"),
+            Self::Http(StatusCode::GONE) => Some("410"),
+            _ if self.kind() == FailureKind::Dns => Some("dns"),
+            _ => None,
+        }
+    }
+}
+
+/// reqwest only flags timeouts, connect and decode errors; DNS and TLS
+/// failures are told apart by the messages of their sources
+fn request_failure_kind(error: &reqwest::Error) -> FailureKind {
+    if error.is_timeout() {
+        return FailureKind::Timeout;
+    }
+    let mut chain = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(error) = source {
+        chain.push_str(": ");
+        chain.push_str(&error.to_string());
+        source = error.source();
+    }
+    let chain = chain.to_lowercase();
+    if [
+        "dns error",
+        "failed to lookup address",
+        "no such host",
+        "name or service not known",
+    ]
+    .iter()
+    .any(|needle| chain.contains(needle))
+    {
+        FailureKind::Dns
+    } else if ["certificate", "tls", "ssl", "handshake"]
+        .iter()
+        .any(|needle| chain.contains(needle))
+    {
+        FailureKind::Tls
+    } else if error.is_connect() || error.is_body() || error.is_request() {
+        FailureKind::Connection
+    } else if error.is_decode() {
+        FailureKind::Parse
+    } else {
+        FailureKind::Other
+    }
 }
 
 impl fmt::Display for FetchError {
@@ -91,33 +293,179 @@ impl fmt::Display for FetchError {
             Self::Http(status) => write!(f, "HTTP {status}"),
             Self::Request(error) => error.fmt(f),
             Self::Redirect(value) => write!(f, "redirect error: {value}"),
+            Self::PrivateAddress(host) => write!(f, "refusing private address: {host}"),
+            Self::Browser(error) => write!(f, "browser error: {error}"),
         }
     }
 }
 
 impl std::error::Error for FetchError {}
 
+/// Cookies keyed by host, sent back only to the host that set them
+#[derive(Default)]
+pub struct CookieStore {
+    hosts: std::sync::Mutex<HashMap<String, BTreeMap<String, String>>>,
+}
+
+impl CookieStore {
+    pub fn header(&self, host: &str) -> Option<String> {
+        let hosts = self.hosts.lock().unwrap();
+        let cookies = hosts.get(host).filter(|cookies| !cookies.is_empty())?;
+        Some(
+            cookies
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect::<Vec<_>>()
+                .join("; "),
+        )
+    }
+
+    pub fn store(&self, host: &str, headers: &HeaderMap) {
+        let mut hosts = self.hosts.lock().unwrap();
+        for value in headers.get_all(SET_COOKIE) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            let mut parts = value.split(';');
+            let Some((name, cookie)) = parts.next().and_then(|pair| pair.split_once('=')) else {
+                continue;
+            };
+            let (name, cookie) = (name.trim(), cookie.trim());
+            let expired = parts.any(|attribute| {
+                let attribute = attribute.trim().to_ascii_lowercase();
+                attribute
+                    .strip_prefix("max-age=")
+                    .and_then(|age| age.parse::<i64>().ok())
+                    .is_some_and(|age| age <= 0)
+            });
+            let cookies = hosts.entry(host.to_string()).or_default();
+            if expired || cookie.is_empty() {
+                cookies.remove(name);
+            } else if cookies.len() < MAX_COOKIES_PER_HOST || cookies.contains_key(name) {
+                cookies.insert(name.to_string(), cookie.to_string());
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        self.hosts.lock().unwrap().clear();
+    }
+}
+
+/// Per-crawl overrides of how pages are requested
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestOptions {
+    /// Instead of `REQUEST_TIMEOUT_SECS`
+    pub timeout: Option<Duration>,
+    /// Instead of `MAX_HTML_BYTES`
+    pub max_html_bytes: Option<usize>,
+    pub headers: HeaderMap,
+}
+
 #[derive(Clone)]
 pub struct WebVisitorImpl {
     client: Client,
     scheduler: OriginScheduler,
+    user_agent: String,
+    cookies: Option<Arc<CookieStore>>,
+    allow_private_networks: bool,
+    profiles: Option<Arc<DomainProfiles>>,
+    options: RequestOptions,
 }
 
 impl WebVisitorImpl {
     pub fn new(user_agent: &str, scheduler: OriginScheduler) -> Self {
-        let client = Client::builder()
-            .user_agent(user_agent)
-            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
-            .redirect(reqwest::redirect::Policy::none())
-            .pool_idle_timeout(Duration::from_secs(90))
-            .pool_max_idle_per_host(1)
-            .build()
-            .expect("failed to create HTTP client");
-        Self { client, scheduler }
+        let allow_private_networks = private_network::allowed_from_env();
+        Self {
+            client: http_client(user_agent, allow_private_networks),
+            scheduler,
+            user_agent: user_agent.to_string(),
+            cookies: None,
+            allow_private_networks,
+            profiles: None,
+            options: RequestOptions::default(),
+        }
+    }
+
+    /// The same visitor, applying the rate limits and cookies of `profiles`
+    pub fn with_domain_profiles(self, profiles: Arc<DomainProfiles>) -> Self {
+        Self {
+            profiles: Some(profiles),
+            ..self
+        }
+    }
+
+    /// The same visitor, with private address space reachable or not
+    pub fn with_private_networks(self, allowed: bool) -> Self {
+        Self {
+            client: http_client(&self.user_agent, allowed),
+            allow_private_networks: allowed,
+            ..self
+        }
+    }
+
+    /// A visitor with its own connection pool and, when `SPIDER_COOKIES` is
+    /// enabled, its own cookie store; per-origin rate limits stay shared
+    pub fn for_runner(&self) -> Self {
+        let cookies = env::var("SPIDER_COOKIES")
+            .is_ok_and(|value| matches!(value.trim(), "1" | "true" | "yes"))
+            .then(|| Arc::new(CookieStore::default()));
+        Self {
+            client: http_client(&self.user_agent, self.allow_private_networks),
+            scheduler: self.scheduler.clone(),
+            user_agent: self.user_agent.clone(),
+            cookies,
+            allow_private_networks: self.allow_private_networks,
+            profiles: self.profiles.clone(),
+            options: self.options.clone(),
+        }
+    }
+
+    /// The same visitor, identifying itself as `user_agent` instead
+    pub fn with_user_agent(&self, user_agent: &str) -> Self {
+        Self {
+            user_agent: user_agent.to_string(),
+            ..self.clone()
+        }
+    }
+
+    /// The same visitor, with a crawl's timeout, body limit and headers
+    pub fn with_request_options(&self, options: RequestOptions) -> Self {
+        Self {
+            options,
+            ..self.clone()
+        }
+    }
+
+    pub fn user_agent(&self) -> &str {
+        &self.user_agent
+    }
+
+    pub async fn throttled_origins(&self) -> Vec<ThrottledOrigin> {
+        self.scheduler.throttled().await
+    }
+
+    /// Refuse `url` up front if it points into private address space, for
+    /// fetches that do not go through this visitor's client
+    pub async fn check_address(&self, url: &Url) -> Result<(), FetchError> {
+        if self.allow_private_networks {
+            return Ok(());
+        }
+        private_network::check(url)
+            .await
+            .map_err(|blocked| FetchError::PrivateAddress(blocked.0))
+    }
+
+    /// Forget cookies collected during a job
+    pub fn clear_cookies(&self) {
+        if let Some(cookies) = &self.cookies {
+            cookies.clear();
+        }
     }
 
     pub async fn fetch_html(&self, url: &str) -> Result<FetchResult, FetchError> {
-        let result = self.fetch_resource(url, MAX_HTML_BYTES, 10).await?;
+        let max_bytes = self.options.max_html_bytes.unwrap_or(MAX_HTML_BYTES);
+        let result = self.fetch_resource(url, max_bytes, 10).await?;
         if result.status == StatusCode::FORBIDDEN {
             return Err(FetchError::Blocked(result.final_url.to_string()));
         }
@@ -150,6 +498,7 @@ impl WebVisitorImpl {
                 .await
             {
                 Ok(result) if result.status == StatusCode::FORBIDDEN => return Ok(result),
+                // The origin's throttle now holds the next request back.
                 Ok(result)
                     if result.status == StatusCode::TOO_MANY_REQUESTS
                         || result.status == StatusCode::SERVICE_UNAVAILABLE =>
@@ -157,10 +506,6 @@ impl WebVisitorImpl {
                     if attempt + 1 == MAX_ATTEMPTS {
                         return Ok(result);
                     }
-                    tokio::time::sleep(
-                        retry_after(&result.headers).unwrap_or_else(|| backoff(attempt)),
-                    )
-                    .await;
                 }
                 Ok(result) if result.status.is_server_error() => {
                     if attempt + 1 == MAX_ATTEMPTS {
@@ -169,7 +514,11 @@ impl WebVisitorImpl {
                     tokio::time::sleep(backoff(attempt)).await;
                 }
                 Ok(result) => return Ok(result),
-                Err(error @ (FetchError::BodyTooLarge | FetchError::Redirect(_))) => {
+                Err(
+                    error @ (FetchError::BodyTooLarge
+                    | FetchError::Redirect(_)
+                    | FetchError::PrivateAddress(_)),
+                ) => {
                     return Err(error);
                 }
                 Err(error) => {
@@ -189,19 +538,61 @@ impl WebVisitorImpl {
         max_bytes: usize,
         max_redirects: usize,
     ) -> Result<FetchResult, FetchError> {
+        // A crawl's headers can carry credentials for its own site only
+        let origin = url.origin();
         for redirects in 0..=max_redirects {
-            let guard = self.scheduler.acquire(&url).await;
-            let response = self
-                .client
-                .get(url.clone())
-                .send()
-                .await
-                .map_err(FetchError::Request)?;
+            // Literal addresses never reach the resolver
+            if !self.allow_private_networks {
+                if let Some(ip) = private_network::private_literal(&url) {
+                    return Err(FetchError::PrivateAddress(ip.to_string()));
+                }
+            }
+            let profile = self
+                .profiles
+                .as_ref()
+                .and_then(|profiles| profiles.for_url(&url));
+            let permit = self
+                .scheduler
+                .acquire(&url, profile.as_ref().and_then(|p| p.request_interval()))
+                .await;
+            let host = url.host_str().unwrap_or_default().to_string();
+            let mut request = self.client.get(url.clone());
+            if url.origin() == origin {
+                request = request.headers(self.options.headers.clone());
+            }
+            request = request.header(USER_AGENT, &self.user_agent);
+            if let Some(timeout) = self.options.timeout {
+                request = request.timeout(timeout);
+            }
+            let cookie = [
+                profile.as_ref().and_then(|profile| profile.cookie_header()),
+                self.cookies
+                    .as_ref()
+                    .and_then(|cookies| cookies.header(&host)),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+            if !cookie.is_empty() {
+                request = request.header(COOKIE, cookie.join("; "));
+            }
+            let response =
+                request.send().await.map_err(|error| {
+                    match private_network::blocked_host(&error) {
+                        Some(host) => FetchError::PrivateAddress(host),
+                        None => FetchError::Request(error),
+                    }
+                })?;
             let status = response.status();
+            let version = response.version();
             let headers = response.headers().clone();
+            permit.record(status, &headers);
+            if let Some(cookies) = &self.cookies {
+                cookies.store(&host, &headers);
+            }
 
             if status.is_redirection() {
-                drop(guard);
+                drop(permit);
                 if redirects == max_redirects {
                     return Err(FetchError::Redirect("limit exceeded".to_string()));
                 }
@@ -239,19 +630,54 @@ impl WebVisitorImpl {
                 }
                 body.extend_from_slice(&chunk);
             }
-            drop(guard);
+            drop(permit);
             return Ok(FetchResult {
                 final_url: url,
                 status,
                 headers,
                 content_type,
                 body,
+                version,
             });
         }
         unreachable!()
     }
 }
 
+/// Redirects are followed by hand so every hop goes through the origin
+/// scheduler; unless private networks are allowed, resolved addresses are
+/// filtered so no hop can land on one. HTTP/2 is negotiated over TLS where
+/// the server offers it, with an adaptive flow-control window and pings
+/// that keep pooled connections to CDNs alive; bodies are decompressed
+/// transparently.
+fn http_client(user_agent: &str, allow_private_networks: bool) -> Client {
+    let max_idle = env::var("SPIDER_POOL_MAX_IDLE_PER_HOST")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(1);
+    let mut builder = Client::builder();
+    if !allow_private_networks {
+        builder = builder.dns_resolver(Arc::new(private_network::PublicResolver));
+    }
+    builder
+        .user_agent(user_agent)
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+        .connect_timeout(Duration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::none())
+        .gzip(true)
+        .brotli(true)
+        .deflate(true)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .pool_max_idle_per_host(max_idle)
+        .tcp_keepalive(Duration::from_secs(60))
+        .http2_adaptive_window(true)
+        .http2_keep_alive_interval(Duration::from_secs(30))
+        .http2_keep_alive_timeout(Duration::from_secs(10))
+        .http2_keep_alive_while_idle(true)
+        .build()
+        .expect("failed to create HTTP client")
+}
+
 pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
     let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
     if let Ok(seconds) = value.parse::<u64>() {
@@ -296,7 +722,13 @@ pub fn normalize_url(value: &str) -> Option<Url> {
     {
         let _ = url.set_port(None);
     }
-    url.set_query(None);
+    // Page numbers are the only query parameters that tell pages apart
+    let pages = url
+        .query_pairs()
+        .filter(|(key, value)| pagination::is_page_param(key, value))
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>();
+    url.set_query((!pages.is_empty()).then(|| pages.join("&")).as_deref());
     Some(url)
 }
 
@@ -312,24 +744,70 @@ fn looks_like_file_url(url: &Url) -> bool {
         .is_some_and(|ext| FILE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
 }
 
-pub fn extract_links(document: &Html, base_url: &Url) -> Vec<Url> {
+/// A link of a page, with what the page says about it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractedLink {
+    pub url: Url,
+    /// Visible text of the link, whitespace collapsed; the `alt` text of an
+    /// image link without text
+    pub anchor_text: String,
+    /// `rel` tokens, lowercased
+    pub rel: Vec<String>,
+    /// `rel` asks crawlers not to follow the link
+    pub nofollow: bool,
+    /// Index among the page's links, in document order
+    pub position: usize,
+}
+
+pub fn extract_links(document: &Html, base_url: &Url) -> Vec<ExtractedLink> {
     let selector = Selector::parse("a[href]").unwrap();
+    let images = Selector::parse("img[alt]").unwrap();
     document
         .select(&selector)
-        .filter_map(|element| element.value().attr("href"))
-        .filter(|href| {
-            let href = href.trim();
-            !href.is_empty()
-                && !["javascript:", "mailto:", "tel:", "data:"]
-                    .iter()
-                    .any(|scheme| href.starts_with(scheme))
+        .filter_map(|element| {
+            let url = resolve_link(base_url, element.value().attr("href")?)?;
+            let mut anchor_text = element.text().collect::<Vec<_>>().join(" ");
+            if anchor_text.trim().is_empty() {
+                anchor_text = element
+                    .select(&images)
+                    .filter_map(|image| image.value().attr("alt"))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+            }
+            let rel = element
+                .value()
+                .attr("rel")
+                .unwrap_or_default()
+                .split_ascii_whitespace()
+                .map(str::to_ascii_lowercase)
+                .collect::<Vec<_>>();
+            Some(ExtractedLink {
+                url,
+                anchor_text: anchor_text.split_whitespace().collect::<Vec<_>>().join(" "),
+                nofollow: rel.iter().any(|token| token == "nofollow"),
+                rel,
+                position: 0,
+            })
         })
-        .filter_map(|href| base_url.join(href).ok())
-        .filter_map(|url| normalize_url(url.as_str()))
-        .filter(|url| !looks_like_file_url(url))
+        .enumerate()
+        .map(|(position, link)| ExtractedLink { position, ..link })
         .collect()
 }
 
+/// The normalized page URL an `href` on `base_url` points to, if any
+pub fn resolve_link(base_url: &Url, href: &str) -> Option<Url> {
+    let href = href.trim();
+    if href.is_empty()
+        || ["javascript:", "mailto:", "tel:", "data:"]
+            .iter()
+            .any(|scheme| href.starts_with(scheme))
+    {
+        return None;
+    }
+    let url = normalize_url(base_url.join(href).ok()?.as_str())?;
+    (!looks_like_file_url(&url)).then_some(url)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -344,19 +822,54 @@ mod tests {
                 .as_str(),
             "https://example.com/a"
         );
+        assert_eq!(
+            normalize_url("https://example.com/blog?utm_source=x&page=2&sort=new")
+                .unwrap()
+                .as_str(),
+            "https://example.com/blog?page=2"
+        );
     }
 
     #[test]
     fn extracts_page_links_and_skips_files() {
         let document = Html::parse_document(
-            r#"<a href="/page">page</a><a href="/image.jpg">image</a><a href="/file.pdf">pdf</a>"#,
+            r#"<a href="/page">page</a><a href="/image.jpg">image</a><a href="/file.pdf">pdf</a>
+            <a href="/ad" rel="Sponsored NOFOLLOW"> Buy
+              now</a><a href="/logo"><img src="/logo.png" alt="Home"></a>"#,
         );
         let base = Url::parse("https://example.com/").unwrap();
         let links = extract_links(&document, &base);
 
         assert_eq!(
-            links.iter().map(Url::as_str).collect::<Vec<_>>(),
-            ["https://example.com/page"]
+            links
+                .iter()
+                .map(|link| (link.url.as_str(), link.anchor_text.as_str(), link.position))
+                .collect::<Vec<_>>(),
+            [
+                ("https://example.com/page", "page", 0),
+                ("https://example.com/ad", "Buy now", 1),
+                ("https://example.com/logo", "Home", 2),
+            ]
+        );
+        assert!(!links[0].nofollow && links[0].rel.is_empty());
+        assert!(links[1].nofollow);
+        assert_eq!(links[1].rel, ["sponsored", "nofollow"]);
+    }
+
+    #[test]
+    fn classifies_fetch_failures() {
+        assert_eq!(
+            FetchError::Http(StatusCode::NOT_FOUND).kind(),
+            FailureKind::ClientError
+        );
+        assert_eq!(
+            FetchError::Http(StatusCode::BAD_GATEWAY).kind(),
+            FailureKind::ServerError
+        );
+        assert_eq!(FetchError::BodyTooLarge.kind(), FailureKind::Content);
+        assert_eq!(
+            FetchError::InvalidUrl("x".to_string()).kind(),
+            FailureKind::Parse
         );
     }
 
@@ -375,14 +888,63 @@ mod tests {
         assert!(retry_after(&headers).unwrap() <= Duration::from_secs(30));
     }
 
+    #[test]
+    fn stores_cookies_per_host() {
+        let store = CookieStore::default();
+        let mut headers = HeaderMap::new();
+        headers.append(
+            SET_COOKIE,
+            HeaderValue::from_static("session=abc; Path=/; HttpOnly"),
+        );
+        headers.append(SET_COOKIE, HeaderValue::from_static("consent=yes"));
+        store.store("example.com", &headers);
+
+        assert_eq!(
+            store.header("example.com").as_deref(),
+            Some("consent=yes; session=abc")
+        );
+        assert_eq!(store.header("other.example"), None);
+
+        let mut expired = HeaderMap::new();
+        expired.append(SET_COOKIE, HeaderValue::from_static("session=; Max-Age=0"));
+        store.store("example.com", &expired);
+        assert_eq!(store.header("example.com").as_deref(), Some("consent=yes"));
+
+        store.clear();
+        assert_eq!(store.header("example.com"), None);
+    }
+
+    #[test]
+    fn throttle_backs_off_exponentially_and_recovers() {
+        let mut throttle = Throttle::default();
+        throttle.record(true, None);
+        assert_eq!(throttle.delay(), Duration::from_secs(4));
+        throttle.record(true, Some(Duration::from_secs(60)));
+        assert_eq!(throttle.delay(), Duration::from_secs(8));
+        assert!(
+            throttle.resume_at.unwrap() > Instant::now() + Duration::from_secs(50),
+            "Retry-After wins when it is longer"
+        );
+        for _ in 0..20 {
+            throttle.record(true, None);
+        }
+        assert_eq!(throttle.delay(), MAX_THROTTLE_DELAY);
+
+        for _ in 0..22 {
+            throttle.record(false, None);
+        }
+        assert_eq!(throttle.delay(), Duration::ZERO);
+        assert_eq!(throttle.resume_at, None);
+    }
+
     #[tokio::test]
     async fn enforces_per_origin_concurrency_and_cooldown() {
         let scheduler = OriginScheduler::default();
         let url = Url::parse("https://example.com/").unwrap();
-        let first = scheduler.acquire(&url).await;
+        let first = scheduler.acquire(&url, None).await;
         let scheduler_clone = scheduler.clone();
         let url_clone = url.clone();
-        let second = tokio::spawn(async move { scheduler_clone.acquire(&url_clone).await });
+        let second = tokio::spawn(async move { scheduler_clone.acquire(&url_clone, None).await });
         tokio::time::sleep(Duration::from_millis(50)).await;
         assert!(!second.is_finished());
         let started = std::time::Instant::now();
@@ -409,7 +971,8 @@ mod tests {
             "HTTP/1.1 200 OK\r\nContent-Type: application/pdf\r\nContent-Length: 1\r\n\r\nx",
         )
         .await;
-        let visitor = WebVisitorImpl::new("TestBot/1.0", OriginScheduler::default());
+        let visitor = WebVisitorImpl::new("TestBot/1.0", OriginScheduler::default())
+            .with_private_networks(true);
         assert!(matches!(
             visitor.fetch_html(&binary).await,
             Err(FetchError::UnsupportedContentType(_))
@@ -418,10 +981,63 @@ mod tests {
         let large =
             server("HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 6000000\r\n\r\n")
                 .await;
-        let visitor = WebVisitorImpl::new("TestBot/1.0", OriginScheduler::default());
+        let visitor = WebVisitorImpl::new("TestBot/1.0", OriginScheduler::default())
+            .with_private_networks(true);
         assert!(matches!(
             visitor.fetch_html(&large).await,
             Err(FetchError::BodyTooLarge)
         ));
+
+        let page = server("HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\nhi").await;
+        let visitor = WebVisitorImpl::new("TestBot/1.0", OriginScheduler::default())
+            .with_private_networks(false);
+        assert!(matches!(
+            visitor.fetch_html(&page).await,
+            Err(FetchError::PrivateAddress(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn keeps_crawl_headers_on_the_seed_origin() {
+        /// Answers one request with `response` and returns what it received
+        async fn server(response: String) -> (String, tokio::task::JoinHandle<String>) {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            let received = tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = [0; 4096];
+                let read = stream.read(&mut request).await.unwrap();
+                stream.write_all(response.as_bytes()).await.unwrap();
+                String::from_utf8_lossy(&request[..read]).to_lowercase()
+            });
+            (format!("http://{address}/"), received)
+        }
+
+        let (elsewhere, elsewhere_received) = server(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 2\r\n\r\nhi".to_string(),
+        )
+        .await;
+        let (seed, seed_received) = server(format!(
+            "HTTP/1.1 302 Found\r\nLocation: {elsewhere}\r\nContent-Length: 0\r\n\r\n"
+        ))
+        .await;
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", "Bearer secret".parse().unwrap());
+        headers.insert("x-api-key", "secret".parse().unwrap());
+        let visitor = WebVisitorImpl::new("TestBot/1.0", OriginScheduler::default())
+            .with_private_networks(true)
+            .with_request_options(RequestOptions {
+                headers,
+                ..RequestOptions::default()
+            });
+
+        visitor.fetch_html(&seed).await.unwrap();
+
+        let seed_received = seed_received.await.unwrap();
+        assert!(seed_received.contains("authorization: bearer secret"));
+        assert!(seed_received.contains("x-api-key: secret"));
+        let elsewhere_received = elsewhere_received.await.unwrap();
+        assert!(!elsewhere_received.contains("secret"));
+        assert!(elsewhere_received.contains("user-agent: testbot/1.0"));
     }
 }
@@ -1,16 +1,118 @@
 use anyhow::{Context, Result};
 use chromiumoxide::browser::{Browser, BrowserConfig};
+use chromiumoxide::cdp::browser_protocol::{
+    fetch::{ContinueRequestParams, EventRequestPaused, FailRequestParams},
+    network::{ErrorReason, ResourceType},
+};
+use chromiumoxide::Page;
 use futures::StreamExt;
+use std::collections::HashMap;
 use std::env;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use tokio::sync::{OnceCell, Semaphore};
+use tokio::task::JoinHandle;
+use url::Url;
+
+use crate::private_network::{self, PrivateAddress};
+
+/// Time for a banner to close (or the page to reload) after accepting
+const CONSENT_CLOSE_MS: u64 = 500;
 
 static BROWSER_POOL: OnceCell<Arc<BrowserPool>> = OnceCell::const_new();
 
+/// Accept buttons of common consent managers: OneTrust, Cookiebot, Didomi,
+/// Usercentrics, Quantcast and Osano's cookieconsent
+const DEFAULT_CONSENT_SELECTORS: &[&str] = &[
+    "#onetrust-accept-btn-handler",
+    "#CybotCookiebotDialogBodyLevelButtonLevelOptinAllowAll",
+    "#CybotCookiebotDialogBodyButtonAccept",
+    "#didomi-notice-agree-button",
+    "[data-testid='uc-accept-all-button']",
+    ".qc-cmp2-summary-buttons button[mode='primary']",
+    ".cc-allow",
+    ".cc-dismiss",
+];
+
+/// Clicks the first visible element matching one of the selectors passed as
+/// its argument or, failing that, a button whose whole label is a generic
+/// "Accept" in English or German; evaluates to whether it clicked
+const DISMISS_CONSENT: &str = r##"((selectors) => {
+    const visible = (element) => element.offsetParent !== null || element.getClientRects().length > 0;
+    for (const selector of selectors) {
+        let element = null;
+        try {
+            element = document.querySelector(selector);
+        } catch (_) {}
+        if (element && visible(element)) {
+            element.click();
+            return true;
+        }
+    }
+    const label = /^(accept|accept all|accept all cookies|accept cookies|agree|i agree|allow all|ok, got it|alle akzeptieren|akzeptieren|zustimmen|alle cookies akzeptieren|einverstanden)$/i;
+    for (const button of document.querySelectorAll('button, [role="button"], a[href="#"]')) {
+        if (label.test(button.textContent.trim()) && visible(button)) {
+            button.click();
+            return true;
+        }
+    }
+    return false;
+})"##;
+
+/// Copies the content of every open shadow root, innermost first, into a
+/// `<div data-shadow-root>` appended to its host, and the body of every
+/// same-origin frame into a `<div data-frame-src>` after the frame, then
+/// serializes the page. `page.content()` only returns the light DOM of the
+/// top document, which for web components is often little more than the
+/// custom element tags and for framed pages is empty. Links inside frames
+/// are made absolute first since they resolve against the frame's URL.
+const FLATTEN_DOCUMENT: &str = r#"(() => {
+    const inline = (root) => {
+        for (const element of root.querySelectorAll('*')) {
+            if (element.shadowRoot) {
+                inline(element.shadowRoot);
+                const container = document.createElement('div');
+                container.setAttribute('data-shadow-root', '');
+                container.innerHTML = element.shadowRoot.innerHTML;
+                element.appendChild(container);
+            }
+            if (element.tagName === 'IFRAME' || element.tagName === 'FRAME') {
+                let frame = null;
+                try {
+                    frame = element.contentDocument;
+                } catch (_) {}
+                if (!frame || !frame.body) continue;
+                inline(frame);
+                for (const link of frame.querySelectorAll('a[href]')) {
+                    link.setAttribute('href', link.href);
+                }
+                const container = document.createElement('div');
+                container.setAttribute('data-frame-src', element.src || 'about:srcdoc');
+                container.innerHTML = frame.body.innerHTML;
+                element.after(container);
+            }
+        }
+    };
+    inline(document);
+    // A frameset document has no body; serialized frame content would be dropped
+    const frameset = document.body;
+    if (frameset && frameset.tagName === 'FRAMESET') {
+        const body = document.createElement('body');
+        body.append(...frameset.querySelectorAll('frameset > [data-frame-src]'));
+        frameset.replaceWith(body);
+    }
+    return '<!DOCTYPE html>' + document.documentElement.outerHTML;
+})()"#;
+
 pub struct BrowserPool {
     browser: Arc<Browser>,
     pages: Arc<Semaphore>,
+    /// Without `ALLOW_PRIVATE_NETWORKS`, every request of a page, including
+    /// redirects, frames and subresources, is intercepted and failed if its
+    /// host is private
+    allow_private_networks: bool,
     settle_ms: u64,
+    scroll_wait_ms: u64,
+    consent_selectors: Vec<String>,
 }
 
 impl BrowserPool {
@@ -19,10 +121,19 @@ impl BrowserPool {
             .map(|v| v.to_lowercase() != "false")
             .unwrap_or(true);
 
+        let allow_private_networks = private_network::allowed_from_env();
         let mut builder = BrowserConfig::builder();
         if !headless {
             builder = builder.with_head();
         }
+        if !allow_private_networks {
+            // Cross-site frames stay in the page's process, so their requests
+            // are intercepted with the page's
+            builder = builder.enable_request_intercept().args([
+                "--disable-site-isolation-trials",
+                "--disable-features=IsolateOrigins,site-per-process",
+            ]);
+        }
 
         let config = builder
             .no_sandbox()
@@ -57,7 +168,10 @@ impl BrowserPool {
             pages: Arc::new(Semaphore::new(
                 env_usize("SPIDER_BROWSER_MAX_PAGES", 1).max(1),
             )),
+            allow_private_networks,
             settle_ms: env_u64("SPIDER_BROWSER_SETTLE_MS", 1_500),
+            scroll_wait_ms: env_u64("SPIDER_BROWSER_SCROLL_WAIT_MS", 750),
+            consent_selectors: consent_selectors(env::var("SPIDER_BROWSER_CONSENT_SELECTORS").ok()),
         })
     }
 
@@ -76,20 +190,24 @@ impl BrowserPool {
 
     pub async fn fetch_page_with_options(
         url: &str,
+        user_agent: &str,
         wait_for_selector: Option<&str>,
         timeout_ms: u64,
+        scroll_count: usize,
     ) -> Result<String> {
         Self::get()
             .await
-            .fetch(url, wait_for_selector, timeout_ms)
+            .fetch(url, user_agent, wait_for_selector, timeout_ms, scroll_count)
             .await
     }
 
     async fn fetch(
         &self,
         url: &str,
+        user_agent: &str,
         wait_for_selector: Option<&str>,
         timeout_ms: u64,
+        scroll_count: usize,
     ) -> Result<String> {
         // ponytail: one Chromium page by default; raise SPIDER_BROWSER_MAX_PAGES only after measuring RAM.
         let _permit = self
@@ -103,8 +221,30 @@ impl BrowserPool {
             .await
             .context("failed to create new page")?;
 
+        let guard = if self.allow_private_networks {
+            None
+        } else {
+            match PrivateNetworkGuard::attach(&page).await {
+                Ok(guard) => Some(guard),
+                Err(error) => {
+                    let _ = page.close().await;
+                    return Err(error);
+                }
+            }
+        };
+
         let result = async {
-            page.goto(url).await.context("failed to navigate to url")?;
+            page.set_user_agent(user_agent)
+                .await
+                .context("failed to set user agent")?;
+            let navigated = page.goto(url).await.context("failed to navigate to url");
+            if let Some(host) = guard
+                .as_ref()
+                .and_then(PrivateNetworkGuard::blocked_document)
+            {
+                return Err(PrivateAddress(host).into());
+            }
+            navigated?;
             page.wait_for_navigation()
                 .await
                 .context("navigation did not complete")?;
@@ -134,12 +274,35 @@ impl BrowserPool {
             } else if self.settle_ms > 0 {
                 tokio::time::sleep(std::time::Duration::from_millis(self.settle_ms)).await;
             }
-            page.content()
+            self.dismiss_consent(&page, url).await;
+            self.scroll(&page, scroll_count).await;
+            // A script or meta refresh may have navigated to a blocked host
+            if let Some(host) = guard
+                .as_ref()
+                .and_then(PrivateNetworkGuard::blocked_document)
+            {
+                return Err(PrivateAddress(host).into());
+            }
+            match page
+                .evaluate(FLATTEN_DOCUMENT)
                 .await
-                .context("failed to retrieve page content")
+                .map_err(anyhow::Error::from)
+                .and_then(|value| value.into_value::<String>().map_err(anyhow::Error::from))
+            {
+                Ok(html) => Ok(html),
+                Err(error) => {
+                    tracing::debug!("Could not flatten shadow roots and frames on {url}: {error}");
+                    page.content()
+                        .await
+                        .context("failed to retrieve page content")
+                }
+            }
         }
         .await;
         let closed = page.close().await.context("failed to close browser page");
+        if let Some(guard) = guard {
+            guard.task.abort();
+        }
         match result {
             Ok(html) => {
                 closed?;
@@ -151,6 +314,147 @@ impl BrowserPool {
             }
         }
     }
+
+    /// Click away a cookie-consent banner so its text is not captured with
+    /// the page, unless `SPIDER_BROWSER_CONSENT_SELECTORS` is `off`
+    async fn dismiss_consent(&self, page: &Page, url: &str) {
+        if self.consent_selectors.is_empty() {
+            return;
+        }
+        let script = format!(
+            "{DISMISS_CONSENT}({})",
+            serde_json::Value::from(self.consent_selectors.clone())
+        );
+        let clicked = page
+            .evaluate(script)
+            .await
+            .ok()
+            .and_then(|value| value.into_value::<bool>().ok())
+            .unwrap_or(false);
+        if clicked {
+            tracing::debug!("Dismissed consent banner on {url}");
+            tokio::time::sleep(std::time::Duration::from_millis(CONSENT_CLOSE_MS)).await;
+        }
+    }
+
+    /// Scroll to the bottom `times` times, waiting `scroll_wait_ms` for new
+    /// content after each, until the document height stops changing
+    async fn scroll(&self, page: &Page, times: usize) {
+        let mut height = None;
+        for _ in 0..times {
+            let scrolled = page
+                .evaluate(
+                    "(() => { const height = document.documentElement.scrollHeight; \
+                     window.scrollTo(0, height); return height; })()",
+                )
+                .await
+                .ok()
+                .and_then(|value| value.into_value::<u64>().ok());
+            let Some(scrolled) = scrolled else {
+                break;
+            };
+            if height == Some(scrolled) {
+                break;
+            }
+            height = Some(scrolled);
+            tokio::time::sleep(std::time::Duration::from_millis(self.scroll_wait_ms)).await;
+        }
+    }
+}
+
+/// Consent accept-button selectors: the defaults when unset, none for `off`,
+/// otherwise the comma-separated list given
+/// Answers a page's intercepted requests, failing those to private hosts
+struct PrivateNetworkGuard {
+    task: JoinHandle<()>,
+    /// Host of the first top-level document that was blocked
+    blocked_document: Arc<Mutex<Option<String>>>,
+}
+
+impl PrivateNetworkGuard {
+    async fn attach(page: &Page) -> Result<Self> {
+        let main_frame = page.mainframe().await?;
+        let mut requests = page
+            .event_listener::<EventRequestPaused>()
+            .await
+            .context("failed to intercept browser requests")?;
+        let blocked_document = Arc::new(Mutex::new(None));
+        let blocked = blocked_document.clone();
+        let page = page.clone();
+        let task = tokio::spawn(async move {
+            // Hosts are looked up once per page
+            let mut private_hosts = HashMap::<String, bool>::new();
+            while let Some(event) = requests.next().await {
+                let host = match Url::parse(&event.request.url) {
+                    Ok(url) if is_network_url(&url) => {
+                        let host = url.host_str().unwrap_or_default().to_string();
+                        let private = match private_hosts.get(&host) {
+                            Some(&private) => private,
+                            None => {
+                                let private = private_network::check(&url).await.is_err();
+                                private_hosts.insert(host.clone(), private);
+                                private
+                            }
+                        };
+                        private.then_some(host)
+                    }
+                    _ => None,
+                };
+                let answered = match host {
+                    Some(host) => {
+                        tracing::debug!("browser request to private host {host} blocked");
+                        if Some(&event.frame_id) == main_frame.as_ref()
+                            && event.resource_type == ResourceType::Document
+                        {
+                            blocked.lock().unwrap().get_or_insert(host);
+                        }
+                        page.execute(FailRequestParams::new(
+                            event.request_id.clone(),
+                            ErrorReason::BlockedByClient,
+                        ))
+                        .await
+                        .map(drop)
+                    }
+                    None => page
+                        .execute(ContinueRequestParams::new(event.request_id.clone()))
+                        .await
+                        .map(drop),
+                };
+                if let Err(error) = answered {
+                    tracing::debug!("failed to answer intercepted browser request: {error}");
+                }
+            }
+        });
+        Ok(Self {
+            task,
+            blocked_document,
+        })
+    }
+
+    fn blocked_document(&self) -> Option<String> {
+        self.blocked_document.lock().unwrap().clone()
+    }
+}
+
+/// URLs that reach a host over the network, unlike `data:` or `blob:`
+fn is_network_url(url: &Url) -> bool {
+    matches!(url.scheme(), "http" | "https" | "ws" | "wss" | "ftp")
+}
+
+fn consent_selectors(value: Option<String>) -> Vec<String> {
+    match value.as_deref().map(str::trim) {
+        None | Some("") => DEFAULT_CONSENT_SELECTORS
+            .iter()
+            .map(|selector| selector.to_string())
+            .collect(),
+        Some("off") => Vec::new(),
+        Some(list) => list
+            .split(',')
+            .map(str::trim)
+            .filter(|selector| !selector.is_empty())
+            .map(str::to_string)
+            .collect(),
+    }
 }
 
 fn env_usize(name: &str, default: usize) -> usize {
@@ -182,4 +486,26 @@ mod tests {
         env::remove_var("SPIDER_BROWSER_MAX_PAGES_TEST");
         env::remove_var("SPIDER_BROWSER_SETTLE_MS_TEST");
     }
+
+    #[test]
+    fn reads_consent_selectors() {
+        assert_eq!(
+            consent_selectors(None).len(),
+            DEFAULT_CONSENT_SELECTORS.len()
+        );
+        assert!(consent_selectors(Some("off".to_string())).is_empty());
+        assert_eq!(
+            consent_selectors(Some("#accept, .cookie-ok ,".to_string())),
+            ["#accept", ".cookie-ok"]
+        );
+    }
+
+    #[test]
+    fn checks_only_requests_that_reach_a_host() {
+        let url = |value: &str| Url::parse(value).unwrap();
+        assert!(is_network_url(&url("http://169.254.169.254/latest/")));
+        assert!(is_network_url(&url("wss://example.com/socket")));
+        assert!(!is_network_url(&url("data:text/html,<p>hi</p>")));
+        assert!(!is_network_url(&url("about:blank")));
+    }
 }
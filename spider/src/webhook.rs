@@ -0,0 +1,281 @@
+//! Webhooks for crawl jobs
+//!
+//! Job summaries are POSTed as JSON to `callback_url` when a job finishes, and
+//! each indexed page's chunks to `page_callback_url` as soon as it is indexed.
+//! A job's pages are sent one at a time and in order from a bounded queue;
+//! pages that find it full are dropped, so a slow endpoint cannot hold back
+//! the crawl or grow its memory.
+//! When `CRAWLER_WEBHOOK_SECRET` is set the raw body is signed with
+//! HMAC-SHA256 and sent as `X-Crawler-Signature: sha256=<hex>`. Unless
+//! `ALLOW_PRIVATE_NETWORKS` is enabled, webhooks are not sent to private
+//! addresses and redirects are not followed, like page fetches.
+
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde::Serialize;
+use sha2::Sha256;
+use shared_crawler_api::WebPageChunk;
+use std::{env, sync::Arc, time::Duration};
+use tokio::{
+    sync::mpsc::{self, error::TrySendError},
+    task::JoinHandle,
+};
+
+use crate::private_network::{self, PrivateAddress, PublicResolver};
+
+const SIGNATURE_HEADER: &str = "X-Crawler-Signature";
+const ATTEMPTS: u64 = 3;
+const TIMEOUT_SECS: u64 = 10;
+/// Pages of one job waiting for their delivery
+pub const PAGE_QUEUE_CAPACITY: usize = 32;
+
+/// An indexed page, delivered to `page_callback_url`
+#[derive(Debug, Clone, Serialize)]
+pub struct PageDelivery {
+    pub job_id: String,
+    pub url: String,
+    pub title: String,
+    /// `new`, `updated` or `unchanged`
+    pub outcome: &'static str,
+    pub chunks: Vec<WebPageChunk>,
+}
+
+pub struct WebhookNotifier {
+    http: Client,
+    secret: Option<String>,
+    allow_private_networks: bool,
+}
+
+impl WebhookNotifier {
+    pub fn new(user_agent: &str, secret: Option<String>, allow_private_networks: bool) -> Self {
+        let mut builder = Client::builder()
+            .user_agent(user_agent)
+            .timeout(Duration::from_secs(TIMEOUT_SECS));
+        if !allow_private_networks {
+            builder = builder
+                .dns_resolver(Arc::new(PublicResolver))
+                .redirect(reqwest::redirect::Policy::none());
+        }
+        let http = builder
+            .build()
+            .expect("failed to create webhook HTTP client");
+        Self {
+            http,
+            secret,
+            allow_private_networks,
+        }
+    }
+
+    pub fn from_env(user_agent: &str) -> Self {
+        let secret = env::var("CRAWLER_WEBHOOK_SECRET")
+            .ok()
+            .filter(|value| !value.is_empty());
+        Self::new(user_agent, secret, private_network::allowed_from_env())
+    }
+
+    pub async fn notify(&self, url: &str, payload: &impl Serialize) -> Result<()> {
+        // Literal addresses never reach the resolver
+        if !self.allow_private_networks {
+            if let Some(ip) = reqwest::Url::parse(url)
+                .ok()
+                .and_then(|url| private_network::private_literal(&url))
+            {
+                return Err(PrivateAddress(ip.to_string()).into());
+            }
+        }
+        let body = serde_json::to_vec(payload)?;
+        let mut last_error = None;
+        for attempt in 0..ATTEMPTS {
+            let mut request = self
+                .http
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            if let Some(secret) = &self.secret {
+                request = request.header(SIGNATURE_HEADER, signature(secret, &body));
+            }
+            match request
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+            {
+                Ok(_) => return Ok(()),
+                Err(error) if private_network::blocked_host(&error).is_some() => {
+                    return Err(error.into());
+                }
+                Err(error) => last_error = Some(error),
+            }
+            if attempt + 1 < ATTEMPTS {
+                tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+            }
+        }
+        Err(last_error.unwrap().into())
+    }
+}
+
+/// The delivery queue of one job's `page_callback_url`
+pub struct PageWebhook {
+    queue: mpsc::Sender<PageDelivery>,
+    worker: JoinHandle<()>,
+}
+
+impl PageWebhook {
+    pub fn start(webhooks: Arc<WebhookNotifier>, url: String, capacity: usize) -> Self {
+        let (queue, mut pages) = mpsc::channel::<PageDelivery>(capacity.max(1));
+        let worker = tokio::spawn(async move {
+            while let Some(page) = pages.recv().await {
+                if let Err(error) = webhooks.notify(&url, &page).await {
+                    tracing::warn!("page webhook for {} to {url} failed: {error}", page.url);
+                }
+            }
+        });
+        Self { queue, worker }
+    }
+
+    /// Queue `page` for delivery; returns `false` if the queue was full and
+    /// the page was dropped
+    pub fn send(&self, page: PageDelivery) -> bool {
+        match self.queue.try_send(page) {
+            Ok(()) => true,
+            Err(TrySendError::Full(page)) => {
+                tracing::warn!("page webhook queue full, dropping {}", page.url);
+                false
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+
+    /// Stop taking pages; the queued ones are still delivered
+    pub fn finish(self) -> JoinHandle<()> {
+        drop(self.queue);
+        self.worker
+    }
+}
+
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn posts_signed_json() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let read = stream.read(&mut request).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&request[..read]).into_owned()
+        });
+
+        let notifier = WebhookNotifier::new("TestBot/1.0", Some("secret".to_string()), true);
+        notifier
+            .notify(
+                &format!("http://{address}/hook"),
+                &serde_json::json!({"status": "completed"}),
+            )
+            .await
+            .unwrap();
+
+        let request = server.await.unwrap().to_ascii_lowercase();
+        let expected = signature("secret", br#"{"status":"completed"}"#);
+        assert!(request.starts_with("post /hook"));
+        assert!(request.contains(&format!("x-crawler-signature: {expected}")));
+    }
+
+    fn page(url: &str) -> PageDelivery {
+        PageDelivery {
+            job_id: "job-1".to_string(),
+            url: url.to_string(),
+            title: String::new(),
+            outcome: "new",
+            chunks: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn drops_pages_when_the_queue_is_full() {
+        let notifier = Arc::new(WebhookNotifier::new("TestBot/1.0", None, true));
+        // The worker cannot run before this test yields, so the queue fills up
+        let pages = PageWebhook::start(notifier, "http://127.0.0.1:9/hook".to_string(), 1);
+        assert!(pages.send(page("https://example.com/a")));
+        assert!(!pages.send(page("https://example.com/b")));
+        assert!(!pages.send(page("https://example.com/c")));
+    }
+
+    #[tokio::test]
+    async fn delivers_pages_in_order() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let mut bodies = Vec::new();
+            for _ in 0..3 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = vec![0; 4096];
+                while !request.ends_with(b"}") {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                stream
+                    .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                    .await
+                    .unwrap();
+                bodies.push(String::from_utf8_lossy(&request).into_owned());
+            }
+            bodies
+        });
+
+        let notifier = Arc::new(WebhookNotifier::new("TestBot/1.0", None, true));
+        let pages = PageWebhook::start(notifier, format!("http://{address}/pages"), 8);
+        for path in ["a", "b", "c"] {
+            assert!(pages.send(page(&format!("https://example.com/{path}"))));
+        }
+        pages.finish().await.unwrap();
+
+        let bodies = server.await.unwrap();
+        for (body, path) in bodies.iter().zip(["a", "b", "c"]) {
+            assert!(body.contains(&format!(r#""url":"https://example.com/{path}""#)));
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_private_endpoints() {
+        let notifier = WebhookNotifier::new("TestBot/1.0", None, false);
+        for url in [
+            "http://169.254.169.254/latest/meta-data/",
+            "http://[::1]:8080/hook",
+            "http://localhost:8080/hook",
+        ] {
+            let error = notifier
+                .notify(url, &serde_json::json!({}))
+                .await
+                .unwrap_err();
+            assert!(
+                private_network::blocked_host(error.as_ref()).is_some(),
+                "{url}: {error:#}"
+            );
+        }
+    }
+}